@@ -0,0 +1,213 @@
+//! Evaluates a saved sheet without opening a window.
+//!
+//! Exits with 1 if any cell failed to compute and with 2 if the arguments or the file were invalid.
+
+use std::{path::PathBuf, process::ExitCode};
+
+use mini_spreadsheet::{
+    common_functions::{column_idx_to_string, computed_to_text, get_cell_idx},
+    spreadsheet::csv::{CsvContent, CsvOptions},
+    Index, SpreadSheet,
+};
+
+const USAGE: &str = "Usage: mini_spreadsheet-cli <FILE> [--format table|csv] [--cell <CELL>]...
+
+Options:
+  --format <FORMAT>  Print every computed value as an aligned table (default) or as CSV
+  --cell <CELL>      Only print the computed value of the given cell, can be repeated
+  -h, --help         Print this message";
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum OutputFormat {
+    Table,
+    Csv,
+}
+
+#[derive(Debug, PartialEq)]
+struct Args {
+    path: PathBuf,
+    format: OutputFormat,
+    cells: Vec<Index>,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut path = None;
+    let mut format = OutputFormat::Table;
+    let mut cells = Vec::new();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => {
+                format = match args.next().as_deref() {
+                    Some("table") => OutputFormat::Table,
+                    Some("csv") => OutputFormat::Csv,
+                    Some(other) => return Err(format!("Unknown format '{other}'")),
+                    None => return Err("--format expects a value".to_string()),
+                }
+            }
+            "--cell" => {
+                let name = args.next().ok_or("--cell expects a cell name")?;
+                let index = get_cell_idx(&name).ok_or(format!("Invalid cell name '{name}'"))?;
+                cells.push(index);
+            }
+            flag if flag.starts_with('-') => return Err(format!("Unknown option '{flag}'")),
+            _ if path.is_some() => return Err(format!("Unexpected argument '{arg}'")),
+            _ => path = Some(PathBuf::from(arg)),
+        }
+    }
+
+    Ok(Args {
+        path: path.ok_or("Missing the file to evaluate")?,
+        format,
+        cells,
+    })
+}
+
+/// Lays out every computed value in columns padded to their widest value.
+fn to_table(spread_sheet: &SpreadSheet) -> String {
+    let Some(extent) = spread_sheet.extent() else {
+        return String::new();
+    };
+
+    let mut rows: Vec<Vec<String>> = vec![std::iter::once(String::new())
+        .chain((0..=extent.x).map(column_idx_to_string))
+        .collect()];
+    for y in 0..=extent.y {
+        let row = std::iter::once((y + 1).to_string())
+            .chain(
+                (0..=extent.x).map(|x| computed_to_text(spread_sheet.get_computed(Index { x, y }))),
+            )
+            .collect();
+        rows.push(row);
+    }
+
+    let mut widths = vec![0; extent.x + 2];
+    for row in &rows {
+        for (width, text) in widths.iter_mut().zip(row) {
+            *width = (*width).max(text.chars().count());
+        }
+    }
+
+    let mut table = String::new();
+    for row in rows {
+        let line: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(text, width)| format!("{text:<width$}"))
+            .collect();
+        table.push_str(line.join(" | ").trim_end());
+        table.push('\n');
+    }
+    table
+}
+
+// The computed values as CSV, numbers at full precision unlike in the table
+fn to_csv(spread_sheet: &SpreadSheet) -> String {
+    spread_sheet.to_csv(&CsvOptions {
+        content: CsvContent::Computed,
+        ..Default::default()
+    })
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{e}\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+
+    let mut spread_sheet = match SpreadSheet::from_file_path(&args.path) {
+        Ok(spread_sheet) => spread_sheet,
+        Err(e) => {
+            eprintln!("Could not open {}: {e}", args.path.display());
+            return ExitCode::from(2);
+        }
+    };
+    spread_sheet.compute_all();
+
+    if args.cells.is_empty() {
+        match args.format {
+            OutputFormat::Table => print!("{}", to_table(&spread_sheet)),
+            OutputFormat::Csv => print!("{}", to_csv(&spread_sheet)),
+        }
+    } else {
+        for index in &args.cells {
+            println!("{}", computed_to_text(spread_sheet.get_computed(*index)));
+        }
+    }
+
+    let errors: Vec<Index> = spread_sheet
+        .iter_cells()
+        .filter_map(|(index, _)| spread_sheet.get_error(*index).map(|_| *index))
+        .collect();
+    if errors.is_empty() {
+        ExitCode::SUCCESS
+    } else {
+        eprintln!("{} cell(s) failed to compute", errors.len());
+        ExitCode::from(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> impl Iterator<Item = String> {
+        args.iter()
+            .map(|arg| arg.to_string())
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let parsed = parse_args(args(&[
+            "sheet.csv",
+            "--cell",
+            "B3",
+            "--format",
+            "csv",
+            "--cell",
+            "A1",
+        ]))
+        .unwrap();
+        assert_eq!(
+            parsed,
+            Args {
+                path: PathBuf::from("sheet.csv"),
+                format: OutputFormat::Csv,
+                cells: vec![Index { x: 1, y: 2 }, Index { x: 0, y: 0 }],
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid_args() {
+        assert!(parse_args(args(&[])).is_err());
+        assert!(parse_args(args(&["sheet.csv", "--cell", "3B"])).is_err());
+        assert!(parse_args(args(&["sheet.csv", "--format", "xml"])).is_err());
+        assert!(parse_args(args(&["a.csv", "b.csv"])).is_err());
+    }
+
+    #[test]
+    fn test_table() {
+        let spread_sheet =
+            SpreadSheet::from_csv("1,=A1 * 1000\n=\"text\"\n", &CsvOptions::default()).unwrap();
+        assert_eq!(
+            to_table(&spread_sheet),
+            "  | A    | B\n1 | 1    | 1000\n2 | text |\n"
+        );
+    }
+
+    #[test]
+    fn test_csv_full_precision() {
+        let spread_sheet =
+            SpreadSheet::from_csv("=1 > 0,=10 ^ 16,=1 / 3\n", &CsvOptions::default()).unwrap();
+        assert_eq!(
+            to_csv(&spread_sheet),
+            format!("TRUE,10000000000000000,{}\r\n", 1.0 / 3.0)
+        );
+    }
+}
@@ -1,22 +1,275 @@
-use crate::common_types::Index;
+use std::ops::Range;
+
+use crate::common_types::{CellReference, ComputeError, Index, NumberLocale, Value};
 
 pub fn get_cell_idx(cell_name: &str) -> Option<Index> {
+    Some(parse_cell_reference(cell_name)?.index)
+}
+
+/// Parses a cell name of the form `$?[A-Z]+$?[0-9]+`, a `$` anchoring the axis after it.
+pub fn parse_cell_reference(cell_name: &str) -> Option<CellReference> {
+    let mut chars = cell_name.chars().peekable();
+
+    let absolute_column = chars.next_if_eq(&'$').is_some();
     let mut x: usize = 0;
-    let mut y = 0;
+    while let Some(c) = chars.next_if(char::is_ascii_uppercase) {
+        // Parse column letters
+        x = x
+            .checked_mul(26)?
+            .checked_add(c as usize - 'A' as usize + 1)?;
+    }
 
-    for (i, c) in cell_name.chars().enumerate() {
-        if c.is_ascii_digit() {
-            // Parse row number
-            y = cell_name[i..].parse::<usize>().ok()?;
-            break;
-        } else {
-            // Parse column letters
-            x = x * 26 + (c as usize - 'A' as usize + 1);
-        }
+    let absolute_row = chars.next_if_eq(&'$').is_some();
+    let row: String = chars.collect();
+    if !row.chars().all(|c| c.is_ascii_digit()) {
+        return None;
     }
+    // Parse row number
+    let y = row.parse::<usize>().ok()?;
+
     if x == 0 || y == 0 {
         return None;
     }
     // Adjust for 0-based indexing
-    Some(Index { x: x - 1, y: y - 1 })
+    Some(CellReference {
+        index: Index { x: x - 1, y: y - 1 },
+        absolute_column,
+        absolute_row,
+    })
+}
+
+/// Parses a number written in a cell, such as `-1,234.5`, `.5` or `1e-6`.
+pub fn parse_number(text: &str, locale: NumberLocale) -> Option<f64> {
+    let (sign, unsigned) = match text.strip_prefix('-') {
+        Some(rest) => (-1.0, rest),
+        None => (1.0, text.strip_prefix('+').unwrap_or(text)),
+    };
+    let chars: Vec<char> = unsigned.chars().collect();
+    match scan_number(&chars, locale, true) {
+        Some((number, length)) if length == chars.len() => Some(sign * number),
+        _ => None,
+    }
+}
+
+/// Reads the unsigned number at the start of `chars`, returning it along with the amount of characters it spans.
+/// Thousands separators are only read with `grouping`, and only between groups of three digits.
+pub fn scan_number(chars: &[char], locale: NumberLocale, grouping: bool) -> Option<(f64, usize)> {
+    let digits_at = |i: usize| {
+        chars[i.min(chars.len())..]
+            .iter()
+            .take_while(|ch| ch.is_ascii_digit())
+            .count()
+    };
+
+    let mut i = digits_at(0);
+    let mut normalized: String = chars[..i].iter().collect();
+    if grouping && (1..=3).contains(&i) {
+        while chars.get(i) == Some(&locale.thousands_separator()) && digits_at(i + 1) == 3 {
+            normalized.extend(&chars[i + 1..i + 4]);
+            i += 4;
+        }
+    }
+
+    if chars.get(i) == Some(&locale.decimal_separator()) {
+        let fraction = digits_at(i + 1);
+        // A separator on its own is not a number
+        if i == 0 && fraction == 0 {
+            return None;
+        }
+        if fraction > 0 {
+            normalized.push('.');
+            normalized.extend(&chars[i + 1..i + 1 + fraction]);
+        }
+        i += 1 + fraction;
+    }
+    if i == 0 {
+        return None;
+    }
+
+    // The exponent is only part of the number when digits follow it
+    if matches!(chars.get(i), Some('e' | 'E')) {
+        let sign = usize::from(matches!(chars.get(i + 1), Some('+' | '-')));
+        let exponent = digits_at(i + 1 + sign);
+        if exponent > 0 {
+            normalized.extend(&chars[i..i + 1 + sign + exponent]);
+            i += 1 + sign + exponent;
+        }
+    }
+
+    normalized.parse().ok().map(|number| (number, i))
+}
+
+// Dates are serial numbers counting days from 1899-12-30 as in other spreadsheet applications, the fraction being
+// the time of day. The conversions follow http://howardhinnant.github.io/date_algorithms.html
+
+/// The serial number of 1970-01-01, where Unix time starts.
+pub const UNIX_EPOCH_SERIAL: f64 = 25569.0;
+
+/// The serial numbers of the dates from the year 0 to 9999, the only ones a [`Value::DateTime`] can hold.
+pub const DATE_SERIALS: Range<f64> = -693_959.0..2_958_466.0;
+
+/// Turns a date outside of [`DATE_SERIALS`] into an error, leaving other values as they are.
+pub fn check_date(value: Value) -> Result<Value, ComputeError> {
+    match value {
+        Value::DateTime(serial) if !DATE_SERIALS.contains(&serial) => Err(
+            ComputeError::NumberError("Dates have to be between the years 0 and 9999".to_string()),
+        ),
+        value => Ok(value),
+    }
+}
+
+/// The serial number of a date of the Gregorian calendar.
+pub fn date_to_serial(year: i64, month: u32, day: u32) -> f64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let (era, year_of_era) = (year.div_euclid(400), year.rem_euclid(400));
+    let shifted_month = i64::from((month + 9) % 12); // Counted from March, so that leap days come last
+    let day_of_year = (153 * shifted_month + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    (era * 146_097 + day_of_era - 719_468) as f64 + UNIX_EPOCH_SERIAL
+}
+
+/// The year, month and day of the date a serial number falls on. Serial numbers outside of
+/// [`DATE_SERIALS`] are taken as the first or last date in it.
+pub fn serial_to_date(serial: f64) -> (i64, u32, u32) {
+    let serial = serial.clamp(DATE_SERIALS.start, DATE_SERIALS.end - 1.0);
+    let days = serial.floor() as i64 - UNIX_EPOCH_SERIAL as i64 + 719_468;
+    let (era, day_of_era) = (days.div_euclid(146_097), days.rem_euclid(146_097));
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = ((shifted_month + 2) % 12 + 1) as u32;
+    (year_of_era + era * 400 + i64::from(month <= 2), month, day)
+}
+
+pub fn days_in_month(year: i64, month: u32) -> u32 {
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Parses an ISO 8601 date such as `2024-03-15`, optionally followed by a time of day such as `T14:30`,
+/// ` 14:30:05` or `T14:30:05.5Z`, into its serial number.
+pub fn parse_date_time(text: &str) -> Option<f64> {
+    let digits = |part: &str, count: usize| {
+        (part.len() == count && part.chars().all(|c| c.is_ascii_digit()))
+            .then(|| part.parse().ok())?
+    };
+
+    let (date, time) = match text.split_once(['T', ' ']) {
+        Some((date, time)) => (date, Some(time)),
+        None => (text, None),
+    };
+    let [year, month, day] = date.split('-').collect::<Vec<_>>()[..] else {
+        return None;
+    };
+    let (year, month, day) = (digits(year, 4)?, digits(month, 2)?, digits(day, 2)?);
+    if !(1..=12).contains(&month) || day == 0 || day > days_in_month(i64::from(year), month) {
+        return None;
+    }
+    let mut serial = date_to_serial(i64::from(year), month, day);
+
+    if let Some(time) = time {
+        let time = time.strip_suffix('Z').unwrap_or(time);
+        let (hours, minutes, seconds) = match time.split(':').collect::<Vec<_>>()[..] {
+            [hours, minutes] => (hours, minutes, 0.0),
+            [hours, minutes, seconds] => {
+                let whole = seconds.split('.').next()?;
+                digits(whole, 2)?;
+                (hours, minutes, seconds.parse::<f64>().ok()?)
+            }
+            _ => return None,
+        };
+        let (hours, minutes) = (digits(hours, 2)?, digits(minutes, 2)?);
+        if hours > 23 || minutes > 59 || seconds >= 60.0 {
+            return None;
+        }
+        serial += (f64::from(hours * 3600 + minutes * 60) + seconds) / 86400.0;
+    }
+    Some(serial)
+}
+
+/// Writes a serial number as an ISO 8601 date, followed by the time of day to the second unless it is midnight.
+/// Serial numbers outside of [`DATE_SERIALS`] are written as numbers.
+pub fn format_date_time(serial: f64) -> String {
+    if !DATE_SERIALS.contains(&serial) {
+        return serial.to_string();
+    }
+    let seconds = (serial * 86400.0).round();
+    let (year, month, day) = serial_to_date((seconds / 86400.0).floor());
+    let time = seconds.rem_euclid(86400.0) as u32;
+    let (hours, minutes, seconds) = (time / 3600, time / 60 % 60, time % 60);
+    match (time, seconds) {
+        (0, _) => format!("{year:04}-{month:02}-{day:02}"),
+        (_, 0) => format!("{year:04}-{month:02}-{day:02} {hours:02}:{minutes:02}"),
+        _ => format!("{year:04}-{month:02}-{day:02} {hours:02}:{minutes:02}:{seconds:02}"),
+    }
+}
+
+pub fn column_idx_to_string(mut idx: usize) -> String {
+    let mut s = String::new();
+
+    loop {
+        let rem = (idx % 26) as u8;
+        s.insert(0, (b'A' + rem) as char); // Prepend the character
+        if idx < 26 {
+            break;
+        }
+        idx = idx / 26 - 1;
+    }
+
+    s
+}
+
+/*
+   Format a float into scientific notation such as: 42.0 -> 4.200e+01
+   width controls the amount of left padded spaces
+   precision is the amount of decimals
+   exp_pad controls the amount of left padded 0s
+*/
+fn fmt_f64(num: f64, width: usize, precision: usize, exp_pad: usize) -> String {
+    if !num.is_finite() {
+        return num.to_string();
+    }
+    let mut num = format!("{:.precision$e}", num, precision = precision);
+    // Safe to `unwrap` as `num` is guaranteed to contain `'e'`
+    let exp = num.split_off(num.find('e').expect("safe"));
+
+    let (sign, exp) = if let Some(exp) = exp.strip_prefix("e-") {
+        ('-', exp)
+    } else {
+        ('+', &exp[1..])
+    };
+    num.push_str(&format!("e{}{:0>pad$}", sign, exp, pad = exp_pad));
+
+    format!("{:>width$}", num, width = width)
+}
+
+/// Formats the computed value of a cell the way it is displayed, empty cells giving an empty string.
+pub fn computed_to_text(computed: Option<Result<Value, ComputeError>>) -> String {
+    match computed {
+        Some(value) => match value {
+            Ok(inner) => match inner {
+                Value::Text(s) => s,
+                Value::Number(num) => {
+                    if num >= 1E15 {
+                        fmt_f64(num, 10, 3, 2)
+                    } else {
+                        num.to_string()
+                    }
+                }
+                Value::Bool(b) => b.to_string(),
+                Value::Error(err) => err.to_string(),
+                Value::Array(array) => array.to_string(),
+                Value::DateTime(serial) => format_date_time(serial),
+            },
+            Err(err) => err.to_string(),
+        },
+        None => String::new(),
+    }
 }
@@ -1,4 +1,6 @@
-use std::fmt::Display;
+use std::{fmt::Display, ops::Range, str::FromStr};
+
+use crate::common_functions::{column_idx_to_string, format_date_time, parse_cell_reference};
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Token {
@@ -9,12 +11,20 @@ pub enum Token {
     Minus,
     Division,
     Multiply,
+    Power,   // ^
+    Mod,     // mod
+    Percent, // %, divides the number before it by 100
+    Concat,  // &
     LParen,
     RParen,
     Colon,
     Comma,
     FunctionName(String),
     Bool(bool),
+    RefError, // #REF!, written in place of references to deleted cells
+    Error(ComputeError), // Any other error value written in a formula, such as #N/A
+    SheetName(String), // The `Sheet2!` prefix of a reference to another sheet
+    Name(String),      // A name defined in the workbook, such as `TaxRate`
 
     // logical operators
     Equals,        // ==
@@ -37,9 +47,13 @@ impl Token {
             Token::Equals | Token::NotEquals | 
             Token::GreaterThan | Token::LessThan |
             Token::GreaterEquals | Token::LessEquals => 2,
-            Token::Plus | Token::Minus => 3,
-            Token::Division | Token::Multiply => 4,
-            Token::Not => 5,
+            Token::Concat => 3,
+            Token::Plus | Token::Minus => 4,
+            Token::Division | Token::Multiply | Token::Mod => 5,
+            // 6 is taken by unary minus
+            Token::Power => 7,
+            Token::Percent => 8,
+            Token::Not => 9,
             _ => 0,
         }
     }
@@ -47,7 +61,7 @@ impl Token {
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum AST {
-    CellName(String),
+    CellName(CellReference),
     Value(Value),
     BinaryOp {
         op: Token,
@@ -59,19 +73,41 @@ pub enum AST {
         expr: Box<AST>,
     },
     Range {
-        from: String,
-        to: String,
+        from: CellReference,
+        to: CellReference,
     },
     FunctionCall {
         name: String,
         arguments: Vec<AST>,
     },
+    RefError,
+    /// A name defined in the workbook, standing for a cell, a range or a constant.
+    Name(String),
+    /// A cell name or range looked up in another sheet, such as `Sheet2!A1:B5`.
+    SheetReference {
+        sheet: String,
+        reference: Box<AST>,
+    },
+}
+
+/// Something an expression needs to be computed before it can be computed itself.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Dependency {
+    Cell(Index),
+    Range(CellRange),
+    /// Cells of another sheet, tracked by the workbook the sheets belong to.
+    Sheet {
+        sheet: String,
+        range: CellRange,
+    },
+    /// A name defined in the workbook, also tracked by the workbook.
+    Name(String),
 }
 
 #[derive(Debug, Clone)]
 pub struct Expression {
     pub ast: AST,
-    pub dependencies: Vec<Index>,
+    pub dependencies: Vec<Dependency>,
 }
 
 #[derive(Debug, Clone)]
@@ -85,6 +121,89 @@ pub enum Value {
     Text(String),
     Number(f64),
     Bool(bool),
+    /// An error such as `#DIV/0!`, passed to functions like `iferror` that inspect errors.
+    /// Computed values hold errors as `Err` instead, see [`Value::into_result`].
+    Error(ComputeError),
+    /// The cells of a range, passed to function arguments declared as [`crate::ArgumentType::Range`].
+    Array(Array),
+    /// A date and time of day as a serial number of days since 1899-12-30, as in other spreadsheet
+    /// applications. Shown as an ISO 8601 date such as `2024-03-15 14:30`.
+    DateTime(f64),
+}
+
+/// The cells of a range row by row, keeping its shape. Empty cells are `None` and cells that failed to
+/// compute hold a [`Value::Error`].
+#[derive(Debug, PartialEq, Clone)]
+pub struct Array {
+    rows: usize,
+    columns: usize,
+    cells: Vec<Option<Value>>,
+}
+
+impl Array {
+    /// Panics if the amount of cells is not `rows * columns`.
+    #[must_use]
+    pub fn new(rows: usize, columns: usize, cells: Vec<Option<Value>>) -> Self {
+        assert_eq!(
+            cells.len(),
+            rows * columns,
+            "An array holds rows * columns cells"
+        );
+        Self {
+            rows,
+            columns,
+            cells,
+        }
+    }
+
+    /// A single cell holding the value, which is how a value that is not a range is passed as one.
+    #[must_use]
+    pub fn scalar(value: Value) -> Self {
+        Self::new(1, 1, vec![Some(value)])
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    /// The value of a cell counted from 0, `None` if it is empty or outside of the array.
+    pub fn get(&self, row: usize, column: usize) -> Option<&Value> {
+        if row >= self.rows || column >= self.columns {
+            return None;
+        }
+        self.cells[row * self.columns + column].as_ref()
+    }
+
+    /// Every cell row by row, including the empty ones.
+    pub fn cells(&self) -> impl Iterator<Item = Option<&Value>> {
+        self.cells.iter().map(Option::as_ref)
+    }
+
+    /// The values of the non empty cells row by row.
+    pub fn values(&self) -> impl Iterator<Item = &Value> {
+        self.cells.iter().flatten()
+    }
+}
+
+impl Display for Array {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let rows: Vec<String> = self
+            .cells
+            .chunks(self.columns.max(1))
+            .map(|row| {
+                let cells: Vec<String> = row
+                    .iter()
+                    .map(|cell| cell.as_ref().map(Value::to_string).unwrap_or_default())
+                    .collect();
+                cells.join(", ")
+            })
+            .collect();
+        write!(f, "{{{}}}", rows.join("; "))
+    }
 }
 
 impl Display for Value {
@@ -93,16 +212,45 @@ impl Display for Value {
             Value::Text(s) => write!(f, "{s}"),
             Value::Number(num) => write!(f, "{num}"),
             Value::Bool(bool) => write!(f, "{}", bool.to_string().to_uppercase()),
+            Value::Error(error) => write!(f, "{error}"),
+            Value::Array(array) => write!(f, "{array}"),
+            Value::DateTime(serial) => write!(f, "{}", format_date_time(*serial)),
         }
     }
 }
 
 impl Value {
+    /// Moves an error value into the `Err` variant, which is how computed values carry errors.
+    pub fn into_result(self) -> Result<Value, ComputeError> {
+        match self {
+            Value::Error(error) => Err(error),
+            value => Ok(value),
+        }
+    }
+
+    /// The number, or the serial number of a date, so that dates can be summed like numbers.
+    pub fn as_number(&self) -> Option<f64> {
+        match self {
+            Value::Number(num) | Value::DateTime(num) => Some(*num),
+            _ => None,
+        }
+    }
+
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            Value::Text(text) => Some(text),
+            _ => None,
+        }
+    }
+
     #[must_use]
     pub fn add(&self, other: Value) -> Option<Value> {
         match (self, other) {
             (Value::Number(a), Value::Number(b)) => Some(Value::Number(a + b)),
             (Value::Text(a), Value::Text(b)) => Some(Value::Text(a.clone() + &b)),
+            // Adding days to a date
+            (Value::DateTime(date), Value::Number(days)) => Some(Value::DateTime(date + days)),
+            (Value::Number(days), Value::DateTime(date)) => Some(Value::DateTime(date + days)),
             _ => None,
         }
     }
@@ -111,6 +259,9 @@ impl Value {
     pub fn sub(&self, other: Value) -> Option<Value> {
         match (self, other) {
             (Value::Number(a), Value::Number(b)) => Some(Value::Number(a - b)),
+            (Value::DateTime(a), Value::Number(days)) => Some(Value::DateTime(a - days)),
+            // The days between two dates
+            (Value::DateTime(a), Value::DateTime(b)) => Some(Value::Number(a - b)),
             _ => None,
         }
     }
@@ -131,6 +282,29 @@ impl Value {
         }
     }
 
+    #[must_use]
+    pub fn pow(&self, other: Value) -> Option<Value> {
+        match (self, other) {
+            (Value::Number(a), Value::Number(b)) => Some(Value::Number(a.powf(b))),
+            _ => None,
+        }
+    }
+
+    /// The remainder of dividing by `other`, taking the sign of `other` like other spreadsheet applications.
+    #[must_use]
+    pub fn modulo(&self, other: Value) -> Option<Value> {
+        match (self, other) {
+            (Value::Number(a), Value::Number(b)) => Some(Value::Number(a - b * (a / b).floor())),
+            _ => None,
+        }
+    }
+
+    /// Joins the text of both values, numbers and booleans are written the way they are displayed.
+    #[must_use]
+    pub fn concat(&self, other: Value) -> Value {
+        Value::Text(format!("{self}{other}"))
+    }
+
     pub fn and(&self, other: Value) -> Option<Value> {
         match (self, other) {
             (Value::Bool(a), Value::Bool(b)) => Some(Value::Bool(*a && b)),
@@ -144,59 +318,198 @@ impl Value {
         }
     }
 
+    /// Whether both values are the same, dates being equal to their serial number.
+    pub fn equals(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Number(a) | Value::DateTime(a), Value::Number(b) | Value::DateTime(b)) => {
+                a == b
+            }
+            _ => self == other,
+        }
+    }
+
     pub fn greater_than(&self, other: Value) -> Option<Value> {
         match (self, other) {
-            (Value::Number(a), Value::Number(b)) => Some(Value::Bool(a > &b)),
+            (Value::Number(a) | Value::DateTime(a), Value::Number(b) | Value::DateTime(b)) => {
+                Some(Value::Bool(a > &b))
+            }
             _ => None,
         }
     }
     pub fn less_than(&self, other: Value) -> Option<Value> {
         match (self, other) {
-            (Value::Number(a), Value::Number(b)) => Some(Value::Bool(a < &b)),
+            (Value::Number(a) | Value::DateTime(a), Value::Number(b) | Value::DateTime(b)) => {
+                Some(Value::Bool(a < &b))
+            }
             _ => None,
         }
     }
 
     pub fn greater_equals(&self, other: Value) -> Option<Value> {
         match (self, other) {
-            (Value::Number(a), Value::Number(b)) => Some(Value::Bool(a >= &b)),
+            (Value::Number(a) | Value::DateTime(a), Value::Number(b) | Value::DateTime(b)) => {
+                Some(Value::Bool(a >= &b))
+            }
             _ => None,
         }
     }
 
     pub fn less_equals(&self, other: Value) -> Option<Value> {
         match (self, other) {
-            (Value::Number(a), Value::Number(b)) => Some(Value::Bool(a <= &b)),
+            (Value::Number(a) | Value::DateTime(a), Value::Number(b) | Value::DateTime(b)) => {
+                Some(Value::Bool(a <= &b))
+            }
             _ => None,
         }
     }
 
 }
 
-#[derive(Debug, Clone)]
-pub struct ParseError(pub String);
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub message: String,
+    /// The characters of the raw cell the error was found at.
+    pub span: Option<Range<usize>>,
+    /// What would have been valid at the span, such as `')'` or `a value`.
+    pub expected: Option<String>,
+}
 
-#[derive(Debug, Clone)]
+impl ParseError {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            span: None,
+            expected: None,
+        }
+    }
+
+    /// Points the error at the characters it was found at, hinting at what was expected there instead.
+    pub fn at(mut self, span: Range<usize>, expected: impl Into<String>) -> Self {
+        self.span = Some(span);
+        self.expected = Some(expected.into());
+        self
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)?;
+        if let Some(expected) = &self.expected {
+            write!(f, ", expected {expected}")?;
+        }
+        Ok(())
+    }
+}
+
+/// How numbers are written in cells and formulas.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NumberLocale {
+    /// `1,234.5`, function arguments are separated by `,`.
+    #[default]
+    DecimalPoint,
+    /// `1.234,5`, function arguments are separated by `;`.
+    DecimalComma,
+}
+
+impl NumberLocale {
+    #[must_use]
+    pub fn decimal_separator(self) -> char {
+        match self {
+            NumberLocale::DecimalPoint => '.',
+            NumberLocale::DecimalComma => ',',
+        }
+    }
+
+    #[must_use]
+    pub fn thousands_separator(self) -> char {
+        match self {
+            NumberLocale::DecimalPoint => ',',
+            NumberLocale::DecimalComma => '.',
+        }
+    }
+
+    #[must_use]
+    pub fn argument_separator(self) -> char {
+        match self {
+            NumberLocale::DecimalPoint => ',',
+            NumberLocale::DecimalComma => ';',
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum ComputeError {
     ParseError(String),
     TypeError(String),
     UnfindableReference(String),
     Cycle,
     UnknownFunction(String),
-    InvalidArgument(String)
+    InvalidArgument(String),
+    DivisionByZero,
+    UnknownName(String),
+    NotAvailable(String),
+    /// A number outside of what a computation accepts or can result in, such as `sqrt(-1)`.
+    NumberError(String),
 }
 
-impl Display for ComputeError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+/// Every error value that can be written in a cell or formula.
+pub const ERROR_CODES: [&str; 8] = [
+    "#DIV/0!", "#VALUE!", "#REF!", "#NAME?", "#NUM!", "#N/A", "#ERROR!", "#CYCLE!",
+];
+
+impl ComputeError {
+    /// The error value shown in place of the computed value, such as `#DIV/0!`.
+    #[must_use]
+    pub fn code(&self) -> &'static str {
         match self {
-            ComputeError::ParseError(_) => write!(f, "!-PARSE ERROR-!"),
-            ComputeError::TypeError(_) => write!(f, "!-TYPE ERROR-!"),
-            ComputeError::UnfindableReference(_) => write!(f, "!-REFERENCE ERROR-!"),
-            ComputeError::Cycle => write!(f, "!-CYCLIC REFERENCE-!"),
-            ComputeError::UnknownFunction(_) => write!(f, "!-UNKNOWN FUNCTION-!"),
-            ComputeError::InvalidArgument(_) => write!(f, "!-INVALID FUNCTION ARGUMENT-!"),
+            ComputeError::DivisionByZero => "#DIV/0!",
+            ComputeError::TypeError(_) | ComputeError::InvalidArgument(_) => "#VALUE!",
+            ComputeError::UnfindableReference(_) => "#REF!",
+            ComputeError::UnknownFunction(_) | ComputeError::UnknownName(_) => "#NAME?",
+            ComputeError::NumberError(_) => "#NUM!",
+            ComputeError::NotAvailable(_) => "#N/A",
+            ComputeError::ParseError(_) => "#ERROR!",
+            ComputeError::Cycle => "#CYCLE!",
         }
     }
+
+    /// The number `errortype` returns, following the numbering other spreadsheet applications use.
+    #[must_use]
+    pub fn type_number(&self) -> usize {
+        match self {
+            ComputeError::DivisionByZero => 2,
+            ComputeError::TypeError(_) | ComputeError::InvalidArgument(_) => 3,
+            ComputeError::UnfindableReference(_) => 4,
+            ComputeError::UnknownFunction(_) | ComputeError::UnknownName(_) => 5,
+            ComputeError::NumberError(_) => 6,
+            ComputeError::NotAvailable(_) => 7,
+            ComputeError::ParseError(_) => 8,
+            ComputeError::Cycle => 9,
+        }
+    }
+
+    /// Returns the error an error value written in a cell or formula stands for.
+    #[must_use]
+    pub fn from_code(code: &str) -> Option<Self> {
+        let message = format!("{code} was entered directly");
+        Some(match code {
+            "#DIV/0!" => ComputeError::DivisionByZero,
+            "#VALUE!" => ComputeError::TypeError(message),
+            "#REF!" => ComputeError::UnfindableReference(message),
+            "#NAME?" => ComputeError::UnknownName(message),
+            "#NUM!" => ComputeError::NumberError(message),
+            "#N/A" => ComputeError::NotAvailable(message),
+            "#ERROR!" => ComputeError::ParseError(message),
+            "#CYCLE!" => ComputeError::Cycle,
+            _ => return None,
+        })
+    }
+}
+
+impl Display for ComputeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.code())
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -225,3 +538,204 @@ pub struct Index {
     pub y: usize,
 }
 
+/// The direction rows or columns are inserted and deleted along.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Axis {
+    Row,
+    Column,
+}
+
+impl Index {
+    /// Returns the row for [`Axis::Row`] and the column for [`Axis::Column`].
+    #[must_use]
+    pub fn get(&self, axis: Axis) -> usize {
+        match axis {
+            Axis::Row => self.y,
+            Axis::Column => self.x,
+        }
+    }
+
+    #[must_use]
+    pub fn with(self, axis: Axis, value: usize) -> Self {
+        match axis {
+            Axis::Row => Self { y: value, ..self },
+            Axis::Column => Self { x: value, ..self },
+        }
+    }
+}
+
+/// Rows or columns inserted into or deleted from a sheet, moving every cell after them.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum StructuralChange {
+    /// `count` empty rows or columns are inserted before `at`.
+    Insert { axis: Axis, at: usize, count: usize },
+    /// `count` rows or columns are deleted starting at `at`.
+    Delete { axis: Axis, at: usize, count: usize },
+}
+
+impl StructuralChange {
+    /// Returns where a cell ends up after the change, or `None` if it is deleted.
+    #[must_use]
+    pub fn move_index(&self, index: Index) -> Option<Index> {
+        let coordinate = match *self {
+            Self::Insert { axis, at, count } => {
+                let c = index.get(axis);
+                if c >= at {
+                    c.checked_add(count)?
+                } else {
+                    c
+                }
+            }
+            Self::Delete { axis, at, count } => {
+                let c = index.get(axis);
+                if c < at {
+                    c
+                } else if c >= at + count {
+                    c - count
+                } else {
+                    return None;
+                }
+            }
+        };
+        Some(index.with(self.axis(), coordinate))
+    }
+
+    /// Returns where a range ends up after the change.
+    /// Inserting inside a range grows it and deleting part of it shrinks it, `None` is returned if all of it is deleted.
+    #[must_use]
+    pub fn move_range(&self, range: CellRange) -> Option<CellRange> {
+        let Self::Delete { axis, at, count } = *self else {
+            return Some(CellRange {
+                start: self.move_index(range.start)?,
+                end: self.move_index(range.end)?,
+            });
+        };
+
+        let (low, high) = (range.start.get(axis), range.end.get(axis));
+        let new_low = match low {
+            c if c < at => c,
+            c if c >= at + count => c - count,
+            _ => at,
+        };
+        let new_high = match high {
+            c if c < at => c,
+            c if c >= at + count => c - count,
+            _ => at.checked_sub(1)?,
+        };
+        if new_high < new_low {
+            return None;
+        }
+
+        Some(CellRange {
+            start: range.start.with(axis, new_low),
+            end: range.end.with(axis, new_high),
+        })
+    }
+
+    #[must_use]
+    pub fn axis(&self) -> Axis {
+        match *self {
+            Self::Insert { axis, .. } | Self::Delete { axis, .. } => axis,
+        }
+    }
+}
+
+/// The largest difference between the first and last row or column of a range written in a formula.
+pub const MAX_RANGE_SPAN: usize = 100;
+
+/// A rectangular block of cells, `start` being its top left and `end` its bottom right corner.
+#[derive(PartialEq, Hash, Eq, Debug, Clone, Copy)]
+pub struct CellRange {
+    pub start: Index,
+    pub end: Index,
+}
+
+impl CellRange {
+    /// Creates the range spanned by two opposite corners given in any order.
+    #[must_use]
+    pub fn new(a: Index, b: Index) -> Self {
+        Self {
+            start: Index {
+                x: a.x.min(b.x),
+                y: a.y.min(b.y),
+            },
+            end: Index {
+                x: a.x.max(b.x),
+                y: a.y.max(b.y),
+            },
+        }
+    }
+
+    #[must_use]
+    pub fn contains(&self, index: Index) -> bool {
+        (self.start.x..=self.end.x).contains(&index.x)
+            && (self.start.y..=self.end.y).contains(&index.y)
+    }
+
+    /// Iterates over every index in the range, column by column.
+    pub fn indices(&self) -> impl Iterator<Item = Index> {
+        let CellRange { start, end } = *self;
+        (start.x..=end.x).flat_map(move |x| (start.y..=end.y).map(move |y| Index { x, y }))
+    }
+}
+
+/// A reference to a cell as written in a formula, such as `A1`, `$A$1`, `A$1` or `$A1`.
+/// Anchored (`$`) axes stay in place when the formula is moved.
+#[derive(PartialEq, Hash, Eq, Debug, Clone, Copy)]
+pub struct CellReference {
+    pub index: Index,
+    pub absolute_column: bool,
+    pub absolute_row: bool,
+}
+
+impl CellReference {
+    #[must_use]
+    pub fn relative(index: Index) -> Self {
+        Self {
+            index,
+            absolute_column: false,
+            absolute_row: false,
+        }
+    }
+
+    /// Moves the relative axes of the reference by the given amount of columns and rows.
+    /// Returns `None` if the reference would end up outside of the grid.
+    #[must_use]
+    pub fn offset(&self, columns: isize, rows: isize) -> Option<Self> {
+        let x = if self.absolute_column {
+            self.index.x
+        } else {
+            self.index.x.checked_add_signed(columns)?
+        };
+        let y = if self.absolute_row {
+            self.index.y
+        } else {
+            self.index.y.checked_add_signed(rows)?
+        };
+        Some(Self {
+            index: Index { x, y },
+            ..*self
+        })
+    }
+}
+
+impl FromStr for CellReference {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_cell_reference(s).ok_or_else(|| ParseError::new(format!("Invalid cell name: {s}")))
+    }
+}
+
+impl Display for CellReference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let column_anchor = if self.absolute_column { "$" } else { "" };
+        let row_anchor = if self.absolute_row { "$" } else { "" };
+        write!(
+            f,
+            "{column_anchor}{}{row_anchor}{}",
+            column_idx_to_string(self.index.x),
+            self.index.y + 1
+        )
+    }
+}
@@ -1,8 +1,15 @@
+use std::{ops::Range, path::PathBuf};
+
+use macroquad::miniquad::window::{clipboard_get, clipboard_set};
 use macroquad::prelude::*;
 use macroquad::ui::widgets::Editbox;
 use macroquad::ui::{hash, root_ui, Skin};
 
-use crate::common_types::{ComputeError, Value};
+use crate::common_functions::{column_idx_to_string, computed_to_text};
+use crate::common_types::{Axis, CellRange, CellReference, ComputeError, NumberLocale};
+use crate::spreadsheet::clipboard::CopiedCells;
+use crate::spreadsheet::parser::FormulaReference;
+use crate::workbook::Workbook;
 use crate::{common_types::Index, spreadsheet::SpreadSheet};
 
 // Window configuration
@@ -10,8 +17,15 @@ const INITIAL_WINDOW_WIDTH: f32 = 1200.0;
 const INITIAL_WINDOW_HEIGHT: f32 = 900.0;
 
 // Grid configuration
-const GRID_ROWS: usize = 20;
-const GRID_COLS: usize = 6;
+const CELL_WIDTH: f32 = 190.0;
+const CELL_HEIGHT: f32 = 40.0;
+const WHEEL_SCROLL_ROWS: isize = 3;
+const SCROLL_PADDING: usize = 50; // Cells the scrollbars reach past the last used or visible cell
+
+// Scrollbars
+const SCROLLBAR_SIZE: f32 = 14.0;
+const SCROLLBAR_TRACK_COLOR: Color = LIGHTGRAY;
+const SCROLLBAR_THUMB_COLOR: Color = GRAY;
 
 // Editor configuration
 const EDITOR_HEIGHT: f32 = 24.0;
@@ -29,6 +43,7 @@ const BACKGROUND_COLOR: Color = BLACK;
 const GRID_BACKGROUND_COLOR: Color = WHITE;
 const SELECTED_CELL_BORDER_COLOR: Color = ORANGE;
 const NORMAL_CELL_BORDER_COLOR: Color = BLACK;
+const SELECTION_BACKGROUND_COLOR: Color = Color::new(1.0, 0.63, 0.0, 0.15); // Translucent orange
 const CELL_TEXT_COLOR: Color = BLACK;
 
 // Labels
@@ -39,18 +54,88 @@ const LABEL_TEXT_COLOR: Color = DARKGRAY;
 const LABEL_BORDER_COLOR: Color = DARKGRAY;
 const SELECTED_LABEL_BACKGROUND: Color = SKYBLUE;
 
+// Right click menu of the labels and sheet tabs
+const MENU_WIDTH: f32 = 150.0;
+const MENU_ITEM_HEIGHT: f32 = 26.0;
+const MENU_FONT_SIZE: u16 = 12;
+const MENU_BACKGROUND: Color = Color::new(0.94, 0.94, 0.94, 1.0);
+const MENU_HOVER_BACKGROUND: Color = Color::new(0.78, 0.78, 1.0, 1.0);
+
+// Sheet tabs
+const TAB_BAR_HEIGHT: f32 = 28.0;
+const TAB_PADDING: f32 = 16.0;
+const TAB_FONT_SIZE: u16 = 12;
+const TAB_BACKGROUND: Color = Color::new(0.86, 0.86, 0.86, 1.0);
+const ACTIVE_TAB_BACKGROUND: Color = WHITE;
+
+// Draw calls that have to happen on top of the grid, run at the end of the frame
+type DeferredDraw = Box<dyn FnMut(&mut GUI)>;
+
+#[derive(Clone, Copy)]
+enum ScrollAxis {
+    Horizontal,
+    Vertical,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum PromptAction {
+    Save,
+    Open,
+    RenameSheet(usize),
+    DefineName,
+    SplitColumns,
+}
+
+// The text prompt shown when saving to or opening a file, renaming a sheet, defining a name or splitting
+// cells into columns
+struct Prompt {
+    action: PromptAction,
+    text: String,
+}
+
+#[derive(Clone, Copy)]
+enum MenuTarget {
+    Label { axis: Axis, index: usize }, // The row or column that was clicked
+    Tab(usize),
+}
+
+// The menu shown when right clicking a row or column label or a sheet tab
+struct Menu {
+    target: MenuTarget,
+    position: (f32, f32),
+}
+
+#[derive(Clone, Copy)]
+enum MenuAction {
+    InsertBefore,
+    InsertAfter,
+    Delete,
+    Rename,
+}
+
+#[allow(clippy::upper_case_acronyms)]
 pub struct GUI {
     selected_cell: Option<Index>,
+    selection_end: Option<Index>, // Opposite corner of a rectangular selection started at the selected cell
+    viewport: Index, // Top left visible cell
+    dragged_scrollbar: Option<ScrollAxis>,
     editor_content: String,
     regular_font: Font,
     bold_font: Font,
-    spread_sheet: SpreadSheet,
+    workbook: Workbook,
+    active_sheet: usize,
     editor_skin: Skin,
-    draws: Vec<Box<dyn FnMut(&mut Self)>>,
+    draws: Vec<DeferredDraw>,
+    file_path: Option<PathBuf>, // File the sheet was last saved to or opened from
+    prompt: Option<Prompt>,
+    status: Option<String>, // Feedback of the last file operation
+    copied: Option<CopiedCells>,
+    cut_source: Option<(usize, CellRange)>, // Sheet and cells to clear on the next paste
+    menu: Option<Menu>,
 }
 
 impl GUI {
-    pub async fn new(spread_sheet: SpreadSheet) -> Self {
+    pub async fn new(workbook: Workbook) -> Self {
         let regular_font = load_ttf_font("fonts/ttf/Hack-Regular.ttf").await.unwrap();
 
         let bold_font = load_ttf_font("fonts/ttf/Hack-Bold.ttf").await.unwrap();
@@ -80,12 +165,22 @@ impl GUI {
 
         Self {
             selected_cell: None,
+            selection_end: None,
+            viewport: Index { x: 0, y: 0 },
+            dragged_scrollbar: None,
             regular_font,
             editor_content: String::new(),
-            spread_sheet,
+            workbook,
+            active_sheet: 0,
             bold_font,
             editor_skin,
             draws: Vec::new(),
+            file_path: None,
+            prompt: None,
+            status: None,
+            copied: None,
+            cut_source: None,
+            menu: None,
         }
     }
 
@@ -95,11 +190,15 @@ impl GUI {
         loop {
             clear_background(BACKGROUND_COLOR);
 
+            self.handle_shortcuts();
             self.draw_editor();
             self.draw_cells(
                 (0.0, EDITOR_WINDOW_HEIGHT),
-                (screen_width(), screen_height()),
+                (screen_width(), screen_height() - TAB_BAR_HEIGHT),
             );
+            self.draw_sheet_tabs((0.0, screen_height() - TAB_BAR_HEIGHT));
+            self.draw_menu();
+            self.draw_prompt();
             while let Some(mut closure) = self.draws.pop() {
                 closure(self)
             }
@@ -122,6 +221,17 @@ impl GUI {
                     .position(vec2(ROW_LABEL_WIDTH, EDITOR_TOP_MARGIN + EDITOR_PADDING))
                     .ui(ui, &mut self.editor_content);
 
+                if let Some(status) = &self.status {
+                    ui.label(
+                        Some(vec2(ROW_LABEL_WIDTH, EDITOR_PADDING + EDITOR_HEIGHT)),
+                        status,
+                    );
+                }
+
+                if self.prompt.is_some() {
+                    return;
+                }
+
                 // Focus the editor when a cell is selected
                 if self.selected_cell.is_some() {
                     ui.set_input_focus(input_text_id);
@@ -132,6 +242,7 @@ impl GUI {
                 if is_key_pressed(KeyCode::Enter) {
                     self.commit_editor();
                     self.selected_cell = None;
+                    self.selection_end = None;
                     self.editor_content.clear();
                 }
             },
@@ -145,27 +256,56 @@ impl GUI {
         let (start_x, start_y) = start;
         let (end_x, end_y) = end;
 
-        let grid_height = end_y - start_y - COL_LABEL_HEIGHT;
-        let grid_width = end_x - start_x - ROW_LABEL_WIDTH;
+        // Leave room for the scrollbars on the right and bottom edges
+        let grid_end = (end_x - SCROLLBAR_SIZE, end_y - SCROLLBAR_SIZE);
+        let grid_height = grid_end.1 - start_y - COL_LABEL_HEIGHT;
+        let grid_width = grid_end.0 - start_x - ROW_LABEL_WIDTH;
+
+        let cell_height = CELL_HEIGHT;
+        let cell_width = CELL_WIDTH;
 
-        let cell_height = grid_height / GRID_ROWS as f32;
-        let cell_width = grid_width / GRID_COLS as f32;
+        let visible_rows = (grid_height / cell_height).ceil().max(1.0) as usize;
+        let visible_cols = (grid_width / cell_width).ceil().max(1.0) as usize;
+        self.handle_scrolling(start, end, (visible_cols, visible_rows));
 
         // Handle if mouse clicked
         let mut hovered: Option<Index> = None;
         let (x, y) = mouse_position();
-        if is_point_in_rect((x, y), start, end) {
-            let col = ((x - start_x - ROW_LABEL_WIDTH) / cell_width) as i32;
-            let row = ((y - start_y - COL_LABEL_HEIGHT) / cell_height) as i32;
-            let x_idx = col.try_into().expect("Got negative idx from click");
-            let y_idx = row.try_into().expect("Got negative idx from click");
+        let grid_start = (start_x + ROW_LABEL_WIDTH, start_y + COL_LABEL_HEIGHT);
+        let accepts_clicks =
+            self.dragged_scrollbar.is_none() && self.prompt.is_none() && self.menu.is_none();
+        if accepts_clicks && is_mouse_button_pressed(MouseButton::Right) {
+            if is_point_in_rect((x, y), (start_x, grid_start.1), (grid_start.0, grid_end.1)) {
+                self.menu = Some(Menu {
+                    target: MenuTarget::Label {
+                        axis: Axis::Row,
+                        index: self.viewport.y + ((y - grid_start.1) / cell_height) as usize,
+                    },
+                    position: (x, y),
+                });
+            } else if is_point_in_rect((x, y), (grid_start.0, start_y), (grid_end.0, grid_start.1))
+            {
+                self.menu = Some(Menu {
+                    target: MenuTarget::Label {
+                        axis: Axis::Column,
+                        index: self.viewport.x + ((x - grid_start.0) / cell_width) as usize,
+                    },
+                    position: (x, y),
+                });
+            }
+        }
+        if accepts_clicks && is_point_in_rect((x, y), grid_start, grid_end) {
+            let col = ((x - grid_start.0) / cell_width) as usize;
+            let row = ((y - grid_start.1) / cell_height) as usize;
+            let x_idx = self.viewport.x + col;
+            let y_idx = self.viewport.y + row;
 
             hovered = Some(Index { x: x_idx, y: y_idx });
 
             if is_mouse_button_pressed(MouseButton::Left) {
                 if is_key_down(KeyCode::LeftControl) {
                     if self.selected_cell.is_some()
-                        && &Some('=') == &self.editor_content.chars().nth(0)
+                        && Some('=') == self.editor_content.chars().nth(0)
                     {
                         self.editor_content.push_str(&format!(
                             "{}{}",
@@ -173,6 +313,10 @@ impl GUI {
                             y_idx + 1
                         ))
                     }
+                } else if is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift) {
+                    if self.selected_cell.is_some() {
+                        self.selection_end = Some(Index { x: x_idx, y: y_idx });
+                    }
                 } else {
                     self.change_selected_cell(Index { x: x_idx, y: y_idx });
                 }
@@ -188,74 +332,217 @@ impl GUI {
             GRID_BACKGROUND_COLOR,
         );
 
+        // Draw all cells in the grid
+        for row in 0..visible_rows {
+            for col in 0..visible_cols {
+                let cell_start_x = start_x + col as f32 * cell_width + ROW_LABEL_WIDTH;
+                let cell_start_y = start_y + row as f32 * cell_height + COL_LABEL_HEIGHT;
+
+                self.draw_cell(
+                    Index {
+                        x: self.viewport.x + col,
+                        y: self.viewport.y + row,
+                    },
+                    (cell_start_x, cell_start_y),
+                    (cell_width, cell_height),
+                );
+            }
+        }
+
+        // Cover the partially visible cells that overflow into the scrollbar area
+        draw_rectangle(
+            grid_end.0,
+            start_y,
+            end_x - grid_end.0,
+            end_y - start_y,
+            GRID_BACKGROUND_COLOR,
+        );
+        draw_rectangle(
+            start_x,
+            grid_end.1,
+            end_x - start_x,
+            end_y - grid_end.1,
+            GRID_BACKGROUND_COLOR,
+        );
+
         // Draw the column labels
-        for col in 0..GRID_COLS {
+        for col in 0..visible_cols {
             let label_start_x = start_x + col as f32 * cell_width + ROW_LABEL_WIDTH;
             let label_start_y = start_y;
+            let label_width = cell_width.min(grid_end.0 - label_start_x);
             self.draw_error_label(
-                col,
+                self.viewport.x + col,
                 false, // Indicating column
                 (label_start_x, label_start_y),
-                (cell_width, COL_LABEL_HEIGHT),
+                (label_width, COL_LABEL_HEIGHT),
             );
         }
 
         // Draw the row labels
-        for row in 0..GRID_ROWS {
+        for row in 0..visible_rows {
             let label_start_x = start_x;
             let label_start_y = start_y + row as f32 * cell_height + COL_LABEL_HEIGHT;
+            let label_height = cell_height.min(grid_end.1 - label_start_y);
             self.draw_error_label(
-                row,
+                self.viewport.y + row,
                 true, // Indicating row
                 (label_start_x, label_start_y),
-                (ROW_LABEL_WIDTH, cell_height),
+                (ROW_LABEL_WIDTH, label_height),
             );
         }
 
-        // Draw all cells in the grid
-        for row in 0..GRID_ROWS {
-            for col in 0..GRID_COLS {
-                let cell_start_x = start_x + col as f32 * cell_width + ROW_LABEL_WIDTH;
-                let cell_start_y = start_y + row as f32 * cell_height + COL_LABEL_HEIGHT;
+        self.draw_scrollbars(start, end, (visible_cols, visible_rows));
 
-                // Adjust the height of the last row to account for any floating-point error
-                let adjusted_cell_height = if row == GRID_ROWS - 1 {
-                    grid_height - (row as f32 * cell_height)
-                } else {
-                    cell_height
-                };
+        // Draw dialog box for hovered cell
+        if let Some(idx) = hovered {
+            let col = (idx.x - self.viewport.x) as f32;
+            let row = (idx.y - self.viewport.y) as f32;
+            let cell_end_x = start_x + col * cell_width + ROW_LABEL_WIDTH + cell_width;
+            let cell_end_y = start_y + row * cell_height + COL_LABEL_HEIGHT;
+            self.draw_dialog(idx, (cell_end_x, cell_end_y), cell_width, cell_height);
+        }
+    }
 
-                self.draw_cell(
-                    Index { x: col, y: row },
-                    (cell_start_x, cell_start_y),
-                    (cell_width, adjusted_cell_height),
-                );
+    /// Returns the amount of columns and rows the scrollbars span.
+    /// This is always a bit more than what is in use so that the grid can grow indefinitely.
+    fn scroll_extent(&self, visible: (usize, usize)) -> (usize, usize) {
+        let (cols, rows) = match self.sheet().extent() {
+            Some(extent) => (extent.x + 1, extent.y + 1),
+            None => (0, 0),
+        };
+        (
+            cols.max(self.viewport.x + visible.0) + SCROLL_PADDING,
+            rows.max(self.viewport.y + visible.1) + SCROLL_PADDING,
+        )
+    }
+
+    fn handle_scrolling(&mut self, start: (f32, f32), end: (f32, f32), visible: (usize, usize)) {
+        let (visible_cols, visible_rows) = visible;
+
+        // Mouse wheel, holding shift scrolls horizontally
+        let (wheel_x, wheel_y) = mouse_wheel();
+        if is_point_in_rect(mouse_position(), start, end) {
+            let shift = is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift);
+            let (horizontal, vertical) = if shift {
+                (wheel_y, wheel_x)
+            } else {
+                (wheel_x, wheel_y)
+            };
+            self.viewport.y = scroll_by(self.viewport.y, wheel_steps(vertical) * WHEEL_SCROLL_ROWS);
+            self.viewport.x = scroll_by(self.viewport.x, wheel_steps(horizontal));
+        }
+
+        // Keyboard paging, holding alt pages horizontally
+        let alt = is_key_down(KeyCode::LeftAlt) || is_key_down(KeyCode::RightAlt);
+        let ctrl = is_key_down(KeyCode::LeftControl) || is_key_down(KeyCode::RightControl);
+        let page = if alt { visible_cols } else { visible_rows } as isize;
+        let page_delta = if is_key_pressed(KeyCode::PageDown) {
+            page
+        } else if is_key_pressed(KeyCode::PageUp) {
+            -page
+        } else {
+            0
+        };
+        if alt {
+            self.viewport.x = scroll_by(self.viewport.x, page_delta);
+        } else {
+            self.viewport.y = scroll_by(self.viewport.y, page_delta);
+        }
+        if ctrl && is_key_pressed(KeyCode::Home) {
+            self.viewport = Index { x: 0, y: 0 };
+        }
+
+        // Dragging the scrollbars
+        let (end_x, end_y) = end;
+        let vertical_track = (end_y - SCROLLBAR_SIZE) - (start.1 + COL_LABEL_HEIGHT);
+        let horizontal_track = (end_x - SCROLLBAR_SIZE) - (start.0 + ROW_LABEL_WIDTH);
+        let (mouse_x, mouse_y) = mouse_position();
+
+        if is_mouse_button_pressed(MouseButton::Left) {
+            if is_point_in_rect(
+                (mouse_x, mouse_y),
+                (end_x - SCROLLBAR_SIZE, start.1 + COL_LABEL_HEIGHT),
+                (end_x, end_y - SCROLLBAR_SIZE),
+            ) {
+                self.dragged_scrollbar = Some(ScrollAxis::Vertical);
+            } else if is_point_in_rect(
+                (mouse_x, mouse_y),
+                (start.0 + ROW_LABEL_WIDTH, end_y - SCROLLBAR_SIZE),
+                (end_x - SCROLLBAR_SIZE, end_y),
+            ) {
+                self.dragged_scrollbar = Some(ScrollAxis::Horizontal);
             }
         }
+        if !is_mouse_button_down(MouseButton::Left) {
+            self.dragged_scrollbar = None;
+        }
 
-        // Draw dialog box for hovered cell
-        if let Some(idx) = hovered {
-            let cell_end_x = start_x + idx.x as f32 * cell_width + ROW_LABEL_WIDTH + cell_width;
-            let cell_end_y = start_y + idx.y as f32 * cell_height + COL_LABEL_HEIGHT;
-            let cell_start_x = start_x + idx.x as f32 * cell_width + ROW_LABEL_WIDTH + cell_width;
-            if cell_end_x > screen_width() / 2.0 {
-                self.draw_dialog(
-                    idx,
-                    (cell_start_x, cell_end_y),
-                    cell_width,
-                    cell_height,
-                );
-            } else {
-                self.draw_dialog(
-                    idx,
-                    (cell_end_x, cell_end_y),
-                    cell_width,
-                    cell_height,
-                );
+        let (extent_cols, extent_rows) = self.scroll_extent(visible);
+        match self.dragged_scrollbar {
+            Some(ScrollAxis::Vertical) => {
+                let ratio =
+                    ((mouse_y - start.1 - COL_LABEL_HEIGHT) / vertical_track).clamp(0.0, 1.0);
+                self.viewport.y = (ratio * (extent_rows - visible_rows) as f32) as usize;
             }
+            Some(ScrollAxis::Horizontal) => {
+                let ratio =
+                    ((mouse_x - start.0 - ROW_LABEL_WIDTH) / horizontal_track).clamp(0.0, 1.0);
+                self.viewport.x = (ratio * (extent_cols - visible_cols) as f32) as usize;
+            }
+            None => (),
         }
     }
 
+    fn draw_scrollbars(&self, start: (f32, f32), end: (f32, f32), visible: (usize, usize)) {
+        let (visible_cols, visible_rows) = visible;
+        let (extent_cols, extent_rows) = self.scroll_extent(visible);
+        let (end_x, end_y) = end;
+
+        // Vertical
+        let track_start = start.1 + COL_LABEL_HEIGHT;
+        let track_len = end_y - SCROLLBAR_SIZE - track_start;
+        draw_rectangle(
+            end_x - SCROLLBAR_SIZE,
+            track_start,
+            SCROLLBAR_SIZE,
+            track_len,
+            SCROLLBAR_TRACK_COLOR,
+        );
+        let thumb_len = (track_len * visible_rows as f32 / extent_rows as f32).max(SCROLLBAR_SIZE);
+        let thumb_start = track_start
+            + (track_len - thumb_len) * self.viewport.y as f32
+                / (extent_rows - visible_rows) as f32;
+        draw_rectangle(
+            end_x - SCROLLBAR_SIZE,
+            thumb_start,
+            SCROLLBAR_SIZE,
+            thumb_len,
+            SCROLLBAR_THUMB_COLOR,
+        );
+
+        // Horizontal
+        let track_start = start.0 + ROW_LABEL_WIDTH;
+        let track_len = end_x - SCROLLBAR_SIZE - track_start;
+        draw_rectangle(
+            track_start,
+            end_y - SCROLLBAR_SIZE,
+            track_len,
+            SCROLLBAR_SIZE,
+            SCROLLBAR_TRACK_COLOR,
+        );
+        let thumb_len = (track_len * visible_cols as f32 / extent_cols as f32).max(SCROLLBAR_SIZE);
+        let thumb_start = track_start
+            + (track_len - thumb_len) * self.viewport.x as f32
+                / (extent_cols - visible_cols) as f32;
+        draw_rectangle(
+            thumb_start,
+            end_y - SCROLLBAR_SIZE,
+            thumb_len,
+            SCROLLBAR_SIZE,
+            SCROLLBAR_THUMB_COLOR,
+        );
+    }
+
     fn draw_cell(&mut self, index: Index, start: (f32, f32), dimensions: (f32, f32)) {
         let (start_x, start_y) = start;
         let (width, height) = dimensions;
@@ -269,12 +556,19 @@ impl GUI {
             (NORMAL_CELL_BORDER_WIDTH, NORMAL_CELL_BORDER_COLOR)
         };
 
+        if self.selection_end.is_some()
+            && self
+                .selection()
+                .is_some_and(|selection| selection.contains(index))
+        {
+            draw_rectangle(start_x, start_y, width, height, SELECTION_BACKGROUND_COLOR);
+        }
         draw_rectangle_lines(start_x, start_y, width, height, border_width, border_color);
 
         let mut text: String = if Some(index) == self.selected_cell {
             self.editor_content.clone()
         } else {
-            let computed = self.spread_sheet.get_computed(index);
+            let computed = self.sheet().get_computed(index);
             if let Some(Err(_)) = computed {
                 let triangle_len = 10.;
                 draw_triangle(
@@ -401,37 +695,590 @@ impl GUI {
 
     fn commit_editor(&mut self) {
         if let Some(idx) = self.selected_cell {
-            let previous_content = self.spread_sheet.get_raw(&idx).unwrap_or_default();
-            let new_content = self.editor_content.trim().to_string();
-
-            match (previous_content, new_content.as_str()) {
-                (prev, new) if prev == new => (),
-                ("", "") => (),
-                ("", _added_content) => self.spread_sheet.add_cell_and_compute(idx, new_content),
-                (_deleted_content, "") => self.spread_sheet.remove_cell(idx),
-                (_mutated_from, _mutated_to) => self.spread_sheet.mutate_cell(idx, new_content),
+            let content = self.editor_content.clone();
+            self.workbook
+                .edit(self.active_sheet, |sheet| sheet.set_raw(idx, content));
+        }
+    }
+
+    fn handle_shortcuts(&mut self) {
+        if self.prompt.is_some()
+            || !(is_key_down(KeyCode::LeftControl) || is_key_down(KeyCode::RightControl))
+        {
+            return;
+        }
+        let shift = is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift);
+
+        if is_key_pressed(KeyCode::S) {
+            self.commit_editor();
+            match (&self.file_path, shift) {
+                (Some(path), false) => self.save_file(path.clone()),
+                _ => self.open_file_prompt(PromptAction::Save),
             }
+        } else if is_key_pressed(KeyCode::O) {
+            self.commit_editor();
+            self.open_file_prompt(PromptAction::Open);
+        } else if is_key_pressed(KeyCode::Z) {
+            // Pending edits are committed first so that they can be undone like any other change
+            self.commit_editor();
+            self.workbook.undo();
+            self.clamp_active_sheet();
+            self.reload_editor();
+        } else if is_key_pressed(KeyCode::Y) {
+            self.commit_editor();
+            self.workbook.redo();
+            self.clamp_active_sheet();
+            self.reload_editor();
+        } else if is_key_pressed(KeyCode::D) {
+            self.commit_editor();
+            self.define_name_prompt();
+        } else if is_key_pressed(KeyCode::L) {
+            self.commit_editor();
+            self.toggle_number_locale();
+        } else if is_key_pressed(KeyCode::T) {
+            self.commit_editor();
+            self.prompt = Some(Prompt {
+                action: PromptAction::SplitColumns,
+                text: ",".to_string(),
+            });
+        } else if is_key_pressed(KeyCode::C) && !self.is_editing() {
+            self.copy_selection(false);
+        } else if is_key_pressed(KeyCode::X) && !self.is_editing() {
+            self.copy_selection(true);
+        } else if is_key_pressed(KeyCode::V) && !self.is_editing() {
+            self.paste();
+        }
+    }
+
+    /// The selected cell, or the rectangle between it and the cell shift clicked last
+    fn selection(&self) -> Option<CellRange> {
+        let selected = self.selected_cell?;
+        Some(CellRange::new(
+            selected,
+            self.selection_end.unwrap_or(selected),
+        ))
+    }
+
+    // While a single cell is being edited the clipboard shortcuts act on the editor text instead of on cells
+    fn is_editing(&self) -> bool {
+        match self.selected_cell {
+            Some(idx) => {
+                self.selection_end.is_none()
+                    && self.sheet().get_raw(&idx).unwrap_or_default() != self.editor_content
+            }
+            None => false,
+        }
+    }
+
+    fn copy_selection(&mut self, cut: bool) {
+        let Some(selection) = self.selection() else {
+            return;
+        };
+        let copied = self.sheet().copy(selection);
+        clipboard_set(&copied.to_tsv());
+        self.copied = Some(copied);
+        self.cut_source = cut.then_some((self.active_sheet, selection));
+        // The editor reacts to the same keys, so its text is restored once it has run
+        self.draws.push(Box::new(|gui| gui.reload_editor()));
+    }
+
+    fn paste(&mut self) {
+        let Some(target) = self.selection().map(|selection| selection.start) else {
+            return;
+        };
+
+        // Text copied from this sheet keeps its origin so references are shifted, anything else is pasted as is
+        let copied = match (clipboard_get(), self.copied.take()) {
+            (Some(text), Some(copied)) if text != copied.to_tsv() => {
+                self.cut_source = None;
+                CopiedCells::from_tsv(&text)
+            }
+            (None, None) => return,
+            (Some(text), None) => CopiedCells::from_tsv(&text),
+            (_, Some(copied)) => copied,
+        };
+
+        let cut_source = self.cut_source.take();
+        let active_sheet = self.active_sheet;
+        if let Some((sheet, source)) = cut_source.filter(|(sheet, _)| *sheet != active_sheet) {
+            self.workbook.edit(sheet, |sheet| sheet.clear_range(source));
+        }
+        self.workbook.edit(active_sheet, |sheet| {
+            sheet.batch(|sheet| {
+                if let Some((_, source)) = cut_source.filter(|(sheet, _)| *sheet == active_sheet) {
+                    sheet.clear_range(source);
+                }
+                sheet.paste(target, &copied);
+            })
+        });
+        // A cut can only be pasted once, afterwards the cells are copies
+        if cut_source.is_none() {
+            self.copied = Some(copied);
+        }
+        self.draws.push(Box::new(|gui| gui.reload_editor()));
+    }
+
+    fn draw_menu(&mut self) {
+        let Some(menu) = &self.menu else {
+            return;
+        };
+        let items: &[(&str, MenuAction)] = match menu.target {
+            MenuTarget::Label {
+                axis: Axis::Row, ..
+            } => &[
+                ("Insert row above", MenuAction::InsertBefore),
+                ("Insert row below", MenuAction::InsertAfter),
+                ("Delete row", MenuAction::Delete),
+            ],
+            MenuTarget::Label {
+                axis: Axis::Column, ..
+            } => &[
+                ("Insert column left", MenuAction::InsertBefore),
+                ("Insert column right", MenuAction::InsertAfter),
+                ("Delete column", MenuAction::Delete),
+            ],
+            MenuTarget::Tab(_) => &[
+                ("Rename sheet", MenuAction::Rename),
+                ("Delete sheet", MenuAction::Delete),
+            ],
+        };
+
+        // Keep the menu inside the window
+        let menu_height = MENU_ITEM_HEIGHT * items.len() as f32;
+        let menu_x = menu.position.0.min(screen_width() - MENU_WIDTH);
+        let menu_y = menu.position.1.min(screen_height() - menu_height);
+
+        draw_rectangle(menu_x, menu_y, MENU_WIDTH, menu_height, MENU_BACKGROUND);
+        let mouse = mouse_position();
+        let mut clicked = None;
+        for (i, (text, action)) in items.iter().enumerate() {
+            let item_y = menu_y + i as f32 * MENU_ITEM_HEIGHT;
+            let item_end = (menu_x + MENU_WIDTH, item_y + MENU_ITEM_HEIGHT);
+            if is_point_in_rect(mouse, (menu_x, item_y), item_end) {
+                draw_rectangle(
+                    menu_x,
+                    item_y,
+                    MENU_WIDTH,
+                    MENU_ITEM_HEIGHT,
+                    MENU_HOVER_BACKGROUND,
+                );
+                if is_mouse_button_pressed(MouseButton::Left) {
+                    clicked = Some(*action);
+                }
+            }
+            draw_text_ex(
+                text,
+                menu_x + 8.0,
+                item_y + MENU_ITEM_HEIGHT / 2.0 + MENU_FONT_SIZE as f32 / 3.0,
+                TextParams {
+                    font: Some(&self.regular_font),
+                    font_size: MENU_FONT_SIZE,
+                    color: CELL_TEXT_COLOR,
+                    ..Default::default()
+                },
+            );
+        }
+        draw_rectangle_lines(
+            menu_x,
+            menu_y,
+            MENU_WIDTH,
+            menu_height,
+            1.0,
+            LABEL_BORDER_COLOR,
+        );
+
+        if let Some(action) = clicked {
+            self.apply_menu_action(action);
+        } else if is_key_pressed(KeyCode::Escape) || is_mouse_button_pressed(MouseButton::Left) {
+            // Clicking anywhere else closes the menu
+            self.menu = None;
+        }
+    }
+
+    fn apply_menu_action(&mut self, action: MenuAction) {
+        let Some(Menu { target, .. }) = self.menu.take() else {
+            return;
+        };
+
+        self.commit_editor();
+        let (workbook, sheet) = (&mut self.workbook, self.active_sheet);
+        match (target, action) {
+            (MenuTarget::Label { axis, index }, action) => {
+                match (axis, action) {
+                    (Axis::Row, MenuAction::InsertBefore) => {
+                        workbook.insert_rows(sheet, index, 1)
+                    }
+                    (Axis::Row, MenuAction::InsertAfter) => {
+                        workbook.insert_rows(sheet, index + 1, 1)
+                    }
+                    (Axis::Row, _) => workbook.delete_rows(sheet, index, 1),
+                    (Axis::Column, MenuAction::InsertBefore) => {
+                        workbook.insert_columns(sheet, index, 1)
+                    }
+                    (Axis::Column, MenuAction::InsertAfter) => {
+                        workbook.insert_columns(sheet, index + 1, 1)
+                    }
+                    (Axis::Column, _) => workbook.delete_columns(sheet, index, 1),
+                }
+                // Copied cells no longer line up with the sheet
+                self.cut_source = None;
+                self.reload_editor();
+            }
+            (MenuTarget::Tab(sheet), MenuAction::Rename) => {
+                self.prompt = Some(Prompt {
+                    action: PromptAction::RenameSheet(sheet),
+                    text: self.workbook.sheet_name(sheet).to_string(),
+                });
+            }
+            (MenuTarget::Tab(sheet), _) => self.remove_sheet(sheet),
+        }
+    }
+
+    fn draw_sheet_tabs(&mut self, start: (f32, f32)) {
+        let (start_x, start_y) = start;
+        draw_rectangle(
+            start_x,
+            start_y,
+            screen_width() - start_x,
+            TAB_BAR_HEIGHT,
+            BACKGROUND_COLOR,
+        );
+
+        let accepts_clicks = self.prompt.is_none() && self.menu.is_none();
+        let mouse = mouse_position();
+        let mut tab_x = start_x + ROW_LABEL_WIDTH;
+        let names: Vec<String> = self.workbook.sheet_names().map(str::to_owned).collect();
+        // The last tab adds a sheet
+        for (i, name) in names.iter().map(String::as_str).chain(["+"]).enumerate() {
+            let text_dimensions = measure_text(name, Some(&self.regular_font), TAB_FONT_SIZE, 1.0);
+            let tab_width = text_dimensions.width + TAB_PADDING * 2.0;
+            let tab_end = (tab_x + tab_width, start_y + TAB_BAR_HEIGHT);
+
+            let background = if i == self.active_sheet {
+                ACTIVE_TAB_BACKGROUND
+            } else {
+                TAB_BACKGROUND
+            };
+            draw_rectangle(tab_x, start_y, tab_width, TAB_BAR_HEIGHT, background);
+            draw_rectangle_lines(
+                tab_x,
+                start_y,
+                tab_width,
+                TAB_BAR_HEIGHT,
+                1.0,
+                LABEL_BORDER_COLOR,
+            );
+            draw_text_ex(
+                name,
+                tab_x + TAB_PADDING,
+                start_y + TAB_BAR_HEIGHT / 2.0 + text_dimensions.height / 2.0,
+                TextParams {
+                    font: Some(if i == self.active_sheet {
+                        &self.bold_font
+                    } else {
+                        &self.regular_font
+                    }),
+                    font_size: TAB_FONT_SIZE,
+                    color: CELL_TEXT_COLOR,
+                    ..Default::default()
+                },
+            );
+
+            if accepts_clicks && is_point_in_rect(mouse, (tab_x, start_y), tab_end) {
+                if is_mouse_button_pressed(MouseButton::Left) {
+                    if i == names.len() {
+                        self.add_sheet();
+                    } else {
+                        self.switch_sheet(i);
+                    }
+                } else if is_mouse_button_pressed(MouseButton::Right) && i < names.len() {
+                    self.menu = Some(Menu {
+                        target: MenuTarget::Tab(i),
+                        position: mouse,
+                    });
+                }
+            }
+            tab_x = tab_end.0;
+        }
+    }
+
+    fn add_sheet(&mut self) {
+        let name = self.workbook.unused_sheet_name();
+        let sheet = self
+            .workbook
+            .add_sheet(&name)
+            .expect("Unused sheet names are valid");
+        self.switch_sheet(sheet);
+    }
+
+    fn switch_sheet(&mut self, sheet: usize) {
+        if sheet == self.active_sheet {
+            return;
+        }
+        self.commit_editor();
+        self.active_sheet = sheet;
+        self.selected_cell = None;
+        self.selection_end = None;
+        self.editor_content.clear();
+        self.viewport = Index { x: 0, y: 0 };
+    }
+
+    fn remove_sheet(&mut self, sheet: usize) {
+        let name = self.workbook.sheet_name(sheet).to_string();
+        if !self.workbook.remove_sheet(sheet) {
+            self.status = Some("The only sheet can not be deleted".to_string());
+            return;
+        }
+
+        self.status = Some(format!("Deleted sheet '{name}', press Ctrl+Z to undo"));
+        self.cut_source = None;
+        let active_removed = sheet == self.active_sheet;
+        if sheet < self.active_sheet || self.active_sheet == self.workbook.sheet_count() {
+            self.active_sheet -= 1;
+        }
+        if active_removed {
+            self.selected_cell = None;
+            self.selection_end = None;
+            self.editor_content.clear();
+            self.viewport = Index { x: 0, y: 0 };
+        }
+    }
+
+    // Undoing and redoing can add and remove sheets, the active one included
+    fn clamp_active_sheet(&mut self) {
+        let sheet_count = self.workbook.sheet_count();
+        if self
+            .cut_source
+            .is_some_and(|(sheet, _)| sheet >= sheet_count)
+        {
+            self.cut_source = None;
+        }
+        if self.active_sheet >= sheet_count {
+            self.active_sheet = sheet_count - 1;
+            self.selected_cell = None;
+            self.selection_end = None;
+            self.editor_content.clear();
+            self.viewport = Index { x: 0, y: 0 };
+        }
+    }
+
+    fn sheet(&self) -> &SpreadSheet {
+        self.workbook.sheet(self.active_sheet)
+    }
+
+    // Suggests binding a name to the selected cells
+    fn define_name_prompt(&mut self) {
+        let definition = self
+            .selection()
+            .map(|selection| {
+                let anchored = |index| CellReference {
+                    index,
+                    absolute_column: true,
+                    absolute_row: true,
+                };
+                FormulaReference {
+                    sheet: None,
+                    from: anchored(selection.start),
+                    to: (selection.start != selection.end).then(|| anchored(selection.end)),
+                }
+                .to_string()
+            })
+            .unwrap_or_default();
+        self.prompt = Some(Prompt {
+            action: PromptAction::DefineName,
+            text: format!("Name = {definition}"),
+        });
+    }
+
+    fn define_name(&mut self, text: &str) {
+        let (name, definition) = text.split_once('=').unwrap_or((text, ""));
+        let (name, definition) = (name.trim(), definition.trim());
+        if definition.is_empty() {
+            self.status = Some(match self.workbook.remove_name(name) {
+                Some(_) => format!("Removed name '{name}'"),
+                None => format!("There is no name '{name}'"),
+            });
+            return;
+        }
+
+        self.status = Some(
+            match self
+                .workbook
+                .define_name(name, self.active_sheet, definition)
+            {
+                Ok(()) => format!(
+                    "Defined {name} = {}",
+                    self.workbook.name_definition(name).unwrap_or_default()
+                ),
+                Err(e) => e.to_string(),
+            },
+        );
+    }
+
+    fn split_to_columns(&mut self, delimiter: &str) {
+        let Some(selection) = self.selection() else {
+            self.status = Some("Select the cells to split first".to_string());
+            return;
+        };
+        if delimiter.is_empty() {
+            self.status = Some("Cells can not be split at an empty delimiter".to_string());
+            return;
+        }
+
+        self.workbook.edit(self.active_sheet, |sheet| {
+            sheet.split_to_columns(selection, delimiter)
+        });
+        self.reload_editor();
+    }
+
+    fn open_file_prompt(&mut self, action: PromptAction) {
+        let text = self
+            .file_path
+            .as_ref()
+            .map(|path| path.display().to_string())
+            .unwrap_or_default();
+        self.prompt = Some(Prompt { action, text });
+    }
+
+    fn draw_prompt(&mut self) {
+        let Some(prompt) = &mut self.prompt else {
+            return;
+        };
+        const PROMPT_WIDTH: f32 = 500.0;
+        const PROMPT_HEIGHT: f32 = 90.0;
+
+        root_ui().push_skin(&self.editor_skin);
+        root_ui().window(
+            hash!(),
+            vec2(
+                (screen_width() - PROMPT_WIDTH) / 2.0,
+                (screen_height() - PROMPT_HEIGHT) / 2.0,
+            ),
+            vec2(PROMPT_WIDTH, PROMPT_HEIGHT),
+            |ui| {
+                let title = match prompt.action {
+                    PromptAction::Save => "Save to (Enter to confirm, Escape to cancel):",
+                    PromptAction::Open => "Open file (Enter to confirm, Escape to cancel):",
+                    PromptAction::RenameSheet(_) => {
+                        "Rename sheet (Enter to confirm, Escape to cancel):"
+                    }
+                    PromptAction::DefineName => {
+                        "Define name, leave the definition empty to remove it:"
+                    }
+                    PromptAction::SplitColumns => {
+                        "Split into columns at (Enter to confirm, Escape to cancel):"
+                    }
+                };
+                ui.label(Some(vec2(10.0, 10.0)), title);
+
+                let input_text_id = hash!();
+                Editbox::new(input_text_id, vec2(PROMPT_WIDTH - 20.0, EDITOR_HEIGHT))
+                    .position(vec2(10.0, 40.0))
+                    .ui(ui, &mut prompt.text);
+                ui.set_input_focus(input_text_id);
+            },
+        );
+        root_ui().pop_skin();
+
+        if is_key_pressed(KeyCode::Escape) {
+            self.prompt = None;
+        } else if is_key_pressed(KeyCode::Enter) {
+            let prompt = self.prompt.take().expect("Checked above");
+            let text = prompt.text.trim();
+            match prompt.action {
+                PromptAction::Save => self.save_file(PathBuf::from(text)),
+                PromptAction::Open => self.open_file(PathBuf::from(text)),
+                PromptAction::RenameSheet(sheet) => {
+                    if let Err(e) = self.workbook.rename_sheet(sheet, text) {
+                        self.status = Some(e.to_string());
+                    }
+                    self.reload_editor();
+                }
+                PromptAction::DefineName => self.define_name(text),
+                // The delimiter may well be a space
+                PromptAction::SplitColumns => self.split_to_columns(&prompt.text),
+            }
+        }
+    }
+
+    /// Saves the active sheet, files hold a single sheet.
+    fn save_file(&mut self, path: PathBuf) {
+        match self.sheet().save_to_file_path(&path) {
+            Ok(()) => {
+                self.status = Some(format!(
+                    "Saved sheet '{}' to {}",
+                    self.workbook.sheet_name(self.active_sheet),
+                    path.display()
+                ));
+                self.file_path = Some(path);
+            }
+            Err(e) => self.status = Some(format!("Could not save to {}: {e}", path.display())),
+        }
+    }
+
+    /// Switches between decimal points and decimal commas in every sheet, without converting written numbers
+    fn toggle_number_locale(&mut self) {
+        let locale = match self.workbook.number_locale() {
+            NumberLocale::DecimalPoint => NumberLocale::DecimalComma,
+            NumberLocale::DecimalComma => NumberLocale::DecimalPoint,
+        };
+        self.workbook.set_number_locale(locale);
+        self.status = Some(format!(
+            "Numbers are written like 1{}234{}5, separate arguments with '{}'",
+            locale.thousands_separator(),
+            locale.decimal_separator(),
+            locale.argument_separator()
+        ));
+    }
+
+    /// Replaces the workbook with the sheet saved at `path`, keeping the current workbook if loading fails.
+    pub fn open_file(&mut self, path: PathBuf) {
+        match SpreadSheet::from_file_path(&path) {
+            Ok(spread_sheet) => {
+                let locale = self.workbook.number_locale();
+                self.workbook = Workbook::from(spread_sheet);
+                self.workbook.set_number_locale(locale);
+                self.active_sheet = 0;
+                self.selected_cell = None;
+                self.selection_end = None;
+                self.copied = None;
+                self.cut_source = None;
+                self.editor_content.clear();
+                self.viewport = Index { x: 0, y: 0 };
+                self.status = Some(format!("Opened {}", path.display()));
+                self.file_path = Some(path);
+            }
+            Err(e) => self.status = Some(format!("Could not open {}: {e}", path.display())),
+        }
+    }
+
+    fn reload_editor(&mut self) {
+        if let Some(idx) = self.selected_cell {
+            self.editor_content = self.sheet().get_raw(&idx).unwrap_or_default().to_owned();
         }
     }
 
     fn change_selected_cell(&mut self, idx: Index) {
+        self.selection_end = None;
         if self.selected_cell == Some(idx) {
             return;
         }
 
         self.commit_editor();
-        self.editor_content = self
-            .spread_sheet
-            .get_raw(&idx)
-            .unwrap_or_default()
-            .to_owned();
+        self.editor_content = self.sheet().get_raw(&idx).unwrap_or_default().to_owned();
         self.selected_cell = Some(idx);
     }
 
     fn draw_dialog(&self, idx: Index, pos: (f32, f32), cell_width: f32, cell_height: f32) {
-        if let Some(err) = self.spread_sheet.get_error(idx) {
-            let dialog_width: f32 = cell_width;
-            let dialog_height: f32 = cell_height * 2.0;
+        if let Some(err) = self.sheet().get_error(idx) {
+            // A formula that does not parse is shown below the error with the problem underlined
+            let formula = self
+                .sheet()
+                .get_parse_error(idx)
+                .and_then(|error| error.span.clone())
+                .zip(self.sheet().get_raw(&idx));
+            let dialog_width: f32 = cell_width * if formula.is_some() { 2.0 } else { 1.0 };
+            let dialog_height: f32 = cell_height * if formula.is_some() { 3.0 } else { 2.0 };
             const DIALOG_FONT_SIZE: u16 = 14;
 
             let reverse_x = pos.0 > screen_width() / 2.0;
@@ -440,7 +1287,7 @@ impl GUI {
             // Determine the position of the dialog box based on `reverse`
             let (base_x, base_y) = pos;
             let dialog_x = if reverse_x {
-                base_x - dialog_width * 2.0 // Move left if reverse is true
+                base_x - cell_width * 2.0 // Move left if reverse is true
             } else {
                 base_x
             };
@@ -470,7 +1317,8 @@ impl GUI {
             );
 
             // Calculate vertical starting position for centering the text block
-            let total_text_height = lines.len() as f32 * (DIALOG_FONT_SIZE as f32 + 4.0); // 4.0 for line spacing
+            let line_count = lines.len() + usize::from(formula.is_some());
+            let total_text_height = line_count as f32 * (DIALOG_FONT_SIZE as f32 + 4.0); // 4.0 for line spacing
             let mut text_y = dialog_y + (dialog_height - total_text_height) / 2.0 + 5.;
 
             // Draw each line of text
@@ -495,6 +1343,42 @@ impl GUI {
 
                 text_y += DIALOG_FONT_SIZE as f32 + 4.0; // Move to next line
             }
+
+            if let Some((span, raw)) = formula {
+                let measure = |text: &str| {
+                    measure_text(text, Some(&self.regular_font), DIALOG_FONT_SIZE, 1.0).width
+                };
+                let (shown, span) = clip_around_span(raw, span, dialog_width - 10.0, measure);
+                let chars: Vec<char> = shown.chars().collect();
+                let text_x = dialog_x + (dialog_width - measure(&shown)) / 2.0;
+                draw_text_ex(
+                    &shown,
+                    text_x,
+                    text_y,
+                    TextParams {
+                        font: Some(&self.regular_font),
+                        font_size: DIALOG_FONT_SIZE,
+                        font_scale: 1.0,
+                        font_scale_aspect: 1.0,
+                        rotation: 0.0,
+                        color: BLACK,
+                    },
+                );
+
+                let before: String = chars[..span.start].iter().collect();
+                let underlined: String = chars[span].iter().collect();
+                // A formula that ended too early is marked just past its end
+                let underline_width = measure(&underlined).max(6.0);
+                let underline_x = text_x + measure(&before);
+                draw_line(
+                    underline_x,
+                    text_y + 3.0,
+                    underline_x + underline_width,
+                    text_y + 3.0,
+                    2.0,
+                    RED,
+                );
+            }
         }
     }
 
@@ -546,19 +1430,17 @@ impl GUI {
     }
 }
 
-fn column_idx_to_string(mut idx: usize) -> String {
-    let mut s = String::new();
-
-    loop {
-        let rem = (idx % 26) as u8;
-        s.insert(0, (b'A' + rem) as char); // Prepend the character
-        if idx < 26 {
-            break;
-        }
-        idx = idx / 26 - 1;
+// One step back for every wheel movement away from the user, none while the wheel is idle
+fn wheel_steps(wheel: f32) -> isize {
+    if wheel == 0.0 {
+        0
+    } else {
+        -wheel.signum() as isize
     }
+}
 
-    s
+fn scroll_by(position: usize, delta: isize) -> usize {
+    position.saturating_add_signed(delta)
 }
 
 fn is_point_in_rect<T: std::cmp::PartialOrd>(
@@ -572,50 +1454,6 @@ fn is_point_in_rect<T: std::cmp::PartialOrd>(
         && point.1 <= rect_end.1
 }
 
-/*
-   Format a float into scientific notation such as: 42.0 -> 4.200e+01
-   width controls the amount of left padded spaces
-   precision is the amount of decimals
-   exp_pad controls the amount of left padded 0s
-*/
-fn fmt_f64(num: f64, width: usize, precision: usize, exp_pad: usize) -> String {
-    if !num.is_finite() {
-        return num.to_string();
-    }
-    let mut num = format!("{:.precision$e}", num, precision = precision);
-    // Safe to `unwrap` as `num` is guaranteed to contain `'e'`
-    let exp = num.split_off(num.find('e').expect("safe"));
-
-    let (sign, exp) = if exp.starts_with("e-") {
-        ('-', &exp[2..])
-    } else {
-        ('+', &exp[1..])
-    };
-    num.push_str(&format!("e{}{:0>pad$}", sign, exp, pad = exp_pad));
-
-    format!("{:>width$}", num, width = width)
-}
-
-fn computed_to_text(computed: Option<Result<Value, ComputeError>>) -> String {
-    match computed {
-        Some(value) => match value {
-            Ok(inner) => match inner {
-                Value::Text(s) => s,
-                Value::Number(num) => {
-                    if num >= 1E15 {
-                        fmt_f64(num, 10, 3, 2)
-                    } else {
-                        num.to_string()
-                    }
-                }
-                Value::Bool(b) => b.to_string(),
-            },
-            Err(err) => err.to_string(),
-        },
-        None => String::new(),
-    }
-}
-
 fn err_to_info(err: ComputeError) -> String {
     match err {
         ComputeError::ParseError(reason) => reason,
@@ -624,9 +1462,37 @@ fn err_to_info(err: ComputeError) -> String {
         ComputeError::Cycle => "Detected cyclic computation".to_string(),
         ComputeError::UnknownFunction(f) => format!("Unknown function '{f}'"),
         ComputeError::InvalidArgument(message) => message,
+        ComputeError::DivisionByZero => "Division by zero".to_string(),
+        ComputeError::UnknownName(message) => message,
+        ComputeError::NotAvailable(message) => message,
+        ComputeError::NumberError(message) => message,
     }
 }
 
+/// Drops characters from both ends of `text` until it is at most `max_width` wide, keeping as much of the span
+/// as possible. Returns the remaining text and the span moved along with it.
+fn clip_around_span(
+    text: &str,
+    span: Range<usize>,
+    max_width: f32,
+    measure: impl Fn(&str) -> f32,
+) -> (String, Range<usize>) {
+    let chars: Vec<char> = text.chars().collect();
+    let span = span.start.min(chars.len())..span.end.min(chars.len());
+    let (mut start, mut end) = (0, chars.len());
+    while measure(&chars[start..end].iter().collect::<String>()) > max_width {
+        if end > span.end {
+            end -= 1;
+        } else if start < span.start {
+            start += 1;
+        } else {
+            break;
+        }
+    }
+    let shown = chars[start..end].iter().collect();
+    (shown, span.start - start..span.end - start)
+}
+
 fn split_into_lines(text: &str, font: &Font, font_size: u16, max_width: f32) -> Vec<String> {
     let mut lines = Vec::new();
     let mut current_line = String::new();
@@ -656,3 +1522,17 @@ fn split_into_lines(text: &str, font: &Font, font_size: u16, max_width: f32) ->
 
     lines
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wheel_scrolling() {
+        assert_eq!(scroll_by(10, wheel_steps(0.0) * WHEEL_SCROLL_ROWS), 10);
+        assert_eq!(scroll_by(10, wheel_steps(-0.0)), 10);
+        assert_eq!(scroll_by(10, wheel_steps(1.0) * WHEEL_SCROLL_ROWS), 7);
+        assert_eq!(scroll_by(10, wheel_steps(-120.0) * WHEEL_SCROLL_ROWS), 13);
+        assert_eq!(scroll_by(1, wheel_steps(1.0) * WHEEL_SCROLL_ROWS), 0);
+    }
+}
@@ -0,0 +1,51 @@
+//! Engine of the mini spreadsheet.
+//!
+//! The engine is usable without a window: create a [`SpreadSheet`], set raw cell
+//! contents with [`SpreadSheet::set_raw`] and read back the computed values with
+//! [`SpreadSheet::get_computed`]. The macroquad frontend lives behind the `gui`
+//! feature, which is enabled by default. Sheets whose formulas refer to each
+//! other, as in `=Sheet2!A1`, are kept together in a [`Workbook`].
+//!
+//! ```
+//! use mini_spreadsheet::{Index, SpreadSheet, Value};
+//!
+//! let mut sheet = SpreadSheet::default();
+//! sheet.set_raw(Index { x: 0, y: 0 }, "20".to_string());
+//! sheet.set_raw(Index { x: 1, y: 0 }, "=A1 * 2".to_string());
+//!
+//! assert_eq!(sheet.get_computed(Index { x: 1, y: 0 }), Some(Ok(Value::Number(40.0))));
+//! ```
+//!
+//! Formulas can call functions registered next to the builtin ones. Arguments are checked against the
+//! declared types before the function is called:
+//!
+//! ```
+//! use mini_spreadsheet::{ArgumentType, Function, Index, SpreadSheet, Value};
+//!
+//! let mut sheet = SpreadSheet::default();
+//! sheet
+//!     .register_function(
+//!         Function::new("vat", "Adds 20% value added tax.", |args| {
+//!             Ok(Value::Number(args[0].as_number().unwrap_or_default() * 1.2))
+//!         })
+//!         .required(&[ArgumentType::Number]),
+//!     )
+//!     .expect("vat can be called from formulas");
+//! sheet.set_raw(Index { x: 0, y: 0 }, "=vat(50)".to_string());
+//!
+//! assert_eq!(sheet.get_computed(Index { x: 0, y: 0 }), Some(Ok(Value::Number(60.0))));
+//! ```
+
+pub mod common_functions;
+pub mod common_types;
+#[cfg(feature = "gui")]
+pub mod gui;
+pub mod spreadsheet;
+pub mod workbook;
+
+pub use common_types::{Array, Cell, ComputeError, Index, ParseError, Value};
+pub use spreadsheet::parser::ast_resolver::function_registry::{
+    ArgumentType, Function, FunctionRegistry, RegistrationError,
+};
+pub use spreadsheet::SpreadSheet;
+pub use workbook::Workbook;
@@ -1,16 +1,15 @@
-pub mod common_types;
-pub mod common_functions;
+use std::path::PathBuf;
 
-use gui::GUI;
-use spreadsheet::SpreadSheet;
+use mini_spreadsheet::{gui::GUI, workbook::Workbook};
 
-mod gui;
 mod renderer;
-mod spreadsheet;
 
 #[macroquad::main("Spredsheet")]
 async fn main() {
-    let spread_sheet = SpreadSheet::default();
-    let mut gui = GUI::new(spread_sheet).await;
+    let workbook = Workbook::default();
+    let mut gui = GUI::new(workbook).await;
+    if let Some(path) = std::env::args().nth(1) {
+        gui.open_file(PathBuf::from(path));
+    }
     gui.start().await;
-}
\ No newline at end of file
+}
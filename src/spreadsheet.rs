@@ -1,84 +1,315 @@
+use clipboard::CopiedCells;
+use csv::{CsvContent, CsvError, CsvOptions};
+use history::{CellChange, History};
 use parser::{
-    ast_resolver::{ASTResolver, VarContext},
+    ast_resolver::{
+        function_registry::{Function, FunctionRegistry, RegistrationError},
+        ASTResolver, VarContext,
+    },
     dependancy_graph::{DependancyGraph, TopologicalSort},
     CellParser,
 };
-use std::{collections::HashMap, fs::File, io::Read, path::PathBuf};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::{self, Read, Write},
+    path::Path,
+    sync::Arc,
+};
 
-use crate::common_types::{Cell, ComputeError, Expression, Index, ParsedCell, Value};
-mod parser;
+use crate::{
+    common_functions::column_idx_to_string,
+    common_types::{
+        Axis, Cell, CellRange, ComputeError, Dependency, Expression, Index, NumberLocale,
+        ParseError, ParsedCell, StructuralChange, Value,
+    },
+};
+pub mod clipboard;
+pub mod csv;
+pub mod history;
+pub mod parser;
 
+/// A sparse grid of cells, along with the dependency graph used to keep computed values up to date.
 #[derive(Debug, Default)]
 pub struct SpreadSheet {
-    pub cells: HashMap<Index, Cell>,
+    cells: HashMap<Index, Cell>,
     dependencies: DependancyGraph,
+    history: History,
+    number_locale: NumberLocale,
+    // Shared with the other sheets of a workbook
+    functions: Arc<FunctionRegistry>,
+    // References found while computing a cell, such as those of `indirect`, kept in the graph next to the parsed ones
+    dynamic_dependencies: HashMap<Index, Vec<Dependency>>,
 }
 
 impl VarContext for SpreadSheet {
     fn get_variable(&self, index: Index) -> Option<Result<Value, ComputeError>> {
         self.get_computed(index)
     }
+
+    fn get_function(&self, name: &str) -> Option<&Function> {
+        self.functions.get(name)
+    }
+
+    fn number_locale(&self) -> NumberLocale {
+        self.number_locale
+    }
+}
+
+// The raw contents a cell keeps, without surrounding whitespace. Empty contents leave the cell empty.
+fn normalize_raw(raw: &str) -> Option<String> {
+    let raw = raw.trim();
+    (!raw.is_empty()).then(|| raw.to_string())
+}
+
+// Computes against the sheet while recording the references only found while computing
+struct TrackingContext<'a> {
+    sheet: &'a SpreadSheet,
+    dependencies: RefCell<Vec<Dependency>>,
+}
+
+impl VarContext for TrackingContext<'_> {
+    fn get_variable(&self, index: Index) -> Option<Result<Value, ComputeError>> {
+        self.sheet.get_variable(index)
+    }
+
+    fn get_function(&self, name: &str) -> Option<&Function> {
+        self.sheet.get_function(name)
+    }
+
+    fn add_dependency(&self, dependency: Dependency) {
+        let mut dependencies = self.dependencies.borrow_mut();
+        if !dependencies.contains(&dependency) {
+            dependencies.push(dependency);
+        }
+    }
+
+    fn number_locale(&self) -> NumberLocale {
+        self.sheet.number_locale
+    }
 }
 
 impl SpreadSheet {
     /// Adds the dependency graph for a cell based on its parsed representation.
     fn add_dependencies(&mut self, index: Index, cell: &Cell) {
+        self.dynamic_dependencies.remove(&index);
         if let Some(Ok(ParsedCell::Expr(Expression {
             ref dependencies, ..
         }))) = cell.parsed_representation
         {
             self.dependencies.add_node(index, dependencies);
         } else {
-            self.dependencies.add_node(index, &vec![]);
+            self.dependencies.add_node(index, &[]);
         }
     }
 
     /// Updates the dependency graph for a cell based on its parsed representation.
     fn update_dependencies(&mut self, index: Index, cell: &Cell) {
+        self.dynamic_dependencies.remove(&index);
         if let Some(Ok(ParsedCell::Expr(Expression {
             ref dependencies, ..
         }))) = cell.parsed_representation
         {
             self.dependencies.change_node(index, dependencies);
         } else {
-            self.dependencies.change_node(index, &vec![]);
+            self.dependencies.change_node(index, &[]);
+        }
+    }
+
+    pub fn number_locale(&self) -> NumberLocale {
+        self.number_locale
+    }
+
+    /// Changes how numbers are written in cells and formulas, then parses every cell again.
+    /// Cells are not converted, so numbers written for the previous locale may no longer parse.
+    pub fn set_number_locale(&mut self, locale: NumberLocale) {
+        self.number_locale = locale;
+        let indices: Vec<Index> = self.cells.keys().copied().collect();
+        for index in indices {
+            let mut cell = self.cells.remove(&index).expect("Index was just listed");
+            CellParser::parse_cell(&mut cell, locale);
+            cell.needs_compute = true;
+            self.update_dependencies(index, &cell);
+            self.cells.insert(index, cell);
+        }
+        self.compute_all();
+    }
+
+    /// The functions formulas can call, the builtin functions unless others were registered.
+    pub fn functions(&self) -> &FunctionRegistry {
+        &self.functions
+    }
+
+    /// Adds a function formulas can call, replacing any function with the same name, then recomputes every cell.
+    ///
+    /// Fails if formulas can not call the function by its name, or if the name is one of the lazily evaluated
+    /// functions such as `if`, see [`FunctionRegistry::register`].
+    pub fn register_function(&mut self, function: Function) -> Result<(), RegistrationError> {
+        Arc::make_mut(&mut self.functions).register(function)?;
+        self.recompute_all();
+        Ok(())
+    }
+
+    /// Replaces the functions formulas can call, then recomputes every cell.
+    pub fn set_functions(&mut self, functions: Arc<FunctionRegistry>) {
+        self.functions = functions;
+        self.recompute_all();
+    }
+
+    fn recompute_all(&mut self) {
+        for cell in self.cells.values_mut() {
+            cell.needs_compute = true;
+        }
+        self.compute_all();
+    }
+
+    /// Computes the value of a cell in the sheet and adds the references found while computing it to the
+    /// dependency graph. Returns whether those references changed, in which case the cell may have read
+    /// cells that were not computed yet.
+    fn compute_cell(&mut self, index: Index) -> bool {
+        let context = TrackingContext {
+            sheet: self,
+            dependencies: RefCell::default(),
+        };
+        let computed = Self::compute_cell_in(&self.cells[&index], &context);
+        let found = context.dependencies.into_inner();
+
+        let cell = self.cells.get_mut(&index).expect("Computed cells exist");
+        cell.computed_value = computed;
+        cell.needs_compute = false;
+
+        let previous = self
+            .dynamic_dependencies
+            .get(&index)
+            .map_or(&[][..], Vec::as_slice);
+        if previous == found {
+            return false;
+        }
+        let mut dependencies = match &self.cells[&index].parsed_representation {
+            Some(Ok(ParsedCell::Expr(expression))) => expression.dependencies.clone(),
+            _ => Vec::new(),
+        };
+        dependencies.extend_from_slice(&found);
+        self.dependencies.change_node(index, &dependencies);
+        if found.is_empty() {
+            self.dynamic_dependencies.remove(&index);
+        } else {
+            self.dynamic_dependencies.insert(index, found);
         }
+        true
     }
 
-    /// Computes the value of a cell based on its parsed representation.
-    fn compute_cell(&self, cell: &Cell) -> Option<Result<Value, ComputeError>> {
+    fn compute_cell_in(
+        cell: &Cell,
+        variables: &dyn VarContext,
+    ) -> Option<Result<Value, ComputeError>> {
         match cell.parsed_representation {
-            Some(Ok(ParsedCell::Expr(ref expr))) => Some(ASTResolver::resolve(&expr.ast, self)),
-            Some(Ok(ParsedCell::Value(ref value))) => Some(Ok(value.clone())),
-            Some(Err(ref e)) => Some(Err(ComputeError::ParseError(e.0.clone()))),
+            Some(Ok(ParsedCell::Expr(ref expr))) => {
+                Some(ASTResolver::resolve(&expr.ast, variables))
+            }
+            Some(Ok(ParsedCell::Value(ref value))) => Some(value.clone().into_result()),
+            Some(Err(ref e)) => Some(Err(ComputeError::ParseError(e.to_string()))),
             None => None,
         }
     }
 
-    #[allow(dead_code)]
-    pub fn from_file_path(input_path: PathBuf) -> Self {
+    /// Loads a sheet saved with [`SpreadSheet::save_to_file_path`], or any CSV file with a `,` delimiter.
+    pub fn from_file_path(input_path: impl AsRef<Path>) -> io::Result<Self> {
         let mut buffer = String::new();
-        let mut f = File::open(input_path).expect("Cannot open file");
-        f.read_to_string(&mut buffer)
-            .expect("Cannot read file to string");
+        let mut f = File::open(input_path)?;
+        f.read_to_string(&mut buffer)?;
+
+        Self::from_csv(&buffer, &CsvOptions::default())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Saves the raw contents of every cell as CSV so that they can be loaded back with [`SpreadSheet::from_file_path`].
+    pub fn save_to_file_path(&self, output_path: impl AsRef<Path>) -> io::Result<()> {
+        let mut f = File::create(output_path)?;
+        f.write_all(self.to_csv(&CsvOptions::default()).as_bytes())
+    }
 
+    /// Builds a sheet from CSV text, every field being the raw contents of a cell.
+    /// Fields are kept as is, including surrounding whitespace, and empty fields are left as empty cells.
+    pub fn from_csv(text: &str, options: &CsvOptions) -> Result<Self, CsvError> {
         let mut spreadsheet = Self::default();
+        let skipped_rows = usize::from(options.has_header);
 
-        for (y, line) in buffer.lines().enumerate() {
-            for (x, cell) in line.split('|').enumerate() {
-                let cell = cell.trim().to_string();
-                if cell.is_empty() {
-                    continue;
-                }
-                spreadsheet.add_cell_and_compute(Index { x, y }, cell);
+        let mut cells = Vec::new();
+        for (y, record) in csv::read_records(text, options.delimiter)?
+            .into_iter()
+            .skip(skipped_rows)
+            .enumerate()
+        {
+            for (x, field) in record.into_iter().enumerate() {
+                cells.push((Index { x, y }, (!field.is_empty()).then_some(field)));
             }
         }
+        spreadsheet.apply_raws(cells);
 
-        spreadsheet
+        Ok(spreadsheet)
     }
 
+    /// Writes every cell from `A1` to the bottom right most cell as CSV.
+    pub fn to_csv(&self, options: &CsvOptions) -> String {
+        let extent = self.extent();
+        let (width, height) = extent.map_or((0, 0), |idx| (idx.x + 1, idx.y + 1));
+
+        let mut records = Vec::new();
+        if options.has_header {
+            records.push((0..width).map(column_idx_to_string).collect());
+        }
+        for y in 0..height {
+            let record = (0..width)
+                .map(|x| {
+                    let index = Index { x, y };
+                    match options.content {
+                        CsvContent::Raw => self.get_raw(&index).unwrap_or_default().to_owned(),
+                        CsvContent::Computed => match self.get_computed(index) {
+                            Some(Ok(value)) => value.to_string(),
+                            Some(Err(e)) => e.to_string(),
+                            None => String::new(),
+                        },
+                    }
+                })
+                .collect();
+            records.push(record);
+        }
+
+        csv::write_records(&records, options)
+    }
+
+    /// Recomputes every cell that is marked as needing computation, in dependency order.
     pub fn compute_all(&mut self) {
-        let TopologicalSort { sorted, cycles } = self.dependencies.topological_sort();
+        let mut order = self.dependencies.topological_sort();
+
+        // References found on an earlier computation may be what put a cell in a cycle. They are dropped
+        // and found again when the cell is computed, so that a cycle through `indirect` can clear
+        let stale: Vec<Index> = order
+            .cycles
+            .iter()
+            .filter(|idx| self.cells.get(idx).is_some_and(|cell| cell.needs_compute))
+            .filter(|idx| self.dynamic_dependencies.contains_key(idx))
+            .copied()
+            .collect();
+        if !stale.is_empty() {
+            for idx in stale {
+                self.dynamic_dependencies.remove(&idx);
+                let dependencies = match &self.cells[&idx].parsed_representation {
+                    Some(Ok(ParsedCell::Expr(expression))) => expression.dependencies.clone(),
+                    _ => Vec::new(),
+                };
+                self.dependencies.change_node(idx, &dependencies);
+            }
+            order = self.dependencies.topological_sort();
+        }
+        self.compute_in_order(order);
+    }
+
+    fn compute_in_order(&mut self, order: TopologicalSort) {
+        let TopologicalSort { sorted, cycles } = order;
+        let mut found_references = Vec::new();
         for idx in sorted {
             let Some(cell) = self.cells.get(&idx) else {
                 continue;
@@ -86,11 +317,9 @@ impl SpreadSheet {
             if !cell.needs_compute {
                 continue;
             }
-            let computed = self.compute_cell(cell);
-
-            let cell = self.cells.get_mut(&idx).expect("should not fail");
-            cell.computed_value = computed;
-            cell.needs_compute = false
+            if self.compute_cell(idx) {
+                found_references.push(idx);
+            }
         }
 
         for idx in cycles {
@@ -100,12 +329,60 @@ impl SpreadSheet {
             }
             cell.computed_value = Some(Err(ComputeError::Cycle));
         }
+
+        // Cells that found new references are computed again, along with their dependants, in the new order
+        if !found_references.is_empty() {
+            for idx in found_references {
+                self.mark_for_compute(idx);
+            }
+            self.compute_in_order(self.dependencies.topological_sort());
+        }
+    }
+
+    fn mark_for_compute(&mut self, index: Index) {
+        for idx in self
+            .dependencies
+            .get_all_dependants(index)
+            .into_iter()
+            .chain([index])
+        {
+            if let Some(cell) = self.cells.get_mut(&idx) {
+                cell.needs_compute = true;
+            }
+        }
     }
 
+    /// Returns the computed value of a cell, or `None` if the cell is empty.
     pub fn get_computed(&self, index: Index) -> Option<Result<Value, ComputeError>> {
         self.cells.get(&index)?.computed_value.clone()
     }
     
+    /// Computes a cell against another context, letting a workbook resolve references to other sheets.
+    pub(crate) fn compute_in(
+        &self,
+        index: Index,
+        variables: &dyn VarContext,
+    ) -> Option<Result<Value, ComputeError>> {
+        Self::compute_cell_in(self.cells.get(&index)?, variables)
+    }
+
+    /// Overwrites the computed value of a cell, used by workbooks after computing it with [`SpreadSheet::compute_in`].
+    pub(crate) fn set_computed(
+        &mut self,
+        index: Index,
+        computed: Option<Result<Value, ComputeError>>,
+    ) {
+        if let Some(cell) = self.cells.get_mut(&index) {
+            cell.computed_value = computed;
+            cell.needs_compute = false;
+        }
+    }
+
+    pub(crate) fn dependencies(&self) -> &DependancyGraph {
+        &self.dependencies
+    }
+
+    /// Returns the error of a cell if its computation failed.
     pub fn get_error(&self, index: Index) -> Option<ComputeError> {
         match &self.cells.get(&index)?.computed_value {
             Some(val) => match val {
@@ -117,17 +394,42 @@ impl SpreadSheet {
         
     }
 
+    /// Returns why the raw contents of a cell could not be parsed, pointing at the offending characters.
+    pub fn get_parse_error(&self, index: Index) -> Option<&ParseError> {
+        match &self.cells.get(&index)?.parsed_representation {
+            Some(Err(error)) => Some(error),
+            _ => None,
+        }
+    }
+
+    /// Records the change of a cell's raw contents to `after` in the undo history.
+    fn record_change(&mut self, index: Index, after: Option<&str>) {
+        let before = self.get_raw(&index).map(str::to_owned);
+        self.history.record(CellChange {
+            index,
+            before,
+            after: after.map(str::to_owned),
+        });
+    }
+
+    /// Adds a new cell and recomputes every cell that depends on it.
     pub fn add_cell_and_compute(&mut self, index: Index, raw: String) {
+        self.record_change(index, Some(&raw));
+        self.insert_cell(index, raw);
+    }
+
+    fn insert_cell(&mut self, index: Index, raw: String) {
         let mut cell = Cell::from_raw(raw);
-        CellParser::parse_cell(&mut cell);
+        CellParser::parse_cell(&mut cell, self.number_locale);
 
         self.add_dependencies(index, &cell);
-
-        cell.computed_value = self.compute_cell(&cell);
-        cell.needs_compute = false;
         self.cells.insert(index, cell);
+        // A cell that found new references is computed again in case it forms a cycle with them
+        let mut need_compute = self.compute_cell(index);
+        if need_compute {
+            self.mark_for_compute(index);
+        }
 
-        let mut need_compute = false;
         for dep in self.dependencies.get_all_dependants(index) {
             if let Some(cell) = self.cells.get_mut(&dep) {
                 cell.needs_compute = true;
@@ -139,7 +441,13 @@ impl SpreadSheet {
         }
     }
 
+    /// Removes a cell and recomputes every cell that depended on it.
     pub fn remove_cell(&mut self, index: Index) {
+        self.record_change(index, None);
+        self.delete_cell(index);
+    }
+
+    fn delete_cell(&mut self, index: Index) {
         let mut need_compute = false;
         for dep in self.dependencies.get_all_dependants(index) {
             if let Some(cell) = self.cells.get_mut(&dep) {
@@ -149,6 +457,7 @@ impl SpreadSheet {
         }
 
         self.dependencies.remove_node(index);
+        self.dynamic_dependencies.remove(&index);
         self.cells.remove(&index);
 
         if need_compute {
@@ -156,11 +465,15 @@ impl SpreadSheet {
         }
     }
 
+    /// Replaces the contents of an existing cell and recomputes every cell that depends on it.
     pub fn mutate_cell(&mut self, index: Index, new_raw: String) {
+        self.record_change(index, Some(&new_raw));
+        self.replace_cell(index, new_raw);
+    }
+
+    fn replace_cell(&mut self, index: Index, new_raw: String) {
         let mut new_cell = Cell::from_raw(new_raw);
-        CellParser::parse_cell(&mut new_cell);
-        new_cell.computed_value = self.compute_cell(&new_cell);
-        new_cell.needs_compute = false;
+        CellParser::parse_cell(&mut new_cell, self.number_locale);
 
         self.update_dependencies(index, &new_cell);
 
@@ -170,7 +483,10 @@ impl SpreadSheet {
             .expect("Expected valid index for mutate cell");
         *cell = new_cell;
 
-        let mut need_compute = false;
+        let mut need_compute = self.compute_cell(index);
+        if need_compute {
+            self.mark_for_compute(index);
+        }
         for dep in self.dependencies.get_all_dependants(index) {
             if let Some(cell) = self.cells.get_mut(&dep) {
                 cell.needs_compute = true;
@@ -182,13 +498,308 @@ impl SpreadSheet {
         }
     }
 
+    /// Sets the raw contents of a cell, adding, mutating or removing it as needed.
+    /// Surrounding whitespace is trimmed and an empty string clears the cell.
+    pub fn set_raw(&mut self, index: Index, raw: String) {
+        let new_content = normalize_raw(&raw);
+        self.record_change(index, new_content.as_deref());
+        self.apply_raw(index, new_content);
+    }
+
+    /// Sets the raw contents of many cells as a single undoable step, trimming them as
+    /// [`SpreadSheet::set_raw`] does. The sheet is computed once every cell is set rather than after each of them.
+    pub fn set_raws(&mut self, changes: impl IntoIterator<Item = (Index, String)>) {
+        let changes: Vec<(Index, Option<String>)> = changes
+            .into_iter()
+            .map(|(index, raw)| (index, normalize_raw(&raw)))
+            .collect();
+
+        self.batch(|sheet| {
+            for (index, raw) in &changes {
+                sheet.record_change(*index, raw.as_deref());
+            }
+        });
+        self.apply_raws(changes);
+    }
+
+    /// Sets the raw contents of many cells without recording the changes in the history. The dependency graph
+    /// is rebuilt and the sheet computed once, rather than for every cell as [`SpreadSheet::apply_raw`] does.
+    fn apply_raws(&mut self, changes: impl IntoIterator<Item = (Index, Option<String>)>) {
+        let mut changed = Vec::new();
+        for (index, raw) in changes {
+            if self.get_raw(&index) == raw.as_deref() {
+                continue;
+            }
+            changed.push(index);
+            self.dynamic_dependencies.remove(&index);
+            match raw {
+                Some(raw) => {
+                    let mut cell = Cell::from_raw(raw);
+                    CellParser::parse_cell(&mut cell, self.number_locale);
+                    self.cells.insert(index, cell);
+                }
+                None => {
+                    self.cells.remove(&index);
+                }
+            }
+        }
+        if changed.is_empty() {
+            return;
+        }
+
+        // References found while computing the cells left as they were are kept
+        self.dependencies = DependancyGraph::default();
+        for (index, cell) in &self.cells {
+            let mut dependencies = match &cell.parsed_representation {
+                Some(Ok(ParsedCell::Expr(expression))) => expression.dependencies.clone(),
+                _ => Vec::new(),
+            };
+            if let Some(found) = self.dynamic_dependencies.get(index) {
+                dependencies.extend_from_slice(found);
+            }
+            self.dependencies.add_node(*index, &dependencies);
+        }
+
+        let mut to_process = changed;
+        let mut marked = HashSet::new();
+        while let Some(index) = to_process.pop() {
+            if marked.insert(index) {
+                to_process.extend(self.dependencies.direct_dependants(index));
+            }
+        }
+        for index in marked {
+            if let Some(cell) = self.cells.get_mut(&index) {
+                cell.needs_compute = true;
+            }
+        }
+        self.compute_all();
+    }
+
+    /// Sets the raw contents of a cell without recording the change in the history.
+    fn apply_raw(&mut self, index: Index, raw: Option<String>) {
+        let previous_content = self.get_raw(&index).unwrap_or_default();
+
+        match (previous_content, raw) {
+            (_, None) if previous_content.is_empty() => (),
+            (_, None) => self.delete_cell(index),
+            (prev, Some(new)) if prev == new => (),
+            ("", Some(new)) => self.insert_cell(index, new),
+            (_, Some(new)) => self.replace_cell(index, new),
+        }
+    }
+
+    /// Groups every mutation made inside `f` into a single undoable step.
+    pub fn batch<F: FnOnce(&mut Self)>(&mut self, f: F) {
+        self.history.start_batch();
+        f(self);
+        self.history.end_batch();
+    }
+
+    /// Reverts the last recorded mutation. Returns false if there was nothing to undo.
+    pub fn undo(&mut self) -> bool {
+        let Some(transaction) = self.history.pop_undo() else {
+            return false;
+        };
+        self.apply_raws(
+            transaction
+                .iter()
+                .rev()
+                .map(|change| (change.index, change.before.clone())),
+        );
+        self.history.push_redo(transaction);
+        true
+    }
+
+    /// Re-applies the last undone mutation. Returns false if there was nothing to redo.
+    pub fn redo(&mut self) -> bool {
+        let Some(transaction) = self.history.pop_redo() else {
+            return false;
+        };
+        self.apply_raws(
+            transaction
+                .iter()
+                .map(|change| (change.index, change.after.clone())),
+        );
+        self.history.push_undo(transaction);
+        true
+    }
+
+    // The number of mutations that can be undone, for the workbook to group them into its own steps
+    pub(crate) fn undo_len(&self) -> usize {
+        self.history.undo_len()
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.history.can_undo()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.history.can_redo()
+    }
+
+    /// Copies the raw contents of every cell in the range.
+    pub fn copy(&self, range: CellRange) -> CopiedCells {
+        let rows = (range.start.y..=range.end.y)
+            .map(|y| {
+                (range.start.x..=range.end.x)
+                    .map(|x| self.get_raw(&Index { x, y }).unwrap_or_default().to_owned())
+                    .collect()
+            })
+            .collect();
+
+        CopiedCells {
+            origin: Some(range.start),
+            rows,
+        }
+    }
+
+    /// Pastes a copied block with its top left corner at `target` as a single undoable step.
+    /// Relative references in formulas are shifted by the distance between the copied block and `target`.
+    pub fn paste(&mut self, target: Index, copied: &CopiedCells) {
+        let (columns, rows) = match copied.origin {
+            Some(origin) => (
+                target.x as isize - origin.x as isize,
+                target.y as isize - origin.y as isize,
+            ),
+            None => (0, 0),
+        };
+
+        self.batch(|sheet| {
+            for (dy, row) in copied.rows.iter().enumerate() {
+                for (dx, raw) in row.iter().enumerate() {
+                    let index = Index {
+                        x: target.x + dx,
+                        y: target.y + dy,
+                    };
+                    sheet.set_raw(index, CellParser::move_formula(raw, columns, rows));
+                }
+            }
+        });
+    }
+
+    /// Empties every cell in the range as a single undoable step.
+    pub fn clear_range(&mut self, range: CellRange) {
+        let indices: Vec<Index> = self
+            .cells
+            .keys()
+            .filter(|index| range.contains(**index))
+            .copied()
+            .collect();
+
+        self.batch(|sheet| {
+            for index in indices {
+                sheet.remove_cell(index);
+            }
+        });
+    }
+
+    /// Splits the text of every cell in the first column of the range at each `delimiter`, as the `split`
+    /// function does, as a single undoable step. The first part stays in the cell and the others are written
+    /// into the cells to its right. Formulas are left as they are.
+    pub fn split_to_columns(&mut self, range: CellRange, delimiter: &str) {
+        if delimiter.is_empty() {
+            return;
+        }
+
+        let mut changes = Vec::new();
+        for y in range.start.y..=range.end.y {
+            let index = Index {
+                x: range.start.x,
+                y,
+            };
+            let Some(raw) = self.get_raw(&index).filter(|raw| !raw.starts_with('=')) else {
+                continue;
+            };
+            for (dx, part) in raw.split(delimiter).enumerate() {
+                changes.push((Index { x: index.x + dx, y }, part.to_string()));
+            }
+        }
+        self.set_raws(changes);
+    }
+
+    /// Inserts `count` empty rows before `row`, moving the cells below down.
+    pub fn insert_rows(&mut self, row: usize, count: usize) {
+        self.apply_structural_change(StructuralChange::Insert {
+            axis: Axis::Row,
+            at: row,
+            count,
+        });
+    }
+
+    /// Deletes `count` rows starting at `row`, moving the cells below up.
+    pub fn delete_rows(&mut self, row: usize, count: usize) {
+        self.apply_structural_change(StructuralChange::Delete {
+            axis: Axis::Row,
+            at: row,
+            count,
+        });
+    }
+
+    /// Inserts `count` empty columns before `column`, moving the cells to the right along.
+    pub fn insert_columns(&mut self, column: usize, count: usize) {
+        self.apply_structural_change(StructuralChange::Insert {
+            axis: Axis::Column,
+            at: column,
+            count,
+        });
+    }
+
+    /// Deletes `count` columns starting at `column`, moving the cells to the right back.
+    pub fn delete_columns(&mut self, column: usize, count: usize) {
+        self.apply_structural_change(StructuralChange::Delete {
+            axis: Axis::Column,
+            at: column,
+            count,
+        });
+    }
+
+    /// Moves every cell and rewrites every formula as a single undoable step.
+    /// References to deleted cells turn into `#REF!` errors.
+    fn apply_structural_change(&mut self, change: StructuralChange) {
+        let moved: HashMap<Index, String> = self
+            .cells
+            .iter()
+            .filter_map(|(index, cell)| {
+                let raw =
+                    CellParser::apply_structural_change(&cell.raw_representation, &change, None);
+                Some((change.move_index(*index)?, raw))
+            })
+            .collect();
+
+        let mut indices: Vec<Index> = self.cells.keys().chain(moved.keys()).copied().collect();
+        indices.sort();
+        indices.dedup();
+
+        let changes: Vec<(Index, String)> = indices
+            .into_iter()
+            .map(|index| (index, moved.get(&index).cloned().unwrap_or_default()))
+            .collect();
+        self.set_raws(changes);
+    }
+
+    /// Returns the raw contents of a cell as it was typed in.
     pub fn get_raw(&self, index: &Index) -> Option<&str> {
         Some(&self.cells.get(index)?.raw_representation)
     }
+
+    /// Returns the bottom right corner of the smallest block starting at `A1` that holds every cell.
+    pub fn extent(&self) -> Option<Index> {
+        self.cells.keys().copied().reduce(|a, b| Index {
+            x: a.x.max(b.x),
+            y: a.y.max(b.y),
+        })
+    }
+
+    /// Iterates over every non-empty cell in no particular order.
+    pub fn iter_cells(&self) -> impl Iterator<Item = (&Index, &Cell)> {
+        self.cells.iter()
+    }
 }
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::common_functions::{computed_to_text, get_cell_idx};
+    use parser::ast_resolver::function_registry::ArgumentType;
 
     #[test]
     fn test_empty_ref() {
@@ -203,6 +814,419 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn test_error_values() {
+        let mut spreadsheet = SpreadSheet::default();
+        let cells = [
+            ("A1", "=1 / 0"),
+            ("A2", "=A1 + 1"),
+            ("A3", "=sum(A1:A2, 5)"),
+            ("A4", "=iferror(A2, 0)"),
+            ("A5", "=iserror(A1)"),
+            ("A6", "=isna(na())"),
+            ("A7", "=errortype(A1)"),
+            ("A8", "=errortype(Missing)"),
+            ("A9", "#N/A"),
+            ("A10", "=iferror(5, 0)"),
+            ("A11", "=isna(#N/A) && !iserror(2)"),
+            ("A12", "=errortype(A9)"),
+        ];
+        for (name, raw) in cells {
+            spreadsheet.add_cell_and_compute(get_cell_idx(name).unwrap(), raw.to_string());
+        }
+        let computed = |name| spreadsheet.get_computed(get_cell_idx(name).unwrap());
+
+        assert_eq!(computed("A1"), Some(Err(ComputeError::DivisionByZero)));
+        assert_eq!(computed("A2"), Some(Err(ComputeError::DivisionByZero)));
+        assert_eq!(computed("A3"), Some(Err(ComputeError::DivisionByZero)));
+        assert_eq!(computed("A4"), Some(Ok(Value::Number(0.0))));
+        assert_eq!(computed("A5"), Some(Ok(Value::Bool(true))));
+        assert_eq!(computed("A6"), Some(Ok(Value::Bool(true))));
+        assert_eq!(computed("A7"), Some(Ok(Value::Number(2.0))));
+        assert_eq!(computed("A8"), Some(Ok(Value::Number(5.0))));
+        assert!(matches!(
+            computed("A9"),
+            Some(Err(ComputeError::NotAvailable(_)))
+        ));
+        assert_eq!(computed("A10"), Some(Ok(Value::Number(5.0))));
+        assert_eq!(computed("A11"), Some(Ok(Value::Bool(true))));
+        assert_eq!(computed("A12"), Some(Ok(Value::Number(7.0))));
+        assert_eq!(computed_to_text(computed("A2")), "#DIV/0!");
+    }
+
+    #[test]
+    fn test_short_circuit() {
+        let mut spreadsheet = SpreadSheet::default();
+        // B1 is empty, so every formula computing it fails
+        let cells = [
+            ("A1", "0"),
+            ("A2", "=if(A1 == 0, 0, 10 / A1)"),
+            ("A3", "=if(TRUE, 1, B1)"),
+            ("A4", "=ifs(A1 > 0, B1, A1 == 0, \"zero\", TRUE, B1)"),
+            ("A5", "=switch(A1, 1, B1, 0, \"zero\", B1)"),
+            ("A6", "=switch(A1 + 5, 1, B1, \"other\")"),
+            ("A7", "=iferror(7, B1)"),
+            ("A8", "=and(FALSE, B1) || or(TRUE, B1)"),
+            ("A9", "=FALSE && B1 == 1"),
+            ("A10", "=TRUE || 1 / 0 > 1"),
+            ("A11", "=if(FALSE, 1, B1)"),
+            ("A12", "=ifs(FALSE, 1)"),
+            ("A13", "=TRUE && B1"),
+        ];
+        for (name, raw) in cells {
+            spreadsheet.add_cell_and_compute(get_cell_idx(name).unwrap(), raw.to_string());
+        }
+        let computed = |name| spreadsheet.get_computed(get_cell_idx(name).unwrap());
+
+        assert_eq!(computed("A2"), Some(Ok(Value::Number(0.0))));
+        assert_eq!(computed("A3"), Some(Ok(Value::Number(1.0))));
+        assert_eq!(computed("A4"), Some(Ok(Value::Text("zero".to_string()))));
+        assert_eq!(computed("A5"), Some(Ok(Value::Text("zero".to_string()))));
+        assert_eq!(computed("A6"), Some(Ok(Value::Text("other".to_string()))));
+        assert_eq!(computed("A7"), Some(Ok(Value::Number(7.0))));
+        assert_eq!(computed("A8"), Some(Ok(Value::Bool(true))));
+        assert_eq!(computed("A9"), Some(Ok(Value::Bool(false))));
+        assert_eq!(computed("A10"), Some(Ok(Value::Bool(true))));
+
+        // Errors in the branches that are taken still show
+        assert!(matches!(
+            computed("A11"),
+            Some(Err(ComputeError::UnfindableReference(_)))
+        ));
+        assert!(matches!(
+            computed("A12"),
+            Some(Err(ComputeError::NotAvailable(_)))
+        ));
+        assert!(matches!(
+            computed("A13"),
+            Some(Err(ComputeError::UnfindableReference(_)))
+        ));
+    }
+
+    #[test]
+    fn test_number_literals() {
+        let mut spreadsheet = SpreadSheet::default();
+        let cells = [
+            ("A1", "1e3"),
+            ("A2", ".5"),
+            ("A3", "=A1 * 1.5E-3 + .5"),
+            ("A4", "1,000.5"),
+            ("A5", "=1,000 + sum(A1,2)"),
+            ("A6", "1.2.3"),
+        ];
+        for (name, raw) in cells {
+            spreadsheet.add_cell_and_compute(get_cell_idx(name).unwrap(), raw.to_string());
+        }
+        let computed = |name| spreadsheet.get_computed(get_cell_idx(name).unwrap());
+        assert_eq!(computed("A1"), Some(Ok(Value::Number(1000.0))));
+        assert_eq!(computed("A2"), Some(Ok(Value::Number(0.5))));
+        assert_eq!(computed("A3"), Some(Ok(Value::Number(2.0))));
+        assert_eq!(computed("A4"), Some(Ok(Value::Number(1000.5))));
+        assert_eq!(computed("A5"), Some(Ok(Value::Number(2002.0))));
+        assert!(matches!(
+            computed("A6"),
+            Some(Err(ComputeError::ParseError(_)))
+        ));
+
+        spreadsheet.set_number_locale(NumberLocale::DecimalComma);
+        spreadsheet.add_cell_and_compute(get_cell_idx("A4").unwrap(), "1.000,5".to_string());
+        spreadsheet.add_cell_and_compute(get_cell_idx("A5").unwrap(), "=sum(A4; 0,5)".to_string());
+        // Numbers in criteria are read the same way
+        spreadsheet.add_cell_and_compute(
+            get_cell_idx("A6").unwrap(),
+            "=countif(A4:A5; \">1.000,5\")".to_string(),
+        );
+        let computed = |name| spreadsheet.get_computed(get_cell_idx(name).unwrap());
+        assert_eq!(computed("A5"), Some(Ok(Value::Number(1001.0))));
+        assert_eq!(computed("A6"), Some(Ok(Value::Number(1.0))));
+        // Formulas written for the other locale no longer parse
+        assert!(matches!(
+            computed("A3"),
+            Some(Err(ComputeError::ParseError(_)))
+        ));
+    }
+
+    #[test]
+    fn test_register_function() {
+        let mut spreadsheet = SpreadSheet::default();
+        let idx = |name| get_cell_idx(name).unwrap();
+        spreadsheet.add_cell_and_compute(idx("A1"), "=double(4)".to_string());
+        assert_eq!(
+            spreadsheet.get_computed(idx("A1")),
+            Some(Err(ComputeError::UnknownFunction("double".to_string())))
+        );
+
+        // Cells calling the function are computed again once it exists
+        spreadsheet
+            .register_function(
+                Function::new("double", "Doubles a number.", |args| {
+                    Ok(Value::Number(args[0].as_number().unwrap_or_default() * 2.0))
+                })
+                .required(&[ArgumentType::Number]),
+            )
+            .unwrap();
+        assert_eq!(
+            spreadsheet.get_computed(idx("A1")),
+            Some(Ok(Value::Number(8.0)))
+        );
+
+        // Functions formulas could not call are refused
+        assert_eq!(
+            spreadsheet.register_function(Function::new("Triple", "", |args| Ok(args[0].clone()))),
+            Err(RegistrationError::InvalidName("Triple".to_string()))
+        );
+
+        spreadsheet.add_cell_and_compute(idx("A2"), "=double(\"four\")".to_string());
+        spreadsheet.add_cell_and_compute(idx("A3"), "=double(1, 2)".to_string());
+        spreadsheet.add_cell_and_compute(idx("A4"), "=sum(double(A1), 1)".to_string());
+        assert!(matches!(
+            spreadsheet.get_computed(idx("A2")),
+            Some(Err(ComputeError::InvalidArgument(_)))
+        ));
+        assert!(matches!(
+            spreadsheet.get_computed(idx("A3")),
+            Some(Err(ComputeError::InvalidArgument(_)))
+        ));
+        assert_eq!(
+            spreadsheet.get_computed(idx("A4")),
+            Some(Ok(Value::Number(17.0)))
+        );
+    }
+
+    #[test]
+    fn test_range_arguments() {
+        let mut spreadsheet = SpreadSheet::default();
+        let idx = |name| get_cell_idx(name).unwrap();
+        for (name, raw) in [
+            ("A1", "1"),
+            ("A2", "2"),
+            ("A4", "4"),
+            ("B1", "2"),
+            ("B2", "4"),
+            ("B4", "8"),
+        ] {
+            spreadsheet.add_cell_and_compute(idx(name), raw.to_string());
+        }
+        spreadsheet.add_cell_and_compute(idx("C1"), "=countblank(A1:B4)".to_string());
+        spreadsheet.add_cell_and_compute(idx("C2"), "=slope(B1:B4, A1:A4)".to_string());
+        spreadsheet.add_cell_and_compute(idx("C3"), "=rank(2, A1:A4)".to_string());
+        assert_eq!(
+            spreadsheet.get_computed(idx("C1")),
+            Some(Ok(Value::Number(2.0)))
+        );
+        assert_eq!(
+            spreadsheet.get_computed(idx("C2")),
+            Some(Ok(Value::Number(2.0)))
+        );
+        assert_eq!(
+            spreadsheet.get_computed(idx("C3")),
+            Some(Ok(Value::Number(2.0)))
+        );
+
+        // Cells in the ranges are dependencies like in any other range
+        spreadsheet.add_cell_and_compute(idx("A3"), "3".to_string());
+        assert_eq!(
+            spreadsheet.get_computed(idx("C1")),
+            Some(Ok(Value::Number(1.0)))
+        );
+    }
+
+    #[test]
+    fn test_dates() {
+        let mut spreadsheet = SpreadSheet::default();
+        let idx = |name| get_cell_idx(name).unwrap();
+        spreadsheet.add_cell_and_compute(idx("A1"), "2024-03-15".to_string());
+        spreadsheet.add_cell_and_compute(idx("A2"), "2024-02-15".to_string());
+        spreadsheet.add_cell_and_compute(idx("B1"), "=A1 - A2".to_string());
+        spreadsheet.add_cell_and_compute(idx("B2"), "=A2 + 31".to_string());
+        spreadsheet.add_cell_and_compute(idx("B3"), "=if(A1 > A2, month(A1), 0)".to_string());
+        assert_eq!(
+            spreadsheet.get_computed(idx("A1")),
+            Some(Ok(Value::DateTime(45366.0)))
+        );
+        assert_eq!(
+            spreadsheet.get_computed(idx("B1")),
+            Some(Ok(Value::Number(29.0)))
+        );
+        assert_eq!(
+            spreadsheet
+                .get_computed(idx("B2"))
+                .map(|value| value.unwrap().to_string()),
+            Some("2024-03-17".to_string())
+        );
+        assert_eq!(
+            spreadsheet.get_computed(idx("B3")),
+            Some(Ok(Value::Number(3.0)))
+        );
+
+        spreadsheet.add_cell_and_compute(idx("B4"), "=A1 + 1e19".to_string());
+        assert!(matches!(
+            spreadsheet.get_computed(idx("B4")),
+            Some(Err(ComputeError::NumberError(_)))
+        ));
+    }
+
+    #[test]
+    fn test_dates_as_numbers() {
+        let mut spreadsheet = SpreadSheet::default();
+        let idx = |name| get_cell_idx(name).unwrap();
+        spreadsheet.add_cell_and_compute(idx("A1"), "2024-03-15".to_string());
+        spreadsheet.add_cell_and_compute(idx("A2"), "2024-02-15".to_string());
+        spreadsheet.add_cell_and_compute(idx("A3"), "1".to_string());
+        let cases = [
+            ("=max(A1:A3)", Value::Number(45366.0)),
+            ("=sum(A1:A3)", Value::Number(90704.0)),
+            ("=round(A1, -1)", Value::Number(45370.0)),
+            ("=A1 > 45000", Value::Bool(true)),
+            ("=45366 >= A1", Value::Bool(true)),
+            ("=A1 == 45366", Value::Bool(true)),
+            ("=A1 != 45366", Value::Bool(false)),
+            ("=countif(A1:A3, \">45000\")", Value::Number(2.0)),
+            ("=sumif(A1:A3, \"<2024-03-01\")", Value::Number(45338.0)),
+        ];
+        for (formula, expected) in cases {
+            spreadsheet.add_cell_and_compute(idx("B1"), formula.to_string());
+            assert_eq!(
+                spreadsheet.get_computed(idx("B1")),
+                Some(Ok(expected)),
+                "{formula}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_offset_limits() {
+        let mut spreadsheet = SpreadSheet::default();
+        let idx = |name| get_cell_idx(name).unwrap();
+        spreadsheet.add_cell_and_compute(idx("A1"), "1".to_string());
+        spreadsheet
+            .add_cell_and_compute(idx("A201"), "=sum(offset(A1, 0, 0, 101, 101))".to_string());
+        assert_eq!(
+            spreadsheet.get_computed(idx("A201")),
+            Some(Ok(Value::Number(1.0)))
+        );
+        for (name, raw) in [
+            ("A202", "=sum(offset(A1, 0, 0, 102, 1))"),
+            ("A203", "=sum(offset(A1, 0, 0, 30000, 30000))"),
+            ("A204", "=sum(offset(A1, 0, 0, 1, 1e20))"),
+            ("A205", "=offset(A1, 1e20, 1e20)"),
+        ] {
+            spreadsheet.add_cell_and_compute(idx(name), raw.to_string());
+            assert!(
+                matches!(
+                    spreadsheet.get_computed(idx(name)),
+                    Some(Err(ComputeError::UnfindableReference(_)))
+                ),
+                "{raw}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_reference_functions() {
+        let mut spreadsheet = SpreadSheet::default();
+        let idx = |name| get_cell_idx(name).unwrap();
+        for (name, raw) in [("A1", "1"), ("A2", "2"), ("A3", "3"), ("B1", "A2")] {
+            spreadsheet.add_cell_and_compute(idx(name), raw.to_string());
+        }
+        spreadsheet.add_cell_and_compute(idx("C1"), "=indirect(B1) * 10".to_string());
+        spreadsheet.add_cell_and_compute(idx("C2"), "=sum(offset(A1, 1, 0, 2))".to_string());
+        spreadsheet.add_cell_and_compute(idx("C3"), "=sum(choose(2, A1, A2:A3))".to_string());
+        spreadsheet.add_cell_and_compute(idx("C4"), "=choose(1, 5, 1 / 0)".to_string());
+        spreadsheet.add_cell_and_compute(idx("C5"), "=offset(A1, -1, 0)".to_string());
+        assert_eq!(
+            spreadsheet.get_computed(idx("C1")),
+            Some(Ok(Value::Number(20.0)))
+        );
+        assert_eq!(
+            spreadsheet.get_computed(idx("C2")),
+            Some(Ok(Value::Number(5.0)))
+        );
+        assert_eq!(
+            spreadsheet.get_computed(idx("C3")),
+            Some(Ok(Value::Number(5.0)))
+        );
+        assert_eq!(
+            spreadsheet.get_computed(idx("C4")),
+            Some(Ok(Value::Number(5.0)))
+        );
+        assert!(matches!(
+            spreadsheet.get_computed(idx("C5")),
+            Some(Err(ComputeError::UnfindableReference(_)))
+        ));
+
+        // The cells found while computing are dependencies, updated when the reference changes
+        spreadsheet.mutate_cell(idx("A2"), "4".to_string());
+        assert_eq!(
+            spreadsheet.get_computed(idx("C1")),
+            Some(Ok(Value::Number(40.0)))
+        );
+        spreadsheet.mutate_cell(idx("B1"), "A3".to_string());
+        assert_eq!(
+            spreadsheet.get_computed(idx("C1")),
+            Some(Ok(Value::Number(30.0)))
+        );
+        spreadsheet.mutate_cell(idx("A2"), "5".to_string());
+        spreadsheet.mutate_cell(idx("A3"), "6".to_string());
+        assert_eq!(
+            spreadsheet.get_computed(idx("C1")),
+            Some(Ok(Value::Number(60.0)))
+        );
+        assert_eq!(
+            spreadsheet.get_computed(idx("C2")),
+            Some(Ok(Value::Number(11.0)))
+        );
+
+        // References to cells computed later are read once those are computed
+        spreadsheet.add_cell_and_compute(idx("D1"), "=indirect(\"E1\")".to_string());
+        spreadsheet.add_cell_and_compute(idx("E1"), "=A1 + 1".to_string());
+        assert_eq!(
+            spreadsheet.get_computed(idx("D1")),
+            Some(Ok(Value::Number(2.0)))
+        );
+        spreadsheet.mutate_cell(idx("B1"), "C1".to_string());
+        assert_eq!(
+            spreadsheet.get_computed(idx("C1")),
+            Some(Err(ComputeError::Cycle))
+        );
+    }
+
+    #[test]
+    fn test_indirect_cycle_clears() {
+        let mut spreadsheet = SpreadSheet::default();
+        let idx = |name| get_cell_idx(name).unwrap();
+        spreadsheet.set_raw(idx("A1"), "3".to_string());
+        spreadsheet.set_raw(idx("D1"), "=indirect(C1)".to_string());
+        spreadsheet.set_raw(idx("C1"), "D1".to_string());
+        assert_eq!(
+            spreadsheet.get_computed(idx("D1")),
+            Some(Err(ComputeError::Cycle))
+        );
+
+        spreadsheet.set_raw(idx("C1"), "A1".to_string());
+        assert_eq!(
+            spreadsheet.get_computed(idx("D1")),
+            Some(Ok(Value::Number(3.0)))
+        );
+
+        assert!(spreadsheet.undo());
+        assert_eq!(
+            spreadsheet.get_computed(idx("D1")),
+            Some(Err(ComputeError::Cycle))
+        );
+        assert!(spreadsheet.undo());
+        assert!(matches!(
+            spreadsheet.get_computed(idx("D1")),
+            Some(Err(ComputeError::UnfindableReference(_)))
+        ));
+        assert!(spreadsheet.redo());
+        assert!(spreadsheet.redo());
+        assert_eq!(
+            spreadsheet.get_computed(idx("D1")),
+            Some(Ok(Value::Number(3.0)))
+        );
+    }
+
     #[test]
     fn test_circular() {
         let mut spreadsheet = SpreadSheet::default();
@@ -337,6 +1361,502 @@ mod tests {
         assert!(matches!(computed, Some(Ok(Value::Number(38.0)))));
     }
 
+    #[test]
+    fn test_dotted_function_calls() {
+        let mut spreadsheet = SpreadSheet::default();
+        let idx = |name| get_cell_idx(name).unwrap();
+        for (y, (a, b)) in [("2", "1"), ("4", "2"), ("4", "3"), ("6", "4")]
+            .into_iter()
+            .enumerate()
+        {
+            spreadsheet.add_cell_and_compute(Index { x: 0, y }, a.to_string());
+            spreadsheet.add_cell_and_compute(Index { x: 1, y }, b.to_string());
+        }
+        let cases = [
+            ("=var.p(A1:A4)", 2.0),
+            ("=stdev.p(A1:A4)", 2f64.sqrt()),
+            ("=covariance.p(A1:A4, B1:B4)", 1.5),
+            ("=var.p (A1:A4) + var.p(B1:B4)", 3.25),
+        ];
+        for (formula, expected) in cases {
+            spreadsheet.add_cell_and_compute(idx("C1"), formula.to_string());
+            assert_eq!(
+                spreadsheet.get_computed(idx("C1")),
+                Some(Ok(Value::Number(expected))),
+                "{formula}"
+            );
+        }
+
+        spreadsheet.set_number_locale(NumberLocale::DecimalComma);
+        spreadsheet.add_cell_and_compute(idx("C1"), "=covariance.p(A1:A4; B1:B4)".to_string());
+        assert_eq!(
+            spreadsheet.get_computed(idx("C1")),
+            Some(Ok(Value::Number(1.5)))
+        );
+    }
+
+    #[test]
+    fn test_set_raw() {
+        let mut spreadsheet = SpreadSheet::default();
+        let a1 = Index { x: 0, y: 0 };
+        let a2 = Index { x: 0, y: 1 };
+
+        spreadsheet.set_raw(a1, "2".to_string());
+        spreadsheet.set_raw(a2, "  =A1 * 5 ".to_string());
+        assert_eq!(spreadsheet.get_raw(&a2), Some("=A1 * 5"));
+        assert_eq!(spreadsheet.get_computed(a2), Some(Ok(Value::Number(10.0))));
+
+        spreadsheet.set_raw(a1, "3".to_string());
+        assert_eq!(spreadsheet.get_computed(a2), Some(Ok(Value::Number(15.0))));
+
+        spreadsheet.set_raw(a1, String::new());
+        assert_eq!(spreadsheet.get_raw(&a1), None);
+        assert_eq!(spreadsheet.iter_cells().count(), 1);
+    }
+
+    #[test]
+    fn test_undo_redo() {
+        let mut spreadsheet = SpreadSheet::default();
+        let a1 = Index { x: 0, y: 0 };
+        let a2 = Index { x: 0, y: 1 };
+
+        spreadsheet.set_raw(a1, "1".to_string());
+        spreadsheet.set_raw(a2, "=A1 + 1".to_string());
+        spreadsheet.set_raw(a1, "5".to_string());
+        assert_eq!(spreadsheet.get_computed(a2), Some(Ok(Value::Number(6.0))));
+
+        assert!(spreadsheet.undo());
+        assert_eq!(spreadsheet.get_raw(&a1), Some("1"));
+        assert_eq!(spreadsheet.get_computed(a2), Some(Ok(Value::Number(2.0))));
+
+        assert!(spreadsheet.undo());
+        assert_eq!(spreadsheet.get_raw(&a2), None);
+
+        assert!(spreadsheet.redo());
+        assert_eq!(spreadsheet.get_computed(a2), Some(Ok(Value::Number(2.0))));
+
+        // A new mutation discards everything that could be redone
+        spreadsheet.remove_cell(a1);
+        assert!(!spreadsheet.can_redo());
+        assert!(matches!(
+            spreadsheet.get_computed(a2),
+            Some(Err(ComputeError::UnfindableReference(_)))
+        ));
+
+        assert!(spreadsheet.undo());
+        assert!(spreadsheet.undo());
+        assert!(spreadsheet.undo());
+        assert!(!spreadsheet.undo());
+        assert_eq!(spreadsheet.iter_cells().count(), 0);
+    }
+
+    #[test]
+    fn test_undo_batch() {
+        let mut spreadsheet = SpreadSheet::default();
+        let a1 = Index { x: 0, y: 0 };
+        let b1 = Index { x: 1, y: 0 };
+        let c1 = Index { x: 2, y: 0 };
+
+        spreadsheet.set_raw(a1, "1".to_string());
+        spreadsheet.batch(|sheet| {
+            sheet.set_raw(a1, "2".to_string());
+            sheet.set_raw(b1, "3".to_string());
+            sheet.set_raw(c1, "=A1 * B1".to_string());
+        });
+        assert_eq!(spreadsheet.get_computed(c1), Some(Ok(Value::Number(6.0))));
+
+        assert!(spreadsheet.undo());
+        assert_eq!(spreadsheet.get_raw(&a1), Some("1"));
+        assert_eq!(spreadsheet.get_raw(&b1), None);
+        assert_eq!(spreadsheet.get_raw(&c1), None);
+
+        assert!(spreadsheet.redo());
+        assert_eq!(spreadsheet.get_computed(c1), Some(Ok(Value::Number(6.0))));
+    }
+
+    #[test]
+    fn test_range_interior_dependency() {
+        let mut spreadsheet = SpreadSheet::default();
+        let a1 = Index { x: 0, y: 0 };
+        let a2 = Index { x: 0, y: 1 };
+        let a3 = Index { x: 0, y: 2 };
+        let a4 = Index { x: 0, y: 3 };
+        let b1 = Index { x: 1, y: 0 };
+
+        spreadsheet.set_raw(a1, "1".to_string());
+        spreadsheet.set_raw(a4, "4".to_string());
+        spreadsheet.set_raw(b1, "=sum(A1:A4)".to_string());
+        assert_eq!(spreadsheet.get_computed(b1), Some(Ok(Value::Number(5.0))));
+
+        // Adding, changing and removing cells inside the range recomputes the sum
+        spreadsheet.set_raw(a2, "2".to_string());
+        assert_eq!(spreadsheet.get_computed(b1), Some(Ok(Value::Number(7.0))));
+        spreadsheet.set_raw(a3, "=A2 * 10".to_string());
+        assert_eq!(spreadsheet.get_computed(b1), Some(Ok(Value::Number(27.0))));
+        spreadsheet.set_raw(a2, "3".to_string());
+        assert_eq!(spreadsheet.get_computed(b1), Some(Ok(Value::Number(38.0))));
+        spreadsheet.set_raw(a3, String::new());
+        assert_eq!(spreadsheet.get_computed(b1), Some(Ok(Value::Number(8.0))));
+    }
+
+    #[test]
+    fn test_range_interior_cycle() {
+        let mut spreadsheet = SpreadSheet::default();
+        let a1 = Index { x: 0, y: 0 };
+        let a2 = Index { x: 0, y: 1 };
+        let b1 = Index { x: 1, y: 0 };
+
+        spreadsheet.set_raw(a1, "1".to_string());
+        spreadsheet.set_raw(b1, "=sum(A1:A4)".to_string());
+        spreadsheet.set_raw(a2, "=B1 + 1".to_string());
+
+        assert_eq!(spreadsheet.get_computed(a2), Some(Err(ComputeError::Cycle)));
+        assert_eq!(spreadsheet.get_computed(b1), Some(Err(ComputeError::Cycle)));
+
+        // Breaking the cycle makes both cells computable again
+        spreadsheet.set_raw(a2, "2".to_string());
+        assert_eq!(spreadsheet.get_computed(b1), Some(Ok(Value::Number(3.0))));
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let mut spreadsheet = SpreadSheet::default();
+        let a1 = Index { x: 0, y: 0 };
+        let b2 = Index { x: 1, y: 1 };
+        spreadsheet.set_raw(a1, "4".to_string());
+        spreadsheet.set_raw(b2, "=A1 * 2".to_string());
+
+        let path = std::env::temp_dir().join("mini_spreadsheet_test_save_and_load.txt");
+        spreadsheet.save_to_file_path(&path).unwrap();
+        let loaded = SpreadSheet::from_file_path(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.get_raw(&a1), Some("4"));
+        assert_eq!(loaded.get_computed(b2), Some(Ok(Value::Number(8.0))));
+        assert_eq!(loaded.iter_cells().count(), 2);
+        assert!(!loaded.can_undo());
+    }
+
+    #[test]
+    fn test_load_sample_file() {
+        let spreadsheet = SpreadSheet::from_file_path("csv/sum.csv").unwrap();
+        assert_eq!(
+            spreadsheet.get_computed(Index { x: 0, y: 2 }),
+            Some(Ok(Value::Number(4.0)))
+        );
+        assert_eq!(
+            spreadsheet.get_computed(Index { x: 1, y: 2 }),
+            Some(Ok(Value::Number(6.0)))
+        );
+    }
+
+    #[test]
+    fn test_load_missing_file() {
+        let path = std::env::temp_dir().join("mini_spreadsheet_this_file_does_not_exist.txt");
+        assert!(SpreadSheet::from_file_path(path).is_err());
+    }
+
+    #[test]
+    fn test_csv_round_trip() {
+        let mut spreadsheet = SpreadSheet::default();
+        let a1 = Index { x: 0, y: 0 };
+        let b1 = Index { x: 1, y: 0 };
+        let a2 = Index { x: 0, y: 1 };
+        spreadsheet.set_raw(a1, "a | b, \"c\"".to_string());
+        spreadsheet.set_raw(b1, "=length(A1)".to_string());
+        spreadsheet.set_raw(a2, "=\"x,y\"".to_string());
+
+        let options = CsvOptions::default();
+        let text = spreadsheet.to_csv(&options);
+        assert_eq!(
+            text,
+            "\"a | b, \"\"c\"\"\",=length(A1)\r\n\"=\"\"x,y\"\"\",\r\n"
+        );
+
+        let loaded = SpreadSheet::from_csv(&text, &options).unwrap();
+        assert_eq!(loaded.get_raw(&a1), Some("a | b, \"c\""));
+        assert_eq!(loaded.get_computed(b1), Some(Ok(Value::Number(10.0))));
+        assert_eq!(
+            loaded.get_computed(a2),
+            Some(Ok(Value::Text("x,y".to_string())))
+        );
+        assert_eq!(loaded.iter_cells().count(), 3);
+    }
+
+    #[test]
+    fn test_csv_whitespace() {
+        let options = CsvOptions::default();
+        let loaded = SpreadSheet::from_csv("  lead,1 \n\" quoted \",  ,\n", &options).unwrap();
+
+        // Fields are kept as they were written, only empty ones leave the cell empty
+        for (name, raw) in [
+            ("A1", Some("  lead")),
+            ("B1", Some("1 ")),
+            ("A2", Some(" quoted ")),
+            ("B2", Some("  ")),
+            ("C2", None),
+        ] {
+            assert_eq!(loaded.get_raw(&get_cell_idx(name).unwrap()), raw, "{name}");
+        }
+
+        let text = loaded.to_csv(&options);
+        let reloaded = SpreadSheet::from_csv(&text, &options).unwrap();
+        assert_eq!(reloaded.to_csv(&options), text);
+        assert_eq!(
+            reloaded.get_raw(&get_cell_idx("A1").unwrap()),
+            Some("  lead")
+        );
+    }
+
+    #[test]
+    fn test_csv_header_and_computed_values() {
+        let options = CsvOptions {
+            delimiter: ';',
+            has_header: true,
+            ..Default::default()
+        };
+        let spreadsheet =
+            SpreadSheet::from_csv("first;second\n2;=A1 > 1\n;=A1 * 3\n", &options).unwrap();
+        assert_eq!(spreadsheet.get_raw(&Index { x: 0, y: 0 }), Some("2"));
+
+        let computed = CsvOptions {
+            content: CsvContent::Computed,
+            ..options
+        };
+        assert_eq!(spreadsheet.to_csv(&computed), "A;B\r\n2;TRUE\r\n;6\r\n");
+    }
+
+    #[test]
+    fn test_copy_paste() {
+        let mut spreadsheet = SpreadSheet::default();
+        spreadsheet.set_raw(Index { x: 0, y: 0 }, "1".to_string());
+        spreadsheet.set_raw(Index { x: 0, y: 1 }, "2".to_string());
+        spreadsheet.set_raw(Index { x: 1, y: 0 }, "=A1 * $A$1".to_string());
+        spreadsheet.set_raw(Index { x: 1, y: 1 }, "=sum(A$1:A2)".to_string());
+
+        let copied = spreadsheet.copy(CellRange::new(Index { x: 1, y: 0 }, Index { x: 1, y: 1 }));
+        spreadsheet.paste(Index { x: 2, y: 1 }, &copied);
+
+        assert_eq!(
+            spreadsheet.get_raw(&Index { x: 2, y: 1 }),
+            Some("=B2 * $A$1")
+        );
+        assert_eq!(
+            spreadsheet.get_raw(&Index { x: 2, y: 2 }),
+            Some("=sum(B$1:B3)")
+        );
+        assert_eq!(
+            spreadsheet.get_computed(Index { x: 2, y: 1 }),
+            Some(Ok(Value::Number(3.0)))
+        );
+
+        // The pasted cells take part in recomputation
+        spreadsheet.set_raw(Index { x: 1, y: 1 }, "10".to_string());
+        assert_eq!(
+            spreadsheet.get_computed(Index { x: 2, y: 1 }),
+            Some(Ok(Value::Number(10.0)))
+        );
+
+        // Pasting is undone in one step
+        assert!(spreadsheet.undo());
+        assert!(spreadsheet.undo());
+        assert_eq!(spreadsheet.get_raw(&Index { x: 2, y: 1 }), None);
+        assert_eq!(spreadsheet.get_raw(&Index { x: 2, y: 2 }), None);
+    }
+
+    #[test]
+    fn test_paste_external_text() {
+        let mut spreadsheet = SpreadSheet::default();
+        spreadsheet.set_raw(Index { x: 1, y: 1 }, "old".to_string());
+
+        let copied = CopiedCells::from_tsv("=A1\t\r\n5\t6\r\n");
+        spreadsheet.paste(Index { x: 1, y: 0 }, &copied);
+
+        assert_eq!(spreadsheet.get_raw(&Index { x: 1, y: 0 }), Some("=A1"));
+        assert_eq!(spreadsheet.get_raw(&Index { x: 2, y: 0 }), None);
+        assert_eq!(spreadsheet.get_raw(&Index { x: 1, y: 1 }), Some("5"));
+        assert_eq!(spreadsheet.get_raw(&Index { x: 2, y: 1 }), Some("6"));
+    }
+
+    #[test]
+    fn test_clear_range() {
+        let mut spreadsheet = SpreadSheet::default();
+        spreadsheet.set_raw(Index { x: 0, y: 0 }, "1".to_string());
+        spreadsheet.set_raw(Index { x: 1, y: 1 }, "2".to_string());
+        spreadsheet.set_raw(Index { x: 2, y: 2 }, "=A1 + B2".to_string());
+
+        spreadsheet.clear_range(CellRange::new(Index { x: 0, y: 0 }, Index { x: 1, y: 1 }));
+        assert_eq!(spreadsheet.iter_cells().count(), 1);
+
+        assert!(spreadsheet.undo());
+        assert_eq!(
+            spreadsheet.get_computed(Index { x: 2, y: 2 }),
+            Some(Ok(Value::Number(3.0)))
+        );
+    }
+
+    #[test]
+    fn test_split_to_columns() {
+        let mut spreadsheet = SpreadSheet::default();
+        let idx = |name| get_cell_idx(name).unwrap();
+        for (name, raw) in [
+            ("A1", "Ada, Lovelace, 1815"),
+            ("A2", "Grace"),
+            ("B2", "kept"),
+            ("A3", "=A1"),
+            ("A4", "Alan, Turing"),
+        ] {
+            spreadsheet.set_raw(idx(name), raw.to_string());
+        }
+
+        spreadsheet.split_to_columns(CellRange::new(idx("A1"), idx("B3")), ",");
+        // The raw contents of the cells joined by `|`, `-` standing for an empty cell
+        let raws = |sheet: &SpreadSheet, names: [&str; 4]| {
+            names
+                .map(|name| sheet.get_raw(&get_cell_idx(name).unwrap()).unwrap_or("-"))
+                .join("|")
+        };
+        assert_eq!(
+            raws(&spreadsheet, ["A1", "B1", "C1", "B2"]),
+            "Ada|Lovelace|1815|kept"
+        );
+        assert_eq!(
+            spreadsheet.get_computed(idx("C1")),
+            Some(Ok(Value::Number(1815.0)))
+        );
+        // Formulas and cells outside of the range are left as they are
+        assert_eq!(
+            raws(&spreadsheet, ["A3", "B3", "A4", "B4"]),
+            "=A1|-|Alan, Turing|-"
+        );
+
+        assert!(spreadsheet.undo());
+        assert_eq!(
+            raws(&spreadsheet, ["A1", "B1", "C1", "B2"]),
+            "Ada, Lovelace, 1815|-|-|kept"
+        );
+    }
+
+    #[test]
+    fn test_insert_rows() {
+        let mut spreadsheet = SpreadSheet::default();
+        spreadsheet.set_raw(Index { x: 0, y: 0 }, "1".to_string());
+        spreadsheet.set_raw(Index { x: 0, y: 1 }, "2".to_string());
+        spreadsheet.set_raw(Index { x: 1, y: 0 }, "=sum(A1:A2) + A2".to_string());
+
+        spreadsheet.insert_rows(1, 2);
+
+        assert_eq!(spreadsheet.get_raw(&Index { x: 0, y: 1 }), None);
+        assert_eq!(spreadsheet.get_raw(&Index { x: 0, y: 3 }), Some("2"));
+        assert_eq!(
+            spreadsheet.get_raw(&Index { x: 1, y: 0 }),
+            Some("=sum(A1:A4) + A4")
+        );
+        assert_eq!(
+            spreadsheet.get_computed(Index { x: 1, y: 0 }),
+            Some(Ok(Value::Number(5.0)))
+        );
+
+        // The moved cell is still tracked by the dependency graph
+        spreadsheet.set_raw(Index { x: 0, y: 3 }, "10".to_string());
+        assert_eq!(
+            spreadsheet.get_computed(Index { x: 1, y: 0 }),
+            Some(Ok(Value::Number(21.0)))
+        );
+        // Cells inserted inside a range are part of it
+        spreadsheet.set_raw(Index { x: 0, y: 2 }, "100".to_string());
+        assert_eq!(
+            spreadsheet.get_computed(Index { x: 1, y: 0 }),
+            Some(Ok(Value::Number(121.0)))
+        );
+    }
+
+    #[test]
+    fn test_insert_rows_in_large_sheet() {
+        let mut spreadsheet = SpreadSheet::default();
+        let rows = 5000;
+        spreadsheet.set_raws((0..rows).flat_map(|y| {
+            [
+                (Index { x: 0, y }, y.to_string()),
+                (Index { x: 1, y }, format!("=A{} * 2", y + 1)),
+            ]
+        }));
+        spreadsheet.set_raw(Index { x: 2, y: 0 }, format!("=B1 + B{rows}"));
+        let total = (2 * (rows - 1)) as f64;
+        assert_eq!(
+            spreadsheet.get_computed(Index { x: 2, y: 0 }),
+            Some(Ok(Value::Number(total)))
+        );
+
+        spreadsheet.insert_rows(0, 1);
+        assert_eq!(
+            spreadsheet.get_raw(&Index { x: 1, y: rows }),
+            Some(format!("=A{} * 2", rows + 1).as_str())
+        );
+        assert_eq!(
+            spreadsheet.get_computed(Index { x: 2, y: 1 }),
+            Some(Ok(Value::Number(total)))
+        );
+        spreadsheet.set_raw(Index { x: 0, y: rows }, "0".to_string());
+        assert_eq!(
+            spreadsheet.get_computed(Index { x: 2, y: 1 }),
+            Some(Ok(Value::Number(0.0)))
+        );
+
+        assert!(spreadsheet.undo());
+        assert!(spreadsheet.undo());
+        assert_eq!(spreadsheet.get_raw(&Index { x: 2, y: 1 }), None);
+        assert_eq!(
+            spreadsheet.get_computed(Index { x: 2, y: 0 }),
+            Some(Ok(Value::Number(total)))
+        );
+        assert!(spreadsheet.redo());
+        assert_eq!(
+            spreadsheet.get_raw(&Index { x: 2, y: 1 }),
+            Some(format!("=B2 + B{}", rows + 1).as_str())
+        );
+    }
+
+    #[test]
+    fn test_delete_columns() {
+        let mut spreadsheet = SpreadSheet::default();
+        spreadsheet.set_raw(Index { x: 0, y: 0 }, "1".to_string());
+        spreadsheet.set_raw(Index { x: 1, y: 0 }, "2".to_string());
+        spreadsheet.set_raw(Index { x: 2, y: 0 }, "3".to_string());
+        spreadsheet.set_raw(Index { x: 3, y: 0 }, "=sum(A1:C1)".to_string());
+        spreadsheet.set_raw(Index { x: 3, y: 1 }, "=B1 + C1".to_string());
+
+        spreadsheet.delete_columns(1, 1);
+
+        assert_eq!(spreadsheet.get_raw(&Index { x: 1, y: 0 }), Some("3"));
+        assert_eq!(spreadsheet.get_raw(&Index { x: 3, y: 0 }), None);
+        assert_eq!(
+            spreadsheet.get_raw(&Index { x: 2, y: 0 }),
+            Some("=sum(A1:B1)")
+        );
+        assert_eq!(
+            spreadsheet.get_computed(Index { x: 2, y: 0 }),
+            Some(Ok(Value::Number(4.0)))
+        );
+        assert_eq!(
+            spreadsheet.get_raw(&Index { x: 2, y: 1 }),
+            Some("=#REF! + B1")
+        );
+        assert!(matches!(
+            spreadsheet.get_computed(Index { x: 2, y: 1 }),
+            Some(Err(ComputeError::UnfindableReference(_)))
+        ));
+
+        // The whole deletion is undone in one step
+        assert!(spreadsheet.undo());
+        assert_eq!(spreadsheet.get_raw(&Index { x: 1, y: 0 }), Some("2"));
+        assert_eq!(spreadsheet.get_raw(&Index { x: 3, y: 1 }), Some("=B1 + C1"));
+        assert_eq!(
+            spreadsheet.get_computed(Index { x: 3, y: 0 }),
+            Some(Ok(Value::Number(6.0)))
+        );
+    }
+
     #[test]
     fn test_string() {
         let mut spreadsheet = SpreadSheet::default();
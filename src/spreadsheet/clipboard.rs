@@ -0,0 +1,67 @@
+use crate::common_types::Index;
+
+use super::csv::{self, CsvOptions};
+
+/// A rectangular block of raw cell contents, copied out of a sheet or pasted in from other applications.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CopiedCells {
+    /// Top left cell the block was copied from, used to shift relative references when pasting.
+    /// `None` for text coming from other applications, which is pasted as is.
+    pub origin: Option<Index>,
+    pub rows: Vec<Vec<String>>,
+}
+
+impl CopiedCells {
+    /// Writes the block as tab separated text, the format other spreadsheet applications use for their clipboard.
+    pub fn to_tsv(&self) -> String {
+        csv::write_records(&self.rows, &Self::tsv_options())
+    }
+
+    pub fn from_tsv(text: &str) -> Self {
+        let rows = csv::read_records(text, '\t').unwrap_or_else(|_| {
+            // Not every application quotes its fields, so unbalanced quotes are taken literally
+            text.lines()
+                .map(|line| line.split('\t').map(str::to_owned).collect())
+                .collect()
+        });
+        Self { origin: None, rows }
+    }
+
+    fn tsv_options() -> CsvOptions {
+        CsvOptions {
+            delimiter: '\t',
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tsv_round_trip() {
+        let copied = CopiedCells {
+            origin: None,
+            rows: vec![
+                vec!["1".to_string(), "=A1 + 1".to_string()],
+                vec!["two\nlines".to_string(), String::new()],
+            ],
+        };
+        let text = copied.to_tsv();
+        assert_eq!(text, "1\t=A1 + 1\r\n\"two\nlines\"\t\r\n");
+        assert_eq!(CopiedCells::from_tsv(&text), copied);
+    }
+
+    #[test]
+    fn test_from_tsv_with_unbalanced_quote() {
+        let copied = CopiedCells::from_tsv("\"a\tb\nc");
+        assert_eq!(
+            copied.rows,
+            vec![
+                vec!["\"a".to_string(), "b".to_string()],
+                vec!["c".to_string()]
+            ]
+        );
+    }
+}
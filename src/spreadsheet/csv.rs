@@ -0,0 +1,228 @@
+use std::fmt::Display;
+
+/// Which representation of the cells is written when exporting.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CsvContent {
+    /// The contents as they were typed in, formulas included. Importing them back gives the same sheet.
+    Raw,
+    /// The computed values, errors being written as their display text.
+    Computed,
+}
+
+/// When fields are surrounded by quotes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QuoteStyle {
+    /// Only fields containing the delimiter, a quote or a line break.
+    Necessary,
+    Always,
+}
+
+#[derive(Debug, Clone)]
+pub struct CsvOptions {
+    pub delimiter: char,
+    pub quote_style: QuoteStyle,
+    /// On import the first record is skipped, on export a record of column names is written first.
+    pub has_header: bool,
+    pub content: CsvContent,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self {
+            delimiter: ',',
+            quote_style: QuoteStyle::Necessary,
+            has_header: false,
+            content: CsvContent::Raw,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CsvError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl Display for CsvError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for CsvError {}
+
+/// Splits RFC 4180 text into records of fields.
+/// Both `\r\n` and `\n` line endings are accepted and quoted fields may span multiple lines.
+pub fn read_records(text: &str, delimiter: char) -> Result<Vec<Vec<String>>, CsvError> {
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+    let mut chars = text.chars().peekable();
+
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut line = 1;
+    let mut in_quotes = false;
+    let mut quote_start_line = 0;
+
+    while let Some(ch) = chars.next() {
+        if in_quotes {
+            match ch {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' => in_quotes = false,
+                '\n' => {
+                    line += 1;
+                    field.push(ch);
+                }
+                _ => field.push(ch),
+            }
+            continue;
+        }
+
+        match ch {
+            '"' if field.is_empty() => {
+                in_quotes = true;
+                quote_start_line = line;
+            }
+            c if c == delimiter => record.push(std::mem::take(&mut field)),
+            '\r' if chars.peek() == Some(&'\n') => (),
+            '\n' => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+                line += 1;
+            }
+            _ => field.push(ch),
+        }
+    }
+
+    if in_quotes {
+        return Err(CsvError {
+            line: quote_start_line,
+            message: "Quoted field is never closed".to_string(),
+        });
+    }
+
+    // The last record does not need to end with a line break
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+
+    Ok(records)
+}
+
+/// Joins records into RFC 4180 text, ending every record with `\r\n`.
+pub fn write_records(records: &[Vec<String>], options: &CsvOptions) -> String {
+    let mut text = String::new();
+    for record in records {
+        for (i, field) in record.iter().enumerate() {
+            if i > 0 {
+                text.push(options.delimiter);
+            }
+            write_field(&mut text, field, options);
+        }
+        text.push_str("\r\n");
+    }
+    text
+}
+
+fn write_field(text: &mut String, field: &str, options: &CsvOptions) {
+    let needs_quotes = options.quote_style == QuoteStyle::Always
+        || field.contains(['"', '\r', '\n'])
+        || field.contains(options.delimiter);
+
+    if needs_quotes {
+        text.push('"');
+        text.push_str(&field.replace('"', "\"\""));
+        text.push('"');
+    } else {
+        text.push_str(field);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn to_records(rows: &[&[&str]]) -> Vec<Vec<String>> {
+        rows.iter()
+            .map(|row| row.iter().map(|field| field.to_string()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_read_simple() {
+        let records = read_records("a,b,c\r\n1,2,3\r\n", ',').unwrap();
+        assert_eq!(records, to_records(&[&["a", "b", "c"], &["1", "2", "3"]]));
+    }
+
+    #[test]
+    fn test_read_without_trailing_line_break() {
+        let records = read_records("a,b\n1,2", ',').unwrap();
+        assert_eq!(records, to_records(&[&["a", "b"], &["1", "2"]]));
+    }
+
+    #[test]
+    fn test_read_quoted_fields() {
+        let text = "\"a,b\",\"say \"\"hi\"\"\",\"two\r\nlines\"\n";
+        let records = read_records(text, ',').unwrap();
+        assert_eq!(
+            records,
+            to_records(&[&["a,b", "say \"hi\"", "two\r\nlines"]])
+        );
+    }
+
+    #[test]
+    fn test_read_keeps_whitespace_and_empty_fields() {
+        let records = read_records("  a ,,\n,\n", ',').unwrap();
+        assert_eq!(records, to_records(&[&["  a ", "", ""], &["", ""]]));
+    }
+
+    #[test]
+    fn test_read_custom_delimiter() {
+        let records = read_records("1;2,5\n", ';').unwrap();
+        assert_eq!(records, to_records(&[&["1", "2,5"]]));
+    }
+
+    #[test]
+    fn test_read_unterminated_quote() {
+        let result = read_records("a\n\"b,c\nd", ',');
+        assert_eq!(
+            result,
+            Err(CsvError {
+                line: 2,
+                message: "Quoted field is never closed".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_write_quotes_when_necessary() {
+        let records = to_records(&[&["plain", "a,b", "say \"hi\"", "two\nlines", " space"]]);
+        let text = write_records(&records, &CsvOptions::default());
+        assert_eq!(
+            text,
+            "plain,\"a,b\",\"say \"\"hi\"\"\",\"two\nlines\", space\r\n"
+        );
+    }
+
+    #[test]
+    fn test_write_always_quote() {
+        let options = CsvOptions {
+            delimiter: '\t',
+            quote_style: QuoteStyle::Always,
+            ..Default::default()
+        };
+        let text = write_records(&to_records(&[&["a", ""]]), &options);
+        assert_eq!(text, "\"a\"\t\"\"\r\n");
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let records = to_records(&[&["=\"x,y\"", " lead", "trail "], &["", "\"", "a\r\nb"]]);
+        let text = write_records(&records, &CsvOptions::default());
+        assert_eq!(read_records(&text, ',').unwrap(), records);
+    }
+}
@@ -0,0 +1,94 @@
+use crate::common_types::Index;
+
+/// A change to the raw contents of a single cell, `None` standing for an empty cell.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CellChange {
+    pub index: Index,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+/// A group of cell changes that are undone and redone together.
+pub type Transaction = Vec<CellChange>;
+
+/// Unbounded undo and redo stacks of the mutations made to a sheet.
+#[derive(Debug, Default)]
+pub struct History {
+    undo_stack: Vec<Transaction>,
+    redo_stack: Vec<Transaction>,
+    batch: Option<Transaction>,
+    batch_depth: usize,
+}
+
+impl History {
+    /// Records a change made by the user. Any redoable transactions are discarded.
+    pub fn record(&mut self, change: CellChange) {
+        if change.before == change.after {
+            return;
+        }
+
+        match &mut self.batch {
+            Some(batch) => batch.push(change),
+            None => self.push_transaction(vec![change]),
+        }
+    }
+
+    /// Starts grouping recorded changes into a single transaction.
+    /// Batches can be nested, only the outermost one creates a transaction.
+    pub fn start_batch(&mut self) {
+        self.batch_depth += 1;
+        self.batch.get_or_insert_with(Vec::new);
+    }
+
+    pub fn end_batch(&mut self) {
+        self.batch_depth = self.batch_depth.saturating_sub(1);
+        if self.batch_depth > 0 {
+            return;
+        }
+
+        if let Some(batch) = self.batch.take() {
+            if !batch.is_empty() {
+                self.push_transaction(batch);
+            }
+        }
+    }
+
+    fn push_transaction(&mut self, transaction: Transaction) {
+        self.undo_stack.push(transaction);
+        self.redo_stack.clear();
+    }
+
+    pub fn pop_undo(&mut self) -> Option<Transaction> {
+        self.undo_stack.pop()
+    }
+
+    pub fn pop_redo(&mut self) -> Option<Transaction> {
+        self.redo_stack.pop()
+    }
+
+    pub fn push_undo(&mut self, transaction: Transaction) {
+        self.undo_stack.push(transaction);
+    }
+
+    pub fn push_redo(&mut self, transaction: Transaction) {
+        self.redo_stack.push(transaction);
+    }
+
+    /// The number of transactions that can be undone.
+    pub fn undo_len(&self) -> usize {
+        self.undo_stack.len()
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+    }
+}
@@ -1,9 +1,16 @@
+use std::{fmt::Display, ops::Range};
+
 use ast_creator::{ASTCreateError, ASTCreator};
-use tokenizer::ExpressionTokenizer;
 
-use crate::{common_functions::get_cell_idx, common_types::{ParseError, Token, Value}};
+use crate::common_functions::{parse_date_time, parse_number};
+use tokenizer::{ExpressionTokenizer, TokenizeError};
+
+use crate::common_types::{
+    CellRange, CellReference, ComputeError, Dependency, Index, NumberLocale, ParseError,
+    StructuralChange, Token, Value, AST,
+};
 
-use super::{Cell, Expression, Index, ParsedCell};
+use super::{Cell, Expression, ParsedCell};
 
 pub mod ast_creator;
 pub mod ast_resolver;
@@ -12,27 +19,65 @@ pub mod tokenizer;
 
 pub struct CellParser {}
 
+/// A reference as it is written in a formula: a cell or a range, optionally in another sheet.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FormulaReference {
+    pub sheet: Option<String>,
+    pub from: CellReference,
+    /// The opposite corner when the reference is a range.
+    pub to: Option<CellReference>,
+}
+
+impl Display for FormulaReference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(sheet) = &self.sheet {
+            write!(f, "{}!", sheet_prefix(sheet))?;
+        }
+        write!(f, "{}", self.from)?;
+        if let Some(to) = &self.to {
+            write!(f, ":{to}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns a sheet name the way it has to be written in front of a reference, quoted when it is not a plain word.
+pub fn sheet_prefix(sheet: &str) -> String {
+    if !sheet.is_empty() && sheet.chars().all(|ch| ch.is_alphanumeric() || ch == '_') {
+        sheet.to_string()
+    } else {
+        format!("'{}'", sheet.replace('\'', "''"))
+    }
+}
+
 impl CellParser {
-    pub fn parse_cell(cell: &mut Cell) {
+    pub fn parse_cell(cell: &mut Cell, locale: NumberLocale) {
         let raw_cell = &cell.raw_representation;
         if raw_cell.is_empty() {
             panic!("Parsing empty cell")
         }
 
-        let parsed_cell = match raw_cell.chars().nth(0).expect("Should never fail") {
-            '=' => Self::parse_expression(raw_cell),
-            d if d.is_ascii_digit() || d == '-' || d == '+' => match raw_cell.parse() {
-                Ok(number) => Ok(ParsedCell::Value(Value::Number(number))),
-                Err(e) => Err(ParseError(format!(
-                    "Had error: -{e}- parsing number {raw_cell}"
-                ))),
-            },
+        let first = raw_cell.chars().nth(0).expect("Should never fail");
+        let parsed_cell = match (
+            first,
+            parse_number(raw_cell, locale),
+            parse_date_time(raw_cell),
+        ) {
+            ('=', ..) => Self::parse_formula(raw_cell, locale).map(ParsedCell::Expr),
+            (_, Some(number), _) => Ok(ParsedCell::Value(Value::Number(number))),
+            (_, None, Some(serial)) => Ok(ParsedCell::Value(Value::DateTime(serial))),
+            (d, None, None) if d.is_ascii_digit() || d == '-' || d == '+' => Err(ParseError::new(
+                format!("Invalid number format: {raw_cell}"),
+            )
+            .at(0..raw_cell.chars().count(), "a number such as 1.5 or 2e3")),
             _ => {
                 let s = raw_cell.to_string();
                 if s == "TRUE" {
                     Ok(ParsedCell::Value(Value::Bool(true)))
                 } else if s == "FALSE" {
                     Ok(ParsedCell::Value(Value::Bool(false)))
+                } else if let Some(error) = ComputeError::from_code(&s) {
+                    Ok(ParsedCell::Value(Value::Error(error)))
                 } else {
                     Ok(ParsedCell::Value(Value::Text(s)))
                 }
@@ -42,47 +87,463 @@ impl CellParser {
         cell.parsed_representation = Some(parsed_cell);
     }
 
-    fn parse_expression(s: &str) -> Result<ParsedCell, ParseError> {
-        let tokens = ExpressionTokenizer::new(s[1..].chars().collect())
-            .tokenize_expression()
-            .map_err(|e| match e {
-                tokenizer::TokenizeError::UnexpectedCharacter(c) => {
-                    ParseError(format!("Unexpected characther: {c}"))
-                }
-                tokenizer::TokenizeError::InvalidCellName(name) => {
-                    ParseError(format!("Invalid cell name: {name}"))
-                }
-                tokenizer::TokenizeError::InvalidNumber(num) => {
-                    ParseError(format!("Invalid number format: {num}"))
-                }
-                tokenizer::TokenizeError::UnterminatedString => {
-                    ParseError("Unterminated String".to_string())
-                }
+    /// Parses a formula starting with `=` into its AST and the dependencies it reads.
+    /// Errors point at the characters of the formula they were found at.
+    pub fn parse_formula(s: &str, locale: NumberLocale) -> Result<Expression, ParseError> {
+        let chars: Vec<char> = s.chars().skip(1).collect();
+        // Tokens are spanned without the leading `=`
+        let in_formula = |span: Range<usize>| span.start + 1..span.end + 1;
+
+        let tokens = ExpressionTokenizer::new(chars.clone())
+            .with_locale(locale)
+            .tokenize_with_spans()
+            .map_err(|(e, span)| {
+                let (message, expected) = match e {
+                    TokenizeError::UnexpectedCharacter(c) => (
+                        format!("Unexpected character '{c}'"),
+                        "an operator, value or reference",
+                    ),
+                    TokenizeError::InvalidCellName(name) => (
+                        format!("Invalid cell name: {name}"),
+                        "a cell name such as A1 or $B$2",
+                    ),
+                    TokenizeError::InvalidNumber(num) => (
+                        format!("Invalid number format: {num}"),
+                        "a number such as 1.5 or 2e3",
+                    ),
+                    TokenizeError::UnterminatedString => {
+                        ("Unterminated String".to_string(), "a closing '\"'")
+                    }
+                    TokenizeError::InvalidSheetName(name) => (
+                        format!("Invalid sheet name: {name}"),
+                        "a sheet name followed by '!'",
+                    ),
+                };
+                ParseError::new(message).at(in_formula(span), expected)
             })?;
 
-        let dependencies = Self::find_dependants(&tokens);
         let ast = ASTCreator::new(tokens.into_iter())
             .parse()
             .map_err(|e| match e {
-                ASTCreateError::UnexpectedToken => ParseError("Unexpected Token".to_string()),
-                ASTCreateError::MismatchedParentheses => {
-                    ParseError("Mismatched Parentheses".to_string())
+                ASTCreateError::UnexpectedToken { span, expected } if span.is_empty() => {
+                    ParseError::new("Unexpected end of formula").at(in_formula(span), expected)
                 }
-                ASTCreateError::InvalidRange => ParseError("Invalid Range Expression".to_string()),
+                ASTCreateError::UnexpectedToken { span, expected } => {
+                    let token: String = chars[span.clone()].iter().collect();
+                    ParseError::new(format!("Unexpected '{token}'")).at(in_formula(span), expected)
+                }
+                ASTCreateError::MismatchedParentheses { span } => {
+                    ParseError::new("Mismatched Parentheses").at(in_formula(span), "a closing ')'")
+                }
+                ASTCreateError::InvalidRange { span } => {
+                    ParseError::new("Invalid Range Expression").at(
+                        in_formula(span),
+                        "a range of at most 100 rows and columns such as A1:B5",
+                    )
+                }
+                ASTCreateError::InvalidCellName { span } => ParseError::new("Invalid cell name")
+                    .at(in_formula(span), "a cell name such as A1"),
             })?;
-        let expr = Expression { ast, dependencies };
-        Ok(ParsedCell::Expr(expr))
+        let dependencies = Self::dependencies(&ast);
+        Ok(Expression { ast, dependencies })
+    }
+
+    /// The cells, ranges and names an expression reads.
+    pub fn dependencies(ast: &AST) -> Vec<Dependency> {
+        let mut dependencies = Vec::new();
+        Self::find_dependants(ast, &mut dependencies);
+        dependencies
     }
 
-    fn find_dependants(tokens: &[Token]) -> Vec<Index> {
-        let cells = tokens
-            .iter()
-            .filter_map(|x| match x {
-                Token::CellName(name) => get_cell_idx(name),
+    /// Parses text such as `B2`, `$A$1:C3`, `Sheet2!A1` or a defined name into the reference it stands for,
+    /// as `indirect` does. Returns `None` for anything else.
+    pub fn parse_reference(text: &str) -> Option<AST> {
+        let tokens = ExpressionTokenizer::new(text.trim().chars().collect())
+            .tokenize_with_spans()
+            .ok()?;
+        let ast = ASTCreator::new(tokens.into_iter()).parse().ok()?;
+        matches!(
+            ast,
+            AST::CellName(_) | AST::Range { .. } | AST::SheetReference { .. } | AST::Name(_)
+        )
+        .then_some(ast)
+    }
+
+    /// Moves a formula by the given amount of columns and rows, shifting its relative references
+    /// while leaving anchored (`$`) axes in place. References pushed outside of the grid become `#REF!`.
+    /// Anything that is not a valid formula is returned unchanged.
+    pub fn move_formula(raw: &str, columns: isize, rows: isize) -> String {
+        Self::rewrite_references(raw, |reference| {
+            Some(FormulaReference {
+                from: reference.from.offset(columns, rows)?,
+                to: match reference.to {
+                    Some(to) => Some(to.offset(columns, rows)?),
+                    None => None,
+                },
+                ..reference
+            })
+        })
+    }
+
+    /// Rewrites the references of a formula after rows or columns were inserted into or deleted from `sheet`,
+    /// `None` being the sheet the formula is in. References to deleted cells become `#REF!` while ranges
+    /// grow and shrink with the rows or columns inside them.
+    pub fn apply_structural_change(
+        raw: &str,
+        change: &StructuralChange,
+        sheet: Option<&str>,
+    ) -> String {
+        Self::rewrite_references(raw, |reference| {
+            if reference.sheet.as_deref() != sheet {
+                return Some(reference);
+            }
+
+            let FormulaReference { from, to, .. } = reference;
+            let Some(to) = to else {
+                return Some(FormulaReference {
+                    from: CellReference {
+                        index: change.move_index(from.index)?,
+                        ..from
+                    },
+                    ..reference
+                });
+            };
+
+            let range = change.move_range(CellRange::new(from.index, to.index))?;
+            // Keep the corners in the order they were written in
+            let (from_x, to_x) = if from.index.x <= to.index.x {
+                (range.start.x, range.end.x)
+            } else {
+                (range.end.x, range.start.x)
+            };
+            let (from_y, to_y) = if from.index.y <= to.index.y {
+                (range.start.y, range.end.y)
+            } else {
+                (range.end.y, range.start.y)
+            };
+            Some(FormulaReference {
+                from: CellReference {
+                    index: Index {
+                        x: from_x,
+                        y: from_y,
+                    },
+                    ..from
+                },
+                to: Some(CellReference {
+                    index: Index { x: to_x, y: to_y },
+                    ..to
+                }),
+                ..reference
+            })
+        })
+    }
+
+    /// Rewrites every reference of a formula, keeping the rest of the text as it was typed.
+    /// A range is rewritten as a whole, and a reference rewritten to `None` is replaced with `#REF!`.
+    pub fn rewrite_references<F>(raw: &str, mut rewrite: F) -> String
+    where
+        F: FnMut(FormulaReference) -> Option<FormulaReference>,
+    {
+        let Some(expression) = raw.strip_prefix('=') else {
+            return raw.to_string();
+        };
+        let chars: Vec<char> = expression.chars().collect();
+        // References are written the same way in every locale, so the formula only has to tokenize in one of them
+        let tokenize = |locale| {
+            ExpressionTokenizer::new(chars.clone())
+                .with_locale(locale)
+                .tokenize_with_spans()
+        };
+        let Ok(tokens) =
+            tokenize(NumberLocale::DecimalPoint).or_else(|_| tokenize(NumberLocale::DecimalComma))
+        else {
+            return raw.to_string();
+        };
+        let reference_at = |i: usize| match tokens.get(i) {
+            Some((Token::CellName(name), span)) => {
+                name.parse::<CellReference>().ok().map(|r| (r, span.end))
+            }
+            _ => None,
+        };
+
+        let mut result = String::from("=");
+        let mut copied_until = 0;
+        let mut i = 0;
+        while i < tokens.len() {
+            let (sheet, start) = match &tokens[i] {
+                (Token::SheetName(sheet), span) => {
+                    i += 1;
+                    (Some(sheet.clone()), span.start)
+                }
+                (_, span) => (None, span.start),
+            };
+            let Some((from, mut end)) = reference_at(i) else {
+                i += 1;
+                continue;
+            };
+            i += 1;
+
+            let to = match (tokens.get(i), reference_at(i + 1)) {
+                (Some((Token::Colon, _)), Some((to, to_end))) => {
+                    i += 2;
+                    end = to_end;
+                    Some(to)
+                }
                 _ => None,
+            };
+
+            result.extend(&chars[copied_until..start]);
+            match rewrite(FormulaReference { sheet, from, to }) {
+                Some(reference) => result.push_str(&reference.to_string()),
+                None => result.push_str("#REF!"),
+            }
+            copied_until = end;
+        }
+        result.extend(&chars[copied_until..]);
+
+        result
+    }
+
+    fn find_dependants(ast: &AST, dependencies: &mut Vec<Dependency>) {
+        match ast {
+            AST::CellName(reference) => dependencies.push(Dependency::Cell(reference.index)),
+            AST::Range { from, to } => {
+                dependencies.push(Dependency::Range(CellRange::new(from.index, to.index)))
+            }
+            AST::BinaryOp { left, right, .. } => {
+                Self::find_dependants(left, dependencies);
+                Self::find_dependants(right, dependencies);
+            }
+            AST::UnaryOp { expr, .. } => Self::find_dependants(expr, dependencies),
+            AST::FunctionCall { arguments, .. } => {
+                for argument in arguments {
+                    Self::find_dependants(argument, dependencies);
+                }
+            }
+            AST::SheetReference { sheet, reference } => {
+                let mut sheet_dependencies = Vec::new();
+                Self::find_dependants(reference, &mut sheet_dependencies);
+                dependencies.extend(sheet_dependencies.into_iter().filter_map(|dependency| {
+                    let range = match dependency {
+                        Dependency::Cell(index) => CellRange::new(index, index),
+                        Dependency::Range(range) => range,
+                        Dependency::Sheet { .. } | Dependency::Name(_) => return None,
+                    };
+                    Some(Dependency::Sheet {
+                        sheet: sheet.clone(),
+                        range,
+                    })
+                }));
+            }
+            AST::Name(name) => dependencies.push(Dependency::Name(name.clone())),
+            AST::Value(_) | AST::RefError => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common_types::Axis;
+
+    #[test]
+    fn test_move_formula() {
+        assert_eq!(
+            CellParser::move_formula("=A1 +  $B$2*sum(A$1:$C3)", 1, 2),
+            "=B3 +  $B$2*sum(B$1:$C5)"
+        );
+    }
+
+    #[test]
+    fn test_move_formula_outside_of_grid() {
+        assert_eq!(
+            CellParser::move_formula("=A2 + $A1", -1, -1),
+            "=#REF! + #REF!"
+        );
+        assert_eq!(
+            CellParser::move_formula("=$A$1 + A$1", -1, -5),
+            "=$A$1 + #REF!"
+        );
+    }
+
+    #[test]
+    fn test_move_non_formula() {
+        assert_eq!(CellParser::move_formula("A1 + B2", 1, 1), "A1 + B2");
+        assert_eq!(CellParser::move_formula("=A1 + \"", 1, 1), "=A1 + \"");
+        assert_eq!(
+            CellParser::move_formula("=\"A1\" + A1", 0, 1),
+            "=\"A1\" + A2"
+        );
+    }
+
+    #[test]
+    fn test_move_range_outside_of_grid() {
+        assert_eq!(
+            CellParser::move_formula("=sum(A1:B2, A5)", 0, -2),
+            "=sum(#REF!, A3)"
+        );
+    }
+
+    #[test]
+    fn test_insert_rows_in_formula() {
+        let change = StructuralChange::Insert {
+            axis: Axis::Row,
+            at: 2,
+            count: 2,
+        };
+        assert_eq!(
+            CellParser::apply_structural_change("=A2 + $A$3 + sum(B1:B3, C3:C4)", &change, None),
+            "=A2 + $A$5 + sum(B1:B5, C5:C6)"
+        );
+    }
+
+    #[test]
+    fn test_delete_columns_in_formula() {
+        let change = StructuralChange::Delete {
+            axis: Axis::Column,
+            at: 1,
+            count: 2,
+        };
+        assert_eq!(
+            CellParser::apply_structural_change(
+                "=A1 + B1 + $D1 + sum(A1:C1) + sum(B2:C3)",
+                &change,
+                None
+            ),
+            "=A1 + #REF! + $B1 + sum(A1:A1) + sum(#REF!)"
+        );
+        // Reversed corners keep their order and a range losing its first column moves back
+        assert_eq!(
+            CellParser::apply_structural_change("=sum(E2:B1)", &change, None),
+            "=sum(C2:B1)"
+        );
+    }
+
+    #[test]
+    fn test_structural_change_in_other_sheet() {
+        let change = StructuralChange::Insert {
+            axis: Axis::Row,
+            at: 0,
+            count: 1,
+        };
+        let raw = "=A1 + Data!A1 + sum('My Sheet'!A1:B2)";
+        assert_eq!(
+            CellParser::apply_structural_change(raw, &change, None),
+            "=A2 + Data!A1 + sum('My Sheet'!A1:B2)"
+        );
+        assert_eq!(
+            CellParser::apply_structural_change(raw, &change, Some("My Sheet")),
+            "=A1 + Data!A1 + sum('My Sheet'!A2:B3)"
+        );
+    }
+
+    #[test]
+    fn test_rewrite_sheet_names() {
+        let raw = "=Data!A1 +  Other!B2:C3 + A1";
+        let renamed = CellParser::rewrite_references(raw, |reference| {
+            Some(match reference.sheet.as_deref() {
+                Some("Data") => FormulaReference {
+                    sheet: Some("New data".to_string()),
+                    ..reference
+                },
+                _ => reference,
             })
-            .collect();
+        });
+        assert_eq!(renamed, "='New data'!A1 +  Other!B2:C3 + A1");
+
+        let removed = CellParser::rewrite_references(raw, |reference| {
+            (reference.sheet.as_deref() != Some("Other")).then_some(reference)
+        });
+        assert_eq!(removed, "=Data!A1 +  #REF! + A1");
+    }
+
+    #[test]
+    fn test_parse_sheet_references() {
+        let mut cell = Cell::from_raw("=Data!A1 + sum(Data!B1:B3, A2)".to_string());
+        CellParser::parse_cell(&mut cell, NumberLocale::DecimalPoint);
+        let Some(Ok(ParsedCell::Expr(expression))) = cell.parsed_representation else {
+            panic!("Expected an expression");
+        };
+        assert_eq!(
+            expression.dependencies,
+            vec![
+                Dependency::Sheet {
+                    sheet: "Data".to_string(),
+                    range: CellRange::new(Index { x: 0, y: 0 }, Index { x: 0, y: 0 })
+                },
+                Dependency::Sheet {
+                    sheet: "Data".to_string(),
+                    range: CellRange::new(Index { x: 1, y: 0 }, Index { x: 1, y: 2 })
+                },
+                Dependency::Cell(Index { x: 0, y: 1 }),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_error_spans() {
+        let cases = [
+            ("=1 + ~2", 5..6, "Unexpected character '~'"),
+            ("=sum(A1 B2)", 8..10, "Unexpected 'B2'"),
+            ("=(1 + 2", 1..2, "Mismatched Parentheses"),
+            ("=1 +", 4..4, "Unexpected end of formula"),
+            ("=\"text", 1..6, "Unterminated String"),
+            ("=A1:A500", 1..8, "Invalid Range Expression"),
+            ("=1.2.3 * 2", 1..6, "Invalid number format: 1.2.3"),
+        ];
+        for (raw, span, message) in cases {
+            let error = CellParser::parse_formula(raw, NumberLocale::DecimalPoint).unwrap_err();
+            assert_eq!(error.span, Some(span), "{raw}");
+            assert_eq!(error.message, message, "{raw}");
+            assert!(error.expected.is_some(), "{raw}");
+        }
 
-        cells
+        let error =
+            CellParser::parse_formula("=sum(A1 B2)", NumberLocale::DecimalPoint).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Unexpected 'B2', expected an operator, another argument or ')'"
+        );
+    }
+
+    #[test]
+    fn test_parse_ref_error() {
+        let mut cell = Cell::from_raw("=A1 + #REF!".to_string());
+        CellParser::parse_cell(&mut cell, NumberLocale::DecimalPoint);
+        let Some(Ok(ParsedCell::Expr(expression))) = cell.parsed_representation else {
+            panic!("Expected an expression");
+        };
+        assert_eq!(
+            expression.dependencies,
+            vec![Dependency::Cell(Index { x: 0, y: 0 })]
+        );
+    }
+
+    #[test]
+    fn test_parse_anchored_references() {
+        let mut cell = Cell::from_raw("=$A$1 + A$2 + $B1".to_string());
+        CellParser::parse_cell(&mut cell, NumberLocale::DecimalPoint);
+        let Some(Ok(ParsedCell::Expr(expr))) = cell.parsed_representation else {
+            panic!("Expected an expression");
+        };
+        assert_eq!(
+            expr.dependencies,
+            vec![
+                Dependency::Cell(Index { x: 0, y: 0 }),
+                Dependency::Cell(Index { x: 0, y: 1 }),
+                Dependency::Cell(Index { x: 1, y: 0 }),
+            ]
+        );
+        let AST::BinaryOp { right, .. } = expr.ast else {
+            panic!("Expected a binary operation");
+        };
+        assert_eq!(
+            *right,
+            AST::CellName(CellReference {
+                index: Index { x: 1, y: 0 },
+                absolute_column: true,
+                absolute_row: false,
+            })
+        );
     }
 }
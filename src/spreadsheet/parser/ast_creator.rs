@@ -1,44 +1,81 @@
-use std::iter::Peekable;
+use std::{iter::Peekable, ops::Range};
 
-use crate::{common_functions::get_cell_idx, common_types::{Token, Value, AST}};
+use crate::common_types::{CellReference, Token, Value, AST, MAX_RANGE_SPAN};
+
+const NEGATION_PRECEDENCE: usize = 6;
 
 pub struct ASTCreator<I>
 where
-    I: Iterator<Item = Token>,
+    I: Iterator<Item = (Token, Range<usize>)>,
 {
     tokens: Peekable<I>,
+    // Where the last consumed token ended, errors about missing tokens point there
+    consumed_until: usize,
 }
+
+/// Every error carries the span of the tokens it was found at.
 #[derive(Debug)]
 pub enum ASTCreateError {
-    UnexpectedToken,
-    MismatchedParentheses,
-    InvalidRange,
+    UnexpectedToken {
+        span: Range<usize>,
+        /// A hint at what would have been valid instead, such as `a value`.
+        expected: &'static str,
+    },
+    /// The span is the parenthesis that is never closed.
+    MismatchedParentheses {
+        span: Range<usize>,
+    },
+    InvalidRange {
+        span: Range<usize>,
+    },
+    InvalidCellName {
+        span: Range<usize>,
+    },
 }
 
 impl<I> ASTCreator<I>
 where
-    I: Iterator<Item = Token>,
+    I: Iterator<Item = (Token, Range<usize>)>,
 {
+    /// Takes tokens paired with their spans, as returned by `ExpressionTokenizer::tokenize_with_spans`.
     pub fn new(tokens: I) -> Self {
         Self {
             tokens: tokens.peekable(),
+            consumed_until: 0,
         }
     }
 
     pub fn parse(&mut self) -> Result<crate::common_types::AST, ASTCreateError> {
-        let result = self.parse_expression(0);
-        if result.is_err(){
-            return result
-        }
+        let result = self.parse_expression(0)?;
 
-        if self.tokens.next().is_some() {
+        match self.next() {
+            None => Ok(result),
             // We have not parsed all tokens
-            Err(ASTCreateError::UnexpectedToken)
-        } else {
-            result
+            unexpected => Err(self.unexpected(unexpected, "an operator")),
         }
     }
 
+    fn next(&mut self) -> Option<(Token, Range<usize>)> {
+        let next = self.tokens.next();
+        if let Some((_, span)) = &next {
+            self.consumed_until = span.end;
+        }
+        next
+    }
+
+    // Points at the unexpected token, or just past the last token when the formula ended early
+    fn unexpected(
+        &self,
+        token: Option<(Token, Range<usize>)>,
+        expected: &'static str,
+    ) -> ASTCreateError {
+        let span = match token {
+            Some((_, span)) => span,
+            None => self.consumed_until..self.consumed_until,
+        };
+        ASTCreateError::UnexpectedToken { span, expected }
+    }
+
     fn parse_expression(&mut self, min_precedence: usize) -> Result<AST, ASTCreateError> {
         // Min presedence arguement is important for recursive calls where it may be higher than 1
 
@@ -50,10 +87,10 @@ where
                 break;
             }
 
-            self.tokens.next(); // Consume the operator
+            self.next(); // Consume the operator
 
-            // Handle unary NOT operator specially
-            if op == Token::Not {
+            // Handle the postfix NOT and percent operators specially
+            if matches!(op, Token::Not | Token::Percent) {
                 left = AST::UnaryOp {
                     op,
                     expr: Box::new(left),
@@ -61,7 +98,12 @@ where
                 continue;
             }
 
-            let right = self.parse_expression(precedence + 1)?;
+            // Powers are right associative, `2^3^2` is `2^(3^2)`
+            let right = if op == Token::Power {
+                self.parse_expression(precedence)?
+            } else {
+                self.parse_expression(precedence + 1)?
+            };
             left = AST::BinaryOp {
                 op,
                 left: Box::new(left),
@@ -73,67 +115,96 @@ where
     }
 
     fn parse_primary(&mut self) -> Result<AST, ASTCreateError> {
-        match self.tokens.next() {
-            Some(Token::FunctionName(name)) => {
-                self.expect_token(Token::LParen)?;
-                let arguments = self.parse_function_arguements()?;
+        match self.next() {
+            Some((Token::FunctionName(name), _)) => {
+                let open = self.expect_token(Token::LParen, "'('")?;
+                let arguments = self.parse_function_arguements(open)?;
                 Ok(AST::FunctionCall { name, arguments })
             }
-            Some(Token::CellName(name)) => {
-                // Check if this might be the start of a range
-                if let Some(Token::Colon) = self.tokens.peek() {
-                    self.tokens.next(); // consume colon
-                    match self.tokens.next() {
-                        Some(Token::CellName(to_name)) => {
-                            let idx_from =
-                                get_cell_idx(&name).ok_or(ASTCreateError::InvalidRange)?;
-                            let idx_to =
-                                get_cell_idx(&to_name).ok_or(ASTCreateError::InvalidRange)?;
-                            if (idx_from.x.abs_diff(idx_to.x)) > 100
-                                || (idx_from.y.abs_diff(idx_to.y)) > 100
-                            {
-                                return Err(ASTCreateError::InvalidRange);
-                            }
-
-                            Ok(AST::Range {
-                                from: name,
-                                to: to_name,
-                            })
-                        }
-                        _ => Err(ASTCreateError::InvalidRange),
-                    }
-                } else {
-                    Ok(AST::CellName(name))
-                }
-            }
-            Some(Token::Number(n)) => Ok(AST::Value(Value::Number(n))),
-            Some(Token::StringLiteral(s)) => Ok(AST::Value(Value::Text(s))),
-            Some(Token::LParen) => {
+            Some((Token::CellName(name), span)) => self.parse_reference(name, span),
+            Some((Token::SheetName(sheet), _)) => match self.next() {
+                Some((Token::CellName(name), span)) => Ok(AST::SheetReference {
+                    sheet,
+                    reference: Box::new(self.parse_reference(name, span)?),
+                }),
+                unexpected => Err(self.unexpected(unexpected, "a cell name")),
+            },
+            Some((Token::Number(n), _)) => Ok(AST::Value(Value::Number(n))),
+            Some((Token::StringLiteral(s), _)) => Ok(AST::Value(Value::Text(s))),
+            Some((Token::LParen, open)) => {
                 let expr = self.parse_expression(0)?;
-                match self.tokens.next() {
-                    Some(Token::RParen) => Ok(expr),
-                    _ => Err(ASTCreateError::MismatchedParentheses),
+                match self.next() {
+                    Some((Token::RParen, _)) => Ok(expr),
+                    None => Err(ASTCreateError::MismatchedParentheses { span: open }),
+                    unexpected => Err(self.unexpected(unexpected, "an operator or ')'")),
                 }
             }
-            Some(Token::Bool(b)) => Ok(AST::Value(Value::Bool(b))),
-            Some(Token::Not) => {
+            Some((Token::Bool(b), _)) => Ok(AST::Value(Value::Bool(b))),
+            Some((Token::RefError, _)) => Ok(AST::RefError),
+            Some((Token::Error(error), _)) => Ok(AST::Value(Value::Error(error))),
+            Some((Token::Name(name), _)) => Ok(AST::Name(name)),
+            Some((Token::Minus, _)) => {
+                // Binds tighter than multiplication but looser than powers, `-2^2` is `-(2^2)`
+                let expr = self.parse_expression(NEGATION_PRECEDENCE)?;
+                Ok(AST::UnaryOp {
+                    op: Token::Minus,
+                    expr: Box::new(expr),
+                })
+            }
+            Some((Token::Not, _)) => {
                 let expr = self.parse_expression(Token::Not.get_precedence())?;
                 Ok(AST::UnaryOp {
                     op: Token::Not,
                     expr: Box::new(expr),
                 })
             }
-            _ => Err(ASTCreateError::UnexpectedToken),
+            unexpected => Err(self.unexpected(unexpected, "a value, reference or function")),
+        }
+    }
+
+    fn parse_reference(&mut self, name: String, span: Range<usize>) -> Result<AST, ASTCreateError> {
+        // Check if this might be the start of a range
+        if let Some((Token::Colon, _)) = self.tokens.peek() {
+            self.next(); // consume colon
+            let next = self.next();
+            let span = span.start..self.consumed_until;
+            match next {
+                Some((Token::CellName(to_name), _)) => {
+                    let from: CellReference = name
+                        .parse()
+                        .map_err(|_| ASTCreateError::InvalidRange { span: span.clone() })?;
+                    let to: CellReference = to_name
+                        .parse()
+                        .map_err(|_| ASTCreateError::InvalidRange { span: span.clone() })?;
+                    if (from.index.x.abs_diff(to.index.x)) > MAX_RANGE_SPAN
+                        || (from.index.y.abs_diff(to.index.y)) > MAX_RANGE_SPAN
+                    {
+                        return Err(ASTCreateError::InvalidRange { span });
+                    }
+
+                    Ok(AST::Range { from, to })
+                }
+                _ => Err(ASTCreateError::InvalidRange { span }),
+            }
+        } else {
+            let reference = name
+                .parse()
+                .map_err(|_| ASTCreateError::InvalidCellName { span })?;
+            Ok(AST::CellName(reference))
         }
     }
 
     fn peek_operator(&mut self) -> Option<Token> {
         match self.tokens.peek() {
-            Some(
-                Token::Plus
+            Some((
+                token @ (Token::Plus
                 | Token::Minus
                 | Token::Multiply
                 | Token::Division
+                | Token::Power
+                | Token::Mod
+                | Token::Percent
+                | Token::Concat
                 | Token::Equals
                 | Token::NotEquals
                 | Token::GreaterThan
@@ -142,23 +213,40 @@ where
                 | Token::LessEquals
                 | Token::And
                 | Token::Or
-                | Token::Not,
-            ) => self.tokens.peek().cloned(),
+                | Token::Not),
+                _,
+            )) => Some(token.clone()),
             _ => None,
         }
     }
 
-    // Helper function to expect a specific token
-    fn expect_token(&mut self, expected: Token) -> Result<(), ASTCreateError> {
-        match self.tokens.next() {
-            Some(token) if token == expected => Ok(()),
-            _ => Err(ASTCreateError::UnexpectedToken),
+    // Helper function to expect a specific token, returning its span
+    fn expect_token(
+        &mut self,
+        expected: Token,
+        hint: &'static str,
+    ) -> Result<Range<usize>, ASTCreateError> {
+        match self.next() {
+            Some((token, span)) if token == expected => Ok(span),
+            unexpected => Err(self.unexpected(unexpected, hint)),
         }
     }
 
-    fn parse_function_arguements(&mut self) -> Result<Vec<AST>, ASTCreateError> {
+    fn parse_function_arguements(
+        &mut self,
+        open: Range<usize>,
+    ) -> Result<Vec<AST>, ASTCreateError> {
         let mut arguements = Vec::new();
 
+        // Functions such as na() take no arguments
+        if self
+            .tokens
+            .next_if(|(token, _)| *token == Token::RParen)
+            .is_some()
+        {
+            return Ok(arguements);
+        }
+
         let mut expecting_comma = false;
 
         loop {
@@ -167,11 +255,15 @@ where
                 let arg = self.parse_expression(0)?;
                 arguements.push(arg);
             } else {
-                match self.tokens.next() {
-                    Some(Token::Comma) => expecting_comma = false,
-                    Some(Token::RParen) => break,
-                    Some(_unexpected) => return Err(ASTCreateError::UnexpectedToken),
-                    None => return Err(ASTCreateError::MismatchedParentheses),
+                match self.next() {
+                    Some((Token::Comma, _)) => expecting_comma = false,
+                    Some((Token::RParen, _)) => break,
+                    None => return Err(ASTCreateError::MismatchedParentheses { span: open }),
+                    unexpected => {
+                        return Err(
+                            self.unexpected(unexpected, "an operator, another argument or ')'")
+                        )
+                    }
                 }
             }
         }
@@ -180,17 +272,24 @@ where
     }
 }
 
-
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    // Spans each token as if it was a single character
+    fn spanned(tokens: Vec<Token>) -> impl Iterator<Item = (Token, Range<usize>)> {
+        tokens
+            .into_iter()
+            .enumerate()
+            .map(|(i, token)| (token, i..i + 1))
+    }
+
     #[test]
     fn test_single_cell_name() {
         let tokens = vec![Token::CellName("A1".to_string())];
-        let mut parser = ASTCreator::new(tokens.into_iter());
+        let mut parser = ASTCreator::new(spanned(tokens));
         let ast = parser.parse().unwrap();
-        assert_eq!(ast, AST::CellName("A1".to_string()));
+        assert_eq!(ast, AST::CellName("A1".parse().unwrap()));
     }
 
     #[test]
@@ -200,14 +299,14 @@ mod tests {
             Token::Plus,
             Token::CellName("B2".to_string()),
         ];
-        let mut parser = ASTCreator::new(tokens.into_iter());
+        let mut parser = ASTCreator::new(spanned(tokens));
         let ast = parser.parse().unwrap();
         assert_eq!(
             ast,
             AST::BinaryOp {
                 op: Token::Plus,
-                left: Box::new(AST::CellName("A1".to_string())),
-                right: Box::new(AST::CellName("B2".to_string())),
+                left: Box::new(AST::CellName("A1".parse().unwrap())),
+                right: Box::new(AST::CellName("B2".parse().unwrap())),
             }
         );
     }
@@ -221,17 +320,17 @@ mod tests {
             Token::Multiply,
             Token::CellName("C3".to_string()),
         ];
-        let mut parser = ASTCreator::new(tokens.into_iter());
+        let mut parser = ASTCreator::new(spanned(tokens));
         let ast = parser.parse().unwrap();
         assert_eq!(
             ast,
             AST::BinaryOp {
                 op: Token::Plus,
-                left: Box::new(AST::CellName("A1".to_string())),
+                left: Box::new(AST::CellName("A1".parse().unwrap())),
                 right: Box::new(AST::BinaryOp {
                     op: Token::Multiply,
-                    left: Box::new(AST::CellName("B2".to_string())),
-                    right: Box::new(AST::CellName("C3".to_string())),
+                    left: Box::new(AST::CellName("B2".parse().unwrap())),
+                    right: Box::new(AST::CellName("C3".parse().unwrap())),
                 }),
             }
         );
@@ -248,7 +347,7 @@ mod tests {
             Token::Multiply,
             Token::CellName("C3".to_string()),
         ];
-        let mut parser = ASTCreator::new(tokens.into_iter());
+        let mut parser = ASTCreator::new(spanned(tokens));
         let ast = parser.parse().unwrap();
         assert_eq!(
             ast,
@@ -256,10 +355,10 @@ mod tests {
                 op: Token::Multiply,
                 left: Box::new(AST::BinaryOp {
                     op: Token::Plus,
-                    left: Box::new(AST::CellName("A1".to_string())),
-                    right: Box::new(AST::CellName("B2".to_string())),
+                    left: Box::new(AST::CellName("A1".parse().unwrap())),
+                    right: Box::new(AST::CellName("B2".parse().unwrap())),
                 }),
-                right: Box::new(AST::CellName("C3".to_string())),
+                right: Box::new(AST::CellName("C3".parse().unwrap())),
             }
         );
     }
@@ -272,17 +371,58 @@ mod tests {
             Token::Plus,
             Token::CellName("B2".to_string()),
         ];
-        let mut parser = ASTCreator::new(tokens.into_iter());
+        let mut parser = ASTCreator::new(spanned(tokens));
         let result = parser.parse();
-        assert!(matches!(result, Err(ASTCreateError::MismatchedParentheses)));
+        assert!(matches!(
+            result,
+            Err(ASTCreateError::MismatchedParentheses { .. })
+        ));
     }
 
     #[test]
     fn test_unexpected_token() {
         let tokens = vec![Token::Plus, Token::CellName("A1".to_string())];
-        let mut parser = ASTCreator::new(tokens.into_iter());
+        let mut parser = ASTCreator::new(spanned(tokens));
         let result = parser.parse();
-        assert!(matches!(result, Err(ASTCreateError::UnexpectedToken)));
+        assert!(matches!(
+            result,
+            Err(ASTCreateError::UnexpectedToken { .. })
+        ));
+    }
+
+    #[test]
+    fn test_error_spans() {
+        let tokens = vec![
+            Token::FunctionName("sum".to_string()),
+            Token::LParen,
+            Token::CellName("A1".to_string()),
+            Token::CellName("B2".to_string()),
+            Token::RParen,
+        ];
+        let mut parser = ASTCreator::new(spanned(tokens));
+        assert!(matches!(
+            parser.parse(),
+            Err(ASTCreateError::UnexpectedToken { span, .. }) if span == (3..4)
+        ));
+
+        let tokens = vec![
+            Token::LParen,
+            Token::LParen,
+            Token::Number(1.0),
+            Token::RParen,
+        ];
+        let mut parser = ASTCreator::new(spanned(tokens));
+        assert!(matches!(
+            parser.parse(),
+            Err(ASTCreateError::MismatchedParentheses { span }) if span == (0..1)
+        ));
+
+        let tokens = vec![Token::Number(1.0), Token::Plus];
+        let mut parser = ASTCreator::new(spanned(tokens));
+        assert!(matches!(
+            parser.parse(),
+            Err(ASTCreateError::UnexpectedToken { span, .. }) if span == (2..2)
+        ));
     }
 
     #[test]
@@ -298,7 +438,7 @@ mod tests {
             Token::CellName("C3".to_string()),
             Token::RParen,
         ];
-        let mut parser = ASTCreator::new(tokens.into_iter());
+        let mut parser = ASTCreator::new(spanned(tokens));
         let ast = parser.parse().unwrap();
         assert_eq!(
             ast,
@@ -306,10 +446,10 @@ mod tests {
                 op: Token::Multiply,
                 left: Box::new(AST::BinaryOp {
                     op: Token::Plus,
-                    left: Box::new(AST::CellName("A1".to_string())),
-                    right: Box::new(AST::CellName("B2".to_string())),
+                    left: Box::new(AST::CellName("A1".parse().unwrap())),
+                    right: Box::new(AST::CellName("B2".parse().unwrap())),
                 }),
-                right: Box::new(AST::CellName("C3".to_string())),
+                right: Box::new(AST::CellName("C3".parse().unwrap())),
             }
         );
     }
@@ -322,13 +462,13 @@ mod tests {
             Token::CellName("A1".to_string()),
             Token::RParen,
         ];
-        let mut parser = ASTCreator::new(tokens.into_iter());
+        let mut parser = ASTCreator::new(spanned(tokens));
         let ast = parser.parse().unwrap();
         assert_eq!(
             ast,
             AST::FunctionCall {
                 name: "sum".to_string(),
-                arguments: vec![AST::CellName("A1".to_string())],
+                arguments: vec![AST::CellName("A1".parse().unwrap())],
             }
         );
     }
@@ -345,15 +485,15 @@ mod tests {
             Token::Number(42.0),
             Token::RParen,
         ];
-        let mut parser = ASTCreator::new(tokens.into_iter());
+        let mut parser = ASTCreator::new(spanned(tokens));
         let ast = parser.parse().unwrap();
         assert_eq!(
             ast,
             AST::FunctionCall {
                 name: "average".to_string(),
                 arguments: vec![
-                    AST::CellName("A1".to_string()),
-                    AST::CellName("B2".to_string()),
+                    AST::CellName("A1".parse().unwrap()),
+                    AST::CellName("B2".parse().unwrap()),
                     AST::Value(Value::Number(42.0)),
                 ],
             }
@@ -373,7 +513,7 @@ mod tests {
             Token::RParen,
             Token::RParen,
         ];
-        let mut parser = ASTCreator::new(tokens.into_iter());
+        let mut parser = ASTCreator::new(spanned(tokens));
         let ast = parser.parse().unwrap();
         assert_eq!(
             ast,
@@ -382,8 +522,8 @@ mod tests {
                 arguments: vec![AST::FunctionCall {
                     name: "average".to_string(),
                     arguments: vec![
-                        AST::CellName("A1".to_string()),
-                        AST::CellName("B2".to_string()),
+                        AST::CellName("A1".parse().unwrap()),
+                        AST::CellName("B2".parse().unwrap()),
                     ],
                 }],
             }
@@ -400,7 +540,7 @@ mod tests {
             Token::Number(10.0),
             Token::RParen,
         ];
-        let mut parser = ASTCreator::new(tokens.into_iter());
+        let mut parser = ASTCreator::new(spanned(tokens));
         let ast = parser.parse().unwrap();
         assert_eq!(
             ast,
@@ -408,7 +548,7 @@ mod tests {
                 name: "max".to_string(),
                 arguments: vec![AST::BinaryOp {
                     op: Token::Plus,
-                    left: Box::new(AST::CellName("A1".to_string())),
+                    left: Box::new(AST::CellName("A1".parse().unwrap())),
                     right: Box::new(AST::Value(Value::Number(10.0))),
                 }],
             }
@@ -421,9 +561,12 @@ mod tests {
             Token::FunctionName("sum".to_string()),
             Token::CellName("A1".to_string()),
         ];
-        let mut parser = ASTCreator::new(tokens.into_iter());
+        let mut parser = ASTCreator::new(spanned(tokens));
         let result = parser.parse();
-        assert!(matches!(result, Err(ASTCreateError::UnexpectedToken)));
+        assert!(matches!(
+            result,
+            Err(ASTCreateError::UnexpectedToken { .. })
+        ));
     }
 
     #[test]
@@ -433,9 +576,12 @@ mod tests {
             Token::LParen,
             Token::CellName("A1".to_string()),
         ];
-        let mut parser = ASTCreator::new(tokens.into_iter());
+        let mut parser = ASTCreator::new(spanned(tokens));
         let result = parser.parse();
-        assert!(matches!(result, Err(ASTCreateError::MismatchedParentheses)));
+        assert!(matches!(
+            result,
+            Err(ASTCreateError::MismatchedParentheses { .. })
+        ));
     }
 
     #[test]
@@ -445,13 +591,13 @@ mod tests {
             Token::Colon,
             Token::CellName("B5".to_string()),
         ];
-        let mut parser = ASTCreator::new(tokens.into_iter());
+        let mut parser = ASTCreator::new(spanned(tokens));
         let ast = parser.parse().unwrap();
         assert_eq!(
             ast,
             AST::Range {
-                from: "A1".to_string(),
-                to: "B5".to_string(),
+                from: "A1".parse().unwrap(),
+                to: "B5".parse().unwrap(),
             }
         );
     }
@@ -466,15 +612,15 @@ mod tests {
             Token::CellName("A10".to_string()),
             Token::RParen,
         ];
-        let mut parser = ASTCreator::new(tokens.into_iter());
+        let mut parser = ASTCreator::new(spanned(tokens));
         let ast = parser.parse().unwrap();
         assert_eq!(
             ast,
             AST::FunctionCall {
                 name: "sum".to_string(),
                 arguments: vec![AST::Range {
-                    from: "A1".to_string(),
-                    to: "A10".to_string(),
+                    from: "A1".parse().unwrap(),
+                    to: "A10".parse().unwrap(),
                 }],
             }
         );
@@ -487,9 +633,9 @@ mod tests {
             Token::Colon,
             Token::Number(42.0), // Should be a cell name
         ];
-        let mut parser = ASTCreator::new(tokens.into_iter());
+        let mut parser = ASTCreator::new(spanned(tokens));
         let result = parser.parse();
-        assert!(matches!(result, Err(ASTCreateError::InvalidRange)));
+        assert!(matches!(result, Err(ASTCreateError::InvalidRange { .. })));
     }
 
     #[test]
@@ -498,9 +644,12 @@ mod tests {
             Token::CellName("A1".to_string()),
             Token::CellName("A10".to_string()),
         ];
-        let mut parser = ASTCreator::new(tokens.into_iter());
+        let mut parser = ASTCreator::new(spanned(tokens));
         let result = parser.parse();
-        assert!(matches!(result, Err(ASTCreateError::UnexpectedToken)));
+        assert!(matches!(
+            result,
+            Err(ASTCreateError::UnexpectedToken { .. })
+        ));
     }
 
     #[test]
@@ -512,15 +661,15 @@ mod tests {
             Token::Plus,
             Token::Number(5.0),
         ];
-        let mut parser = ASTCreator::new(tokens.into_iter());
+        let mut parser = ASTCreator::new(spanned(tokens));
         let ast = parser.parse().unwrap();
         assert_eq!(
             ast,
             AST::BinaryOp {
                 op: Token::Plus,
                 left: Box::new(AST::Range {
-                    from: "A1".to_string(),
-                    to: "A10".to_string(),
+                    from: "A1".parse().unwrap(),
+                    to: "A10".parse().unwrap(),
                 }),
                 right: Box::new(AST::Value(Value::Number(5.0))),
             }
@@ -530,12 +679,12 @@ mod tests {
     #[test]
     fn test_boolean_literals() {
         let tokens = vec![Token::Bool(true)];
-        let mut parser = ASTCreator::new(tokens.into_iter());
+        let mut parser = ASTCreator::new(spanned(tokens));
         let ast = parser.parse().unwrap();
         assert_eq!(ast, AST::Value(Value::Bool(true)));
 
         let tokens = vec![Token::Bool(false)];
-        let mut parser = ASTCreator::new(tokens.into_iter());
+        let mut parser = ASTCreator::new(spanned(tokens));
         let ast = parser.parse().unwrap();
         assert_eq!(ast, AST::Value(Value::Bool(false)));
     }
@@ -548,13 +697,13 @@ mod tests {
             Token::Equals,
             Token::Bool(true),
         ];
-        let mut parser = ASTCreator::new(tokens.into_iter());
+        let mut parser = ASTCreator::new(spanned(tokens));
         let ast = parser.parse().unwrap();
         assert_eq!(
             ast,
             AST::BinaryOp {
                 op: Token::Equals,
-                left: Box::new(AST::CellName("A1".to_string())),
+                left: Box::new(AST::CellName("A1".parse().unwrap())),
                 right: Box::new(AST::Value(Value::Bool(true))),
             }
         );
@@ -563,7 +712,7 @@ mod tests {
     #[test]
     fn test_not_operator() {
         let tokens = vec![Token::Not, Token::Bool(true)];
-        let mut parser = ASTCreator::new(tokens.into_iter());
+        let mut parser = ASTCreator::new(spanned(tokens));
         let ast = parser.parse().unwrap();
         assert_eq!(
             ast,
@@ -585,7 +734,7 @@ mod tests {
             Token::LessThan,
             Token::Number(20.0),
         ];
-        let mut parser = ASTCreator::new(tokens.into_iter());
+        let mut parser = ASTCreator::new(spanned(tokens));
         let ast = parser.parse().unwrap();
         assert_eq!(
             ast,
@@ -593,12 +742,12 @@ mod tests {
                 op: Token::And,
                 left: Box::new(AST::BinaryOp {
                     op: Token::GreaterThan,
-                    left: Box::new(AST::CellName("A1".to_string())),
+                    left: Box::new(AST::CellName("A1".parse().unwrap())),
                     right: Box::new(AST::Value(Value::Number(10.0))),
                 }),
                 right: Box::new(AST::BinaryOp {
                     op: Token::LessThan,
-                    left: Box::new(AST::CellName("B1".to_string())),
+                    left: Box::new(AST::CellName("B1".parse().unwrap())),
                     right: Box::new(AST::Value(Value::Number(20.0))),
                 }),
             }
@@ -616,7 +765,7 @@ mod tests {
             Token::Or,
             Token::Bool(false),
         ];
-        let mut parser = ASTCreator::new(tokens.into_iter());
+        let mut parser = ASTCreator::new(spanned(tokens));
         let ast = parser.parse().unwrap();
         assert_eq!(
             ast,
@@ -626,7 +775,7 @@ mod tests {
                     op: Token::And,
                     left: Box::new(AST::UnaryOp {
                         op: Token::Not,
-                        expr: Box::new(AST::CellName("A1".to_string())),
+                        expr: Box::new(AST::CellName("A1".parse().unwrap())),
                     }),
                     right: Box::new(AST::Value(Value::Bool(true))),
                 }),
@@ -650,7 +799,7 @@ mod tests {
             Token::Bool(false),
             Token::RParen,
         ];
-        let mut parser = ASTCreator::new(tokens.into_iter());
+        let mut parser = ASTCreator::new(spanned(tokens));
         let ast = parser.parse().unwrap();
         assert_eq!(
             ast,
@@ -659,7 +808,7 @@ mod tests {
                 arguments: vec![
                     AST::BinaryOp {
                         op: Token::GreaterThan,
-                        left: Box::new(AST::CellName("A1".to_string())),
+                        left: Box::new(AST::CellName("A1".parse().unwrap())),
                         right: Box::new(AST::Value(Value::Number(10.0))),
                     },
                     AST::Value(Value::Bool(true)),
@@ -683,7 +832,7 @@ mod tests {
             Token::CellName("B1".to_string()),
             Token::RParen,
         ];
-        let mut parser = ASTCreator::new(tokens.into_iter());
+        let mut parser = ASTCreator::new(spanned(tokens));
         let ast = parser.parse().unwrap();
         assert_eq!(
             ast,
@@ -693,11 +842,11 @@ mod tests {
                     op: Token::Not,
                     expr: Box::new(AST::BinaryOp {
                         op: Token::Equals,
-                        left: Box::new(AST::CellName("A1".to_string())),
+                        left: Box::new(AST::CellName("A1".parse().unwrap())),
                         right: Box::new(AST::Value(Value::Bool(true))),
                     }),
                 }),
-                right: Box::new(AST::CellName("B1".to_string())),
+                right: Box::new(AST::CellName("B1".parse().unwrap())),
             }
         );
     }
@@ -706,20 +855,20 @@ mod tests {
     #[test]
     fn test_invalid_not_operator() {
         let tokens = vec![Token::Not];
-        let mut parser = ASTCreator::new(tokens.into_iter());
+        let mut parser = ASTCreator::new(spanned(tokens));
         assert!(matches!(
             parser.parse(),
-            Err(ASTCreateError::UnexpectedToken)
+            Err(ASTCreateError::UnexpectedToken { .. })
         ));
     }
 
     #[test]
     fn test_invalid_comparison() {
         let tokens = vec![Token::CellName("A1".to_string()), Token::GreaterThan];
-        let mut parser = ASTCreator::new(tokens.into_iter());
+        let mut parser = ASTCreator::new(spanned(tokens));
         assert!(matches!(
             parser.parse(),
-            Err(ASTCreateError::UnexpectedToken)
+            Err(ASTCreateError::UnexpectedToken { .. })
         ));
     }
 
@@ -732,7 +881,7 @@ mod tests {
             Token::GreaterThan,
             Token::Number(10.0),
         ];
-        let mut parser = ASTCreator::new(tokens.into_iter());
+        let mut parser = ASTCreator::new(spanned(tokens));
         let ast = parser.parse().unwrap();
         assert_eq!(
             ast,
@@ -740,7 +889,7 @@ mod tests {
                 op: Token::GreaterThan,
                 left: Box::new(AST::BinaryOp {
                     op: Token::Plus,
-                    left: Box::new(AST::CellName("A1".to_string())),
+                    left: Box::new(AST::CellName("A1".parse().unwrap())),
                     right: Box::new(AST::Value(Value::Number(5.0))),
                 }),
                 right: Box::new(AST::Value(Value::Number(10.0))),
@@ -758,8 +907,37 @@ mod tests {
             Token::Number(10.0),
             Token::RParen,
         ];
-        let mut parser = ASTCreator::new(tokens.into_iter());
+        let mut parser = ASTCreator::new(spanned(tokens));
         let result = parser.parse();
-        assert!(matches!(result, Err(ASTCreateError::InvalidRange)));
+        assert!(matches!(result, Err(ASTCreateError::InvalidRange { .. })));
+    }
+
+    #[test]
+    fn test_sheet_reference() {
+        let tokens = vec![
+            Token::SheetName("Sheet2".to_string()),
+            Token::CellName("A1".to_string()),
+            Token::Colon,
+            Token::CellName("B5".to_string()),
+        ];
+        let mut parser = ASTCreator::new(spanned(tokens));
+        let ast = parser.parse().unwrap();
+        assert_eq!(
+            ast,
+            AST::SheetReference {
+                sheet: "Sheet2".to_string(),
+                reference: Box::new(AST::Range {
+                    from: "A1".parse().unwrap(),
+                    to: "B5".parse().unwrap(),
+                }),
+            }
+        );
+
+        let tokens = vec![Token::SheetName("Sheet2".to_string()), Token::Number(1.0)];
+        let mut parser = ASTCreator::new(spanned(tokens));
+        assert!(matches!(
+            parser.parse(),
+            Err(ASTCreateError::UnexpectedToken { .. })
+        ));
     }
 }
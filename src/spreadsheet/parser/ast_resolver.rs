@@ -1,31 +1,124 @@
-use builtin_functions::get_func;
+use function_registry::{ArgumentType, Function, FunctionRegistry};
 
-use crate::{common_functions::get_cell_idx, common_types::{ComputeError, Index, Token, Value, AST}};
+use crate::common_functions::check_date;
+use crate::common_types::{
+    Array, CellRange, CellReference, ComputeError, Dependency, Index, NumberLocale, Token, Value,
+    AST, MAX_RANGE_SPAN,
+};
+
+use super::CellParser;
 mod builtin_functions;
+mod criteria;
+pub mod function_registry;
 pub trait VarContext {
     fn get_variable(&self, index: Index) -> Option<Result<Value, ComputeError>>;
+
+    /// Looks up a cell of another sheet. Only contexts that know about other sheets, such as a workbook, can find them.
+    fn get_sheet_variable(
+        &self,
+        sheet: &str,
+        _index: Index,
+    ) -> Option<Result<Value, ComputeError>> {
+        Some(Err(ComputeError::UnfindableReference(format!(
+            "Unknown sheet '{sheet}'"
+        ))))
+    }
+
+    /// Looks up the definition of a name, such as `TaxRate`. Only contexts that keep names, such as a workbook, can find them.
+    fn get_name(&self, _name: &str) -> Option<&AST> {
+        None
+    }
+
+    /// Looks up a function formulas can call, the builtin functions unless the context keeps its own registry.
+    fn get_function(&self, name: &str) -> Option<&Function> {
+        FunctionRegistry::builtins().get(name)
+    }
+
+    /// Records a reference only known once the formula is computed, such as the cell `indirect("B" & A1)` reads.
+    /// Contexts keeping a dependency graph add it to the dependencies of the cell being computed.
+    fn add_dependency(&self, _dependency: Dependency) {}
+
+    /// How numbers are written in the formulas computed, which functions reading numbers out of text follow.
+    fn number_locale(&self) -> NumberLocale {
+        NumberLocale::default()
+    }
+}
+
+// Resolves the cell names inside a sheet reference against the referenced sheet
+struct SheetContext<'a> {
+    variables: &'a dyn VarContext,
+    sheet: &'a str,
+}
+
+impl VarContext for SheetContext<'_> {
+    fn get_variable(&self, index: Index) -> Option<Result<Value, ComputeError>> {
+        self.variables.get_sheet_variable(self.sheet, index)
+    }
+
+    fn get_sheet_variable(&self, sheet: &str, index: Index) -> Option<Result<Value, ComputeError>> {
+        self.variables.get_sheet_variable(sheet, index)
+    }
+
+    fn get_name(&self, name: &str) -> Option<&AST> {
+        self.variables.get_name(name)
+    }
+
+    fn get_function(&self, name: &str) -> Option<&Function> {
+        self.variables.get_function(name)
+    }
+
+    fn add_dependency(&self, dependency: Dependency) {
+        self.variables.add_dependency(dependency)
+    }
+
+    fn number_locale(&self) -> NumberLocale {
+        self.variables.number_locale()
+    }
 }
 
+/// Special forms resulting in a reference or an expression rather than a value, which can stand wherever a reference can.
+const REFERENCE_FORMS: [&str; 3] = ["choose", "offset", "indirect"];
+
 pub struct ASTResolver {}
 
 impl ASTResolver {
     pub fn resolve(ast: &AST, variables: &dyn VarContext) -> Result<Value, ComputeError> {
         match ast {
-            AST::Value(value) => Ok(value.clone()),
-            AST::CellName(name) => match variables.get_variable(
-                get_cell_idx(name)
-                    .ok_or(ComputeError::ParseError("Invalid cell name".to_string()))?,
-            ) {
+            AST::Value(value) => value.clone().into_result(),
+            AST::CellName(reference) => match variables.get_variable(reference.index) {
                 Some(value) => value,
                 None => Err(ComputeError::UnfindableReference(format!(
-                    "Could not find variable {name} with in context"
+                    "Could not find variable {reference} with in context"
                 ))),
             },
+            AST::BinaryOp {
+                op: op @ (Token::And | Token::Or),
+                left,
+                right,
+            } => {
+                // The right side is only computed when the left side does not decide the result
+                let error = || {
+                    ComputeError::TypeError(match op {
+                        Token::And => "Logical AND requires two boolean values".to_string(),
+                        _ => "Logical OR requires two boolean values".to_string(),
+                    })
+                };
+                let Value::Bool(left_resolved) = Self::resolve(left, variables)? else {
+                    return Err(error());
+                };
+                if left_resolved == (*op == Token::Or) {
+                    return Ok(Value::Bool(left_resolved));
+                }
+                match Self::resolve(right, variables)? {
+                    Value::Bool(right_resolved) => Ok(Value::Bool(right_resolved)),
+                    _ => Err(error()),
+                }
+            }
             AST::BinaryOp { op, left, right } => {
                 let left_resolved = Self::resolve(left, variables)?;
                 let right_resolved = Self::resolve(right, variables)?;
 
-                match op {
+                let result = match op {
                     Token::Plus => {
                         left_resolved
                             .add(right_resolved)
@@ -40,6 +133,34 @@ impl ASTResolver {
                                 "Subtraction requires two numeric values".to_string(),
                             ))
                     }
+                    Token::Division | Token::Mod if right_resolved == Value::Number(0.0) => {
+                        Err(ComputeError::DivisionByZero)
+                    }
+                    Token::Mod => {
+                        left_resolved
+                            .modulo(right_resolved)
+                            .ok_or(ComputeError::TypeError(
+                                "Modulo requires two numeric values".to_string(),
+                            ))
+                    }
+                    Token::Power => match left_resolved.pow(right_resolved.clone()) {
+                        // Negative powers of zero divide by zero
+                        Some(Value::Number(result))
+                            if result.is_infinite() && left_resolved == Value::Number(0.0) =>
+                        {
+                            Err(ComputeError::DivisionByZero)
+                        }
+                        Some(Value::Number(result)) if result.is_nan() => {
+                            Err(ComputeError::NumberError(format!(
+                                "{left_resolved} ^ {right_resolved} has no real result"
+                            )))
+                        }
+                        Some(result) => Ok(result),
+                        None => Err(ComputeError::TypeError(
+                            "Exponentiation requires two numeric values".to_string(),
+                        )),
+                    },
+                    Token::Concat => Ok(left_resolved.concat(right_resolved)),
                     Token::Division => {
                         left_resolved
                             .div(right_resolved)
@@ -55,8 +176,8 @@ impl ASTResolver {
                             ))
                     }
 
-                    Token::Equals => Ok(Value::Bool(left_resolved.eq(&right_resolved))),
-                    Token::NotEquals => Ok(Value::Bool(left_resolved.ne(&right_resolved))),
+                    Token::Equals => Ok(Value::Bool(left_resolved.equals(&right_resolved))),
+                    Token::NotEquals => Ok(Value::Bool(!left_resolved.equals(&right_resolved))),
                     Token::GreaterThan => {
                         left_resolved
                             .greater_than(right_resolved)
@@ -86,70 +207,429 @@ impl ASTResolver {
                                 "Less or equal comparison requires two numeric values".to_string(),
                             ))
                     }
-                    Token::And => left_resolved
-                        .and(right_resolved)
-                        .ok_or(ComputeError::TypeError(
-                            "Logical AND requires two boolean values".to_string(),
-                        )),
-                    Token::Or => left_resolved
-                        .or(right_resolved)
-                        .ok_or(ComputeError::TypeError(
-                            "Logical OR requires two boolean values".to_string(),
-                        )),
                     other => panic!("{other:?} is not a binary operator"),
+                };
+                match result {
+                    Ok(Value::Number(num)) if num.is_infinite() => Err(ComputeError::NumberError(
+                        "The result is too large to be a number".to_string(),
+                    )),
+                    result => result.and_then(check_date),
                 }
             }
+            AST::RefError => Err(ComputeError::UnfindableReference(
+                "Refers to a cell that was deleted".to_owned(),
+            )),
+            AST::SheetReference { sheet, reference } => {
+                Self::resolve(reference, &SheetContext { variables, sheet })
+            }
+            AST::Name(name) => Self::resolve(Self::get_name(name, variables)?, variables),
             AST::Range { from: _, to: _ } => Err(ComputeError::TypeError(
                 "Ranges can only appear as function arguments".to_owned(),
             )),
 
-            AST::FunctionCall { name, arguments } => {
-                let mut resolved_args = Vec::new();
-                for arg in arguments {
-                    match arg {
-                        AST::Range { from, to } => {
-                            for index in Self::range_to_indeces(from, to) {
-                                if let Some(var) = variables.get_variable(index) {
-                                    resolved_args.push(var?)
-                                }
-                            }
-                        }
-                        ast => resolved_args.push(Self::resolve(ast, variables)?),
+            AST::FunctionCall { name, arguments } => match name.as_str() {
+                "if" => Self::resolve_if(arguments, variables),
+                "ifs" => Self::resolve_ifs(arguments, variables),
+                "switch" => Self::resolve_switch(arguments, variables),
+                "iferror" => Self::resolve_iferror(arguments, variables),
+                "and" | "or" => Self::resolve_and_or(name, arguments, variables),
+                "choose" | "offset" | "indirect" => Self::resolve(
+                    &Self::resolve_reference(name, arguments, variables)?,
+                    variables,
+                ),
+                _ => Self::resolve_function(name, arguments, variables),
+            },
+            AST::UnaryOp { op, expr } => match (op, Self::resolve(expr, variables)?) {
+                (Token::Not, Value::Bool(boolean)) => Ok(Value::Bool(!boolean)),
+                (Token::Not, _) => Err(ComputeError::TypeError(
+                    "Not(!) operator can only work on boolean expressions".to_owned(),
+                )),
+                (Token::Minus, Value::Number(num)) => Ok(Value::Number(-num)),
+                (Token::Minus, _) => Err(ComputeError::TypeError(
+                    "Negation requires a numeric value".to_owned(),
+                )),
+                (Token::Percent, Value::Number(num)) => Ok(Value::Number(num / 100.0)),
+                (Token::Percent, _) => Err(ComputeError::TypeError(
+                    "Percent requires a numeric value".to_owned(),
+                )),
+                (other, _) => panic!("{other:?} is not a unary operator"),
+            },
+        }
+    }
+
+    fn resolve_function(
+        name: &str,
+        arguments: &[AST],
+        variables: &dyn VarContext,
+    ) -> Result<Value, ComputeError> {
+        let Some(function) = variables.get_function(name) else {
+            return Err(ComputeError::UnknownFunction(name.to_owned()));
+        };
+
+        // Arguments that fail to compute are passed on as error values
+        let mut resolved_args = Vec::new();
+        for arg in arguments {
+            let position = resolved_args.len();
+            let resolved =
+                if function.signature().argument_type(position) == Some(ArgumentType::Range) {
+                    Self::resolve_array(arg, variables)
+                        .map(|array| resolved_args.push(Value::Array(array)))
+                } else {
+                    Self::resolve_argument(arg, variables, &mut resolved_args)
+                };
+            if let Err(error) = resolved {
+                resolved_args.push(Value::Error(error));
+            }
+        }
+
+        function.call_with_locale(resolved_args, variables.number_locale())
+    }
+
+    // The functions below are special forms, they only compute the arguments needed for their result
+
+    fn resolve_if(arguments: &[AST], variables: &dyn VarContext) -> Result<Value, ComputeError> {
+        let [condition, if_true, if_false] = arguments else {
+            return Err(ComputeError::InvalidArgument(
+                "if expects exactly three arguments".to_string(),
+            ));
+        };
+
+        if Self::resolve_condition(condition, variables, "if")? {
+            Self::resolve(if_true, variables)
+        } else {
+            Self::resolve(if_false, variables)
+        }
+    }
+
+    fn resolve_ifs(arguments: &[AST], variables: &dyn VarContext) -> Result<Value, ComputeError> {
+        if arguments.is_empty() || !arguments.len().is_multiple_of(2) {
+            return Err(ComputeError::InvalidArgument(
+                "ifs expects pairs of a condition and a value".to_string(),
+            ));
+        }
+
+        for pair in arguments.chunks(2) {
+            if Self::resolve_condition(&pair[0], variables, "ifs")? {
+                return Self::resolve(&pair[1], variables);
+            }
+        }
+        Err(ComputeError::NotAvailable(
+            "None of the conditions of ifs hold".to_string(),
+        ))
+    }
+
+    fn resolve_switch(
+        arguments: &[AST],
+        variables: &dyn VarContext,
+    ) -> Result<Value, ComputeError> {
+        let Some((expression, cases)) = arguments
+            .split_first()
+            .filter(|(_, cases)| cases.len() >= 2)
+        else {
+            return Err(ComputeError::InvalidArgument(
+                "switch expects a value followed by pairs of a case and a result".to_string(),
+            ));
+        };
+
+        let value = Self::resolve(expression, variables)?;
+        let mut pairs = cases.chunks_exact(2);
+        for pair in pairs.by_ref() {
+            if Self::resolve(&pair[0], variables)? == value {
+                return Self::resolve(&pair[1], variables);
+            }
+        }
+        // An argument left over after the pairs is the default
+        match pairs.remainder() {
+            [default] => Self::resolve(default, variables),
+            _ => Err(ComputeError::NotAvailable(format!(
+                "switch has no case matching {value}"
+            ))),
+        }
+    }
+
+    fn resolve_iferror(
+        arguments: &[AST],
+        variables: &dyn VarContext,
+    ) -> Result<Value, ComputeError> {
+        let [value, fallback] = arguments else {
+            return Err(ComputeError::InvalidArgument(
+                "iferror expects exactly two arguments".to_string(),
+            ));
+        };
+
+        Self::resolve(value, variables).or_else(|_| Self::resolve(fallback, variables))
+    }
+
+    fn resolve_and_or(
+        name: &str,
+        arguments: &[AST],
+        variables: &dyn VarContext,
+    ) -> Result<Value, ComputeError> {
+        if arguments.is_empty() {
+            return Err(ComputeError::InvalidArgument(format!(
+                "{name} expects at least one boolean value"
+            )));
+        }
+
+        // `and` stops at the first false value and `or` at the first true one
+        let decisive = name == "or";
+        for argument in arguments {
+            let mut values = Vec::new();
+            Self::resolve_argument(argument, variables, &mut values)?;
+            for value in values {
+                match value {
+                    Value::Bool(b) if b == decisive => return Ok(Value::Bool(decisive)),
+                    Value::Bool(_) => (),
+                    Value::Error(error) => return Err(error),
+                    _ => {
+                        return Err(ComputeError::InvalidArgument(format!(
+                            "{name} expects only boolean values"
+                        )))
                     }
                 }
+            }
+        }
+        Ok(Value::Bool(!decisive))
+    }
 
-                if let Some(func) = get_func(name) {
-                    func(resolved_args)
-                } else {
-                    Err(ComputeError::UnknownFunction(name.to_owned()))
+    fn resolve_condition(
+        condition: &AST,
+        variables: &dyn VarContext,
+        name: &str,
+    ) -> Result<bool, ComputeError> {
+        match Self::resolve(condition, variables)? {
+            Value::Bool(b) => Ok(b),
+            _ => Err(ComputeError::InvalidArgument(format!(
+                "{name} expects boolean conditions"
+            ))),
+        }
+    }
+
+    // Ranges are flattened into the values of their non empty cells
+    fn resolve_argument(
+        ast: &AST,
+        variables: &dyn VarContext,
+        resolved_args: &mut Vec<Value>,
+    ) -> Result<(), ComputeError> {
+        match ast {
+            AST::Range { from, to } => {
+                for index in CellRange::new(from.index, to.index).indices() {
+                    if let Some(var) = variables.get_variable(index) {
+                        resolved_args.push(var.unwrap_or_else(Value::Error))
+                    }
                 }
             }
-            AST::UnaryOp { op, expr } => {
-                matches!(op, Token::Not);
-                if let Value::Bool(boolean) = Self::resolve(expr, variables)? {
-                    Ok(Value::Bool(!boolean))
-                } else {
-                    Err(ComputeError::TypeError(
-                        "Not(!) operator can only work on boolean expressions".to_owned(),
+            AST::SheetReference { sheet, reference } => Self::resolve_argument(
+                reference,
+                &SheetContext { variables, sheet },
+                resolved_args,
+            )?,
+            AST::Name(name) => {
+                Self::resolve_argument(Self::get_name(name, variables)?, variables, resolved_args)?
+            }
+            AST::FunctionCall { name, arguments } if REFERENCE_FORMS.contains(&name.as_str()) => {
+                let reference = Self::resolve_reference(name, arguments, variables)?;
+                Self::resolve_argument(&reference, variables, resolved_args)?
+            }
+            ast => resolved_args.push(Self::resolve(ast, variables)?),
+        }
+        Ok(())
+    }
+
+    // Ranges keep their shape and empty cells for the arguments declared as ranges
+    fn resolve_array(ast: &AST, variables: &dyn VarContext) -> Result<Array, ComputeError> {
+        match ast {
+            AST::Range { from, to } => {
+                let CellRange { start, end } = CellRange::new(from.index, to.index);
+                let cells = (start.y..=end.y)
+                    .flat_map(|y| (start.x..=end.x).map(move |x| Index { x, y }))
+                    .map(|index| {
+                        variables
+                            .get_variable(index)
+                            .map(|var| var.unwrap_or_else(Value::Error))
+                    })
+                    .collect();
+                Ok(Array::new(end.y - start.y + 1, end.x - start.x + 1, cells))
+            }
+            AST::SheetReference { sheet, reference } => {
+                Self::resolve_array(reference, &SheetContext { variables, sheet })
+            }
+            AST::Name(name) => Self::resolve_array(Self::get_name(name, variables)?, variables),
+            AST::FunctionCall { name, arguments } if REFERENCE_FORMS.contains(&name.as_str()) => {
+                Self::resolve_array(
+                    &Self::resolve_reference(name, arguments, variables)?,
+                    variables,
+                )
+            }
+            ast => Ok(Array::scalar(Self::resolve(ast, variables)?)),
+        }
+    }
+
+    // Results in the expression the function stands for, so that it can be used wherever a reference can,
+    // as in `sum(offset(A1, 1, 0, 3))`
+    fn resolve_reference(
+        name: &str,
+        arguments: &[AST],
+        variables: &dyn VarContext,
+    ) -> Result<AST, ComputeError> {
+        let reference =
+            match (name, arguments) {
+                ("choose", [index, choices @ ..]) if !choices.is_empty() => {
+                    let position = Self::resolve_number(index, variables, "choose")?.trunc();
+                    if position < 1.0 || position > choices.len() as f64 {
+                        return Err(ComputeError::InvalidArgument(format!(
+                            "choose expects an index between 1 and {}",
+                            choices.len()
+                        )));
+                    }
+                    // The choice is not a reference found while computing, it is already a dependency
+                    return Ok(choices[position as usize - 1].clone());
+                }
+                ("indirect", [text]) => match Self::resolve(text, variables)? {
+                    Value::Text(text) => CellParser::parse_reference(&text).ok_or_else(|| {
+                        ComputeError::UnfindableReference(format!("'{text}' is not a reference"))
+                    })?,
+                    other => {
+                        return Err(ComputeError::InvalidArgument(format!(
+                            "indirect expects the text of a reference, got {other}"
+                        )))
+                    }
+                },
+                ("offset", [reference, rows, columns, size @ ..]) if size.len() <= 2 => {
+                    Self::resolve_offset(reference, [rows, columns], size, variables)?
+                }
+                ("choose", _) => {
+                    return Err(ComputeError::InvalidArgument(
+                        "choose expects an index followed by the values to choose from".to_string(),
                     ))
                 }
-            }
+                ("indirect", _) => {
+                    return Err(ComputeError::InvalidArgument(
+                        "indirect expects exactly 1 argument".to_string(),
+                    ))
+                }
+                _ => return Err(ComputeError::InvalidArgument(
+                    "offset expects a reference, rows, columns and optionally a height and width"
+                        .to_string(),
+                )),
+            };
+
+        for dependency in CellParser::dependencies(&reference) {
+            variables.add_dependency(dependency);
         }
+        Ok(reference)
     }
 
-   
+    // Moves the reference by the given rows and columns, resizing it when a height or width is given
+    fn resolve_offset(
+        reference: &AST,
+        [rows, columns]: [&AST; 2],
+        size: &[AST],
+        variables: &dyn VarContext,
+    ) -> Result<AST, ComputeError> {
+        let range = match reference {
+            AST::CellName(cell) => CellRange::new(cell.index, cell.index),
+            AST::Range { from, to } => CellRange::new(from.index, to.index),
+            AST::SheetReference { sheet, reference } => {
+                return Ok(AST::SheetReference {
+                    sheet: sheet.clone(),
+                    reference: Box::new(Self::resolve_offset(
+                        reference,
+                        [rows, columns],
+                        size,
+                        variables,
+                    )?),
+                })
+            }
+            AST::Name(name) => {
+                let reference = Self::get_name(name, variables)?;
+                return Self::resolve_offset(reference, [rows, columns], size, variables);
+            }
+            AST::FunctionCall { name, arguments } if REFERENCE_FORMS.contains(&name.as_str()) => {
+                let reference = Self::resolve_reference(name, arguments, variables)?;
+                return Self::resolve_offset(&reference, [rows, columns], size, variables);
+            }
+            _ => {
+                return Err(ComputeError::InvalidArgument(
+                    "offset expects a cell or range to start from".to_string(),
+                ))
+            }
+        };
+
+        let mut numbers = Vec::new();
+        for argument in [rows, columns].into_iter().chain(size) {
+            numbers.push(Self::resolve_number(argument, variables, "offset")?.trunc());
+        }
+        let height = numbers
+            .get(2)
+            .copied()
+            .unwrap_or((range.end.y - range.start.y + 1) as f64);
+        let width = numbers
+            .get(3)
+            .copied()
+            .unwrap_or((range.end.x - range.start.x + 1) as f64);
+        let outside =
+            || ComputeError::UnfindableReference("offset is outside of the grid".to_string());
+        if height < 1.0 || width < 1.0 {
+            return Err(outside());
+        }
+        // The same limit as for ranges written in a formula
+        if height > (MAX_RANGE_SPAN + 1) as f64 || width > (MAX_RANGE_SPAN + 1) as f64 {
+            return Err(ComputeError::UnfindableReference(format!(
+                "offset can not reach more than {} rows or columns",
+                MAX_RANGE_SPAN + 1
+            )));
+        }
 
-    fn range_to_indeces(from: &str, to: &str) -> Vec<Index> {
-        let start = get_cell_idx(from).unwrap_or(Index{ x:0, y:0 });
-        let end = get_cell_idx(to).unwrap_or(Index{ x:0, y:0 });
-        let mut indices = Vec::new();
-        for x in start.x..=end.x {
-            for y in start.y..=end.y {
-                indices.push(Index { x, y });
+        let start = Index {
+            x: range
+                .start
+                .x
+                .checked_add_signed(numbers[1] as isize)
+                .ok_or_else(outside)?,
+            y: range
+                .start
+                .y
+                .checked_add_signed(numbers[0] as isize)
+                .ok_or_else(outside)?,
+        };
+        let end = Index {
+            x: start
+                .x
+                .checked_add(width as usize - 1)
+                .ok_or_else(outside)?,
+            y: start
+                .y
+                .checked_add(height as usize - 1)
+                .ok_or_else(outside)?,
+        };
+        Ok(if start == end {
+            AST::CellName(CellReference::relative(start))
+        } else {
+            AST::Range {
+                from: CellReference::relative(start),
+                to: CellReference::relative(end),
             }
+        })
+    }
+
+    fn resolve_number(
+        ast: &AST,
+        variables: &dyn VarContext,
+        name: &str,
+    ) -> Result<f64, ComputeError> {
+        match Self::resolve(ast, variables)? {
+            Value::Number(num) => Ok(num),
+            other => Err(ComputeError::InvalidArgument(format!(
+                "{name} expects a number, got {other}"
+            ))),
         }
+    }
 
-        indices
+    fn get_name<'a>(name: &str, variables: &'a dyn VarContext) -> Result<&'a AST, ComputeError> {
+        variables
+            .get_name(name)
+            .ok_or_else(|| ComputeError::UnknownName(format!("Unknown name '{name}'")))
     }
 }
 
@@ -189,7 +669,7 @@ mod tests {
         vars.insert(Index { x: 0, y: 0 }, Value::Number(10.0));
 
         let variables = MockVarContext::new(vars);
-        let ast = AST::CellName("A1".to_string());
+        let ast = AST::CellName("A1".parse().unwrap());
 
         let result = ASTResolver::resolve(&ast, &variables).unwrap();
         assert_eq!(result, Value::Number(10.0));
@@ -204,8 +684,8 @@ mod tests {
         let variables = MockVarContext::new(vars);
         let ast = AST::BinaryOp {
             op: Token::Plus,
-            left: Box::new(AST::CellName("A1".to_string())),
-            right: Box::new(AST::CellName("B1".to_string())),
+            left: Box::new(AST::CellName("A1".parse().unwrap())),
+            right: Box::new(AST::CellName("B1".parse().unwrap())),
         };
 
         let result = ASTResolver::resolve(&ast, &variables).unwrap();
@@ -221,8 +701,8 @@ mod tests {
         let variables = MockVarContext::new(vars);
         let ast = AST::BinaryOp {
             op: Token::Minus,
-            left: Box::new(AST::CellName("A1".to_string())),
-            right: Box::new(AST::CellName("B1".to_string())),
+            left: Box::new(AST::CellName("A1".parse().unwrap())),
+            right: Box::new(AST::CellName("B1".parse().unwrap())),
         };
 
         let result = ASTResolver::resolve(&ast, &variables).unwrap();
@@ -238,8 +718,8 @@ mod tests {
         let variables = MockVarContext::new(vars);
         let ast = AST::BinaryOp {
             op: Token::Multiply,
-            left: Box::new(AST::CellName("A1".to_string())),
-            right: Box::new(AST::CellName("B1".to_string())),
+            left: Box::new(AST::CellName("A1".parse().unwrap())),
+            right: Box::new(AST::CellName("B1".parse().unwrap())),
         };
 
         let result = ASTResolver::resolve(&ast, &variables).unwrap();
@@ -255,8 +735,8 @@ mod tests {
         let variables = MockVarContext::new(vars);
         let ast = AST::BinaryOp {
             op: Token::Division,
-            left: Box::new(AST::CellName("A1".to_string())),
-            right: Box::new(AST::CellName("B1".to_string())),
+            left: Box::new(AST::CellName("A1".parse().unwrap())),
+            right: Box::new(AST::CellName("B1".parse().unwrap())),
         };
 
         let result = ASTResolver::resolve(&ast, &variables).unwrap();
@@ -267,7 +747,7 @@ mod tests {
     #[should_panic]
     fn test_resolve_missing_cellname() {
         let variables = MockVarContext::new(HashMap::new());
-        let ast = AST::CellName("A1".to_string());
+        let ast = AST::CellName("A1".parse().unwrap());
 
         // This should panic because "A1" is not in the context
         ASTResolver::resolve(&ast, &variables).unwrap();
@@ -285,10 +765,10 @@ mod tests {
             op: Token::Plus,
             left: Box::new(AST::BinaryOp {
                 op: Token::Multiply,
-                left: Box::new(AST::CellName("A1".to_string())),
-                right: Box::new(AST::CellName("B1".to_string())),
+                left: Box::new(AST::CellName("A1".parse().unwrap())),
+                right: Box::new(AST::CellName("B1".parse().unwrap())),
             }),
-            right: Box::new(AST::CellName("C1".to_string())),
+            right: Box::new(AST::CellName("C1".parse().unwrap())),
         };
 
         let result = ASTResolver::resolve(&ast, &variables).unwrap();
@@ -307,10 +787,10 @@ mod tests {
             op: Token::Minus,
             left: Box::new(AST::BinaryOp {
                 op: Token::Division,
-                left: Box::new(AST::CellName("A1".to_string())),
-                right: Box::new(AST::CellName("B1".to_string())),
+                left: Box::new(AST::CellName("A1".parse().unwrap())),
+                right: Box::new(AST::CellName("B1".parse().unwrap())),
             }),
-            right: Box::new(AST::CellName("C1".to_string())),
+            right: Box::new(AST::CellName("C1".parse().unwrap())),
         };
 
         let result = ASTResolver::resolve(&ast, &variables).unwrap();
@@ -318,6 +798,7 @@ mod tests {
     }
 
     #[cfg(test)]
+    #[allow(clippy::module_inception)]
     mod tests {
         use super::*;
         use crate::common_types::{Index, Token};
@@ -333,8 +814,8 @@ mod tests {
             let ast = AST::FunctionCall {
                 name: "sum".to_string(),
                 arguments: vec![
-                    AST::CellName("A1".to_string()),
-                    AST::CellName("B1".to_string()),
+                    AST::CellName("A1".parse().unwrap()),
+                    AST::CellName("B1".parse().unwrap()),
                 ],
             };
 
@@ -353,8 +834,8 @@ mod tests {
             let ast = AST::FunctionCall {
                 name: "sum".to_string(),
                 arguments: vec![AST::Range {
-                    from: "A1".to_string(),
-                    to: "A3".to_string(),
+                    from: "A1".parse().unwrap(),
+                    to: "A3".parse().unwrap(),
                 }],
             };
 
@@ -373,8 +854,8 @@ mod tests {
                 name: "sum".to_string(),
                 arguments: vec![
                     AST::Range {
-                        from: "A1".to_string(),
-                        to: "A2".to_string(),
+                        from: "A1".parse().unwrap(),
+                        to: "A2".parse().unwrap(),
                     },
                     AST::Value(Value::Number(5.0)),
                 ],
@@ -395,7 +876,7 @@ mod tests {
                 arguments: vec![
                     AST::BinaryOp {
                         op: Token::Plus,
-                        left: Box::new(AST::CellName("A1".to_string())),
+                        left: Box::new(AST::CellName("A1".parse().unwrap())),
                         right: Box::new(AST::Value(Value::Number(5.0))),
                     },
                     AST::Value(Value::Number(15.0)),
@@ -419,8 +900,8 @@ mod tests {
                     AST::FunctionCall {
                         name: "sum".to_string(),
                         arguments: vec![
-                            AST::CellName("A1".to_string()),
-                            AST::CellName("B1".to_string()),
+                            AST::CellName("A1".parse().unwrap()),
+                            AST::CellName("B1".parse().unwrap()),
                         ],
                     },
                     AST::Value(Value::Number(5.0)),
@@ -452,7 +933,7 @@ mod tests {
 
             let ast = AST::FunctionCall {
                 name: "sum".to_string(),
-                arguments: vec![AST::CellName("A1".to_string())],
+                arguments: vec![AST::CellName("A1".parse().unwrap())],
             };
 
             let result = ASTResolver::resolve(&ast, &variables);
@@ -466,8 +947,8 @@ mod tests {
             let ast = AST::FunctionCall {
                 name: "sum".to_string(),
                 arguments: vec![AST::Range {
-                    from: "A1".to_string(),
-                    to: "A2".to_string(),
+                    from: "A1".parse().unwrap(),
+                    to: "A2".parse().unwrap(),
                 }],
             };
 
@@ -505,8 +986,8 @@ mod tests {
         // Test true && false
         let ast = AST::BinaryOp {
             op: Token::And,
-            left: Box::new(AST::CellName("A1".to_string())),
-            right: Box::new(AST::CellName("A2".to_string())),
+            left: Box::new(AST::CellName("A1".parse().unwrap())),
+            right: Box::new(AST::CellName("A2".parse().unwrap())),
         };
         let result = ASTResolver::resolve(&ast, &variables).unwrap();
         assert_eq!(result, Value::Bool(false));
@@ -522,8 +1003,8 @@ mod tests {
         // Test true || false
         let ast = AST::BinaryOp {
             op: Token::Or,
-            left: Box::new(AST::CellName("A1".to_string())),
-            right: Box::new(AST::CellName("A2".to_string())),
+            left: Box::new(AST::CellName("A1".parse().unwrap())),
+            right: Box::new(AST::CellName("A2".parse().unwrap())),
         };
         let result = ASTResolver::resolve(&ast, &variables).unwrap();
         assert_eq!(result, Value::Bool(true));
@@ -539,8 +1020,8 @@ mod tests {
         // Test greater than
         let ast = AST::BinaryOp {
             op: Token::GreaterThan,
-            left: Box::new(AST::CellName("A1".to_string())),
-            right: Box::new(AST::CellName("A2".to_string())),
+            left: Box::new(AST::CellName("A1".parse().unwrap())),
+            right: Box::new(AST::CellName("A2".parse().unwrap())),
         };
         let result = ASTResolver::resolve(&ast, &variables).unwrap();
         assert_eq!(result, Value::Bool(false));
@@ -548,8 +1029,8 @@ mod tests {
         // Test less than
         let ast = AST::BinaryOp {
             op: Token::LessThan,
-            left: Box::new(AST::CellName("A1".to_string())),
-            right: Box::new(AST::CellName("A2".to_string())),
+            left: Box::new(AST::CellName("A1".parse().unwrap())),
+            right: Box::new(AST::CellName("A2".parse().unwrap())),
         };
         let result = ASTResolver::resolve(&ast, &variables).unwrap();
         assert_eq!(result, Value::Bool(true));
@@ -566,8 +1047,8 @@ mod tests {
         // Test number equality
         let ast = AST::BinaryOp {
             op: Token::Equals,
-            left: Box::new(AST::CellName("A1".to_string())),
-            right: Box::new(AST::CellName("A2".to_string())),
+            left: Box::new(AST::CellName("A1".parse().unwrap())),
+            right: Box::new(AST::CellName("A2".parse().unwrap())),
         };
         let result = ASTResolver::resolve(&ast, &variables).unwrap();
         assert_eq!(result, Value::Bool(true));
@@ -575,8 +1056,8 @@ mod tests {
         // Test different types equality
         let ast = AST::BinaryOp {
             op: Token::Equals,
-            left: Box::new(AST::CellName("A1".to_string())),
-            right: Box::new(AST::CellName("A3".to_string())),
+            left: Box::new(AST::CellName("A1".parse().unwrap())),
+            right: Box::new(AST::CellName("A3".parse().unwrap())),
         };
         let result = ASTResolver::resolve(&ast, &variables).unwrap();
         assert_eq!(result, Value::Bool(false));
@@ -593,11 +1074,11 @@ mod tests {
         // Test (A1 && (A2 > A3))
         let ast = AST::BinaryOp {
             op: Token::And,
-            left: Box::new(AST::CellName("A1".to_string())),
+            left: Box::new(AST::CellName("A1".parse().unwrap())),
             right: Box::new(AST::BinaryOp {
                 op: Token::GreaterThan,
-                left: Box::new(AST::CellName("A2".to_string())),
-                right: Box::new(AST::CellName("A3".to_string())),
+                left: Box::new(AST::CellName("A2".parse().unwrap())),
+                right: Box::new(AST::CellName("A3".parse().unwrap())),
             }),
         };
         let result = ASTResolver::resolve(&ast, &variables).unwrap();
@@ -616,8 +1097,8 @@ mod tests {
 
         let ast = AST::BinaryOp {
             op: Token::And,
-            left: Box::new(AST::CellName("A1".to_string())),
-            right: Box::new(AST::CellName("A2".to_string())),
+            left: Box::new(AST::CellName("A1".parse().unwrap())),
+            right: Box::new(AST::CellName("A2".parse().unwrap())),
         };
         let result = ASTResolver::resolve(&ast, &variables);
         assert!(matches!(result, Err(ComputeError::TypeError(_))));
@@ -631,7 +1112,7 @@ mod tests {
 
         let ast = AST::UnaryOp {
             op: Token::Not,
-            expr: Box::new(AST::CellName("A1".to_string())),
+            expr: Box::new(AST::CellName("A1".parse().unwrap())),
         };
         let result = ASTResolver::resolve(&ast, &variables);
         assert!(matches!(result, Err(ComputeError::TypeError(_))));
@@ -646,10 +1127,106 @@ mod tests {
 
         let ast = AST::BinaryOp {
             op: Token::GreaterThan,
-            left: Box::new(AST::CellName("A1".to_string())),
-            right: Box::new(AST::CellName("A2".to_string())),
+            left: Box::new(AST::CellName("A1".parse().unwrap())),
+            right: Box::new(AST::CellName("A2".parse().unwrap())),
         };
         let result = ASTResolver::resolve(&ast, &variables);
         assert!(matches!(result, Err(ComputeError::TypeError(_))));
     }
+
+    // Parses and computes an expression without any cells
+    fn evaluate(expression: &str) -> Result<Value, ComputeError> {
+        use crate::spreadsheet::parser::{ast_creator::ASTCreator, tokenizer::ExpressionTokenizer};
+
+        let tokens = ExpressionTokenizer::new(expression.chars().collect())
+            .tokenize_with_spans()
+            .unwrap();
+        let ast = ASTCreator::new(tokens.into_iter()).parse().unwrap();
+        ASTResolver::resolve(&ast, &MockVarContext::new(HashMap::new()))
+    }
+
+    #[test]
+    fn test_operator_precedence() {
+        let cases = [
+            ("1 + 2 * 3", 7.0),
+            ("(1 + 2) * 3", 9.0),
+            ("10 - 4 - 3", 3.0),
+            ("64 / 4 / 2", 8.0),
+            ("2 * 3 ^ 2", 18.0),
+            ("2 ^ 3 ^ 2", 512.0),
+            ("(2 ^ 3) ^ 2", 64.0),
+            ("-2 ^ 2", -4.0),
+            ("(-2) ^ 2", 4.0),
+            ("2 ^ -1", 0.5),
+            ("-3 * 2", -6.0),
+            ("--3", 3.0),
+            ("1 - -1", 2.0),
+            ("50%", 0.5),
+            ("200 * 10%", 20.0),
+            ("-50%", -0.5),
+            ("2 ^ 200%", 4.0),
+            ("7 mod 3", 1.0),
+            ("-7 mod 3", 2.0),
+            ("7 mod -3", -2.0),
+            ("1 + 7 mod 4 * 2", 7.0),
+            ("2 * 7 mod 4", 2.0),
+        ];
+        for (expression, expected) in cases {
+            assert_eq!(
+                evaluate(expression),
+                Ok(Value::Number(expected)),
+                "{expression}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_concatenation_precedence() {
+        let cases = [
+            ("\"a\" & \"b\"", Value::Text("ab".to_string())),
+            ("1 + 2 & 3", Value::Text("33".to_string())),
+            ("\"x\" & 2 * 3", Value::Text("x6".to_string())),
+            ("\"a\" & TRUE", Value::Text("aTRUE".to_string())),
+            ("\"a\" & \"b\" == \"ab\"", Value::Bool(true)),
+            ("1 & 2 == \"12\" && TRUE", Value::Bool(true)),
+            ("-1 < 0 || FALSE", Value::Bool(true)),
+        ];
+        for (expression, expected) in cases {
+            assert_eq!(evaluate(expression), Ok(expected), "{expression}");
+        }
+    }
+
+    #[test]
+    fn test_operator_errors() {
+        assert_eq!(evaluate("7 mod 0"), Err(ComputeError::DivisionByZero));
+        assert_eq!(evaluate("0 ^ -1"), Err(ComputeError::DivisionByZero));
+        assert!(matches!(
+            evaluate("(-8) ^ 0.5"),
+            Err(ComputeError::NumberError(_))
+        ));
+        assert!(matches!(
+            evaluate("1e300 * 1e300"),
+            Err(ComputeError::NumberError(_))
+        ));
+        assert!(matches!(
+            evaluate("-\"a\""),
+            Err(ComputeError::TypeError(_))
+        ));
+        assert!(matches!(
+            evaluate("\"a\"%"),
+            Err(ComputeError::TypeError(_))
+        ));
+        assert!(matches!(
+            evaluate("2 ^ TRUE"),
+            Err(ComputeError::TypeError(_))
+        ));
+        assert!(matches!(
+            evaluate("\"a\" mod 2"),
+            Err(ComputeError::TypeError(_))
+        ));
+        assert_eq!(
+            evaluate("#N/A & \"a\""),
+            Err(ComputeError::from_code("#N/A").unwrap())
+        );
+    }
 }
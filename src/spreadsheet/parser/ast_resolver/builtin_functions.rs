@@ -1,156 +1,223 @@
-use crate::common_types::{ComputeError, Value};
-
-pub fn get_func(name: &str) -> Option<fn(Vec<Value>) -> Result<Value, ComputeError>> {
-    match name {
-        "sum" => Some(self::sum),
-        "product" => Some(self::product),
-        "max" => Some(self::max),
-        "min" => Some(self::min),
-        "average" => Some(self::average),
-        "count" => Some(self::count),
-        "length" => Some(self::length),
-        "if" => Some(self::if_func),
-        "round" => Some(self::round),
-        "pow" => Some(self::power),
-        _ => None,
-    }
+use crate::common_types::{Array, ComputeError, Value};
+
+use super::function_registry::{ArgumentType, Function, FunctionRegistry};
+
+use ArgumentType::{Any, Number};
+
+mod conditional;
+mod date;
+mod finance;
+mod lookup;
+mod math;
+mod statistics;
+mod text;
+
+pub fn register(registry: &mut FunctionRegistry) {
+    registry.add_builtin(Function::new("sum", "Sum of numeric values.", sum).repeated(Number));
+    registry.add_builtin(
+        Function::new("product", "Product of numeric values.", product).repeated(Number),
+    );
+    registry.add_builtin(
+        Function::new("max", "Maximum value.", max)
+            .required(&[Number])
+            .repeated(Number),
+    );
+    registry.add_builtin(
+        Function::new("min", "Minimum value.", min)
+            .required(&[Number])
+            .repeated(Number),
+    );
+    registry.add_builtin(
+        Function::new("average", "Arithmetic mean.", average)
+            .required(&[Number])
+            .repeated(Number),
+    );
+    registry.add_builtin(Function::new("count", "Count numeric values.", count).repeated(Number));
+    registry.add_builtin(
+        Function::new("iserror", "TRUE if the value is an error.", iserror)
+            .required(&[Any])
+            .inspecting_errors(),
+    );
+    registry.add_builtin(
+        Function::new("isna", "TRUE if the value is the #N/A error.", isna)
+            .required(&[Any])
+            .inspecting_errors(),
+    );
+    registry.add_builtin(
+        Function::new(
+            "errortype",
+            "Number of the error, #N/A if the value is not an error.",
+            errortype,
+        )
+        .required(&[Any])
+        .inspecting_errors(),
+    );
+    registry.add_builtin(Function::new(
+        "na",
+        "The #N/A error, marking a value that is not available.",
+        na,
+    ));
+
+    math::register(registry);
+    statistics::register(registry);
+    lookup::register(registry);
+    conditional::register(registry);
+    date::register(registry);
+    finance::register(registry);
+    text::register(registry);
+}
+
+// Signatures are checked before a function is called, so these only see the types they declared
+
+fn numbers(args: &[Value]) -> impl Iterator<Item = f64> + '_ {
+    args.iter().filter_map(Value::as_number)
 }
 
 pub fn sum(args: Vec<Value>) -> Result<Value, ComputeError> {
-    let mut sum = 0.0;
-    for arg in args {
-        if let Value::Number(num) = arg {
-            sum += num;
-        } else {
-            return Err(ComputeError::InvalidArgument("sum expects only numeric values".to_string()));
-        }
-    }
-    Ok(Value::Number(sum))
+    Ok(Value::Number(numbers(&args).sum()))
 }
 
 pub fn product(args: Vec<Value>) -> Result<Value, ComputeError> {
-    let mut prod = 1.0;
-    for arg in args {
-        if let Value::Number(num) = arg {
-            prod *= num;
-        } else {
-            return Err(ComputeError::InvalidArgument("product expects only numeric values".to_string()));
-        }
-    }
-    Ok(Value::Number(prod))
+    Ok(Value::Number(numbers(&args).product()))
 }
 
 pub fn max(args: Vec<Value>) -> Result<Value, ComputeError> {
-    if args.is_empty() {
-        return Err(ComputeError::InvalidArgument("max expects at least one numeric value".to_string()));
-    }
-
-    let mut max = f64::MIN;
-    for arg in args {
-        if let Value::Number(num) = arg {
-            max = f64::max(max, num);
-        } else {
-            return Err(ComputeError::InvalidArgument("max expects only numeric values".to_string()));
-        }
-    }
-    Ok(Value::Number(max))
+    Ok(Value::Number(numbers(&args).fold(f64::MIN, f64::max)))
 }
 
 pub fn min(args: Vec<Value>) -> Result<Value, ComputeError> {
-    if args.is_empty() {
-        return Err(ComputeError::InvalidArgument("min expects at least one numeric value".to_string()));
-    }
-
-    let mut min = f64::MAX;
-    for arg in args {
-        if let Value::Number(num) = arg {
-            min = f64::min(min, num);
-        } else {
-            return Err(ComputeError::InvalidArgument("min expects only numeric values".to_string()));
-        }
-    }
-    Ok(Value::Number(min))
+    Ok(Value::Number(numbers(&args).fold(f64::MAX, f64::min)))
 }
 
 pub fn average(args: Vec<Value>) -> Result<Value, ComputeError> {
-    if args.is_empty() {
-        return Err(ComputeError::InvalidArgument("average expects at least one numeric value".to_string()));
-    }
-
-    let mut sum = 0.0;
-    let len = args.len() as f64;
-    for arg in args {
-        if let Value::Number(num) = arg {
-            sum += num;
-        } else {
-            return Err(ComputeError::InvalidArgument("average expects only numeric values".to_string()));
-        }
-    }
-    Ok(Value::Number(sum / len))
+    Ok(Value::Number(
+        numbers(&args).sum::<f64>() / args.len() as f64,
+    ))
 }
 
 pub fn count(args: Vec<Value>) -> Result<Value, ComputeError> {
-    let mut count = 0.0;
-    for arg in args {
-        if let Value::Number(_) = arg {
-            count += 1.0;
-        } else {
-            return Err(ComputeError::InvalidArgument("count expects only numeric values".to_string()));
-        }
-    }
-    Ok(Value::Number(count))
+    Ok(Value::Number(args.len() as f64))
 }
 
-pub fn length(args: Vec<Value>) -> Result<Value, ComputeError> {
-    if args.len() != 1 {
-        return Err(ComputeError::InvalidArgument("length expects exactly one argument".to_string()));
-    }
+pub fn iserror(args: Vec<Value>) -> Result<Value, ComputeError> {
+    Ok(Value::Bool(matches!(args[0], Value::Error(_))))
+}
 
-    match &args[0] {
-        Value::Text(t) => Ok(Value::Number(t.len() as f64)),
-        _ => Err(ComputeError::InvalidArgument("length expects a string argument".to_string())),
-    }
+pub fn isna(args: Vec<Value>) -> Result<Value, ComputeError> {
+    Ok(Value::Bool(matches!(
+        args[0],
+        Value::Error(ComputeError::NotAvailable(_))
+    )))
 }
 
-pub fn if_func(mut args: Vec<Value>) -> Result<Value, ComputeError> {
-    if args.len() != 3 {
-        return Err(ComputeError::InvalidArgument("if expects exactly three arguments".to_string()));
+pub fn errortype(args: Vec<Value>) -> Result<Value, ComputeError> {
+    match &args[0] {
+        Value::Error(error) => Ok(Value::Number(error.type_number() as f64)),
+        _ => Err(ComputeError::NotAvailable(
+            "errortype expects an error value".to_string(),
+        )),
     }
+}
 
-    match args[0] {
-        Value::Bool(b) => {
-            if b {
-                Ok(args.remove(1))
-            } else {
-                Ok(args.remove(2))
-            }
-        }
-        _ => Err(ComputeError::InvalidArgument("if expects a boolean as the first argument".to_string())),
-    }
+pub fn na(_args: Vec<Value>) -> Result<Value, ComputeError> {
+    Err(ComputeError::NotAvailable(
+        "Value is not available".to_string(),
+    ))
 }
 
-pub fn round(args: Vec<Value>) -> Result<Value, ComputeError> {
-    if args.len() != 1 {
-        return Err(ComputeError::InvalidArgument("round expects exactly one numeric argument".to_string()));
-    }
+// Helpers shared by the functions of the submodules
 
-    match args[0] {
-        Value::Number(num) => Ok(Value::Number(num.round())),
-        _ => Err(ComputeError::InvalidArgument("round expects a numeric argument".to_string())),
-    }
+fn number(value: &Value) -> f64 {
+    value.as_number().unwrap_or_default()
 }
 
+fn out_of_domain(message: &str) -> ComputeError {
+    ComputeError::NumberError(message.to_string())
+}
 
-pub fn power(mut args: Vec<Value>) -> Result<Value, ComputeError> {
-    if args.len() != 2 {
-        return Err(ComputeError::InvalidArgument("pow expects exactly two numeric arguments".to_string()));
+fn array(arg: &Value) -> &Array {
+    match arg {
+        Value::Array(array) => array,
+        _ => unreachable!("Range arguments are passed as arrays"),
     }
+}
 
-    let num2 = args.pop().unwrap();
-    let num1 = args.pop().unwrap();
+// The numbers of a range argument, or the first error in it
+fn range_numbers(arg: &Value) -> Result<Vec<f64>, ComputeError> {
+    array(arg)
+        .values()
+        .filter_map(|value| match value {
+            Value::Number(num) | Value::DateTime(num) => Some(Ok(*num)),
+            Value::Error(error) => Some(Err(error.clone())),
+            _ => None,
+        })
+        .collect()
+}
+
+fn text(value: &Value) -> String {
+    value.to_string()
+}
 
-    match (num1, num2) {
-        (Value::Number(n1), Value::Number(n2)) => Ok(Value::Number(n1.powf(n2))),
-        _ => Err(ComputeError::InvalidArgument("pow expects both arguments to be numeric".to_string())),
+#[cfg(test)]
+mod test_helpers {
+    use super::*;
+
+    pub(super) fn call(name: &str, args: &[Value]) -> Result<Value, ComputeError> {
+        let registry = FunctionRegistry::builtins();
+        registry
+            .get(name)
+            .expect("Builtin function")
+            .call(args.to_vec())
+    }
+
+    pub(super) fn string(s: &str) -> Value {
+        Value::Text(s.to_string())
+    }
+
+    pub(super) fn numbers(nums: &[f64]) -> Vec<Value> {
+        nums.iter().copied().map(Value::Number).collect()
+    }
+
+    pub(super) fn column(nums: &[f64]) -> Value {
+        let cells = nums.iter().map(|num| Some(Value::Number(*num))).collect();
+        Value::Array(Array::new(nums.len(), 1, cells))
+    }
+
+    pub(super) fn assert_close(result: Result<Value, ComputeError>, expected: f64) {
+        assert_within(result, expected, 1e-9);
+    }
+
+    pub(super) fn assert_within(
+        result: Result<Value, ComputeError>,
+        expected: f64,
+        tolerance: f64,
+    ) {
+        let Ok(Value::Number(result)) = result else {
+            panic!("{result:?} is not a number");
+        };
+        assert!(
+            (result - expected).abs() < tolerance,
+            "{result} is not {expected}"
+        );
+    }
+
+    pub(super) fn products() -> Value {
+        let rows = [
+            ("apple", 0.5, 10.0),
+            ("banana", 0.25, 0.0),
+            ("cherry", 3.0, 7.0),
+            ("date", 2.0, 12.0),
+        ];
+        let cells = rows
+            .iter()
+            .flat_map(|(name, price, stock)| {
+                [
+                    Some(string(name)),
+                    Some(Value::Number(*price)),
+                    (*stock != 0.0).then_some(Value::Number(*stock)),
+                ]
+            })
+            .collect();
+        Value::Array(Array::new(rows.len(), 3, cells))
     }
 }
@@ -0,0 +1,352 @@
+//! Conditional functions pair the cells of ranges of the same size by position. Only the ranges
+//! aggregated pass their errors on, cells of the criteria ranges that are errors simply do not meet
+//! the criteria.
+
+use crate::common_types::{ComputeError, NumberLocale, Value};
+
+use super::super::criteria::Criterion;
+use super::super::function_registry::{ArgumentType, Function, FunctionRegistry};
+use super::array;
+
+use ArgumentType::{Any, Range};
+
+pub fn register(registry: &mut FunctionRegistry) {
+    registry.add_builtin(
+        Function::with_locale(
+            "sumif",
+            "Sum of the cells of the sum range, or of the range itself, where the range meets the \
+             criteria.",
+            sumif,
+        )
+        .required(&[Range, Any])
+        .optional(&[Range]),
+    );
+    registry.add_builtin(
+        Function::with_locale(
+            "countif",
+            "Count the cells of the range meeting the criteria.",
+            countif,
+        )
+        .required(&[Range, Any]),
+    );
+    registry.add_builtin(
+        Function::with_locale(
+            "averageif",
+            "Mean of the cells of the average range, or of the range itself, where the range \
+             meets the criteria.",
+            averageif,
+        )
+        .required(&[Range, Any])
+        .optional(&[Range]),
+    );
+    registry.add_builtin(
+        Function::with_locale(
+            "sumifs",
+            "Sum of the cells of the sum range where every range meets its criteria.",
+            sumifs,
+        )
+        .required(&[Range, Range, Any])
+        .repeated_group(&[Range, Any]),
+    );
+    registry.add_builtin(
+        Function::with_locale(
+            "countifs",
+            "Count the positions where every range meets its criteria.",
+            countifs,
+        )
+        .required(&[Range, Any])
+        .repeated_group(&[Range, Any]),
+    );
+    registry.add_builtin(
+        Function::with_locale(
+            "averageifs",
+            "Mean of the cells of the average range where every range meets its criteria.",
+            averageifs,
+        )
+        .required(&[Range, Range, Any])
+        .repeated_group(&[Range, Any]),
+    );
+    registry.add_builtin(
+        Function::with_locale(
+            "maxifs",
+            "Maximum of the cells of the max range where every range meets its criteria, 0 if \
+             none does.",
+            maxifs,
+        )
+        .required(&[Range, Range, Any])
+        .repeated_group(&[Range, Any]),
+    );
+    registry.add_builtin(
+        Function::with_locale(
+            "minifs",
+            "Minimum of the cells of the min range where every range meets its criteria, 0 if \
+             none does.",
+            minifs,
+        )
+        .required(&[Range, Range, Any])
+        .repeated_group(&[Range, Any]),
+    );
+}
+
+// Whether each position meets the criteria of every (range, criteria) pair, checking the ranges
+// have the given size
+fn meets_criteria(
+    pairs: &[Value],
+    size: (usize, usize),
+    name: &str,
+    locale: NumberLocale,
+) -> Result<Vec<bool>, ComputeError> {
+    let mut meets = vec![true; size.0 * size.1];
+    for pair in pairs.chunks(2) {
+        let (range, criterion) = (array(&pair[0]), Criterion::new(&pair[1], locale));
+        if (range.rows(), range.columns()) != size {
+            return Err(ComputeError::InvalidArgument(format!(
+                "{name} expects ranges of the same size"
+            )));
+        }
+        for (meets, cell) in meets.iter_mut().zip(range.cells()) {
+            *meets = *meets && criterion.matches(cell);
+        }
+    }
+    Ok(meets)
+}
+
+// The numbers of the aggregated range at the positions meeting the criteria
+fn numbers_meeting(
+    range: &Value,
+    pairs: &[Value],
+    name: &str,
+    locale: NumberLocale,
+) -> Result<Vec<f64>, ComputeError> {
+    let range = array(range);
+    let meets = meets_criteria(pairs, (range.rows(), range.columns()), name, locale)?;
+    let mut nums = Vec::new();
+    for (cell, _) in range.cells().zip(meets).filter(|(_, meets)| *meets) {
+        match cell {
+            Some(Value::Number(num) | Value::DateTime(num)) => nums.push(*num),
+            Some(Value::Error(error)) => return Err(error.clone()),
+            _ => {}
+        }
+    }
+    Ok(nums)
+}
+
+// The single criteria functions aggregate the range itself unless given another one
+fn numbers_meeting_if(
+    args: &[Value],
+    name: &str,
+    locale: NumberLocale,
+) -> Result<Vec<f64>, ComputeError> {
+    numbers_meeting(args.get(2).unwrap_or(&args[0]), &args[..2], name, locale)
+}
+
+fn mean(nums: &[f64]) -> Result<Value, ComputeError> {
+    if nums.is_empty() {
+        return Err(ComputeError::DivisionByZero);
+    }
+    Ok(Value::Number(nums.iter().sum::<f64>() / nums.len() as f64))
+}
+
+pub fn sumif(args: Vec<Value>, locale: NumberLocale) -> Result<Value, ComputeError> {
+    Ok(Value::Number(
+        numbers_meeting_if(&args, "sumif", locale)?.iter().sum(),
+    ))
+}
+
+pub fn countif(args: Vec<Value>, locale: NumberLocale) -> Result<Value, ComputeError> {
+    countifs(args, locale)
+}
+
+pub fn averageif(args: Vec<Value>, locale: NumberLocale) -> Result<Value, ComputeError> {
+    mean(&numbers_meeting_if(&args, "averageif", locale)?)
+}
+
+pub fn sumifs(args: Vec<Value>, locale: NumberLocale) -> Result<Value, ComputeError> {
+    Ok(Value::Number(
+        numbers_meeting(&args[0], &args[1..], "sumifs", locale)?
+            .iter()
+            .sum(),
+    ))
+}
+
+pub fn countifs(args: Vec<Value>, locale: NumberLocale) -> Result<Value, ComputeError> {
+    let range = array(&args[0]);
+    let meets = meets_criteria(&args, (range.rows(), range.columns()), "countifs", locale)?;
+    Ok(Value::Number(
+        meets.into_iter().filter(|meets| *meets).count() as f64,
+    ))
+}
+
+pub fn averageifs(args: Vec<Value>, locale: NumberLocale) -> Result<Value, ComputeError> {
+    mean(&numbers_meeting(
+        &args[0],
+        &args[1..],
+        "averageifs",
+        locale,
+    )?)
+}
+
+pub fn maxifs(args: Vec<Value>, locale: NumberLocale) -> Result<Value, ComputeError> {
+    let nums = numbers_meeting(&args[0], &args[1..], "maxifs", locale)?;
+    Ok(Value::Number(
+        nums.into_iter().reduce(f64::max).unwrap_or(0.0),
+    ))
+}
+
+pub fn minifs(args: Vec<Value>, locale: NumberLocale) -> Result<Value, ComputeError> {
+    let nums = numbers_meeting(&args[0], &args[1..], "minifs", locale)?;
+    Ok(Value::Number(
+        nums.into_iter().reduce(f64::min).unwrap_or(0.0),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_helpers::{call, column, products, string};
+    use super::*;
+    use crate::common_types::Array;
+
+    #[test]
+    fn test_conditional_aggregates() {
+        let num = Value::Number;
+        let names = Value::Array(Array::new(
+            4,
+            1,
+            ["apple", "banana", "cherry", "avocado"]
+                .map(|name| Some(string(name)))
+                .to_vec(),
+        ));
+        let prices = column(&[0.5, 0.25, 3.0, 2.0]);
+        let stock = Value::Array(Array::new(
+            4,
+            1,
+            vec![Some(num(10.0)), None, Some(num(7.0)), Some(string("none"))],
+        ));
+
+        assert_eq!(
+            call("countif", &[names.clone(), string("a*")]),
+            Ok(num(2.0))
+        );
+        assert_eq!(call("countif", &[stock.clone(), string("")]), Ok(num(1.0)));
+        assert_eq!(call("countif", &[prices.clone(), num(3.0)]), Ok(num(1.0)));
+        assert_eq!(
+            call("sumif", &[prices.clone(), string(">=2")]),
+            Ok(num(5.0))
+        );
+        assert_eq!(
+            call(
+                "sumif",
+                &[names.clone(), string("<>banana"), prices.clone()]
+            ),
+            Ok(num(5.5))
+        );
+        assert_eq!(
+            call(
+                "averageif",
+                &[names.clone(), string("?????"), prices.clone()]
+            ),
+            Ok(num(0.5))
+        );
+        assert_eq!(
+            call(
+                "averageif",
+                &[names.clone(), string("kiwi"), prices.clone()]
+            ),
+            Err(ComputeError::DivisionByZero)
+        );
+
+        assert_eq!(
+            call(
+                "sumifs",
+                &[
+                    prices.clone(),
+                    names.clone(),
+                    string("a*"),
+                    stock.clone(),
+                    string(">5")
+                ]
+            ),
+            Ok(num(0.5))
+        );
+        assert_eq!(
+            call(
+                "countifs",
+                &[names.clone(), string("*e*"), prices.clone(), string("<1")]
+            ),
+            Ok(num(1.0))
+        );
+        assert_eq!(
+            call("averageifs", &[prices.clone(), stock.clone(), string("<>")]),
+            Ok(num(5.5 / 3.0))
+        );
+        assert_eq!(
+            call("maxifs", &[prices.clone(), names.clone(), string("<c")]),
+            Ok(num(2.0))
+        );
+        assert_eq!(
+            call("minifs", &[prices.clone(), names.clone(), string("a*")]),
+            Ok(num(0.5))
+        );
+        assert_eq!(
+            call("maxifs", &[prices.clone(), names.clone(), string("kiwi")]),
+            Ok(num(0.0))
+        );
+
+        assert!(matches!(
+            call("sumif", &[names.clone(), string("a*"), column(&[1.0])]),
+            Err(ComputeError::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            call("sumifs", &[prices.clone(), names.clone()]),
+            Err(ComputeError::InvalidArgument(_))
+        ));
+        let with_error = Value::Array(Array::new(
+            4,
+            1,
+            vec![
+                Some(num(1.0)),
+                Some(Value::Error(ComputeError::DivisionByZero)),
+                Some(num(2.0)),
+                None,
+            ],
+        ));
+        assert_eq!(
+            call("sumif", &[with_error.clone(), string(">1")]),
+            Ok(num(2.0))
+        );
+        assert_eq!(
+            call("sumif", &[names.clone(), string("b*"), with_error]),
+            Err(ComputeError::DivisionByZero)
+        );
+
+        // Exact lookups match text with wildcards as well
+        assert_eq!(
+            call("match", &[string("c*"), names.clone(), num(0.0)]),
+            Ok(num(3.0))
+        );
+        assert_eq!(
+            call(
+                "vlookup",
+                &[string("b?n*"), products(), num(2.0), Value::Bool(false)]
+            ),
+            Ok(num(0.25))
+        );
+        assert_eq!(
+            call(
+                "xlookup",
+                &[
+                    string("*do"),
+                    names.clone(),
+                    prices.clone(),
+                    string("none"),
+                    num(2.0)
+                ]
+            ),
+            Ok(num(2.0))
+        );
+        assert_eq!(
+            call("xlookup", &[string("*do"), names, prices, string("none")]),
+            Ok(string("none"))
+        );
+    }
+}
@@ -0,0 +1,443 @@
+//! Dates are serial numbers of days, see `Value::DateTime`. Functions taking dates accept plain
+//! numbers too, as long as they are within the years 0 to 9999.
+
+use crate::common_functions::{
+    check_date, date_to_serial, days_in_month, serial_to_date, UNIX_EPOCH_SERIAL,
+};
+use crate::common_types::{ComputeError, Value};
+
+use super::super::function_registry::{ArgumentType, Function, FunctionRegistry};
+use super::{array, number, out_of_domain, text};
+
+use ArgumentType::{Date, Number, Range, Text};
+
+pub fn register(registry: &mut FunctionRegistry) {
+    registry.add_builtin(Function::new("today", "The current date in UTC.", today));
+    registry.add_builtin(Function::new(
+        "now",
+        "The current date and time in UTC.",
+        now,
+    ));
+    registry.add_builtin(
+        Function::new(
+            "date",
+            "The date of the given year, month and day. Months and days past the end carry over, \
+             so month 13 is January of the next year.",
+            date,
+        )
+        .required(&[Number, Number, Number]),
+    );
+    registry.add_builtin(Function::new("year", "The year of the date.", year).required(&[Date]));
+    registry.add_builtin(
+        Function::new("month", "The month of the date, from 1 to 12.", month).required(&[Date]),
+    );
+    registry.add_builtin(
+        Function::new(
+            "day",
+            "The day of the month of the date, from 1 to 31.",
+            day,
+        )
+        .required(&[Date]),
+    );
+    registry.add_builtin(
+        Function::new(
+            "weekday",
+            "The day of the week of the date, from 1 for Sunday to 7 for Saturday. Type 2 counts \
+             from 1 for Monday and type 3 from 0 for Monday.",
+            weekday,
+        )
+        .required(&[Date])
+        .optional(&[Number]),
+    );
+    registry.add_builtin(
+        Function::new(
+            "edate",
+            "The date the given number of months before or after the date, on the last day of the \
+             month if the day is past it.",
+            edate,
+        )
+        .required(&[Date, Number]),
+    );
+    registry.add_builtin(
+        Function::new(
+            "eomonth",
+            "The last day of the month the given number of months before or after the date.",
+            eomonth,
+        )
+        .required(&[Date, Number]),
+    );
+    registry.add_builtin(
+        Function::new(
+            "datedif",
+            "The difference between two dates in complete years (Y), months (M) or days (D), or \
+             in months ignoring years (YM), days ignoring months (MD) or days ignoring years \
+             (YD).",
+            datedif,
+        )
+        .required(&[Date, Date, Text]),
+    );
+    registry.add_builtin(
+        Function::new(
+            "networkdays",
+            "Count the days from Monday to Friday between two dates, both included, leaving out \
+             the holidays.",
+            networkdays,
+        )
+        .required(&[Date, Date])
+        .optional(&[Range]),
+    );
+}
+
+fn serial(value: &Value) -> Result<f64, ComputeError> {
+    let serial = match value {
+        Value::DateTime(serial) | Value::Number(serial) => *serial,
+        _ => 0.0,
+    };
+    check_date(Value::DateTime(serial)).map(|_| serial)
+}
+
+// The year and month of a count of months since January of the year 0, if within the years 0 to
+// 9999
+fn year_and_month(months: f64) -> Result<(i64, u32), ComputeError> {
+    if !(0.0..120_000.0).contains(&months) {
+        return Err(out_of_domain(
+            "Dates have to be between the years 0 and 9999",
+        ));
+    }
+    Ok(((months / 12.0).floor() as i64, (months % 12.0) as u32 + 1))
+}
+
+// Seconds since 1970-01-01 in UTC
+#[cfg(not(target_arch = "wasm32"))]
+fn unix_time() -> Result<f64, ComputeError> {
+    let elapsed = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH);
+    elapsed
+        .map(|elapsed| elapsed.as_secs_f64())
+        .map_err(|_| ComputeError::NotAvailable("The clock is set before 1970".to_string()))
+}
+
+// The standard library has no clock in the browser
+#[cfg(target_arch = "wasm32")]
+fn unix_time() -> Result<f64, ComputeError> {
+    Err(ComputeError::NotAvailable(
+        "The clock is not available on this platform".to_string(),
+    ))
+}
+
+pub fn today(_args: Vec<Value>) -> Result<Value, ComputeError> {
+    Ok(Value::DateTime(
+        (unix_time()? / 86400.0).floor() + UNIX_EPOCH_SERIAL,
+    ))
+}
+
+pub fn now(_args: Vec<Value>) -> Result<Value, ComputeError> {
+    Ok(Value::DateTime(unix_time()? / 86400.0 + UNIX_EPOCH_SERIAL))
+}
+
+// The date of the day in the month counted from the given year and month, carrying over past their
+// ends. Only called with dates close to the years 0 to 9999.
+fn date_of(year: i64, month: i64, day: i64) -> f64 {
+    let months = year * 12 + month - 1;
+    date_to_serial(months.div_euclid(12), months.rem_euclid(12) as u32 + 1, 1) + (day - 1) as f64
+}
+
+pub fn date(args: Vec<Value>) -> Result<Value, ComputeError> {
+    let [year, month, day] = [&args[0], &args[1], &args[2]].map(|arg| number(arg).trunc());
+    if !(0.0..=9999.0).contains(&year) {
+        return Err(out_of_domain("date expects a year between 0 and 9999"));
+    }
+    let (year, month) = year_and_month(year * 12.0 + month - 1.0)?;
+    // Days past the years 0 to 9999 are turned into an error with the result
+    Ok(Value::DateTime(date_to_serial(year, month, 1) + day - 1.0))
+}
+
+pub fn year(args: Vec<Value>) -> Result<Value, ComputeError> {
+    Ok(Value::Number(serial_to_date(serial(&args[0])?).0 as f64))
+}
+
+pub fn month(args: Vec<Value>) -> Result<Value, ComputeError> {
+    Ok(Value::Number(serial_to_date(serial(&args[0])?).1 as f64))
+}
+
+pub fn day(args: Vec<Value>) -> Result<Value, ComputeError> {
+    Ok(Value::Number(serial_to_date(serial(&args[0])?).2 as f64))
+}
+
+// Days since Monday, 0 to 6. Serial number 0 is a Saturday.
+fn days_since_monday(serial: f64) -> i64 {
+    (serial.floor() as i64 + 5).rem_euclid(7)
+}
+
+pub fn weekday(args: Vec<Value>) -> Result<Value, ComputeError> {
+    let since_monday = days_since_monday(serial(&args[0])?);
+    let weekday = match args.get(1).map_or(1.0, number) {
+        1.0 => (since_monday + 1) % 7 + 1,
+        2.0 => since_monday + 1,
+        3.0 => since_monday,
+        _ => return Err(out_of_domain("weekday expects a type of 1, 2 or 3")),
+    };
+    Ok(Value::Number(weekday as f64))
+}
+
+// The year and month the given number of months after the date, and its day
+fn add_months(args: &[Value]) -> Result<(i64, u32, u32), ComputeError> {
+    let (year, month, day) = serial_to_date(serial(&args[0])?);
+    let (year, month) =
+        year_and_month((year * 12) as f64 + f64::from(month) - 1.0 + number(&args[1]).trunc())?;
+    Ok((year, month, day))
+}
+
+pub fn edate(args: Vec<Value>) -> Result<Value, ComputeError> {
+    let (year, month, day) = add_months(&args)?;
+    Ok(Value::DateTime(date_to_serial(
+        year,
+        month,
+        day.min(days_in_month(year, month)),
+    )))
+}
+
+pub fn eomonth(args: Vec<Value>) -> Result<Value, ComputeError> {
+    let (year, month, _) = add_months(&args)?;
+    Ok(Value::DateTime(date_to_serial(
+        year,
+        month,
+        days_in_month(year, month),
+    )))
+}
+
+pub fn datedif(args: Vec<Value>) -> Result<Value, ComputeError> {
+    let (start, end) = (serial(&args[0])?.floor(), serial(&args[1])?.floor());
+    if start > end {
+        return Err(out_of_domain(
+            "datedif expects the start date before the end date",
+        ));
+    }
+    let ((start_year, start_month, start_day), (end_year, end_month, end_day)) =
+        (serial_to_date(start), serial_to_date(end));
+    let months = (end_year - start_year) * 12 + i64::from(end_month)
+        - i64::from(start_month)
+        - i64::from(end_day < start_day);
+    let difference = match text(&args[2]).to_uppercase().as_str() {
+        "Y" => months / 12,
+        "M" => months,
+        "D" => (end - start) as i64,
+        "YM" => months % 12,
+        // Days from the start day in the month before the end date, or in its own month. A start day
+        // past the end of the month before counts from its last day
+        "MD" if end_day >= start_day => i64::from(end_day - start_day),
+        "MD" => {
+            let months = end_year * 12 + i64::from(end_month) - 2;
+            let (year, month) = (months.div_euclid(12), months.rem_euclid(12) as u32 + 1);
+            let day = start_day.min(days_in_month(year, month));
+            (end - date_to_serial(year, month, day)) as i64
+        }
+        "YD" => {
+            let anniversary =
+                |year: i64| date_of(year, i64::from(start_month), i64::from(start_day));
+            let this_year = anniversary(end_year);
+            (end - if this_year > end {
+                anniversary(end_year - 1)
+            } else {
+                this_year
+            }) as i64
+        }
+        _ => {
+            return Err(out_of_domain(
+                "datedif expects a unit of Y, M, D, YM, MD or YD",
+            ))
+        }
+    };
+    Ok(Value::Number(difference as f64))
+}
+
+// Days from Monday to Friday from the first to the last day, both included
+fn weekdays_between(first: i64, last: i64) -> i64 {
+    let days = last - first + 1;
+    let partial_week = (0..days % 7)
+        .filter(|day| days_since_monday((first + day) as f64) < 5)
+        .count() as i64;
+    days / 7 * 5 + partial_week
+}
+
+pub fn networkdays(args: Vec<Value>) -> Result<Value, ComputeError> {
+    let (start, end) = (serial(&args[0])?.floor(), serial(&args[1])?.floor());
+    let (first, last) = (start.min(end), start.max(end));
+    let mut holidays = Vec::new();
+    if let Some(range) = args.get(2) {
+        for value in array(range).values() {
+            match value {
+                Value::DateTime(day) | Value::Number(day) => holidays.push(day.floor()),
+                Value::Error(error) => return Err(error.clone()),
+                _ => {}
+            }
+        }
+    }
+    // Holidays on weekends or given twice are only left out once, if at all
+    holidays.retain(|day| (first..=last).contains(day) && days_since_monday(*day) < 5);
+    holidays.sort_by(f64::total_cmp);
+    holidays.dedup();
+    let working_days = (weekdays_between(first as i64, last as i64) - holidays.len() as i64) as f64;
+    Ok(Value::Number(if start > end {
+        -working_days
+    } else {
+        working_days
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_helpers::{call, numbers, string};
+    use super::*;
+    use crate::common_functions::{format_date_time, parse_date_time};
+    use crate::common_types::Array;
+
+    #[test]
+    fn test_date_functions() {
+        let (num, date) = (Value::Number, Value::DateTime);
+        let march_15 = || date(45366.0);
+        assert_eq!(format_date_time(45366.0), "2024-03-15");
+        assert_eq!(parse_date_time("2024-03-15T18:00:00Z"), Some(45366.75));
+        assert_eq!(format_date_time(45366.75), "2024-03-15 18:00");
+        assert_eq!(parse_date_time("2024-02-30"), None);
+
+        assert_eq!(
+            call("date", &[num(2024.0), num(3.0), num(15.0)]),
+            Ok(march_15())
+        );
+        assert_eq!(
+            call("date", &[num(2024.0), num(14.0), num(0.0)]),
+            Ok(date(45688.0))
+        );
+        assert_eq!(call("year", &[march_15()]), Ok(num(2024.0)));
+        assert_eq!(
+            (call("month", &[march_15()]), call("day", &[num(45366.75)])),
+            (Ok(num(3.0)), Ok(num(15.0)))
+        );
+        assert_eq!(call("weekday", &[march_15()]), Ok(num(6.0)));
+        assert_eq!(call("weekday", &[march_15(), num(2.0)]), Ok(num(5.0)));
+        assert_eq!(call("weekday", &[march_15(), num(3.0)]), Ok(num(4.0)));
+        assert!(matches!(
+            call("weekday", &[march_15(), num(4.0)]),
+            Err(ComputeError::NumberError(_))
+        ));
+        assert!(matches!(call("today", &[]), Ok(Value::DateTime(serial)) if serial.fract() == 0.0));
+
+        let january_31 = date(45322.0);
+        assert_eq!(
+            call("edate", &[january_31.clone(), num(1.0)]),
+            Ok(date(45351.0))
+        );
+        assert_eq!(
+            call("edate", &[january_31.clone(), num(-2.0)]),
+            Ok(date(45260.0))
+        );
+        assert_eq!(call("eomonth", &[march_15(), num(-3.0)]), Ok(date(45291.0)));
+    }
+
+    #[test]
+    fn test_date_differences() {
+        let (num, date) = (Value::Number, Value::DateTime);
+        // From 2020-02-29 to 2024-02-28
+        let datedif = |unit: &str| call("datedif", &[date(43890.0), date(45350.0), string(unit)]);
+        assert_eq!(
+            [datedif("Y"), datedif("m"), datedif("D")],
+            [Ok(num(3.0)), Ok(num(47.0)), Ok(num(1460.0))]
+        );
+        assert_eq!(
+            [datedif("YM"), datedif("MD"), datedif("YD")],
+            [Ok(num(11.0)), Ok(num(30.0)), Ok(num(364.0))]
+        );
+        assert!(matches!(datedif("W"), Err(ComputeError::NumberError(_))));
+        // From 2024-01-31 to 2024-03-01, counting from the last day of February
+        assert_eq!(
+            call("datedif", &[date(45322.0), date(45352.0), string("md")]),
+            Ok(num(1.0))
+        );
+        assert!(matches!(
+            call("datedif", &[date(45350.0), date(43890.0), string("D")]),
+            Err(ComputeError::NumberError(_))
+        ));
+
+        // From Friday 2024-03-01 to Friday 2024-03-15
+        let (start, end) = (date(45352.0), date(45366.0));
+        assert_eq!(
+            call("networkdays", &[start.clone(), end.clone()]),
+            Ok(num(11.0))
+        );
+        assert_eq!(
+            call("networkdays", &[end.clone(), start.clone()]),
+            Ok(num(-11.0))
+        );
+        let holidays = Value::Array(Array::new(
+            2,
+            1,
+            vec![Some(date(45359.0)), Some(date(45360.0))],
+        ));
+        assert_eq!(call("networkdays", &[start, end, holidays]), Ok(num(10.0)));
+        // Holidays given twice count once
+        let holidays = Value::Array(Array::new(
+            2,
+            1,
+            vec![Some(date(45359.0)), Some(num(45359.5))],
+        ));
+        assert_eq!(
+            call("networkdays", &[date(45352.0), date(45366.0), holidays]),
+            Ok(num(10.0))
+        );
+
+        let weekdays = |first: f64, last: f64| {
+            (first as i64..=last as i64)
+                .filter(|day| days_since_monday(*day as f64) < 5)
+                .count() as f64
+        };
+        for first in 45350..45357 {
+            for last in first..first + 22 {
+                let (first, last) = (f64::from(first), f64::from(last));
+                assert_eq!(
+                    call("networkdays", &numbers(&[first, last])),
+                    Ok(num(weekdays(first, last))),
+                    "{first} to {last}"
+                );
+            }
+        }
+        // From 0000-01-01 to 9999-12-31
+        assert_eq!(
+            call("networkdays", &numbers(&[-693959.0, 2958465.0])),
+            Ok(num(2608875.0))
+        );
+    }
+
+    #[test]
+    fn test_date_limits() {
+        let (num, date) = (Value::Number, Value::DateTime);
+        let out_of_range = |name: &str, args: &[Value]| {
+            matches!(call(name, args), Err(ComputeError::NumberError(_)))
+        };
+        assert!(out_of_range("year", &[num(1e19)]));
+        assert!(out_of_range("month", &[num(-693960.0)]));
+        assert!(out_of_range("weekday", &[date(f64::MAX)]));
+        assert!(out_of_range("date", &[num(2024.0), num(1e19), num(1.0)]));
+        assert!(out_of_range("date", &[num(2024.0), num(1.0), num(1e19)]));
+        assert!(out_of_range("date", &[num(9999.0), num(13.0), num(1.0)]));
+        assert!(out_of_range("edate", &[num(1e17), num(1.0)]));
+        assert!(out_of_range("edate", &[date(45366.0), num(1e19)]));
+        assert!(out_of_range("eomonth", &[num(-693959.0), num(-1.0)]));
+        assert!(out_of_range("networkdays", &[num(0.0), num(1e12)]));
+        assert!(out_of_range("datedif", &[num(0.0), num(1e12), string("D")]));
+
+        assert_eq!(
+            call("date", &[num(0.0), num(1.0), num(1.0)]),
+            Ok(date(-693959.0))
+        );
+        assert_eq!(
+            call("date", &[num(9999.0), num(12.0), num(31.0)]),
+            Ok(date(2958465.0))
+        );
+        assert_eq!(
+            call("eomonth", &[date(2958450.0), num(0.0)]),
+            Ok(date(2958465.0))
+        );
+        assert_eq!(format_date_time(-693959.0), "0000-01-01");
+        assert_eq!(format_date_time(1e19), "10000000000000000000");
+    }
+}
@@ -0,0 +1,612 @@
+//! Financial functions follow the sign convention of other spreadsheets: money paid out is negative
+//! and money received positive. The optional type says whether payments are due at the start (1) or
+//! end (0) of each period.
+
+use crate::common_types::{ComputeError, Value};
+
+use super::super::function_registry::{ArgumentType, Function, FunctionRegistry};
+use super::{array, number, numbers, out_of_domain, range_numbers};
+
+use ArgumentType::{Number, Range};
+
+pub fn register(registry: &mut FunctionRegistry) {
+    registry.add_builtin(
+        Function::new(
+            "pmt",
+            "The payment each period of a loan or investment at a constant interest rate. Type 1 \
+             pays at the start of each period rather than at its end.",
+            pmt,
+        )
+        .required(&[Number, Number, Number])
+        .optional(&[Number, Number]),
+    );
+    registry.add_builtin(
+        Function::new(
+            "ipmt",
+            "The interest part of the payment of the given period, counted from 1.",
+            ipmt,
+        )
+        .required(&[Number, Number, Number, Number])
+        .optional(&[Number, Number]),
+    );
+    registry.add_builtin(
+        Function::new(
+            "ppmt",
+            "The principal part of the payment of the given period, counted from 1.",
+            ppmt,
+        )
+        .required(&[Number, Number, Number, Number])
+        .optional(&[Number, Number]),
+    );
+    registry.add_builtin(
+        Function::new(
+            "pv",
+            "The present value of a loan or investment with constant payments.",
+            pv,
+        )
+        .required(&[Number, Number, Number])
+        .optional(&[Number, Number]),
+    );
+    registry.add_builtin(
+        Function::new(
+            "fv",
+            "The future value of a loan or investment with constant payments.",
+            fv,
+        )
+        .required(&[Number, Number, Number])
+        .optional(&[Number, Number]),
+    );
+    registry.add_builtin(
+        Function::new(
+            "nper",
+            "The number of periods a loan or investment with constant payments takes.",
+            nper,
+        )
+        .required(&[Number, Number, Number])
+        .optional(&[Number, Number]),
+    );
+    registry.add_builtin(
+        Function::new(
+            "rate",
+            "The interest rate per period of a loan or investment with constant payments, solved \
+             from the guess (10% by default).",
+            rate,
+        )
+        .required(&[Number, Number, Number])
+        .optional(&[Number, Number, Number]),
+    );
+    registry.add_builtin(
+        Function::new(
+            "npv",
+            "The net present value of cash flows at the end of each period, discounted at the \
+             rate.",
+            npv,
+        )
+        .required(&[Number, Number])
+        .repeated(Number),
+    );
+    registry.add_builtin(
+        Function::new(
+            "xnpv",
+            "The net present value of cash flows on the given dates, discounted at the yearly \
+             rate.",
+            xnpv,
+        )
+        .required(&[Number, Range, Range]),
+    );
+    registry.add_builtin(
+        Function::new(
+            "irr",
+            "The internal rate of return of cash flows at the end of each period, solved from the \
+             guess (10% by default).",
+            irr,
+        )
+        .required(&[Range])
+        .optional(&[Number]),
+    );
+    registry.add_builtin(
+        Function::new(
+            "xirr",
+            "The yearly internal rate of return of cash flows on the given dates, solved from the \
+             guess (10% by default).",
+            xirr,
+        )
+        .required(&[Range, Range])
+        .optional(&[Number]),
+    );
+    registry.add_builtin(
+        Function::new(
+            "sln",
+            "The straight line depreciation of an asset for each period of its life.",
+            sln,
+        )
+        .required(&[Number, Number, Number]),
+    );
+    registry.add_builtin(
+        Function::new(
+            "db",
+            "The fixed declining balance depreciation of an asset for the given period. The first \
+             year may have fewer months.",
+            db,
+        )
+        .required(&[Number, Number, Number, Number])
+        .optional(&[Number]),
+    );
+}
+
+// The optional future or present value and the type after the required arguments
+fn amount_and_type(args: &[Value], first: usize) -> (f64, bool) {
+    (
+        args.get(first).map_or(0.0, number),
+        args.get(first + 1).is_some_and(|due| number(due) != 0.0),
+    )
+}
+
+// What a payment of 1 each period grows to by the end of the periods
+fn annuity(rate: f64, periods: f64) -> f64 {
+    if rate == 0.0 {
+        periods
+    } else {
+        ((1.0 + rate).powf(periods) - 1.0) / rate
+    }
+}
+
+// The future value, from pv * (1 + rate)^periods + payment * (1 + rate * type) * annuity + fv = 0
+fn future_value(rate: f64, periods: f64, payment: f64, present: f64, due: bool) -> f64 {
+    let payments = if due { 1.0 + rate } else { 1.0 };
+    -(present * (1.0 + rate).powf(periods) + payment * payments * annuity(rate, periods))
+}
+
+fn payment(
+    rate: f64,
+    periods: f64,
+    present: f64,
+    future: f64,
+    due: bool,
+) -> Result<f64, ComputeError> {
+    let payments = if due { 1.0 + rate } else { 1.0 } * annuity(rate, periods);
+    if payments == 0.0 {
+        return Err(ComputeError::DivisionByZero);
+    }
+    Ok(-(future + present * (1.0 + rate).powf(periods)) / payments)
+}
+
+pub fn pmt(args: Vec<Value>) -> Result<Value, ComputeError> {
+    let (future, due) = amount_and_type(&args, 3);
+    Ok(Value::Number(payment(
+        number(&args[0]),
+        number(&args[1]),
+        number(&args[2]),
+        future,
+        due,
+    )?))
+}
+
+// The interest paid in a period, which is the interest on what is left to pay after the previous
+// periods
+fn interest_payment(args: &[Value]) -> Result<(f64, f64), ComputeError> {
+    let [rate, period, periods, present] = [&args[0], &args[1], &args[2], &args[3]].map(number);
+    let (future, due) = amount_and_type(args, 4);
+    if period < 1.0 || period > periods {
+        return Err(out_of_domain(
+            "The period has to be between 1 and the number of periods",
+        ));
+    }
+    let payment = payment(rate, periods, present, future, due)?;
+    let interest = match (due, period) {
+        // Nothing is owed yet when paying at the start of the first period
+        (true, 1.0) => 0.0,
+        (true, _) => future_value(rate, period - 1.0, payment, present, due) * rate / (1.0 + rate),
+        (false, _) => future_value(rate, period - 1.0, payment, present, due) * rate,
+    };
+    Ok((interest, payment))
+}
+
+pub fn ipmt(args: Vec<Value>) -> Result<Value, ComputeError> {
+    Ok(Value::Number(interest_payment(&args)?.0))
+}
+
+pub fn ppmt(args: Vec<Value>) -> Result<Value, ComputeError> {
+    let (interest, payment) = interest_payment(&args)?;
+    Ok(Value::Number(payment - interest))
+}
+
+pub fn pv(args: Vec<Value>) -> Result<Value, ComputeError> {
+    let [rate, periods, payment] = [&args[0], &args[1], &args[2]].map(number);
+    let (future, due) = amount_and_type(&args, 3);
+    let payments = if due { 1.0 + rate } else { 1.0 } * annuity(rate, periods);
+    Ok(Value::Number(
+        -(future + payment * payments) / (1.0 + rate).powf(periods),
+    ))
+}
+
+pub fn fv(args: Vec<Value>) -> Result<Value, ComputeError> {
+    let [rate, periods, payment] = [&args[0], &args[1], &args[2]].map(number);
+    let (present, due) = amount_and_type(&args, 3);
+    Ok(Value::Number(future_value(
+        rate, periods, payment, present, due,
+    )))
+}
+
+pub fn nper(args: Vec<Value>) -> Result<Value, ComputeError> {
+    let [rate, payment, present] = [&args[0], &args[1], &args[2]].map(number);
+    let (future, due) = amount_and_type(&args, 3);
+    if rate == 0.0 {
+        if payment == 0.0 {
+            return Err(ComputeError::DivisionByZero);
+        }
+        return Ok(Value::Number(-(present + future) / payment));
+    }
+    let payment = if due { payment * (1.0 + rate) } else { payment };
+    let growth = (payment - future * rate) / (payment + present * rate);
+    if rate <= -1.0 || !growth.is_finite() || growth <= 0.0 {
+        return Err(out_of_domain(
+            "nper found no number of periods paying off the loan",
+        ));
+    }
+    Ok(Value::Number(growth.ln() / (1.0 + rate).ln()))
+}
+
+// Finds a rate where `f` is zero with Newton's method starting at the guess. When that does not
+// converge, looks for rates between which `f` changes sign and halves the interval between them
+// until it is small.
+fn solve_rate(f: impl Fn(f64) -> f64, guess: f64, name: &str) -> Result<f64, ComputeError> {
+    const TOLERANCE: f64 = 1e-10;
+    let mut rate = guess;
+    for _ in 0..100 {
+        let step = 1e-6 * rate.abs().max(1.0);
+        let slope = (f(rate + step) - f(rate - step)) / (2.0 * step);
+        let next = rate - f(rate) / slope;
+        if !next.is_finite() || next <= -1.0 {
+            break;
+        }
+        if (next - rate).abs() < TOLERANCE * next.abs().max(1.0) {
+            return Ok(next);
+        }
+        rate = next;
+    }
+
+    let rates = [
+        -0.999, -0.99, -0.9, -0.75, -0.5, -0.25, 0.0, 0.1, 0.25, 0.5, 1.0, 2.0, 5.0, 10.0, 100.0,
+        1000.0,
+    ];
+    let bracket = rates.windows(2).find(|pair| {
+        let (low, high) = (f(pair[0]), f(pair[1]));
+        low.is_finite() && high.is_finite() && low.signum() != high.signum()
+    });
+    let Some(&[mut low, mut high]) = bracket else {
+        return Err(out_of_domain(&format!(
+            "{name} did not converge, try another guess"
+        )));
+    };
+    while high - low > TOLERANCE * high.abs().max(1.0) {
+        let middle = (low + high) / 2.0;
+        if f(middle).signum() == f(low).signum() {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+    Ok((low + high) / 2.0)
+}
+
+pub fn rate(args: Vec<Value>) -> Result<Value, ComputeError> {
+    let [periods, payment, present] = [&args[0], &args[1], &args[2]].map(number);
+    let (future, due) = amount_and_type(&args, 3);
+    let guess = args.get(5).map_or(0.1, number);
+    let balance = |rate: f64| future - future_value(rate, periods, payment, present, due);
+    Ok(Value::Number(solve_rate(balance, guess, "rate")?))
+}
+
+// The value of the cash flows discounted to the time of the first one, each one `periods` after it
+fn discounted(rate: f64, flows: &[(f64, f64)]) -> f64 {
+    flows
+        .iter()
+        .map(|(flow, periods)| flow / (1.0 + rate).powf(*periods))
+        .sum()
+}
+
+pub fn npv(args: Vec<Value>) -> Result<Value, ComputeError> {
+    let rate = number(&args[0]);
+    let flows: Vec<(f64, f64)> = numbers(&args[1..])
+        .zip(1..)
+        .map(|(flow, period)| (flow, f64::from(period)))
+        .collect();
+    if rate == -1.0 {
+        return Err(ComputeError::DivisionByZero);
+    }
+    Ok(Value::Number(discounted(rate, &flows)))
+}
+
+// Each cash flow with the years from the first date to its own. Both ranges need the same size and
+// numbers only.
+fn dated_flows(flows: &Value, dates: &Value, name: &str) -> Result<Vec<(f64, f64)>, ComputeError> {
+    let (flows, dates) = (array(flows), array(dates));
+    if flows.rows() * flows.columns() != dates.rows() * dates.columns() {
+        return Err(out_of_domain(&format!(
+            "{name} expects as many dates as values"
+        )));
+    }
+    let mut dated = Vec::new();
+    for (flow, date) in flows.cells().zip(dates.cells()) {
+        match (flow, date) {
+            (Some(Value::Error(error)), _) | (_, Some(Value::Error(error))) => {
+                return Err(error.clone())
+            }
+            (Some(Value::Number(flow)), Some(Value::Number(date) | Value::DateTime(date))) => {
+                dated.push((*flow, date.floor()))
+            }
+            _ => {
+                return Err(ComputeError::TypeError(format!(
+                    "{name} expects numbers as values and dates"
+                )))
+            }
+        }
+    }
+    let Some(&(_, first)) = dated.first() else {
+        return Err(out_of_domain(&format!("{name} expects at least one value")));
+    };
+    if dated.iter().any(|(_, date)| *date < first) {
+        return Err(out_of_domain(&format!(
+            "{name} expects no date before the first one"
+        )));
+    }
+    Ok(dated
+        .into_iter()
+        .map(|(flow, date)| (flow, (date - first) / 365.0))
+        .collect())
+}
+
+pub fn xnpv(args: Vec<Value>) -> Result<Value, ComputeError> {
+    let rate = number(&args[0]);
+    if rate <= -1.0 {
+        return Err(out_of_domain("xnpv expects a rate above -100%"));
+    }
+    Ok(Value::Number(discounted(
+        rate,
+        &dated_flows(&args[1], &args[2], "xnpv")?,
+    )))
+}
+
+// The rate at which the cash flows are worth nothing, which needs both money paid and received
+fn internal_rate(flows: &[(f64, f64)], guess: f64, name: &str) -> Result<f64, ComputeError> {
+    if !flows.iter().any(|(flow, _)| *flow > 0.0) || !flows.iter().any(|(flow, _)| *flow < 0.0) {
+        return Err(out_of_domain(&format!(
+            "{name} expects at least one positive and one negative value"
+        )));
+    }
+    solve_rate(|rate| discounted(rate, flows), guess, name)
+}
+
+pub fn irr(args: Vec<Value>) -> Result<Value, ComputeError> {
+    let flows: Vec<(f64, f64)> = range_numbers(&args[0])?
+        .into_iter()
+        .zip(0..)
+        .map(|(flow, period)| (flow, f64::from(period)))
+        .collect();
+    Ok(Value::Number(internal_rate(
+        &flows,
+        args.get(1).map_or(0.1, number),
+        "irr",
+    )?))
+}
+
+pub fn xirr(args: Vec<Value>) -> Result<Value, ComputeError> {
+    let flows = dated_flows(&args[0], &args[1], "xirr")?;
+    Ok(Value::Number(internal_rate(
+        &flows,
+        args.get(2).map_or(0.1, number),
+        "xirr",
+    )?))
+}
+
+pub fn sln(args: Vec<Value>) -> Result<Value, ComputeError> {
+    let [cost, salvage, life] = [&args[0], &args[1], &args[2]].map(number);
+    if life == 0.0 {
+        return Err(ComputeError::DivisionByZero);
+    }
+    Ok(Value::Number((cost - salvage) / life))
+}
+
+// Depreciates by a fixed rate of what is left each year, rounded to three decimals as other
+// spreadsheets do. When the first year has fewer months, the rest of it is depreciated in an extra
+// year after the life.
+pub fn db(args: Vec<Value>) -> Result<Value, ComputeError> {
+    let [cost, salvage, life, period] = [&args[0], &args[1], &args[2], &args[3]].map(number);
+    let months = args.get(4).map_or(12.0, number).trunc();
+    let period = period.trunc();
+    let last_period = if months < 12.0 { life + 1.0 } else { life };
+    if cost < 0.0
+        || salvage < 0.0
+        || life <= 0.0
+        || !(1.0..=12.0).contains(&months)
+        || period < 1.0
+        || period > last_period
+    {
+        return Err(out_of_domain(
+            "db expects positive amounts and a period within the life of the asset",
+        ));
+    }
+    if cost == 0.0 {
+        return Ok(Value::Number(0.0));
+    }
+    let rate = ((1.0 - (salvage / cost).powf(1.0 / life)) * 1000.0).round() / 1000.0;
+    let first_year = cost * rate * months / 12.0;
+    if period == 1.0 {
+        return Ok(Value::Number(first_year));
+    }
+    // After the first year, what is left shrinks by the rate every year
+    let depreciation = (cost - first_year) * (1.0 - rate).powf(period - 2.0) * rate;
+    Ok(Value::Number(if period > life {
+        depreciation * (12.0 - months) / 12.0
+    } else {
+        depreciation
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_helpers::{assert_close, assert_within, call, column, numbers};
+    use super::*;
+    use crate::common_types::Array;
+
+    // Reference values are the examples of the same functions in other spreadsheets, rounded to the
+    // cent
+    #[test]
+    fn test_loan_functions() {
+        let num = Value::Number;
+        let cents = |name: &str, args: &[f64], expected: f64| {
+            assert_within(call(name, &numbers(args)), expected, 0.005)
+        };
+        cents("pmt", &[0.08 / 12.0, 10.0, 10000.0], -1037.03);
+        cents("pmt", &[0.08 / 12.0, 10.0, 10000.0, 0.0, 1.0], -1030.16);
+        cents("pmt", &[0.06 / 12.0, 18.0 * 12.0, 0.0, 50000.0], -129.08);
+        cents("pmt", &[0.0, 10.0, 1000.0], -100.0);
+        cents("ipmt", &[0.1 / 12.0, 1.0, 36.0, 8000.0], -66.67);
+        cents("ipmt", &[0.1, 3.0, 3.0, 8000.0], -292.45);
+        cents("ppmt", &[0.1 / 12.0, 1.0, 24.0, 2000.0], -75.62);
+        cents("ppmt", &[0.08, 10.0, 10.0, 200000.0], -27598.05);
+        cents("pv", &[0.08 / 12.0, 12.0 * 20.0, 500.0], -59777.15);
+        cents("fv", &[0.06 / 12.0, 10.0, -200.0, -500.0, 1.0], 2581.40);
+        cents("fv", &[0.12 / 12.0, 12.0, -1000.0], 12682.50);
+        assert_within(
+            call(
+                "nper",
+                &numbers(&[0.12 / 12.0, -100.0, -1000.0, 10000.0, 1.0]),
+            ),
+            59.6738657,
+            1e-7,
+        );
+        assert_within(
+            call("nper", &numbers(&[0.01, -100.0, -1000.0, 10000.0])),
+            60.0821229,
+            1e-7,
+        );
+        assert_within(
+            call("rate", &numbers(&[48.0, -200.0, 8000.0])),
+            0.007701472488202,
+            1e-12,
+        );
+
+        // Paying at the start of each period, the interest of the first one is 0 and the rest is
+        // principal
+        assert_eq!(
+            call("ipmt", &numbers(&[0.1, 1.0, 3.0, 8000.0, 0.0, 1.0])),
+            Ok(num(0.0))
+        );
+        assert!(matches!(
+            call("ipmt", &numbers(&[0.1, 4.0, 3.0, 8000.0])),
+            Err(ComputeError::NumberError(_))
+        ));
+        assert!(matches!(
+            call("pmt", &numbers(&[0.1, 0.0, 8000.0])),
+            Err(ComputeError::DivisionByZero)
+        ));
+        assert!(matches!(
+            call("nper", &numbers(&[0.1, -100.0, 8000.0])),
+            Err(ComputeError::NumberError(_))
+        ));
+        assert!(matches!(
+            call("rate", &numbers(&[48.0, 200.0, 8000.0])),
+            Err(ComputeError::NumberError(_))
+        ));
+    }
+
+    #[test]
+    fn test_cash_flow_functions() {
+        let num = Value::Number;
+        assert_within(
+            call("npv", &numbers(&[0.1, -10000.0, 3000.0, 4200.0, 6800.0])),
+            1188.44,
+            0.005,
+        );
+
+        let flows = column(&[-70000.0, 12000.0, 15000.0, 18000.0, 21000.0, 26000.0]);
+        assert_within(call("irr", &[flows]), 0.0866309480365, 1e-10);
+        assert_within(
+            call(
+                "irr",
+                &[column(&[-70000.0, 12000.0, 15000.0, 18000.0, 21000.0])],
+            ),
+            -0.0212448482734,
+            1e-10,
+        );
+        assert_within(
+            call("irr", &[column(&[-70000.0, 12000.0, 15000.0]), num(-0.1)]),
+            -0.4435069413347,
+            1e-10,
+        );
+        // A guess far from the rate still finds it
+        assert_within(
+            call("irr", &[column(&[-70000.0, 12000.0, 15000.0]), num(10.0)]),
+            -0.4435069413347,
+            1e-10,
+        );
+        assert!(matches!(
+            call("irr", &[column(&[1000.0, 12000.0])]),
+            Err(ComputeError::NumberError(_))
+        ));
+
+        let values = column(&[-10000.0, 2750.0, 4250.0, 3250.0, 2750.0]);
+        let dates = Value::Array(Array::new(
+            5,
+            1,
+            [39448.0, 39508.0, 39751.0, 39859.0, 39904.0]
+                .map(|serial| Some(Value::DateTime(serial)))
+                .to_vec(),
+        ));
+        assert_within(
+            call("xnpv", &[num(0.09), values.clone(), dates.clone()]),
+            2086.65,
+            0.005,
+        );
+        assert_within(
+            call("xirr", &[values.clone(), dates]),
+            0.3733625335188,
+            1e-10,
+        );
+        assert!(matches!(
+            call("xirr", &[values.clone(), column(&[39448.0, 39508.0])]),
+            Err(ComputeError::NumberError(_))
+        ));
+        assert!(matches!(
+            call(
+                "xnpv",
+                &[
+                    num(0.09),
+                    values,
+                    column(&[39448.0, 39400.0, 39751.0, 39859.0, 39904.0])
+                ]
+            ),
+            Err(ComputeError::NumberError(_))
+        ));
+    }
+
+    #[test]
+    fn test_depreciation() {
+        assert_close(call("sln", &numbers(&[30000.0, 7500.0, 10.0])), 2250.0);
+        assert!(matches!(
+            call("sln", &numbers(&[30000.0, 7500.0, 0.0])),
+            Err(ComputeError::DivisionByZero)
+        ));
+
+        let db = |period: f64| call("db", &numbers(&[1000000.0, 100000.0, 6.0, period, 7.0]));
+        let expected = [
+            186083.33, 259639.42, 176814.44, 120410.64, 81999.64, 55841.76, 15845.10,
+        ];
+        for (period, expected) in (1..).zip(expected) {
+            assert_within(db(f64::from(period)), expected, 0.005);
+        }
+        assert!(matches!(db(8.0), Err(ComputeError::NumberError(_))));
+        assert_within(
+            call("db", &numbers(&[1000.0, 100.0, 1e12, 1e12])),
+            0.0,
+            1e-9,
+        );
+        // Without a shorter first year there is no extra year
+        assert!(matches!(
+            call("db", &numbers(&[1000000.0, 100000.0, 6.0, 7.0])),
+            Err(ComputeError::NumberError(_))
+        ));
+    }
+}
@@ -0,0 +1,432 @@
+//! Lookups compare numbers with numbers, text with text ignoring case and booleans with booleans
+
+use std::cmp::Ordering;
+
+use crate::common_types::{ComputeError, Value};
+
+use super::super::criteria::wildcard_match;
+use super::super::function_registry::{ArgumentType, Function, FunctionRegistry};
+use super::{array, number};
+
+use ArgumentType::{Any, Bool, Number, Range};
+
+pub fn register(registry: &mut FunctionRegistry) {
+    registry.add_builtin(
+        Function::new(
+            "vlookup",
+            "Looks for the value in the first column of the table and results in the cell of the \
+             given column on the same row. Unless approximate is FALSE, the first column is \
+             sorted ascending and the last value not greater than the value is found.",
+            vlookup,
+        )
+        .required(&[Any, Range, Number])
+        .optional(&[Bool]),
+    );
+    registry.add_builtin(
+        Function::new(
+            "hlookup",
+            "Looks for the value in the first row of the table and results in the cell of the \
+             given row in the same column. Unless approximate is FALSE, the first row is sorted \
+             ascending and the last value not greater than the value is found.",
+            hlookup,
+        )
+        .required(&[Any, Range, Number])
+        .optional(&[Bool]),
+    );
+    registry.add_builtin(
+        Function::new(
+            "index",
+            "The cell at the given row and column of the range, counted from 1. A single row or \
+             column only needs the position.",
+            index,
+        )
+        .required(&[Range, Number])
+        .optional(&[Number]),
+    );
+    registry.add_builtin(
+        Function::new(
+            "match",
+            "Position of the value in a row or column. Match type 0 finds the exact value, 1 \
+             (default) the largest value not greater in an ascending range, -1 the smallest value \
+             not less in a descending range.",
+            match_position,
+        )
+        .required(&[Any, Range])
+        .optional(&[Number]),
+    );
+    registry.add_builtin(
+        Function::new(
+            "xlookup",
+            "Looks for the value in the lookup range and results in the cell at the same position \
+             in the result range. Match mode 0 (default) finds the exact value, -1 also the next \
+             smaller one, 1 the next larger one. Search mode -1 searches from the end.",
+            xlookup,
+        )
+        .required(&[Any, Range, Range])
+        .optional(&[Any, Number, Number]),
+    );
+    registry.add_builtin(
+        Function::new("rows", "Number of rows of the range.", rows).required(&[Range]),
+    );
+    registry.add_builtin(
+        Function::new("columns", "Number of columns of the range.", columns).required(&[Range]),
+    );
+}
+
+fn compare(cell: &Value, value: &Value) -> Option<Ordering> {
+    match (cell, value) {
+        (Value::Number(a) | Value::DateTime(a), Value::Number(b) | Value::DateTime(b)) => {
+            a.partial_cmp(b)
+        }
+        (Value::Text(a), Value::Text(b)) => Some(a.to_lowercase().cmp(&b.to_lowercase())),
+        (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+        _ => None,
+    }
+}
+
+fn not_found(name: &str, value: &Value) -> ComputeError {
+    ComputeError::NotAvailable(format!("{name} can not find {value}"))
+}
+
+/// How a lookup finds the value among the cells.
+#[derive(Clone, Copy, PartialEq)]
+enum MatchMode {
+    Exact,
+    // Text is matched with wildcards, see `wildcard_match`
+    Wildcard,
+    // The closest cell on the given side of the value when there is no exact one
+    ExactOrNext(Ordering),
+    // The last cell not past the value on the given side, stopping at the first one past it as in a
+    // sorted range
+    Sorted(Ordering),
+}
+
+// Position among the cells of the cell matching the value, searching from the end if reversed
+fn find_match(
+    cells: &[Option<&Value>],
+    value: &Value,
+    mode: MatchMode,
+    reversed: bool,
+) -> Option<usize> {
+    let mut positions: Vec<usize> = (0..cells.len()).collect();
+    if reversed {
+        positions.reverse();
+    }
+    let mut found: Option<(usize, &Value)> = None;
+    for position in positions {
+        let Some(cell) = cells[position] else {
+            continue;
+        };
+        if let (MatchMode::Wildcard, Value::Text(text), Value::Text(pattern)) = (mode, cell, value)
+        {
+            if wildcard_match(pattern, text) {
+                return Some(position);
+            }
+            continue;
+        }
+        let Some(ordering) = compare(cell, value) else {
+            continue;
+        };
+        match mode {
+            _ if ordering == Ordering::Equal => return Some(position),
+            MatchMode::Exact | MatchMode::Wildcard => {}
+            MatchMode::ExactOrNext(side) => {
+                let closer =
+                    found.is_none_or(|(_, best)| compare(cell, best) == Some(side.reverse()));
+                if ordering == side && closer {
+                    found = Some((position, cell));
+                }
+            }
+            MatchMode::Sorted(side) if ordering == side => break,
+            MatchMode::Sorted(_) => found = Some((position, cell)),
+        }
+    }
+    found.map(|(position, _)| position)
+}
+
+// The first row or column of the table holds the values looked up, the result is taken from the
+// given one
+fn lookup(args: &[Value], name: &str, by_row: bool) -> Result<Value, ComputeError> {
+    let (value, table) = (&args[0], array(&args[1]));
+    let (lines, length) = if by_row {
+        (table.columns(), table.rows())
+    } else {
+        (table.rows(), table.columns())
+    };
+    let line = number(&args[2]).trunc();
+    if line < 1.0 {
+        return Err(ComputeError::InvalidArgument(format!(
+            "{name} expects an index of at least 1"
+        )));
+    }
+    if line > length as f64 {
+        return Err(ComputeError::UnfindableReference(format!(
+            "{name} can not take index {line} of a table of {length}"
+        )));
+    }
+    let cell = |position: usize, line: usize| {
+        if by_row {
+            table.get(line, position)
+        } else {
+            table.get(position, line)
+        }
+    };
+    let keys: Vec<Option<&Value>> = (0..lines).map(|position| cell(position, 0)).collect();
+    let mode = match args.get(3) {
+        Some(Value::Bool(false)) => MatchMode::Wildcard,
+        _ => MatchMode::Sorted(Ordering::Greater),
+    };
+    let position = find_match(&keys, value, mode, false).ok_or_else(|| not_found(name, value))?;
+    cell(position, line as usize - 1)
+        .cloned()
+        .map_or(Ok(Value::Number(0.0)), Value::into_result)
+}
+
+pub fn vlookup(args: Vec<Value>) -> Result<Value, ComputeError> {
+    lookup(&args, "vlookup", false)
+}
+
+pub fn hlookup(args: Vec<Value>) -> Result<Value, ComputeError> {
+    lookup(&args, "hlookup", true)
+}
+
+pub fn index(args: Vec<Value>) -> Result<Value, ComputeError> {
+    let range = array(&args[0]);
+    let (mut row, mut column) = (
+        number(&args[1]).trunc(),
+        args.get(2).map_or(1.0, number).trunc(),
+    );
+    if range.rows() == 1 && args.len() == 2 {
+        (row, column) = (1.0, row);
+    }
+    if row < 1.0 || column < 1.0 {
+        return Err(ComputeError::InvalidArgument(
+            "index expects a row and column of at least 1".to_string(),
+        ));
+    }
+    if row > range.rows() as f64 || column > range.columns() as f64 {
+        return Err(ComputeError::UnfindableReference(
+            "index is outside of the range".to_string(),
+        ));
+    }
+    // Empty cells are shown as 0, as when referring to them
+    range
+        .get(row as usize - 1, column as usize - 1)
+        .cloned()
+        .map_or(Ok(Value::Number(0.0)), Value::into_result)
+}
+
+// The cells of a range with a single row or column
+fn line<'a>(arg: &'a Value, name: &str) -> Result<Vec<Option<&'a Value>>, ComputeError> {
+    let range = array(arg);
+    if range.rows() > 1 && range.columns() > 1 {
+        return Err(ComputeError::NotAvailable(format!(
+            "{name} expects a single row or column"
+        )));
+    }
+    Ok(range.cells().collect())
+}
+
+pub fn match_position(args: Vec<Value>) -> Result<Value, ComputeError> {
+    let cells = line(&args[1], "match")?;
+    let mode = match args.get(2).map_or(1.0, number) {
+        0.0 => MatchMode::Wildcard,
+        match_type if match_type > 0.0 => MatchMode::Sorted(Ordering::Greater),
+        _ => MatchMode::Sorted(Ordering::Less),
+    };
+    let position =
+        find_match(&cells, &args[0], mode, false).ok_or_else(|| not_found("match", &args[0]))?;
+    Ok(Value::Number(position as f64 + 1.0))
+}
+
+pub fn xlookup(args: Vec<Value>) -> Result<Value, ComputeError> {
+    let (keys, results) = (line(&args[1], "xlookup")?, array(&args[2]));
+    let lookup_range = array(&args[1]);
+    if (results.rows(), results.columns()) != (lookup_range.rows(), lookup_range.columns()) {
+        return Err(ComputeError::InvalidArgument(
+            "xlookup expects lookup and result ranges of the same size".to_string(),
+        ));
+    }
+    let mode = match args.get(4).map_or(0.0, number) {
+        0.0 => MatchMode::Exact,
+        -1.0 => MatchMode::ExactOrNext(Ordering::Less),
+        1.0 => MatchMode::ExactOrNext(Ordering::Greater),
+        2.0 => MatchMode::Wildcard,
+        _ => {
+            return Err(ComputeError::InvalidArgument(
+                "xlookup expects a match mode of -1, 0, 1 or 2".to_string(),
+            ))
+        }
+    };
+    let reversed = match args.get(5).map_or(1.0, number) {
+        1.0 => false,
+        -1.0 => true,
+        _ => {
+            return Err(ComputeError::InvalidArgument(
+                "xlookup expects a search mode of 1 or -1".to_string(),
+            ))
+        }
+    };
+    match find_match(&keys, &args[0], mode, reversed) {
+        Some(position) => results
+            .cells()
+            .nth(position)
+            .flatten()
+            .cloned()
+            .map_or(Ok(Value::Number(0.0)), Value::into_result),
+        None => args
+            .get(3)
+            .cloned()
+            .ok_or_else(|| not_found("xlookup", &args[0])),
+    }
+}
+
+pub fn rows(args: Vec<Value>) -> Result<Value, ComputeError> {
+    Ok(Value::Number(array(&args[0]).rows() as f64))
+}
+
+pub fn columns(args: Vec<Value>) -> Result<Value, ComputeError> {
+    Ok(Value::Number(array(&args[0]).columns() as f64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_helpers::{call, column, products, string};
+    use super::*;
+    use crate::common_types::Array;
+
+    // Products with their price and stock, sorted by name
+
+    #[test]
+    fn test_lookups() {
+        let num = Value::Number;
+        assert_eq!(
+            call(
+                "vlookup",
+                &[string("Cherry"), products(), num(2.0), Value::Bool(false)]
+            ),
+            Ok(num(3.0))
+        );
+        assert_eq!(
+            call("vlookup", &[string("coconut"), products(), num(2.0)]),
+            Ok(num(3.0))
+        );
+        assert_eq!(
+            call("vlookup", &[string("banana"), products(), num(3.0)]),
+            Ok(num(0.0))
+        );
+        assert!(matches!(
+            call(
+                "vlookup",
+                &[string("coconut"), products(), num(2.0), Value::Bool(false)]
+            ),
+            Err(ComputeError::NotAvailable(_))
+        ));
+        assert!(matches!(
+            call("vlookup", &[string("aardvark"), products(), num(2.0)]),
+            Err(ComputeError::NotAvailable(_))
+        ));
+        assert!(matches!(
+            call("vlookup", &[string("apple"), products(), num(4.0)]),
+            Err(ComputeError::UnfindableReference(_))
+        ));
+
+        let header = Value::Array(Array::new(
+            2,
+            3,
+            [1.0, 2.0, 3.0, 10.0, 20.0, 30.0]
+                .map(|n| Some(num(n)))
+                .to_vec(),
+        ));
+        assert_eq!(
+            call("hlookup", &[num(2.5), header.clone(), num(2.0)]),
+            Ok(num(20.0))
+        );
+
+        assert_eq!(
+            call("index", &[products(), num(4.0), num(1.0)]),
+            Ok(string("date"))
+        );
+        assert_eq!(
+            call("index", &[column(&[4.0, 5.0, 6.0]), num(2.0)]),
+            Ok(num(5.0))
+        );
+        assert!(matches!(
+            call("index", &[products(), num(5.0), num(1.0)]),
+            Err(ComputeError::UnfindableReference(_))
+        ));
+        assert_eq!(
+            (call("rows", &[products()]), call("columns", &[header])),
+            (Ok(num(4.0)), Ok(num(3.0)))
+        );
+    }
+
+    #[test]
+    fn test_match_and_xlookup() {
+        let num = Value::Number;
+        let ascending = column(&[10.0, 20.0, 30.0, 40.0]);
+        let descending = column(&[40.0, 30.0, 20.0, 10.0]);
+        assert_eq!(call("match", &[num(25.0), ascending.clone()]), Ok(num(2.0)));
+        assert_eq!(
+            call("match", &[num(30.0), ascending.clone(), num(0.0)]),
+            Ok(num(3.0))
+        );
+        assert_eq!(
+            call("match", &[num(25.0), descending, num(-1.0)]),
+            Ok(num(2.0))
+        );
+        assert!(matches!(
+            call("match", &[num(25.0), ascending.clone(), num(0.0)]),
+            Err(ComputeError::NotAvailable(_))
+        ));
+        assert!(matches!(
+            call("match", &[num(5.0), ascending.clone()]),
+            Err(ComputeError::NotAvailable(_))
+        ));
+        assert!(matches!(
+            call("match", &[num(1.0), products()]),
+            Err(ComputeError::NotAvailable(_))
+        ));
+
+        let (keys, results) = (
+            column(&[3.0, 1.0, 2.0, 1.0]),
+            column(&[30.0, 10.0, 20.0, 11.0]),
+        );
+        let xlookup = |args: &[Value]| {
+            call(
+                "xlookup",
+                &[
+                    &[args[0].clone(), keys.clone(), results.clone()],
+                    &args[1..],
+                ]
+                .concat(),
+            )
+        };
+        assert_eq!(xlookup(&[num(1.0)]), Ok(num(10.0)));
+        assert_eq!(
+            xlookup(&[num(1.0), string("none"), num(0.0), num(-1.0)]),
+            Ok(num(11.0))
+        );
+        assert_eq!(xlookup(&[num(2.5), string("none")]), Ok(string("none")));
+        assert_eq!(
+            xlookup(&[num(2.5), string("none"), num(-1.0)]),
+            Ok(num(20.0))
+        );
+        assert_eq!(
+            xlookup(&[num(2.5), string("none"), num(1.0)]),
+            Ok(num(30.0))
+        );
+        assert!(matches!(
+            xlookup(&[num(4.0)]),
+            Err(ComputeError::NotAvailable(_))
+        ));
+        assert_eq!(
+            call("xlookup", &[num(1.0), keys.clone(), ascending]),
+            Ok(num(20.0))
+        );
+        assert!(matches!(
+            call("xlookup", &[num(1.0), keys.clone(), column(&[1.0])]),
+            Err(ComputeError::InvalidArgument(_))
+        ));
+    }
+}
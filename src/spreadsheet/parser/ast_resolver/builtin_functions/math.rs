@@ -0,0 +1,481 @@
+//! Math functions report arguments outside of their domain as #NUM! rather than computing NaN
+
+use crate::common_types::{ComputeError, Value};
+
+use super::super::function_registry::{ArgumentType, Function, FunctionRegistry};
+use super::{number, numbers, out_of_domain};
+
+use ArgumentType::Number;
+
+pub fn register(registry: &mut FunctionRegistry) {
+    registry.add_builtin(
+        Function::new(
+            "round",
+            "Rounds to the given amount of decimal digits, 0 by default. Halves are rounded away \
+             from zero.",
+            round,
+        )
+        .required(&[Number])
+        .optional(&[Number]),
+    );
+    registry
+        .add_builtin(Function::new("pow", "Power calculation.", power).required(&[Number, Number]));
+    registry.add_builtin(
+        Function::new(
+            "roundup",
+            "Rounds away from zero to the given amount of decimal digits, 0 by default.",
+            roundup,
+        )
+        .required(&[Number])
+        .optional(&[Number]),
+    );
+    registry.add_builtin(
+        Function::new(
+            "rounddown",
+            "Rounds towards zero to the given amount of decimal digits, 0 by default.",
+            rounddown,
+        )
+        .required(&[Number])
+        .optional(&[Number]),
+    );
+    registry.add_builtin(
+        Function::new(
+            "trunc",
+            "Cuts off the digits after the given amount of decimal digits, 0 by default.",
+            rounddown,
+        )
+        .required(&[Number])
+        .optional(&[Number]),
+    );
+    registry.add_builtin(
+        Function::new("int", "Rounds down to the nearest integer.", int).required(&[Number]),
+    );
+    registry.add_builtin(
+        Function::new(
+            "floor",
+            "Rounds down to a multiple of the significance, 1 by default.",
+            floor,
+        )
+        .required(&[Number])
+        .optional(&[Number]),
+    );
+    registry.add_builtin(
+        Function::new(
+            "ceiling",
+            "Rounds up to a multiple of the significance, 1 by default.",
+            ceiling,
+        )
+        .required(&[Number])
+        .optional(&[Number]),
+    );
+    registry.add_builtin(Function::new("abs", "Absolute value.", abs).required(&[Number]));
+    registry.add_builtin(
+        Function::new(
+            "sign",
+            "1 for positive numbers, -1 for negative numbers and 0 for zero.",
+            sign,
+        )
+        .required(&[Number]),
+    );
+    registry.add_builtin(
+        Function::new(
+            "mod",
+            "Remainder of dividing the number by the divisor, with the sign of the divisor.",
+            modulo,
+        )
+        .required(&[Number, Number]),
+    );
+    registry.add_builtin(Function::new("sqrt", "Square root.", sqrt).required(&[Number]));
+    registry.add_builtin(
+        Function::new("exp", "e raised to the power of the number.", exp).required(&[Number]),
+    );
+    registry.add_builtin(Function::new("ln", "Natural logarithm.", ln).required(&[Number]));
+    registry.add_builtin(
+        Function::new("log", "Logarithm to the given base, 10 by default.", log)
+            .required(&[Number])
+            .optional(&[Number]),
+    );
+    registry
+        .add_builtin(Function::new("log10", "Logarithm to base 10.", log10).required(&[Number]));
+    registry.add_builtin(
+        Function::new("gcd", "Greatest common divisor of integers.", gcd)
+            .required(&[Number])
+            .repeated(Number),
+    );
+    registry.add_builtin(
+        Function::new("lcm", "Least common multiple of integers.", lcm)
+            .required(&[Number])
+            .repeated(Number),
+    );
+    registry.add_builtin(
+        Function::new(
+            "factorial",
+            "Product of the integers from 1 to the number.",
+            factorial,
+        )
+        .required(&[Number]),
+    );
+    registry.add_builtin(Function::new("pi", "The number pi.", pi));
+    registry
+        .add_builtin(Function::new("sin", "Sine of an angle in radians.", sin).required(&[Number]));
+    registry.add_builtin(
+        Function::new("cos", "Cosine of an angle in radians.", cos).required(&[Number]),
+    );
+    registry.add_builtin(
+        Function::new("tan", "Tangent of an angle in radians.", tan).required(&[Number]),
+    );
+    registry.add_builtin(Function::new("asin", "Arcsine in radians.", asin).required(&[Number]));
+    registry.add_builtin(Function::new("acos", "Arccosine in radians.", acos).required(&[Number]));
+    registry.add_builtin(Function::new("atan", "Arctangent in radians.", atan).required(&[Number]));
+    registry.add_builtin(
+        Function::new(
+            "atan2",
+            "Angle in radians between the x axis and the point (x, y).",
+            atan2,
+        )
+        .required(&[Number, Number]),
+    );
+    registry.add_builtin(
+        Function::new("degrees", "Converts radians to degrees.", degrees).required(&[Number]),
+    );
+    registry.add_builtin(
+        Function::new("radians", "Converts degrees to radians.", radians).required(&[Number]),
+    );
+}
+
+pub fn round(args: Vec<Value>) -> Result<Value, ComputeError> {
+    Ok(Value::Number(round_with(&args, f64::round)))
+}
+
+pub fn power(args: Vec<Value>) -> Result<Value, ComputeError> {
+    let [base, exponent] = [&args[0], &args[1]].map(|arg| arg.as_number().unwrap_or_default());
+    Ok(Value::Number(base.powf(exponent)))
+}
+
+// Drops the error floating point arithmetic leaves after the 15th significant digit, so that 1.005
+// * 100 is 100.5
+fn significant(num: f64) -> f64 {
+    format!("{num:.14e}").parse().unwrap_or(num)
+}
+
+// Rounds the first argument to the amount of decimal digits in the optional second one. Numbers that
+// have no more than 15 significant digits up to the rounded one are already as precise as they get
+fn round_with(args: &[Value], rounding: fn(f64) -> f64) -> f64 {
+    let num = number(&args[0]);
+    let digits = args.get(1).map_or(0.0, number).trunc().clamp(-308.0, 308.0);
+    if num == 0.0 || !num.is_finite() || num.abs().log10().floor() + digits >= 15.0 {
+        return num;
+    }
+    // Powers of ten below one are not exact, so rounding to tens and more divides by the scale instead
+    let scale = 10f64.powf(digits.abs());
+    if digits < 0.0 {
+        rounding(significant(num / scale)) * scale
+    } else {
+        rounding(significant(num * scale)) / scale
+    }
+}
+
+pub fn roundup(args: Vec<Value>) -> Result<Value, ComputeError> {
+    Ok(Value::Number(round_with(&args, |num| {
+        num.signum() * num.abs().ceil()
+    })))
+}
+
+pub fn rounddown(args: Vec<Value>) -> Result<Value, ComputeError> {
+    Ok(Value::Number(round_with(&args, f64::trunc)))
+}
+
+pub fn int(args: Vec<Value>) -> Result<Value, ComputeError> {
+    Ok(Value::Number(number(&args[0]).floor()))
+}
+
+// The significance has to have the sign of the number, unless the number is negative
+fn multiple_of(
+    args: &[Value],
+    name: &str,
+    rounding: fn(f64) -> f64,
+) -> Result<Value, ComputeError> {
+    let num = number(&args[0]);
+    let significance = args.get(1).map_or(1.0, number);
+    if num > 0.0 && significance < 0.0 {
+        return Err(ComputeError::NumberError(format!(
+            "{name} expects a positive significance for a positive number"
+        )));
+    }
+    Ok(Value::Number(
+        rounding(significant(num / significance)) * significance,
+    ))
+}
+
+pub fn floor(args: Vec<Value>) -> Result<Value, ComputeError> {
+    if args.get(1).map(number) == Some(0.0) {
+        return Err(ComputeError::DivisionByZero);
+    }
+    multiple_of(&args, "floor", f64::floor)
+}
+
+pub fn ceiling(args: Vec<Value>) -> Result<Value, ComputeError> {
+    if args.get(1).map(number) == Some(0.0) {
+        return Ok(Value::Number(0.0));
+    }
+    multiple_of(&args, "ceiling", f64::ceil)
+}
+
+pub fn abs(args: Vec<Value>) -> Result<Value, ComputeError> {
+    Ok(Value::Number(number(&args[0]).abs()))
+}
+
+pub fn sign(args: Vec<Value>) -> Result<Value, ComputeError> {
+    let num = number(&args[0]);
+    Ok(Value::Number(if num == 0.0 { 0.0 } else { num.signum() }))
+}
+
+pub fn modulo(args: Vec<Value>) -> Result<Value, ComputeError> {
+    if number(&args[1]) == 0.0 {
+        return Err(ComputeError::DivisionByZero);
+    }
+    Ok(args[0]
+        .modulo(args[1].clone())
+        .expect("mod takes two numbers"))
+}
+
+pub fn sqrt(args: Vec<Value>) -> Result<Value, ComputeError> {
+    match number(&args[0]) {
+        num if num < 0.0 => Err(out_of_domain("sqrt expects a number that is not negative")),
+        num => Ok(Value::Number(num.sqrt())),
+    }
+}
+
+pub fn exp(args: Vec<Value>) -> Result<Value, ComputeError> {
+    Ok(Value::Number(number(&args[0]).exp()))
+}
+
+pub fn ln(args: Vec<Value>) -> Result<Value, ComputeError> {
+    match number(&args[0]) {
+        num if num <= 0.0 => Err(out_of_domain("ln expects a positive number")),
+        num => Ok(Value::Number(num.ln())),
+    }
+}
+
+pub fn log(args: Vec<Value>) -> Result<Value, ComputeError> {
+    let (num, base) = (number(&args[0]), args.get(1).map_or(10.0, number));
+    if num <= 0.0 || base <= 0.0 {
+        return Err(out_of_domain("log expects a positive number and base"));
+    }
+    if base == 1.0 {
+        return Err(ComputeError::DivisionByZero);
+    }
+    Ok(Value::Number(num.log(base)))
+}
+
+pub fn log10(args: Vec<Value>) -> Result<Value, ComputeError> {
+    match number(&args[0]) {
+        num if num <= 0.0 => Err(out_of_domain("log10 expects a positive number")),
+        num => Ok(Value::Number(num.log10())),
+    }
+}
+
+// The integer parts of the numbers, which can not be negative
+fn non_negative_integers(args: &[Value], name: &str) -> Result<Vec<u64>, ComputeError> {
+    numbers(args)
+        .map(|num| match num.trunc() {
+            int if int < 0.0 => Err(ComputeError::NumberError(format!(
+                "{name} expects numbers that are not negative"
+            ))),
+            int => Ok(int as u64),
+        })
+        .collect()
+}
+
+fn greatest_common_divisor(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+pub fn gcd(args: Vec<Value>) -> Result<Value, ComputeError> {
+    let integers = non_negative_integers(&args, "gcd")?;
+    Ok(Value::Number(
+        integers.into_iter().fold(0, greatest_common_divisor) as f64,
+    ))
+}
+
+pub fn lcm(args: Vec<Value>) -> Result<Value, ComputeError> {
+    let integers = non_negative_integers(&args, "lcm")?;
+    let mut result = 1.0;
+    for int in integers {
+        if int == 0 {
+            return Ok(Value::Number(0.0));
+        }
+        let divisor = greatest_common_divisor(result as u64, int);
+        result = result / divisor as f64 * int as f64;
+    }
+    Ok(Value::Number(result))
+}
+
+pub fn factorial(args: Vec<Value>) -> Result<Value, ComputeError> {
+    match number(&args[0]).trunc() {
+        num if num < 0.0 => Err(out_of_domain(
+            "factorial expects a number that is not negative",
+        )),
+        // Anything above 170! is too large for a number
+        num => Ok(Value::Number(
+            (1..=num.min(171.0) as u64).map(|i| i as f64).product(),
+        )),
+    }
+}
+
+pub fn pi(_args: Vec<Value>) -> Result<Value, ComputeError> {
+    Ok(Value::Number(std::f64::consts::PI))
+}
+
+pub fn sin(args: Vec<Value>) -> Result<Value, ComputeError> {
+    Ok(Value::Number(number(&args[0]).sin()))
+}
+
+pub fn cos(args: Vec<Value>) -> Result<Value, ComputeError> {
+    Ok(Value::Number(number(&args[0]).cos()))
+}
+
+pub fn tan(args: Vec<Value>) -> Result<Value, ComputeError> {
+    Ok(Value::Number(number(&args[0]).tan()))
+}
+
+pub fn asin(args: Vec<Value>) -> Result<Value, ComputeError> {
+    match number(&args[0]) {
+        num if num.abs() > 1.0 => Err(out_of_domain("asin expects a number between -1 and 1")),
+        num => Ok(Value::Number(num.asin())),
+    }
+}
+
+pub fn acos(args: Vec<Value>) -> Result<Value, ComputeError> {
+    match number(&args[0]) {
+        num if num.abs() > 1.0 => Err(out_of_domain("acos expects a number between -1 and 1")),
+        num => Ok(Value::Number(num.acos())),
+    }
+}
+
+pub fn atan(args: Vec<Value>) -> Result<Value, ComputeError> {
+    Ok(Value::Number(number(&args[0]).atan()))
+}
+
+pub fn atan2(args: Vec<Value>) -> Result<Value, ComputeError> {
+    match (number(&args[0]), number(&args[1])) {
+        (0.0, 0.0) => Err(ComputeError::DivisionByZero),
+        (x, y) => Ok(Value::Number(y.atan2(x))),
+    }
+}
+
+pub fn degrees(args: Vec<Value>) -> Result<Value, ComputeError> {
+    Ok(Value::Number(number(&args[0]).to_degrees()))
+}
+
+pub fn radians(args: Vec<Value>) -> Result<Value, ComputeError> {
+    Ok(Value::Number(number(&args[0]).to_radians()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_helpers::call;
+    use super::*;
+
+    #[test]
+    fn test_math_functions() {
+        let num = Value::Number;
+        let cases = [
+            ("round", vec![num(2.5)], 3.0),
+            ("round", vec![num(1.005), num(2.0)], 1.01),
+            ("round", vec![num(-1234.5), num(-2.0)], -1200.0),
+            ("roundup", vec![num(-3.21), num(1.0)], -3.3),
+            ("rounddown", vec![num(3.29), num(1.0)], 3.2),
+            ("trunc", vec![num(-8.9)], -8.0),
+            ("int", vec![num(-8.9)], -9.0),
+            ("floor", vec![num(2.5), num(2.0)], 2.0),
+            ("floor", vec![num(-2.5), num(2.0)], -4.0),
+            ("ceiling", vec![num(0.3), num(0.1)], 0.3),
+            ("ceiling", vec![num(-2.5), num(2.0)], -2.0),
+            ("abs", vec![num(-2.0)], 2.0),
+            ("sign", vec![num(-0.0)], 0.0),
+            ("mod", vec![num(-3.0), num(2.0)], 1.0),
+            ("sqrt", vec![num(16.0)], 4.0),
+            ("ln", vec![num(1.0)], 0.0),
+            ("log", vec![num(8.0), num(2.0)], 3.0),
+            ("log", vec![num(1000.0)], 3.0),
+            ("log10", vec![num(0.01)], -2.0),
+            ("gcd", vec![num(12.0), num(18.0), num(8.5)], 2.0),
+            ("lcm", vec![num(4.0), num(6.0), num(10.0)], 60.0),
+            ("factorial", vec![num(5.9)], 120.0),
+            ("factorial", vec![num(0.0)], 1.0),
+            ("degrees", vec![num(std::f64::consts::PI)], 180.0),
+            (
+                "atan2",
+                vec![num(1.0), num(1.0)],
+                std::f64::consts::FRAC_PI_4,
+            ),
+        ];
+        for (name, args, expected) in cases {
+            let Ok(Value::Number(result)) = call(name, &args) else {
+                panic!("{name}({args:?}) failed");
+            };
+            assert!(
+                (result - expected).abs() < 1e-12,
+                "{name}({args:?}) is {result}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_rounding_precision() {
+        let num = Value::Number;
+        let cases = [
+            ("round", vec![num(123.456), num(300.0)], 123.456),
+            ("round", vec![num(5.0), num(400.0)], 5.0),
+            ("round", vec![num(1e300), num(5.0)], 1e300),
+            ("round", vec![num(0.1 + 0.2), num(14.0)], 0.3),
+            ("round", vec![num(1e300), num(-299.0)], 1e300),
+            ("round", vec![num(4e300), num(-301.0)], 0.0),
+            ("roundup", vec![num(1.1), num(400.0)], 1.1),
+            ("trunc", vec![num(1e300), num(10.0)], 1e300),
+            ("trunc", vec![num(-1e-300), num(400.0)], -1e-300),
+        ];
+        for (name, args, expected) in cases {
+            assert_eq!(call(name, &args), Ok(num(expected)), "{name}({args:?})");
+        }
+    }
+
+    #[test]
+    fn test_math_domain_errors() {
+        let num = Value::Number;
+        let cases = [
+            ("sqrt", vec![num(-1.0)]),
+            ("ln", vec![num(0.0)]),
+            ("log", vec![num(10.0), num(-2.0)]),
+            ("asin", vec![num(1.5)]),
+            ("factorial", vec![num(-1.0)]),
+            ("factorial", vec![num(171.0)]),
+            ("exp", vec![num(1000.0)]),
+            ("gcd", vec![num(-4.0), num(2.0)]),
+            ("floor", vec![num(2.5), num(-2.0)]),
+            ("pow", vec![num(-8.0), num(0.5)]),
+        ];
+        for (name, args) in cases {
+            assert!(
+                matches!(call(name, &args), Err(ComputeError::NumberError(_))),
+                "{name}({args:?})"
+            );
+        }
+        assert_eq!(
+            call("mod", &[num(1.0), num(0.0)]),
+            Err(ComputeError::DivisionByZero)
+        );
+        assert_eq!(
+            call("log", &[num(1.0), num(1.0)]),
+            Err(ComputeError::DivisionByZero)
+        );
+        assert_eq!(
+            call("floor", &[num(1.0), num(0.0)]),
+            Err(ComputeError::DivisionByZero)
+        );
+    }
+}
@@ -0,0 +1,552 @@
+//! Statistics ignore text, booleans and empty cells in ranges, but result in the first error found
+//! in them
+
+use crate::common_types::{ComputeError, Value};
+
+use super::super::function_registry::{ArgumentType, Function, FunctionRegistry};
+use super::{array, number, numbers, out_of_domain, range_numbers};
+
+use ArgumentType::{Any, Number, Range};
+
+pub fn register(registry: &mut FunctionRegistry) {
+    registry.add_builtin(
+        Function::new(
+            "median",
+            "The middle value, or the mean of the two middle values.",
+            median,
+        )
+        .required(&[Number])
+        .repeated(Number),
+    );
+    registry.add_builtin(
+        Function::new(
+            "mode",
+            "The most frequent value, the first one if several are as frequent.",
+            mode,
+        )
+        .required(&[Number])
+        .repeated(Number),
+    );
+    registry.add_builtin(
+        Function::new("var", "Variance of a sample.", var)
+            .required(&[Number])
+            .repeated(Number),
+    );
+    registry.add_builtin(
+        Function::new("var.p", "Variance of a whole population.", var_p)
+            .required(&[Number])
+            .repeated(Number),
+    );
+    registry.add_builtin(
+        Function::new("stdev", "Standard deviation of a sample.", stdev)
+            .required(&[Number])
+            .repeated(Number),
+    );
+    registry.add_builtin(
+        Function::new(
+            "stdev.p",
+            "Standard deviation of a whole population.",
+            stdev_p,
+        )
+        .required(&[Number])
+        .repeated(Number),
+    );
+    registry.add_builtin(
+        Function::new(
+            "counta",
+            "Count non empty values, including text and errors.",
+            counta,
+        )
+        .repeated(Any)
+        .inspecting_errors(),
+    );
+    registry.add_builtin(
+        Function::new(
+            "countblank",
+            "Count empty cells and empty text in the range.",
+            countblank,
+        )
+        .required(&[Range]),
+    );
+    registry.add_builtin(
+        Function::new(
+            "percentile",
+            "The k-th percentile of the numbers in the range, with k between 0 and 1, \
+             interpolating between values.",
+            percentile,
+        )
+        .required(&[Range, Number]),
+    );
+    registry.add_builtin(
+        Function::new(
+            "quartile",
+            "The quartile of the numbers in the range, from 0 for the minimum to 4 for the \
+             maximum.",
+            quartile,
+        )
+        .required(&[Range, Number]),
+    );
+    registry.add_builtin(
+        Function::new(
+            "rank",
+            "Position of the number among the numbers in the range, largest first unless order is \
+             not 0.",
+            rank,
+        )
+        .required(&[Number, Range])
+        .optional(&[Number]),
+    );
+    registry.add_builtin(
+        Function::new("large", "The k-th largest number in the range.", large)
+            .required(&[Range, Number]),
+    );
+    registry.add_builtin(
+        Function::new("small", "The k-th smallest number in the range.", small)
+            .required(&[Range, Number]),
+    );
+    registry.add_builtin(
+        Function::new(
+            "correl",
+            "Correlation coefficient of two ranges of the same size.",
+            correl,
+        )
+        .required(&[Range, Range]),
+    );
+    registry.add_builtin(
+        Function::new(
+            "covariance",
+            "Covariance of two ranges of the same size, as samples.",
+            covariance,
+        )
+        .required(&[Range, Range]),
+    );
+    registry.add_builtin(
+        Function::new(
+            "covariance.p",
+            "Covariance of two ranges of the same size, as whole populations.",
+            covariance_p,
+        )
+        .required(&[Range, Range]),
+    );
+    registry.add_builtin(
+        Function::new(
+            "slope",
+            "Slope of the linear regression line through the known ys and xs.",
+            slope,
+        )
+        .required(&[Range, Range]),
+    );
+    registry.add_builtin(
+        Function::new(
+            "intercept",
+            "Value at x = 0 of the linear regression line through the known ys and xs.",
+            intercept,
+        )
+        .required(&[Range, Range]),
+    );
+    registry.add_builtin(
+        Function::new(
+            "forecast",
+            "Value at x of the linear regression line through the known ys and xs.",
+            forecast,
+        )
+        .required(&[Number, Range, Range]),
+    );
+}
+
+fn sorted(mut nums: Vec<f64>) -> Vec<f64> {
+    nums.sort_by(f64::total_cmp);
+    nums
+}
+
+pub fn median(args: Vec<Value>) -> Result<Value, ComputeError> {
+    let nums = sorted(numbers(&args).collect());
+    let middle = nums.len() / 2;
+    if nums.len().is_multiple_of(2) {
+        Ok(Value::Number(
+            nums[middle - 1] + (nums[middle] - nums[middle - 1]) / 2.0,
+        ))
+    } else {
+        Ok(Value::Number(nums[middle]))
+    }
+}
+
+pub fn mode(args: Vec<Value>) -> Result<Value, ComputeError> {
+    let nums: Vec<f64> = numbers(&args).collect();
+    let mut best = (1, None);
+    for (position, num) in nums.iter().enumerate() {
+        let frequency = nums[position..]
+            .iter()
+            .filter(|other| *other == num)
+            .count();
+        if frequency > best.0 && !nums[..position].contains(num) {
+            best = (frequency, Some(*num));
+        }
+    }
+    best.1.map(Value::Number).ok_or_else(|| {
+        ComputeError::NotAvailable("mode expects a value that appears more than once".to_string())
+    })
+}
+
+/// The count, means and sums of squared deviations of pairs of numbers. The deviations are taken
+/// from the means computed in a first pass, so that large values with small differences keep their
+/// precision.
+#[derive(Default)]
+struct Moments {
+    count: f64,
+    mean_x: f64,
+    mean_y: f64,
+    squares_x: f64,
+    squares_y: f64,
+    products: f64,
+}
+
+impl Moments {
+    fn new(pairs: Vec<(f64, f64)>) -> Self {
+        let count = pairs.len() as f64;
+        let mean_x = pairs.iter().map(|(x, _)| x).sum::<f64>() / count;
+        let mean_y = pairs.iter().map(|(_, y)| y).sum::<f64>() / count;
+        let mut moments = Moments {
+            count,
+            mean_x,
+            mean_y,
+            ..Moments::default()
+        };
+        for (x, y) in pairs {
+            let (delta_x, delta_y) = (x - mean_x, y - mean_y);
+            moments.squares_x += delta_x * delta_x;
+            moments.squares_y += delta_y * delta_y;
+            moments.products += delta_x * delta_y;
+        }
+        moments
+    }
+
+    fn of_numbers(args: &[Value]) -> Self {
+        Self::new(numbers(args).map(|num| (num, num)).collect())
+    }
+
+    // Sample statistics divide by one less than the count
+    fn divide(&self, sum: f64, sample: bool) -> Result<f64, ComputeError> {
+        let count = if sample { self.count - 1.0 } else { self.count };
+        if count <= 0.0 {
+            return Err(ComputeError::DivisionByZero);
+        }
+        Ok(sum / count)
+    }
+}
+
+pub fn var(args: Vec<Value>) -> Result<Value, ComputeError> {
+    let moments = Moments::of_numbers(&args);
+    moments.divide(moments.squares_x, true).map(Value::Number)
+}
+
+pub fn var_p(args: Vec<Value>) -> Result<Value, ComputeError> {
+    let moments = Moments::of_numbers(&args);
+    moments.divide(moments.squares_x, false).map(Value::Number)
+}
+
+pub fn stdev(args: Vec<Value>) -> Result<Value, ComputeError> {
+    var(args).map(|variance| Value::Number(number(&variance).sqrt()))
+}
+
+pub fn stdev_p(args: Vec<Value>) -> Result<Value, ComputeError> {
+    var_p(args).map(|variance| Value::Number(number(&variance).sqrt()))
+}
+
+pub fn counta(args: Vec<Value>) -> Result<Value, ComputeError> {
+    Ok(Value::Number(args.len() as f64))
+}
+
+pub fn countblank(args: Vec<Value>) -> Result<Value, ComputeError> {
+    let blank = |cell: &Option<&Value>| cell.is_none_or(|value| value.as_text() == Some(""));
+    Ok(Value::Number(
+        array(&args[0]).cells().filter(blank).count() as f64
+    ))
+}
+
+// Interpolates between the closest ranks, where 0 is the minimum and 1 the maximum
+fn interpolate(args: &[Value], fraction: f64, name: &str) -> Result<Value, ComputeError> {
+    let nums = sorted(range_numbers(&args[0])?);
+    if nums.is_empty() || !(0.0..=1.0).contains(&fraction) {
+        return Err(ComputeError::NumberError(format!(
+            "{name} expects a range with numbers and a k between 0 and 1"
+        )));
+    }
+    let position = fraction * (nums.len() - 1) as f64;
+    let (lower, upper) = (
+        nums[position.floor() as usize],
+        nums[position.ceil() as usize],
+    );
+    Ok(Value::Number(lower + (upper - lower) * position.fract()))
+}
+
+pub fn percentile(args: Vec<Value>) -> Result<Value, ComputeError> {
+    interpolate(&args, number(&args[1]), "percentile")
+}
+
+pub fn quartile(args: Vec<Value>) -> Result<Value, ComputeError> {
+    match number(&args[1]).trunc() {
+        quart if (0.0..=4.0).contains(&quart) => interpolate(&args, quart / 4.0, "quartile"),
+        _ => Err(out_of_domain("quartile expects a quartile between 0 and 4")),
+    }
+}
+
+pub fn rank(args: Vec<Value>) -> Result<Value, ComputeError> {
+    let num = number(&args[0]);
+    let nums = range_numbers(&args[1])?;
+    if !nums.contains(&num) {
+        return Err(ComputeError::NotAvailable(format!(
+            "rank can not find {num} in the range"
+        )));
+    }
+    let descending = args.get(2).map_or(0.0, number) == 0.0;
+    let before = nums.iter().filter(|other| {
+        if descending {
+            **other > num
+        } else {
+            **other < num
+        }
+    });
+    Ok(Value::Number(before.count() as f64 + 1.0))
+}
+
+// The k-th number, counted from 1, of the numbers in the range sorted ascending or descending
+fn kth(args: &[Value], name: &str, descending: bool) -> Result<Value, ComputeError> {
+    let mut nums = sorted(range_numbers(&args[0])?);
+    if descending {
+        nums.reverse();
+    }
+    let k = number(&args[1]).ceil();
+    if k < 1.0 || k > nums.len() as f64 {
+        return Err(ComputeError::NumberError(format!(
+            "{name} expects a k between 1 and the amount of numbers in the range"
+        )));
+    }
+    Ok(Value::Number(nums[k as usize - 1]))
+}
+
+pub fn large(args: Vec<Value>) -> Result<Value, ComputeError> {
+    kth(&args, "large", true)
+}
+
+pub fn small(args: Vec<Value>) -> Result<Value, ComputeError> {
+    kth(&args, "small", false)
+}
+
+// Pairs the cells of two ranges of the same size, leaving out pairs where either cell is not a
+// number
+fn paired_moments(xs: &Value, ys: &Value, name: &str) -> Result<Moments, ComputeError> {
+    let (xs, ys) = (array(xs), array(ys));
+    if xs.rows() * xs.columns() != ys.rows() * ys.columns() {
+        return Err(ComputeError::NotAvailable(format!(
+            "{name} expects ranges of the same size"
+        )));
+    }
+    let mut pairs = Vec::new();
+    for (x, y) in xs.cells().zip(ys.cells()) {
+        match (x, y) {
+            (Some(Value::Error(error)), _) | (_, Some(Value::Error(error))) => {
+                return Err(error.clone())
+            }
+            (
+                Some(Value::Number(x) | Value::DateTime(x)),
+                Some(Value::Number(y) | Value::DateTime(y)),
+            ) => pairs.push((*x, *y)),
+            _ => {}
+        }
+    }
+    Ok(Moments::new(pairs))
+}
+
+pub fn correl(args: Vec<Value>) -> Result<Value, ComputeError> {
+    let moments = paired_moments(&args[0], &args[1], "correl")?;
+    let spread = (moments.squares_x * moments.squares_y).sqrt();
+    if spread == 0.0 {
+        return Err(ComputeError::DivisionByZero);
+    }
+    Ok(Value::Number(moments.products / spread))
+}
+
+pub fn covariance(args: Vec<Value>) -> Result<Value, ComputeError> {
+    let moments = paired_moments(&args[0], &args[1], "covariance")?;
+    moments.divide(moments.products, true).map(Value::Number)
+}
+
+pub fn covariance_p(args: Vec<Value>) -> Result<Value, ComputeError> {
+    let moments = paired_moments(&args[0], &args[1], "covariance.p")?;
+    moments.divide(moments.products, false).map(Value::Number)
+}
+
+// The slope and intercept of the least squares line through the known ys and xs
+fn regression(ys: &Value, xs: &Value, name: &str) -> Result<(f64, f64), ComputeError> {
+    let moments = paired_moments(xs, ys, name)?;
+    if moments.squares_x == 0.0 {
+        return Err(ComputeError::DivisionByZero);
+    }
+    let slope = moments.products / moments.squares_x;
+    Ok((slope, moments.mean_y - slope * moments.mean_x))
+}
+
+pub fn slope(args: Vec<Value>) -> Result<Value, ComputeError> {
+    regression(&args[0], &args[1], "slope").map(|(slope, _)| Value::Number(slope))
+}
+
+pub fn intercept(args: Vec<Value>) -> Result<Value, ComputeError> {
+    regression(&args[0], &args[1], "intercept").map(|(_, intercept)| Value::Number(intercept))
+}
+
+pub fn forecast(args: Vec<Value>) -> Result<Value, ComputeError> {
+    let (slope, intercept) = regression(&args[1], &args[2], "forecast")?;
+    Ok(Value::Number(intercept + slope * number(&args[0])))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_helpers::{assert_close, call, column, numbers, string};
+    use super::*;
+    use crate::common_types::Array;
+
+    #[test]
+    fn test_descriptive_statistics() {
+        let data = numbers(&[2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0]);
+        assert_close(call("median", &data), 4.5);
+        assert_close(call("median", &data[1..]), 5.0);
+        assert_close(call("mode", &data), 4.0);
+        assert_close(call("var.p", &data), 4.0);
+        assert_close(call("stdev.p", &data), 2.0);
+        assert_close(call("var", &data), 32.0 / 7.0);
+        assert_close(call("stdev", &data), (32.0f64 / 7.0).sqrt());
+
+        // Shifting the values by a large offset keeps the variance exact
+        let shifted: Vec<Value> = data
+            .iter()
+            .map(|value| Value::Number(number(value) + 1e9))
+            .collect();
+        assert_close(call("var.p", &shifted), 4.0);
+
+        assert!(matches!(
+            call("mode", &numbers(&[1.0, 2.0])),
+            Err(ComputeError::NotAvailable(_))
+        ));
+        assert_eq!(
+            call("var", &numbers(&[1.0])),
+            Err(ComputeError::DivisionByZero)
+        );
+        assert_eq!(
+            call(
+                "counta",
+                &[string("a"), Value::Error(ComputeError::DivisionByZero)]
+            ),
+            Ok(Value::Number(2.0))
+        );
+    }
+
+    #[test]
+    fn test_range_statistics() {
+        assert_close(
+            call(
+                "percentile",
+                &[column(&[1.0, 2.0, 3.0, 4.0]), Value::Number(0.3)],
+            ),
+            1.9,
+        );
+        let quartiles = column(&[1.0, 2.0, 4.0, 7.0, 8.0, 9.0, 10.0, 12.0]);
+        assert_close(
+            call("quartile", &[quartiles.clone(), Value::Number(1.0)]),
+            3.5,
+        );
+        assert_close(call("quartile", &[quartiles, Value::Number(4.0)]), 12.0);
+
+        let ranks = column(&[7.0, 3.5, 3.5, 1.0, 2.0]);
+        assert_close(
+            call(
+                "rank",
+                &[Value::Number(3.5), ranks.clone(), Value::Number(1.0)],
+            ),
+            3.0,
+        );
+        assert_close(call("rank", &[Value::Number(7.0), ranks.clone()]), 1.0);
+        assert!(matches!(
+            call("rank", &[Value::Number(5.0), ranks]),
+            Err(ComputeError::NotAvailable(_))
+        ));
+
+        let values = column(&[3.0, 5.0, 3.0, 5.0, 4.0, 4.0, 2.0, 4.0, 6.0, 7.0]);
+        assert_close(call("large", &[values.clone(), Value::Number(3.0)]), 5.0);
+        assert_close(call("large", &[values.clone(), Value::Number(7.0)]), 4.0);
+        assert_close(call("small", &[values.clone(), Value::Number(2.0)]), 3.0);
+        assert!(matches!(
+            call("small", &[values, Value::Number(11.0)]),
+            Err(ComputeError::NumberError(_))
+        ));
+
+        let with_blanks = Array::new(
+            2,
+            2,
+            vec![
+                Some(Value::Number(1.0)),
+                None,
+                Some(string("")),
+                Some(string("a")),
+            ],
+        );
+        assert_close(call("countblank", &[Value::Array(with_blanks)]), 2.0);
+    }
+
+    #[test]
+    fn test_regression() {
+        let (xs, ys) = (
+            column(&[3.0, 2.0, 4.0, 5.0, 6.0]),
+            column(&[9.0, 7.0, 12.0, 15.0, 17.0]),
+        );
+        assert_close(call("correl", &[xs.clone(), ys.clone()]), 0.997054485501581);
+        assert_close(call("covariance.p", &[xs.clone(), ys.clone()]), 5.2);
+        assert_close(
+            call(
+                "covariance",
+                &[column(&[2.0, 4.0, 8.0]), column(&[5.0, 11.0, 12.0])],
+            ),
+            29.0 / 3.0,
+        );
+
+        let (ys, xs) = (
+            column(&[2.0, 3.0, 9.0, 1.0, 8.0, 7.0, 5.0]),
+            column(&[6.0, 5.0, 11.0, 7.0, 5.0, 4.0, 4.0]),
+        );
+        assert_close(call("slope", &[ys, xs]), 11.0 / 36.0);
+        let (ys, xs) = (
+            column(&[2.0, 3.0, 9.0, 1.0, 8.0]),
+            column(&[6.0, 5.0, 11.0, 7.0, 5.0]),
+        );
+        assert_close(call("intercept", &[ys, xs]), 3.0 / 62.0);
+        let (ys, xs) = (
+            column(&[6.0, 7.0, 9.0, 15.0, 21.0]),
+            column(&[20.0, 28.0, 31.0, 38.0, 40.0]),
+        );
+        assert_close(
+            call("forecast", &[Value::Number(30.0), ys, xs]),
+            10.607253086419755,
+        );
+
+        assert!(matches!(
+            call("correl", &[column(&[1.0, 2.0]), column(&[1.0])]),
+            Err(ComputeError::NotAvailable(_))
+        ));
+        assert_eq!(
+            call("slope", &[column(&[1.0, 2.0]), column(&[3.0, 3.0])]),
+            Err(ComputeError::DivisionByZero)
+        );
+        let with_error = Value::Array(Array::new(
+            2,
+            1,
+            vec![
+                Some(Value::Number(1.0)),
+                Some(Value::Error(ComputeError::NotAvailable(String::new()))),
+            ],
+        ));
+        assert!(matches!(
+            call("correl", &[with_error, column(&[1.0, 2.0])]),
+            Err(ComputeError::NotAvailable(_))
+        ));
+    }
+}
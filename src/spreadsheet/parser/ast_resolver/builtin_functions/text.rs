@@ -0,0 +1,502 @@
+//! Text functions also accept numbers and booleans, using the text they are shown as.
+//! Positions and counts are in characters rather than bytes.
+
+use crate::common_types::{ComputeError, Value};
+
+use super::super::function_registry::{ArgumentType, Function, FunctionRegistry};
+use super::text;
+
+use ArgumentType::{Any, Bool, Number};
+
+pub fn register(registry: &mut FunctionRegistry) {
+    registry.add_builtin(
+        Function::new("length", "Number of characters in the text.", length).required(&[Any]),
+    );
+    registry
+        .add_builtin(Function::new("concat", "Joins the values as text.", concat).repeated(Any));
+    registry.add_builtin(
+        Function::new(
+            "textjoin",
+            "Joins the values as text with the delimiter between them, leaving out empty text if \
+             ignore_empty is TRUE.",
+            textjoin,
+        )
+        .required(&[Any, Bool])
+        .repeated(Any),
+    );
+    registry.add_builtin(Function::new("upper", "The text in uppercase.", upper).required(&[Any]));
+    registry.add_builtin(Function::new("lower", "The text in lowercase.", lower).required(&[Any]));
+    registry.add_builtin(
+        Function::new(
+            "proper",
+            "The text with the first letter of every word in uppercase and the others in \
+             lowercase.",
+            proper,
+        )
+        .required(&[Any]),
+    );
+    registry.add_builtin(
+        Function::new(
+            "left",
+            "The first count characters of the text, 1 by default.",
+            left,
+        )
+        .required(&[Any])
+        .optional(&[Number]),
+    );
+    registry.add_builtin(
+        Function::new(
+            "right",
+            "The last count characters of the text, 1 by default.",
+            right,
+        )
+        .required(&[Any])
+        .optional(&[Number]),
+    );
+    registry.add_builtin(
+        Function::new(
+            "mid",
+            "Count characters of the text starting at the given position, counted from 1.",
+            mid,
+        )
+        .required(&[Any, Number, Number]),
+    );
+    registry.add_builtin(
+        Function::new(
+            "trim",
+            "The text without leading and trailing whitespace and with single spaces between \
+             words.",
+            trim,
+        )
+        .required(&[Any]),
+    );
+    registry.add_builtin(
+        Function::new(
+            "substitute",
+            "Replaces every occurrence of old in the text with new, or only the given occurrence.",
+            substitute,
+        )
+        .required(&[Any, Any, Any])
+        .optional(&[Number]),
+    );
+    registry.add_builtin(
+        Function::new(
+            "replace",
+            "Replaces count characters of the text starting at the given position with new.",
+            replace,
+        )
+        .required(&[Any, Number, Number, Any]),
+    );
+    registry.add_builtin(
+        Function::new(
+            "find",
+            "Position of the first occurrence of needle in the text from the start position on, \
+             case sensitive.",
+            find,
+        )
+        .required(&[Any, Any])
+        .optional(&[Number]),
+    );
+    registry.add_builtin(
+        Function::new(
+            "search",
+            "Position of the first occurrence of needle in the text from the start position on, \
+             ignoring case.",
+            search,
+        )
+        .required(&[Any, Any])
+        .optional(&[Number]),
+    );
+    registry.add_builtin(
+        Function::new("rept", "The text repeated count times.", rept).required(&[Any, Number]),
+    );
+    registry.add_builtin(
+        Function::new(
+            "split",
+            "The part of the text at the given position, counted from 1, after splitting it at \
+             every delimiter.",
+            split,
+        )
+        .required(&[Any, Any, Number]),
+    );
+    registry.add_builtin(
+        Function::new(
+            "exact",
+            "TRUE if both texts are the same, case sensitive.",
+            exact,
+        )
+        .required(&[Any, Any]),
+    );
+    registry.add_builtin(
+        Function::new(
+            "char",
+            "The character with the given Unicode code point.",
+            char,
+        )
+        .required(&[Number]),
+    );
+    registry.add_builtin(
+        Function::new(
+            "code",
+            "The Unicode code point of the first character of the text.",
+            code,
+        )
+        .required(&[Any]),
+    );
+}
+
+pub fn length(args: Vec<Value>) -> Result<Value, ComputeError> {
+    Ok(Value::Number(text(&args[0]).chars().count() as f64))
+}
+
+/// The longest text a function builds, as in other spreadsheets.
+const MAX_TEXT_LENGTH: usize = 32767;
+
+// Counts and positions past `usize::MAX` saturate, which only ever selects characters up to the end
+// of the text
+fn count_argument(value: &Value, name: &str, what: &str) -> Result<usize, ComputeError> {
+    match value.as_number() {
+        Some(num) if num >= 0.0 => Ok(num as usize),
+        _ => Err(ComputeError::InvalidArgument(format!(
+            "{name} expects {what} to be zero or more"
+        ))),
+    }
+}
+
+fn position_argument(value: &Value, name: &str) -> Result<usize, ComputeError> {
+    match value.as_number() {
+        Some(num) if num >= 1.0 => Ok(num as usize),
+        _ => Err(ComputeError::InvalidArgument(format!(
+            "{name} expects positions counted from 1"
+        ))),
+    }
+}
+
+pub fn concat(args: Vec<Value>) -> Result<Value, ComputeError> {
+    Ok(Value::Text(args.iter().map(text).collect()))
+}
+
+pub fn textjoin(args: Vec<Value>) -> Result<Value, ComputeError> {
+    let delimiter = text(&args[0]);
+    let ignore_empty = args[1] == Value::Bool(true);
+    let parts: Vec<String> = args[2..]
+        .iter()
+        .map(text)
+        .filter(|part| !(ignore_empty && part.is_empty()))
+        .collect();
+    Ok(Value::Text(parts.join(&delimiter)))
+}
+
+pub fn upper(args: Vec<Value>) -> Result<Value, ComputeError> {
+    Ok(Value::Text(text(&args[0]).to_uppercase()))
+}
+
+pub fn lower(args: Vec<Value>) -> Result<Value, ComputeError> {
+    Ok(Value::Text(text(&args[0]).to_lowercase()))
+}
+
+pub fn proper(args: Vec<Value>) -> Result<Value, ComputeError> {
+    let mut result = String::new();
+    let mut after_letter = false;
+    for ch in text(&args[0]).chars() {
+        if after_letter {
+            result.extend(ch.to_lowercase());
+        } else {
+            result.extend(ch.to_uppercase());
+        }
+        after_letter = ch.is_alphabetic();
+    }
+    Ok(Value::Text(result))
+}
+
+pub fn left(args: Vec<Value>) -> Result<Value, ComputeError> {
+    let count = args
+        .get(1)
+        .map_or(Ok(1), |count| count_argument(count, "left", "the count"))?;
+    Ok(Value::Text(text(&args[0]).chars().take(count).collect()))
+}
+
+pub fn right(args: Vec<Value>) -> Result<Value, ComputeError> {
+    let count = args
+        .get(1)
+        .map_or(Ok(1), |count| count_argument(count, "right", "the count"))?;
+    let chars: Vec<char> = text(&args[0]).chars().collect();
+    Ok(Value::Text(
+        chars[chars.len().saturating_sub(count)..].iter().collect(),
+    ))
+}
+
+pub fn mid(args: Vec<Value>) -> Result<Value, ComputeError> {
+    let start = position_argument(&args[1], "mid")?;
+    let count = count_argument(&args[2], "mid", "the count")?;
+    Ok(Value::Text(
+        text(&args[0]).chars().skip(start - 1).take(count).collect(),
+    ))
+}
+
+pub fn trim(args: Vec<Value>) -> Result<Value, ComputeError> {
+    Ok(Value::Text(
+        text(&args[0])
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" "),
+    ))
+}
+
+pub fn substitute(args: Vec<Value>) -> Result<Value, ComputeError> {
+    let (source, old, new) = (text(&args[0]), text(&args[1]), text(&args[2]));
+    if old.is_empty() {
+        return Ok(Value::Text(source));
+    }
+    let Some(instance) = args.get(3) else {
+        return Ok(Value::Text(source.replace(&old, &new)));
+    };
+
+    let instance = position_argument(instance, "substitute")?;
+    match source.match_indices(&old).nth(instance - 1) {
+        Some((at, _)) => Ok(Value::Text(format!(
+            "{}{new}{}",
+            &source[..at],
+            &source[at + old.len()..]
+        ))),
+        None => Ok(Value::Text(source)),
+    }
+}
+
+pub fn replace(args: Vec<Value>) -> Result<Value, ComputeError> {
+    let chars: Vec<char> = text(&args[0]).chars().collect();
+    let start = (position_argument(&args[1], "replace")? - 1).min(chars.len());
+    let end = start
+        .saturating_add(count_argument(&args[2], "replace", "the count")?)
+        .min(chars.len());
+    let result = chars[..start].iter().collect::<String>()
+        + &text(&args[3])
+        + &chars[end..].iter().collect::<String>();
+    Ok(Value::Text(result))
+}
+
+// Position counted from 1 of the needle in the haystack, searching from the character at `start` on
+fn find_position(
+    name: &str,
+    needle: &[char],
+    haystack: &[char],
+    start: &Option<&Value>,
+) -> Result<Value, ComputeError> {
+    let start = start.map_or(Ok(1), |start| position_argument(start, name))? - 1;
+    let found = (start..=haystack.len().saturating_sub(needle.len()))
+        .find(|&at| haystack[at..].starts_with(needle));
+    match found {
+        Some(at) => Ok(Value::Number((at + 1) as f64)),
+        None => Err(ComputeError::InvalidArgument(format!(
+            "{name} did not find '{}'",
+            needle.iter().collect::<String>()
+        ))),
+    }
+}
+
+pub fn find(args: Vec<Value>) -> Result<Value, ComputeError> {
+    let needle: Vec<char> = text(&args[0]).chars().collect();
+    let haystack: Vec<char> = text(&args[1]).chars().collect();
+    find_position("find", &needle, &haystack, &args.get(2))
+}
+
+pub fn search(args: Vec<Value>) -> Result<Value, ComputeError> {
+    // Characters are compared one by one, as lowercasing the whole text could change its length
+    let lowercase = |value: &Value| {
+        text(value)
+            .chars()
+            .map(|ch| ch.to_lowercase().next().unwrap_or(ch))
+            .collect::<Vec<char>>()
+    };
+    find_position(
+        "search",
+        &lowercase(&args[0]),
+        &lowercase(&args[1]),
+        &args.get(2),
+    )
+}
+
+pub fn rept(args: Vec<Value>) -> Result<Value, ComputeError> {
+    let (source, times) = (
+        text(&args[0]),
+        count_argument(&args[1], "rept", "the count")?,
+    );
+    if source.chars().count().saturating_mul(times) > MAX_TEXT_LENGTH {
+        return Err(ComputeError::InvalidArgument(format!(
+            "rept can not build text longer than {MAX_TEXT_LENGTH} characters"
+        )));
+    }
+    Ok(Value::Text(source.repeat(times)))
+}
+
+// Cells hold a single value, so formulas take one part per cell. `SpreadSheet::split_to_columns`
+// writes every part into the cells next to the text instead
+pub fn split(args: Vec<Value>) -> Result<Value, ComputeError> {
+    let (source, delimiter) = (text(&args[0]), text(&args[1]));
+    let position = position_argument(&args[2], "split")?;
+    if delimiter.is_empty() {
+        return Err(ComputeError::InvalidArgument(
+            "split expects a delimiter that is not empty".to_string(),
+        ));
+    }
+    match source.split(&delimiter).nth(position - 1) {
+        Some(part) => Ok(Value::Text(part.to_string())),
+        None => Err(ComputeError::NotAvailable(format!(
+            "split found less than {position} parts"
+        ))),
+    }
+}
+
+pub fn exact(args: Vec<Value>) -> Result<Value, ComputeError> {
+    Ok(Value::Bool(text(&args[0]) == text(&args[1])))
+}
+
+pub fn char(args: Vec<Value>) -> Result<Value, ComputeError> {
+    let code = args[0].as_number().unwrap_or_default();
+    match char::from_u32(code as u32).filter(|_| code >= 1.0 && code.fract() == 0.0) {
+        Some(ch) => Ok(Value::Text(ch.to_string())),
+        None => Err(ComputeError::InvalidArgument(format!(
+            "{code} is not a Unicode code point"
+        ))),
+    }
+}
+
+pub fn code(args: Vec<Value>) -> Result<Value, ComputeError> {
+    match text(&args[0]).chars().next() {
+        Some(ch) => Ok(Value::Number(ch as u32 as f64)),
+        None => Err(ComputeError::InvalidArgument(
+            "code expects text that is not empty".to_string(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_helpers::{call, string};
+    use super::*;
+
+    #[test]
+    fn test_text_functions() {
+        let num = Value::Number;
+        let cases = [
+            (
+                "concat",
+                vec![string("a"), num(1.0), Value::Bool(true)],
+                string("a1TRUE"),
+            ),
+            (
+                "textjoin",
+                vec![
+                    string(", "),
+                    Value::Bool(true),
+                    string("a"),
+                    string(""),
+                    string("b"),
+                ],
+                string("a, b"),
+            ),
+            (
+                "textjoin",
+                vec![
+                    string("-"),
+                    Value::Bool(false),
+                    string("a"),
+                    string(""),
+                    num(2.5),
+                ],
+                string("a--2.5"),
+            ),
+            ("upper", vec![string("straße")], string("STRASSE")),
+            ("lower", vec![string("ÀB")], string("àb")),
+            (
+                "proper",
+                vec![string("hello wORLD-wide 2nd")],
+                string("Hello World-Wide 2Nd"),
+            ),
+            ("left", vec![string("héllo"), num(2.0)], string("hé")),
+            ("left", vec![string("héllo")], string("h")),
+            ("right", vec![string("héllo"), num(10.0)], string("héllo")),
+            (
+                "mid",
+                vec![string("日本語テキスト"), num(2.0), num(3.0)],
+                string("本語テ"),
+            ),
+            ("trim", vec![string("  a \t  b  ")], string("a b")),
+            (
+                "substitute",
+                vec![string("a-b-c"), string("-"), string("+")],
+                string("a+b+c"),
+            ),
+            (
+                "substitute",
+                vec![string("a-b-c"), string("-"), string("+"), num(2.0)],
+                string("a-b+c"),
+            ),
+            (
+                "replace",
+                vec![string("abcdef"), num(2.0), num(3.0), string("Z")],
+                string("aZef"),
+            ),
+            (
+                "find",
+                vec![string("b"), string("abcb"), num(3.0)],
+                num(4.0),
+            ),
+            ("search", vec![string("É"), string("caféé")], num(4.0)),
+            ("rept", vec![string("ab"), num(3.0)], string("ababab")),
+            (
+                "split",
+                vec![string("a,b,,c"), string(","), num(4.0)],
+                string("c"),
+            ),
+            ("exact", vec![string("a"), string("A")], Value::Bool(false)),
+            ("char", vec![num(233.0)], string("é")),
+            ("code", vec![string("€")], num(8364.0)),
+            ("length", vec![string("naïve")], num(5.0)),
+        ];
+        for (name, args, expected) in cases {
+            assert_eq!(call(name, &args), Ok(expected), "{name}({args:?})");
+        }
+    }
+
+    #[test]
+    fn test_text_function_errors() {
+        let num = Value::Number;
+        let cases = [
+            ("find", vec![string("B"), string("abc")]),
+            ("mid", vec![string("abc"), num(0.0), num(1.0)]),
+            ("left", vec![string("abc"), num(-1.0)]),
+            ("char", vec![num(0.0)]),
+            ("code", vec![string("")]),
+            ("split", vec![string("abc"), string(""), num(1.0)]),
+            ("textjoin", vec![string(",")]),
+            ("rept", vec![string("ab"), num(1e19)]),
+            ("rept", vec![string("ab"), num(1e10)]),
+        ];
+        for (name, args) in cases {
+            assert!(
+                matches!(call(name, &args), Err(ComputeError::InvalidArgument(_))),
+                "{name}({args:?})"
+            );
+        }
+        assert!(matches!(
+            call("split", &[string("a,b"), string(","), num(3.0)]),
+            Err(ComputeError::NotAvailable(_))
+        ));
+
+        // Huge counts select up to the end of the text
+        assert_eq!(call("left", &[string("abc"), num(1e30)]), Ok(string("abc")));
+        assert_eq!(
+            call("mid", &[string("abc"), num(1e30), num(1e30)]),
+            Ok(string(""))
+        );
+        assert_eq!(
+            call(
+                "replace",
+                &[string("abc"), num(2.0), num(1e30), string("x")]
+            ),
+            Ok(string("ax"))
+        );
+        assert_eq!(call("rept", &[string(""), num(1e19)]), Ok(string("")));
+    }
+}
@@ -0,0 +1,230 @@
+use std::cmp::Ordering;
+
+use crate::{
+    common_functions::{parse_date_time, parse_number},
+    common_types::{NumberLocale, Value},
+};
+
+/// A condition on the cells of a range, as given to `sumif` and the other conditional functions.
+///
+/// Text criteria may start with a comparison such as `">10"` or `"<>done"`, without one they compare for
+/// equality. Text compared for equality ignores case and may hold wildcards, see [`wildcard_match`].
+/// Numbers in text criteria are written as in the cells of the sheet, following its [`NumberLocale`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Criterion {
+    comparison: Comparison,
+    value: Value,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+}
+
+impl Comparison {
+    fn holds(self, ordering: Ordering) -> bool {
+        match self {
+            Comparison::Equal => ordering.is_eq(),
+            Comparison::NotEqual => ordering.is_ne(),
+            Comparison::Less => ordering.is_lt(),
+            Comparison::LessEqual => ordering.is_le(),
+            Comparison::Greater => ordering.is_gt(),
+            Comparison::GreaterEqual => ordering.is_ge(),
+        }
+    }
+}
+
+impl Criterion {
+    pub fn new(criteria: &Value, locale: NumberLocale) -> Self {
+        let Value::Text(text) = criteria else {
+            return Self {
+                comparison: Comparison::Equal,
+                value: criteria.clone(),
+            };
+        };
+
+        // Longer prefixes first, so that `<=` is not read as `<`
+        let prefixes = [
+            ("<=", Comparison::LessEqual),
+            (">=", Comparison::GreaterEqual),
+            ("<>", Comparison::NotEqual),
+            ("<", Comparison::Less),
+            (">", Comparison::Greater),
+            ("=", Comparison::Equal),
+        ];
+        let (comparison, operand) = prefixes
+            .iter()
+            .find_map(|(prefix, comparison)| Some((*comparison, text.strip_prefix(prefix)?)))
+            .unwrap_or((Comparison::Equal, text));
+
+        let value = if let Some(num) = parse_number(operand.trim(), locale) {
+            Value::Number(num)
+        } else if let Some(serial) = parse_date_time(operand.trim()) {
+            Value::DateTime(serial)
+        } else if operand.eq_ignore_ascii_case("true") || operand.eq_ignore_ascii_case("false") {
+            Value::Bool(operand.eq_ignore_ascii_case("true"))
+        } else {
+            Value::Text(operand.to_string())
+        };
+        Self { comparison, value }
+    }
+
+    /// Whether the cell meets the criterion, `None` standing for an empty cell.
+    pub fn matches(&self, cell: Option<&Value>) -> bool {
+        match self.comparison {
+            Comparison::Equal => self.equals(cell),
+            Comparison::NotEqual => !self.equals(cell),
+            comparison => {
+                let ordering = match (cell, &self.value) {
+                    (
+                        Some(Value::Number(a) | Value::DateTime(a)),
+                        Value::Number(b) | Value::DateTime(b),
+                    ) => a.partial_cmp(b),
+                    (Some(Value::Text(a)), Value::Text(b)) => {
+                        Some(a.to_lowercase().cmp(&b.to_lowercase()))
+                    }
+                    _ => None,
+                };
+                ordering.is_some_and(|ordering| comparison.holds(ordering))
+            }
+        }
+    }
+
+    fn equals(&self, cell: Option<&Value>) -> bool {
+        match (cell, &self.value) {
+            // `""` and `"="` look for empty cells
+            (None, Value::Text(text)) => text.is_empty(),
+            (None, _) => false,
+            (Some(Value::Text(text)), Value::Text(pattern)) => wildcard_match(pattern, text),
+            (Some(cell), value) => cell.equals(value),
+        }
+    }
+}
+
+enum PatternToken {
+    AnyCharacters,
+    AnyCharacter,
+    Character(char),
+}
+
+/// Whether the text matches the pattern ignoring case, where `*` stands for any characters, `?` for a
+/// single one and `~` makes the character after it literal, as in `"what~?"`.
+pub fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let mut tokens = Vec::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        tokens.push(match c {
+            '*' => PatternToken::AnyCharacters,
+            '?' => PatternToken::AnyCharacter,
+            '~' => PatternToken::Character(chars.next().unwrap_or('~')),
+            c => PatternToken::Character(c),
+        });
+    }
+    let text: Vec<char> = text.chars().collect();
+
+    // Goes back to the last `*` when a character does not match, letting it stand for one more character
+    let (mut token, mut position) = (0, 0);
+    let mut last_star: Option<(usize, usize)> = None;
+    while position < text.len() {
+        match tokens.get(token) {
+            Some(PatternToken::AnyCharacters) => {
+                last_star = Some((token, position));
+                token += 1;
+            }
+            Some(PatternToken::AnyCharacter) => (token, position) = (token + 1, position + 1),
+            Some(PatternToken::Character(c))
+                if c.to_lowercase().eq(text[position].to_lowercase()) =>
+            {
+                (token, position) = (token + 1, position + 1)
+            }
+            _ => match last_star {
+                Some((star, matched)) => {
+                    last_star = Some((star, matched + 1));
+                    (token, position) = (star + 1, matched + 1);
+                }
+                None => return false,
+            },
+        }
+    }
+    tokens[token..]
+        .iter()
+        .all(|token| matches!(token, PatternToken::AnyCharacters))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wildcard_match() {
+        assert!(wildcard_match("a*", "Apple"));
+        assert!(wildcard_match("*an*", "banana"));
+        assert!(wildcard_match("b?n*a", "banana"));
+        assert!(wildcard_match("*", ""));
+        assert!(wildcard_match("日?", "日本"));
+        assert!(wildcard_match("what~?", "What?"));
+        assert!(wildcard_match("5~*", "5*"));
+        assert!(!wildcard_match("what~?", "whats"));
+        assert!(!wildcard_match("a?", "a"));
+        assert!(!wildcard_match("*x", "banana"));
+        assert!(!wildcard_match("apple", "apples"));
+    }
+
+    #[test]
+    fn test_criteria() {
+        let text = |s: &str| Criterion::new(&Value::Text(s.to_string()), NumberLocale::default());
+        let num = |n: f64| Some(Value::Number(n));
+        let cell = |s: &str| Some(Value::Text(s.to_string()));
+
+        assert!(text(">10").matches(num(11.0).as_ref()));
+        assert!(!text(">10").matches(num(10.0).as_ref()));
+        assert!(text(">=10").matches(num(10.0).as_ref()));
+        assert!(text("<=1e1").matches(num(10.0).as_ref()));
+        assert!(!text(">10").matches(cell("20").as_ref()));
+        assert!(text("10").matches(num(10.0).as_ref()));
+        assert!(
+            Criterion::new(&Value::Number(10.0), NumberLocale::default())
+                .matches(num(10.0).as_ref())
+        );
+
+        assert!(text("<>done").matches(cell("todo").as_ref()));
+        assert!(text("<>done").matches(None));
+        assert!(!text("<>DONE").matches(cell("done").as_ref()));
+        assert!(text("d*").matches(cell("Done").as_ref()));
+        assert!(text("<b").matches(cell("apple").as_ref()));
+        assert!(text("true").matches(Some(&Value::Bool(true))));
+        assert!(text(">=2024-03-01").matches(Some(&Value::DateTime(45352.0))));
+        assert!(!text("<2024-03-01").matches(Some(&Value::DateTime(45352.0))));
+
+        assert!(text("").matches(None));
+        assert!(text("=").matches(None));
+        assert!(!text("").matches(num(0.0).as_ref()));
+        assert!(text("<>").matches(num(0.0).as_ref()));
+    }
+
+    #[test]
+    fn test_criteria_numbers() {
+        let criterion = |s: &str, locale| Criterion::new(&Value::Text(s.to_string()), locale);
+        let num = |n: f64| Some(Value::Number(n));
+        let cell = |s: &str| Some(Value::Text(s.to_string()));
+
+        assert!(criterion(">1,000", NumberLocale::DecimalPoint).matches(num(1500.0).as_ref()));
+        assert!(criterion(">1,5", NumberLocale::DecimalComma).matches(num(2.0).as_ref()));
+        assert!(!criterion(">1,5", NumberLocale::DecimalComma).matches(num(1.0).as_ref()));
+        assert!(criterion("<=1.000,5", NumberLocale::DecimalComma).matches(num(1000.5).as_ref()));
+        assert!(criterion("1,5", NumberLocale::DecimalPoint).matches(cell("1,5").as_ref()));
+
+        // Only digits make numbers, other text is compared as text
+        for text in ["nan", "inf", "infinity", "-inf"] {
+            let criterion = criterion(text, NumberLocale::DecimalPoint);
+            assert!(criterion.matches(cell(&text.to_uppercase()).as_ref()));
+            assert!(!criterion.matches(num(f64::INFINITY).as_ref()));
+        }
+        assert!(!criterion(">inf", NumberLocale::DecimalPoint).matches(num(1.0).as_ref()));
+    }
+}
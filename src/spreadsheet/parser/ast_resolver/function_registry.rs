@@ -0,0 +1,521 @@
+use std::{collections::BTreeMap, fmt::Debug, fmt::Display, sync::Arc, sync::OnceLock};
+
+use crate::common_functions::check_date;
+use crate::common_types::{ComputeError, NumberLocale, Token, Value};
+use crate::spreadsheet::parser::tokenizer::ExpressionTokenizer;
+
+use super::builtin_functions;
+
+/// Functions evaluated by the resolver itself, they only compute the arguments needed for their result.
+pub const SPECIAL_FORMS: [&str; 9] = [
+    "if", "ifs", "switch", "iferror", "and", "or", "choose", "offset", "indirect",
+];
+
+/// Why a function can not be registered.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RegistrationError {
+    /// The name is one of the [`SPECIAL_FORMS`].
+    SpecialForm(String),
+    /// Formulas can not call a function by this name, names are words starting with a lowercase letter or
+    /// `_`, which may be joined by dots as in `stdev.p`.
+    InvalidName(String),
+}
+
+impl Display for RegistrationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RegistrationError::SpecialForm(name) => {
+                write!(
+                    f,
+                    "{name} is evaluated by the resolver and can not be registered"
+                )
+            }
+            RegistrationError::InvalidName(name) => {
+                write!(f, "'{name}' can not be called as a function")
+            }
+        }
+    }
+}
+
+impl std::error::Error for RegistrationError {}
+
+pub type FunctionBody =
+    Arc<dyn Fn(Vec<Value>, NumberLocale) -> Result<Value, ComputeError> + Send + Sync>;
+
+/// The kind of value a function accepts for an argument.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgumentType {
+    Any,
+    Number,
+    Text,
+    Bool,
+    /// A [`Value::DateTime`], or a number taken as the serial number of a date.
+    Date,
+    /// A range passed as a [`Value::Array`] keeping its shape and empty cells, instead of as the values of its
+    /// non empty cells. Other values are passed as an array of a single cell.
+    Range,
+}
+
+impl ArgumentType {
+    fn accepts(&self, value: &Value) -> bool {
+        matches!(
+            (self, value),
+            (ArgumentType::Any, _)
+                | (ArgumentType::Number, Value::Number(_) | Value::DateTime(_))
+                | (ArgumentType::Text, Value::Text(_))
+                | (ArgumentType::Bool, Value::Bool(_))
+                | (ArgumentType::Date, Value::DateTime(_) | Value::Number(_))
+                | (ArgumentType::Range, Value::Array(_))
+        )
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            ArgumentType::Any => "value",
+            ArgumentType::Number => "number",
+            ArgumentType::Text => "text",
+            ArgumentType::Bool => "boolean",
+            ArgumentType::Date => "date",
+            ArgumentType::Range => "range",
+        }
+    }
+}
+
+/// The arguments a function accepts: required ones, then optional ones, then any number of repeated ones.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Signature {
+    pub required: Vec<ArgumentType>,
+    pub optional: Vec<ArgumentType>,
+    /// Repeated as a whole when there are several, such as the ranges and criteria of `sumifs`.
+    pub repeated: Vec<ArgumentType>,
+}
+
+impl Signature {
+    /// The type of the argument at the position counted from 0, `None` if there can not be one.
+    pub fn argument_type(&self, position: usize) -> Option<ArgumentType> {
+        let fixed = self.required.len() + self.optional.len();
+        match position.checked_sub(fixed) {
+            None => self
+                .required
+                .iter()
+                .chain(&self.optional)
+                .nth(position)
+                .copied(),
+            Some(_) if self.repeated.is_empty() => None,
+            Some(repetition) => Some(self.repeated[repetition % self.repeated.len()]),
+        }
+    }
+
+    fn count_error(&self, name: &str) -> ComputeError {
+        let min = self.required.len();
+        let max = min + self.optional.len();
+        let plural = |count: usize| if count == 1 { "argument" } else { "arguments" };
+        ComputeError::InvalidArgument(match (min, self.repeated.len()) {
+            (0, 0) if max == 0 => format!("{name} expects no arguments"),
+            (_, 0) if min == max => format!("{name} expects exactly {min} {}", plural(min)),
+            (_, 0) => format!("{name} expects between {min} and {max} arguments"),
+            (_, 1) => format!("{name} expects at least {min} {}", plural(min)),
+            (_, group) => {
+                format!(
+                    "{name} expects {min} {} followed by groups of {group} arguments",
+                    plural(min)
+                )
+            }
+        })
+    }
+
+    /// Checks the amount and the types of the arguments given to the function `name`.
+    pub fn validate(&self, name: &str, args: &[Value]) -> Result<(), ComputeError> {
+        let max = self.required.len() + self.optional.len();
+        let repeated = args.len().saturating_sub(max);
+        let incomplete_group =
+            !self.repeated.is_empty() && !repeated.is_multiple_of(self.repeated.len());
+        if args.len() < self.required.len()
+            || (self.repeated.is_empty() && repeated > 0)
+            || incomplete_group
+        {
+            return Err(self.count_error(name));
+        }
+
+        for (position, arg) in args.iter().enumerate() {
+            let expected = self
+                .argument_type(position)
+                .expect("The amount was checked");
+            if !expected.accepts(arg) {
+                return Err(ComputeError::InvalidArgument(format!(
+                    "{name} expects a {} as argument {}, got {arg}",
+                    expected.name(),
+                    position + 1
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A function that formulas can call by its name, such as `sum` in `=sum(A1:A4)`.
+///
+/// Arguments are checked against the signature before the body is called, so the body can rely on their
+/// amount and types. Ranges are passed as the values of their non empty cells.
+#[derive(Clone)]
+pub struct Function {
+    name: String,
+    documentation: String,
+    signature: Signature,
+    inspects_errors: bool,
+    body: FunctionBody,
+}
+
+impl Debug for Function {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Function")
+            .field("name", &self.name)
+            .field("signature", &self.signature)
+            .field("inspects_errors", &self.inspects_errors)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Function {
+    /// Creates a function that takes no arguments until they are added with [`Function::required`],
+    /// [`Function::optional`] or [`Function::repeated`].
+    pub fn new(
+        name: &str,
+        documentation: &str,
+        body: impl Fn(Vec<Value>) -> Result<Value, ComputeError> + Send + Sync + 'static,
+    ) -> Self {
+        Self::with_locale(name, documentation, move |args, _| body(args))
+    }
+
+    /// Creates a function whose body is also given how numbers are written in the sheet, for functions
+    /// reading numbers out of text such as the criteria of `sumif`.
+    pub fn with_locale(
+        name: &str,
+        documentation: &str,
+        body: impl Fn(Vec<Value>, NumberLocale) -> Result<Value, ComputeError> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            documentation: documentation.to_string(),
+            signature: Signature::default(),
+            inspects_errors: false,
+            body: Arc::new(body),
+        }
+    }
+
+    pub fn required(mut self, types: &[ArgumentType]) -> Self {
+        self.signature.required.extend_from_slice(types);
+        self
+    }
+
+    /// Arguments that may be left out after the required ones.
+    pub fn optional(mut self, types: &[ArgumentType]) -> Self {
+        self.signature.optional.extend_from_slice(types);
+        self
+    }
+
+    /// Any number of arguments after the required and optional ones, such as the values of `sum`.
+    pub fn repeated(mut self, argument_type: ArgumentType) -> Self {
+        self.signature.repeated = vec![argument_type];
+        self
+    }
+
+    /// Any number of groups of arguments after the required and optional ones, such as the range and
+    /// criteria pairs of `sumifs`.
+    pub fn repeated_group(mut self, types: &[ArgumentType]) -> Self {
+        self.signature.repeated = types.to_vec();
+        self
+    }
+
+    /// Passes error values to the body instead of making the first one the result, as `iserror` does.
+    pub fn inspecting_errors(mut self) -> Self {
+        self.inspects_errors = true;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn documentation(&self) -> &str {
+        &self.documentation
+    }
+
+    pub fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    /// How the function is called, such as `round(number, [number])`, `sum(number...)` or
+    /// `countifs(range, value, [range, value]...)`.
+    pub fn usage(&self) -> String {
+        let names: Vec<&str> = self
+            .signature
+            .repeated
+            .iter()
+            .map(ArgumentType::name)
+            .collect();
+        let repeated = match names.as_slice() {
+            [] => None,
+            [name] => Some(format!("{name}...")),
+            names => Some(format!("[{}]...", names.join(", "))),
+        };
+        let arguments: Vec<String> = self
+            .signature
+            .required
+            .iter()
+            .map(|argument| argument.name().to_string())
+            .chain(
+                self.signature
+                    .optional
+                    .iter()
+                    .map(|argument| format!("[{}]", argument.name())),
+            )
+            .chain(repeated)
+            .collect();
+        format!("{}({})", self.name, arguments.join(", "))
+    }
+
+    /// Calls the function as a sheet with the default number locale would, see [`Function::call_with_locale`].
+    pub fn call(&self, args: Vec<Value>) -> Result<Value, ComputeError> {
+        self.call_with_locale(args, NumberLocale::default())
+    }
+
+    /// Calls the function, resulting in the first error value among the arguments unless it inspects errors.
+    pub fn call_with_locale(
+        &self,
+        args: Vec<Value>,
+        locale: NumberLocale,
+    ) -> Result<Value, ComputeError> {
+        if !self.inspects_errors {
+            if let Some(Value::Error(error)) =
+                args.iter().find(|arg| matches!(arg, Value::Error(_)))
+            {
+                return Err(error.clone());
+            }
+        }
+        self.signature.validate(&self.name, &args)?;
+        match (self.body)(args, locale).and_then(Value::into_result) {
+            // Results such as infinity or NaN are not numbers a cell can hold
+            Ok(Value::Number(num)) if !num.is_finite() => Err(ComputeError::NumberError(format!(
+                "{} has no finite result for these arguments",
+                self.name
+            ))),
+            result => result.and_then(check_date),
+        }
+    }
+}
+
+/// The functions formulas can call. The default registry holds the builtin functions.
+#[derive(Debug, Clone)]
+pub struct FunctionRegistry {
+    functions: BTreeMap<String, Function>,
+}
+
+impl Default for FunctionRegistry {
+    fn default() -> Self {
+        Self::builtins().clone()
+    }
+}
+
+impl FunctionRegistry {
+    /// Creates a registry without any functions.
+    pub fn new() -> Self {
+        Self {
+            functions: BTreeMap::new(),
+        }
+    }
+
+    /// The registry holding only the builtin functions.
+    pub fn builtins() -> &'static FunctionRegistry {
+        static BUILTINS: OnceLock<FunctionRegistry> = OnceLock::new();
+        BUILTINS.get_or_init(|| {
+            let mut registry = Self::new();
+            builtin_functions::register(&mut registry);
+            registry
+        })
+    }
+
+    /// Adds a function, returning the function it replaced if one had the same name.
+    ///
+    /// Fails if formulas can not call the function by its name, or if the name is one of the [`SPECIAL_FORMS`],
+    /// which can not be replaced.
+    pub fn register(&mut self, function: Function) -> Result<Option<Function>, RegistrationError> {
+        let name = &function.name;
+        if SPECIAL_FORMS.contains(&name.as_str()) {
+            return Err(RegistrationError::SpecialForm(name.clone()));
+        }
+        let is_callable = ExpressionTokenizer::new(format!("{name}()").chars().collect())
+            .tokenize_expression()
+            .is_ok_and(|tokens| {
+                tokens
+                    == [
+                        Token::FunctionName(name.clone()),
+                        Token::LParen,
+                        Token::RParen,
+                    ]
+            });
+        if !is_callable {
+            return Err(RegistrationError::InvalidName(name.clone()));
+        }
+        Ok(self.add_builtin(function))
+    }
+
+    // Adds one of the builtin functions, whose names are checked by the tests instead
+    pub(super) fn add_builtin(&mut self, function: Function) -> Option<Function> {
+        self.functions.insert(function.name.clone(), function)
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Function> {
+        self.functions.remove(name)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Function> {
+        self.functions.get(name)
+    }
+
+    /// Every function, sorted by name.
+    pub fn functions(&self) -> impl Iterator<Item = &Function> {
+        self.functions.values()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signature_validation() {
+        let function = Function::new("f", "", |_| Ok(Value::Bool(true)))
+            .required(&[ArgumentType::Text])
+            .optional(&[ArgumentType::Number]);
+        let text = || Value::Text("a".to_string());
+
+        assert_eq!(function.usage(), "f(text, [number])");
+        assert_eq!(function.call(vec![text()]), Ok(Value::Bool(true)));
+        assert_eq!(
+            function.call(vec![text(), Value::Number(1.0)]),
+            Ok(Value::Bool(true))
+        );
+        assert_eq!(
+            function.call(vec![]),
+            Err(ComputeError::InvalidArgument(
+                "f expects between 1 and 2 arguments".to_string()
+            ))
+        );
+        assert_eq!(
+            function.call(vec![text(), text()]),
+            Err(ComputeError::InvalidArgument(
+                "f expects a number as argument 2, got a".to_string()
+            ))
+        );
+        assert_eq!(
+            function.call(vec![text(), Value::Error(ComputeError::DivisionByZero)]),
+            Err(ComputeError::DivisionByZero)
+        );
+    }
+
+    #[test]
+    fn test_repeated_group() {
+        let function = Function::new("f", "", |args| Ok(Value::Number(args.len() as f64)))
+            .required(&[ArgumentType::Number])
+            .repeated_group(&[ArgumentType::Text, ArgumentType::Number]);
+        let text = || Value::Text("a".to_string());
+
+        assert_eq!(function.usage(), "f(number, [text, number]...)");
+        assert_eq!(
+            function.call(vec![Value::Number(1.0)]),
+            Ok(Value::Number(1.0))
+        );
+        assert_eq!(
+            function.call(vec![Value::Number(1.0), text(), Value::Number(2.0)]),
+            Ok(Value::Number(3.0))
+        );
+        assert_eq!(
+            function.call(vec![Value::Number(1.0), text()]),
+            Err(ComputeError::InvalidArgument(
+                "f expects 1 argument followed by groups of 2 arguments".to_string()
+            ))
+        );
+        assert_eq!(
+            function.call(vec![Value::Number(1.0), text(), text()]),
+            Err(ComputeError::InvalidArgument(
+                "f expects a number as argument 3, got a".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_register() {
+        let mut registry = FunctionRegistry::default();
+        assert!(registry.get("sum").is_some());
+        assert!(FunctionRegistry::new().get("sum").is_none());
+
+        let double = Function::new("double", "Doubles a number.", |args| {
+            Ok(Value::Number(args[0].as_number().unwrap_or_default() * 2.0))
+        })
+        .required(&[ArgumentType::Number]);
+        assert_eq!(
+            registry.register(double).map(|replaced| replaced.is_none()),
+            Ok(true)
+        );
+        assert_eq!(
+            registry
+                .get("double")
+                .unwrap()
+                .call(vec![Value::Number(4.0)]),
+            Ok(Value::Number(8.0))
+        );
+        assert_eq!(
+            registry.get("double").unwrap().call(vec![]),
+            Err(ComputeError::InvalidArgument(
+                "double expects exactly 1 argument".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_register_invalid_names() {
+        let function = |name: &str| Function::new(name, "", |_| Ok(Value::Bool(true)));
+        let mut registry = FunctionRegistry::new();
+        assert_eq!(
+            registry.register(function("if")).map(|_| ()),
+            Err(RegistrationError::SpecialForm("if".to_string()))
+        );
+        for name in [
+            "Double",
+            "SUM",
+            "A1",
+            "two words",
+            "",
+            "1st",
+            "tax.",
+            "vat-2",
+        ] {
+            assert_eq!(
+                registry.register(function(name)).map(|_| ()),
+                Err(RegistrationError::InvalidName(name.to_string())),
+                "{name}"
+            );
+        }
+        for name in ["double", "stdev.s", "_private", "log2"] {
+            assert_eq!(
+                registry.register(function(name)).map(|_| ()),
+                Ok(()),
+                "{name}"
+            );
+        }
+        assert!(registry.get("Double").is_none());
+    }
+
+    #[test]
+    fn test_builtin_names() {
+        let mut registry = FunctionRegistry::new();
+        for function in FunctionRegistry::builtins().functions() {
+            assert!(
+                registry.register(function.clone()).is_ok(),
+                "{}",
+                function.name
+            );
+        }
+    }
+}
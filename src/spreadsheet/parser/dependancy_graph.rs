@@ -1,11 +1,20 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
+use crate::common_types::{CellRange, Dependency};
 use crate::spreadsheet::Index;
 
 #[derive(Debug, Default)]
 pub struct DependancyGraph {
     //? It is possible to speed up change and remove node from O(n) time to O(1) by adding the field depends on. This can be done in the future to boost performance.
     allows_compute: HashMap<Index, Vec<Index>>, // Given a key return nodes this node allows for compute
+    // Ranges are kept whole instead of adding an edge for each cell inside them, so large ranges stay cheap.
+    // Every node inside the range allows the paired node to compute.
+    range_dependants: Vec<(CellRange, Index)>,
+    // Cells of other sheets are only recorded here, recomputing them is up to the workbook owning the sheets
+    sheet_dependants: Vec<(String, CellRange, Index)>,
+    // Names are defined by the workbook as well, so they are only recorded here too
+    name_dependants: Vec<(String, Index)>,
+    nodes: HashSet<Index>, // Every node that has been added to the graph
 }
 
 #[derive(Debug)]
@@ -15,10 +24,61 @@ pub struct TopologicalSort {
 }
 
 impl DependancyGraph {
-    pub fn add_node(&mut self, idx: Index, cell_depends_on: &Vec<Index>) {
+    pub fn add_node(&mut self, idx: Index, cell_depends_on: &[Dependency]) {
+        self.nodes.insert(idx);
         for dependency in cell_depends_on {
-            self.allows_compute.entry(*dependency).or_default().push(idx);
+            match dependency {
+                Dependency::Cell(cell) => self.allows_compute.entry(*cell).or_default().push(idx),
+                Dependency::Range(range) => self.range_dependants.push((*range, idx)),
+                Dependency::Sheet { sheet, range } => {
+                    self.sheet_dependants.push((sheet.clone(), *range, idx))
+                }
+                Dependency::Name(name) => self.name_dependants.push((name.clone(), idx)),
+            }
+        }
+    }
+
+    /// Return the nodes that directly depend on this one, either by reference or through a range
+    pub fn direct_dependants(&self, index: Index) -> Vec<Index> {
+        let mut dependants = self
+            .allows_compute
+            .get(&index)
+            .cloned()
+            .unwrap_or_default();
+
+        for (range, dependant) in &self.range_dependants {
+            if range.contains(index) {
+                dependants.push(*dependant);
+            }
         }
+
+        dependants
+    }
+
+    /// Return the nodes that directly depend on a cell of another sheet
+    pub fn sheet_dependants(&self, sheet: &str, index: Index) -> Vec<Index> {
+        self.sheet_dependants
+            .iter()
+            .filter(|(name, range, _)| name == sheet && range.contains(index))
+            .map(|(_, _, dependant)| *dependant)
+            .collect()
+    }
+
+    /// Return the nodes that directly depend on a name
+    pub fn name_dependants(&self, name: &str) -> Vec<Index> {
+        self.name_dependants
+            .iter()
+            .filter(|(other, _)| other == name)
+            .map(|(_, dependant)| *dependant)
+            .collect()
+    }
+
+    /// Return every node that depends on a cell of another sheet or on a name
+    pub fn nodes_reading_workbook(&self) -> impl Iterator<Item = Index> + '_ {
+        self.sheet_dependants
+            .iter()
+            .map(|(_, _, dependant)| *dependant)
+            .chain(self.name_dependants.iter().map(|(_, dependant)| *dependant))
     }
 
     pub fn topological_sort(&self) -> TopologicalSort {
@@ -27,8 +87,19 @@ impl DependancyGraph {
         let mut sorted: Vec<Index> = vec![];
         let mut cycles: Vec<Index> = vec![];
 
+        let all_nodes: HashSet<Index> = self
+            .allows_compute
+            .keys()
+            .chain(self.nodes.iter())
+            .copied()
+            .collect();
+        let edges: HashMap<Index, Vec<Index>> = all_nodes
+            .iter()
+            .map(|node| (*node, self.direct_dependants(*node)))
+            .collect();
+
         // Calculate in-degrees for all nodes
-        for (node, dependents) in &self.allows_compute {
+        for (node, dependents) in &edges {
             in_degree.entry(*node).or_insert(0); // Ensure all nodes exist in the map
             for dependent in dependents {
                 *in_degree.entry(*dependent).or_insert(0) += 1;
@@ -47,7 +118,7 @@ impl DependancyGraph {
             sorted.push(node);
 
             // Decrease the in-degree of all its dependents
-            if let Some(dependents) = self.allows_compute.get(&node) {
+            if let Some(dependents) = edges.get(&node) {
                 for dependent in dependents {
                     if let Some(degree) = in_degree.get_mut(dependent) {
                         *degree -= 1;
@@ -74,26 +145,28 @@ impl DependancyGraph {
         for dependants in self.allows_compute.values_mut() {
             dependants.retain(|&x| x != index);
         }
+        self.range_dependants.retain(|(_, x)| *x != index);
+        self.sheet_dependants.retain(|(_, _, x)| *x != index);
+        self.name_dependants.retain(|(_, x)| *x != index);
+        self.nodes.remove(&index);
     }
 
-    pub fn change_node(&mut self, index: Index, dependencies: &Vec<Index>) {
+    pub fn change_node(&mut self, index: Index, dependencies: &[Dependency]) {
         self.remove_node(index);
         // Re-add the node with the new dependencies
         self.add_node(index, dependencies);
     }
 
     /// Return all nodes that depend on this
-    pub fn get_all_dependants(&self, index: Index) -> Vec<Index> {        
+    pub fn get_all_dependants(&self, index: Index) -> Vec<Index> {
         let mut result = Vec::new();
         let mut to_process = vec![index];
 
         while let Some(cell) = to_process.pop() {
-            if let Some(dependants) = self.allows_compute.get(&cell) {
-                for dependant in dependants.iter() {
-                    if !result.contains(dependant) {
-                        result.push(*dependant);
-                        to_process.push(*dependant);
-                    }
+            for dependant in self.direct_dependants(cell) {
+                if !result.contains(&dependant) {
+                    result.push(dependant);
+                    to_process.push(dependant);
                 }
             }
         }
@@ -1,8 +1,17 @@
-use crate::common_types::Token;
+use std::ops::Range;
+
+use crate::common_functions::{parse_cell_reference, scan_number};
+use crate::common_types::{ComputeError, NumberLocale, Token, ERROR_CODES};
+
+/// A token and the range of characters it was read from.
+pub type SpannedToken = (Token, Range<usize>);
 
 pub struct ExpressionTokenizer {
     index: usize,
     chars: Vec<char>,
+    locale: NumberLocale,
+    // Whether each open parenthesis holds the arguments of a function call
+    calls: Vec<bool>,
 }
 
 #[derive(Debug)]
@@ -11,41 +20,126 @@ pub enum TokenizeError {
     InvalidCellName(String),
     InvalidNumber(String),
     UnterminatedString,
+    InvalidSheetName(String),
 }
 
 impl ExpressionTokenizer {
     pub fn new(chars: Vec<char>) -> Self {
-        Self { index: 0, chars }
+        Self {
+            index: 0,
+            chars,
+            locale: NumberLocale::default(),
+            calls: Vec::new(),
+        }
+    }
+
+    /// Reads numbers and argument separators the way the locale writes them.
+    pub fn with_locale(mut self, locale: NumberLocale) -> Self {
+        self.locale = locale;
+        self
     }
 
     pub fn tokenize_expression(&mut self) -> Result<Vec<Token>, TokenizeError> {
+        Ok(self
+            .tokenize_with_spans()
+            .map_err(|(error, _)| error)?
+            .into_iter()
+            .map(|(token, _)| token)
+            .collect())
+    }
+
+    /// Tokenizes the expression, pairing every token with the range of characters it was read from.
+    /// An error is paired with the characters that could not be tokenized.
+    pub fn tokenize_with_spans(
+        &mut self,
+    ) -> Result<Vec<SpannedToken>, (TokenizeError, Range<usize>)> {
         self.skip_whitespace();
         let mut expr_tokens = Vec::new();
         while !self.is_done() {
-            let token = match self.peek().expect("Should never fail") {
-                '+' | '-' | '/' | '*' | '(' | ')' | ':' | ',' => self.parse_operator(),
-                '=' | '!' | '>' | '<' | '&' | '|' => self.parse_logical_operator()?,
-                '"' => self.parse_string_literal()?,
-                letter if letter.is_uppercase() => self.parse_cell_name_or_bool()?,
-                letter if letter.is_lowercase() => self.parse_function_name()?,
-                digit if digit.is_ascii_digit() => self.parse_number()?,
-                unknown => return Err(TokenizeError::UnexpectedCharacter(*unknown)),
-            };
-
-            expr_tokens.push(token);
-
+            let start = self.index;
+            let token = self.next_token(&expr_tokens).map_err(|error| {
+                // The failed token was read up to the current index, or not at all
+                let end = self.index.clamp(start + 1, self.chars.len().max(start + 1));
+                (error, start..end)
+            })?;
+            expr_tokens.push((token, start..self.index));
             self.skip_whitespace();
         }
 
         Ok(expr_tokens)
     }
 
+    fn next_token(&mut self, previous: &[SpannedToken]) -> Result<Token, TokenizeError> {
+        if let Some(sheet) = self.parse_sheet_name()? {
+            return Ok(sheet);
+        }
+
+        let decimal_separator = self.locale.decimal_separator();
+        let token = match self.peek().expect("Should never fail") {
+            separator if *separator == self.locale.argument_separator() => {
+                self.pop();
+                Token::Comma
+            }
+            '+' | '-' | '/' | '*' | '^' | '%' | '(' | ')' | ':' => self.parse_operator(),
+            '=' | '!' | '>' | '<' | '&' | '|' => self.parse_logical_operator()?,
+            '"' => self.parse_string_literal()?,
+            '#' => self.parse_error_value()?,
+            letter if letter.is_uppercase() || *letter == '$' => self.parse_cell_name_or_bool()?,
+            letter if letter.is_lowercase() || *letter == '_' => {
+                self.parse_function_or_name(previous)?
+            }
+            digit if digit.is_ascii_digit() || *digit == decimal_separator => {
+                self.parse_number()?
+            }
+            unknown => return Err(TokenizeError::UnexpectedCharacter(*unknown)),
+        };
+
+        match token {
+            Token::LParen => {
+                let is_call = matches!(previous.last(), Some((Token::FunctionName(_), _)));
+                self.calls.push(is_call);
+            }
+            Token::RParen => {
+                self.calls.pop();
+            }
+            _ => (),
+        }
+        Ok(token)
+    }
+
     fn parse_cell_name_or_bool(&mut self) -> Result<Token, TokenizeError> {
-        // [A-Z]+\d+
+        // Words without `$` anchors may also be a defined name
+        if self.peek() != Some(&'$') {
+            let length = self.chars[self.index..]
+                .iter()
+                .take_while(|ch| ch.is_alphanumeric() || **ch == '_')
+                .count();
+            let end = self.index + length;
+            if self.chars.get(end) != Some(&'$') {
+                let word: String = self.chars[self.index..end].iter().collect();
+                self.index = end;
+                return match word.as_str() {
+                    "TRUE" => Ok(Token::Bool(true)),
+                    "FALSE" => Ok(Token::Bool(false)),
+                    _ if !is_cell_name_shaped(&word) => Ok(Token::Name(word)),
+                    _ if parse_cell_reference(&word).is_some() => Ok(Token::CellName(word)),
+                    _ => Err(TokenizeError::InvalidCellName(word)),
+                };
+            }
+        }
+
+        // \$?[A-Z]+\$?\d+
 
         let mut is_valid = false;
         let mut letters = String::new();
 
+        // A leading $ anchors the column
+        let absolute_column = self.peek() == Some(&'$');
+        if absolute_column {
+            letters.push('$');
+            self.pop();
+        }
+
         // Collect the uppercase letters
         while let Some(&ch) = self.peek() {
             if ch.is_ascii_uppercase() {
@@ -56,6 +150,8 @@ impl ExpressionTokenizer {
             }
         }
 
+        let column_start = usize::from(absolute_column);
+
         if letters == "TRUE" {
             return Ok(Token::Bool(true));
         }
@@ -67,8 +163,14 @@ impl ExpressionTokenizer {
         // At this point we know that we are parsing a Cell Name
 
         // Ensure there are letters
-        if letters.is_empty() {
-            return Err(TokenizeError::InvalidCellName(String::new()));
+        if letters.len() == column_start {
+            return Err(TokenizeError::InvalidCellName(letters));
+        }
+
+        // A $ between the letters and digits anchors the row
+        if self.peek() == Some(&'$') {
+            letters.push('$');
+            self.pop();
         }
 
         // Collect the digits
@@ -90,16 +192,72 @@ impl ExpressionTokenizer {
         Ok(Token::CellName(letters))
     }
 
+    /// Parses the `Sheet2!` or `'My Sheet'!` prefix of a reference, consuming nothing if there is none.
+    fn parse_sheet_name(&mut self) -> Result<Option<Token>, TokenizeError> {
+        if self.peek() == Some(&'\'') {
+            self.pop();
+            let mut name = String::new();
+            loop {
+                match self.pop().copied() {
+                    Some('\'') if self.peek() == Some(&'\'') => {
+                        self.pop();
+                        name.push('\'');
+                    }
+                    Some('\'') => break,
+                    Some(ch) => name.push(ch),
+                    None => return Err(TokenizeError::InvalidSheetName(name)),
+                }
+            }
+            if self.pop() != Some(&'!') || name.is_empty() {
+                return Err(TokenizeError::InvalidSheetName(name));
+            }
+            return Ok(Some(Token::SheetName(name)));
+        }
+
+        let length = self.chars[self.index..]
+            .iter()
+            .take_while(|ch| ch.is_alphanumeric() || **ch == '_')
+            .count();
+        let end = self.index + length;
+        // `A1!=B1` compares two cells
+        if length == 0 || self.chars.get(end) != Some(&'!') || self.chars.get(end + 1) == Some(&'=')
+        {
+            return Ok(None);
+        }
+
+        let name = self.chars[self.index..end].iter().collect();
+        self.index = end + 1;
+        Ok(Some(Token::SheetName(name)))
+    }
+
+    fn parse_error_value(&mut self) -> Result<Token, TokenizeError> {
+        let code = ERROR_CODES
+            .into_iter()
+            .find(|code| {
+                code.chars()
+                    .enumerate()
+                    .all(|(i, ch)| self.chars.get(self.index + i) == Some(&ch))
+            })
+            .ok_or(TokenizeError::UnexpectedCharacter('#'))?;
+
+        self.index += code.len();
+        match ComputeError::from_code(code).expect("Error codes are known") {
+            ComputeError::UnfindableReference(_) => Ok(Token::RefError),
+            error => Ok(Token::Error(error)),
+        }
+    }
+
     fn parse_operator(&mut self) -> Token {
         match self.pop().expect("Shoud never fail") {
             '+' => Token::Plus,
             '-' => Token::Minus,
             '/' => Token::Division,
             '*' => Token::Multiply,
+            '^' => Token::Power,
+            '%' => Token::Percent,
             '(' => Token::LParen,
             ')' => Token::RParen,
             ':' => Token::Colon,
-            ',' => Token::Comma,
             _ => unreachable!(),
         }
     }
@@ -130,34 +288,89 @@ impl ExpressionTokenizer {
     }
 
     fn parse_number(&mut self) -> Result<Token, TokenizeError> {
-        let mut number = String::new();
-        while let Some(&ch) = self.peek() {
-            if ch.is_ascii_digit() || ch == '.' {
-                number.push(ch);
-                self.pop();
-            } else {
-                break;
+        // Thousands separators can not be told apart from argument separators inside function calls
+        let in_call = self.calls.last().copied().unwrap_or(false);
+        let grouping =
+            self.locale.thousands_separator() != self.locale.argument_separator() || !in_call;
+
+        let start = self.index;
+        let scanned = scan_number(&self.chars[start..], self.locale, grouping);
+        let end = start + scanned.map_or(0, |(_, length)| length);
+
+        // A number running into more digits or separators, as in `1.2.3`, is malformed
+        let decimal_separator = self.locale.decimal_separator();
+        let rest = self.chars[end..]
+            .iter()
+            .take_while(|ch| ch.is_ascii_digit() || **ch == decimal_separator)
+            .count();
+        match scanned {
+            Some((number, length)) if rest == 0 => {
+                self.index += length;
+                Ok(Token::Number(number))
+            }
+            _ => {
+                self.index = end + rest;
+                Err(TokenizeError::InvalidNumber(
+                    self.chars[start..self.index].iter().collect(),
+                ))
             }
-        }
-
-        match number.parse() {
-            Ok(inner) => Ok(Token::Number(inner)),
-            Err(_) => Err(TokenizeError::InvalidNumber(number)),
         }
     }
 
-    fn parse_function_name(&mut self) -> Result<Token, TokenizeError> {
-        let mut name = String::new();
-        while let Some(&ch) = self.peek() {
-            if ch.is_ascii_alphabetic() || ch == '_' {
-                name.push(ch);
-                self.pop();
+    // A word followed by a parenthesis calls a function, otherwise it is the mod operator or a defined name
+    fn parse_function_or_name(
+        &mut self,
+        previous: &[SpannedToken],
+    ) -> Result<Token, TokenizeError> {
+        let is_word_char = |ch: &char| ch.is_alphanumeric() || *ch == '_';
+        let rest = &self.chars[self.index..];
+        let mut length = 0;
+        while let Some(ch) = rest.get(length) {
+            let joins_words = *ch == '.' && rest.get(length + 1).is_some_and(is_word_char);
+            if is_word_char(ch) || joins_words {
+                length += 1;
             } else {
                 break;
             }
         }
 
-        Ok(Token::FunctionName(name))
+        // Only function names may contain dots, as in `stdev.p(`
+        let is_call = rest[length..].iter().find(|ch| !ch.is_ascii_whitespace()) == Some(&'(');
+        if !is_call {
+            length = rest[..length]
+                .iter()
+                .position(|ch| *ch == '.')
+                .unwrap_or(length);
+        }
+        let name: String = rest[..length].iter().collect();
+        self.index += length;
+
+        let next = self.chars[self.index..]
+            .iter()
+            .find(|ch| !ch.is_ascii_whitespace());
+        // After a value `mod` is the operator, so `7 mod (1 + 2)` does not call the mod function
+        let after_value = matches!(
+            previous.last(),
+            Some((
+                Token::Number(_)
+                    | Token::CellName(_)
+                    | Token::Name(_)
+                    | Token::StringLiteral(_)
+                    | Token::Bool(_)
+                    | Token::Error(_)
+                    | Token::RefError
+                    | Token::RParen
+                    | Token::Percent,
+                _
+            ))
+        );
+        if next == Some(&'(') && !(name == "mod" && after_value) {
+            Ok(Token::FunctionName(name))
+        } else if name == "mod" {
+            Ok(Token::Mod)
+        } else {
+            Ok(Token::Name(name))
+        }
     }
 
     fn parse_logical_operator(&mut self) -> Result<Token, TokenizeError> {
@@ -200,7 +413,7 @@ impl ExpressionTokenizer {
                     self.pop();
                     Token::And
                 } else {
-                    return Err(TokenizeError::UnexpectedCharacter('&'));
+                    Token::Concat
                 }
             }
             '|' => {
@@ -237,6 +450,12 @@ impl ExpressionTokenizer {
     }
 }
 
+// Uppercase letters followed by digits, which can only be a cell name
+fn is_cell_name_shaped(word: &str) -> bool {
+    let digits = word.trim_start_matches(|ch: char| ch.is_ascii_uppercase());
+    digits.len() < word.len() && !digits.is_empty() && digits.chars().all(|ch| ch.is_ascii_digit())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -348,6 +567,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::approx_constant)]
     fn test_expression_with_numbers() {
         let s = "3.14 + 42";
         let tokens = ExpressionTokenizer::new(s.chars().collect())
@@ -361,11 +581,36 @@ mod tests {
 
     #[test]
     fn test_expression_with_invalid_cell_name() {
-        let s = "A + B2";
+        let s = "A99999999999999999999 + B2";
         let result = ExpressionTokenizer::new(s.chars().collect()).tokenize_expression();
         assert!(matches!(result, Err(TokenizeError::InvalidCellName(_))));
     }
 
+    #[test]
+    fn test_names() {
+        let s = "TaxRate * sum(Revenue) + A + tax_2024 + B2";
+        let tokens = ExpressionTokenizer::new(s.chars().collect())
+            .tokenize_expression()
+            .unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Name("TaxRate".to_string()),
+                Token::Multiply,
+                Token::FunctionName("sum".to_string()),
+                Token::LParen,
+                Token::Name("Revenue".to_string()),
+                Token::RParen,
+                Token::Plus,
+                Token::Name("A".to_string()),
+                Token::Plus,
+                Token::Name("tax_2024".to_string()),
+                Token::Plus,
+                Token::CellName("B2".to_string()),
+            ]
+        );
+    }
+
     #[test]
     fn test_expression_with_invalid_number() {
         let s = "42.3.14 + B2";
@@ -373,16 +618,257 @@ mod tests {
         assert!(matches!(result, Err(TokenizeError::InvalidNumber(_))));
     }
 
+    #[test]
+    fn test_number_literals() {
+        let s = "1e-6 * .5 + 2.5E3 + 1,000.25 + sum(1,000)";
+        let tokens = ExpressionTokenizer::new(s.chars().collect())
+            .tokenize_expression()
+            .unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Number(1e-6),
+                Token::Multiply,
+                Token::Number(0.5),
+                Token::Plus,
+                Token::Number(2500.0),
+                Token::Plus,
+                Token::Number(1000.25),
+                Token::Plus,
+                Token::FunctionName("sum".to_string()),
+                Token::LParen,
+                Token::Number(1.0),
+                Token::Comma,
+                Token::Number(0.0),
+                Token::RParen,
+            ]
+        );
+
+        for s in ["1.2.3", "1e5.5"] {
+            let result = ExpressionTokenizer::new(s.chars().collect()).tokenize_expression();
+            assert!(
+                matches!(result, Err(TokenizeError::InvalidNumber(_))),
+                "{s} should be an invalid number"
+            );
+        }
+    }
+
+    #[test]
+    fn test_decimal_comma_locale() {
+        let s = "sum(1.000,5; ,5e2) + 2,5";
+        let tokens = ExpressionTokenizer::new(s.chars().collect())
+            .with_locale(NumberLocale::DecimalComma)
+            .tokenize_expression()
+            .unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::FunctionName("sum".to_string()),
+                Token::LParen,
+                Token::Number(1000.5),
+                Token::Comma,
+                Token::Number(50.0),
+                Token::RParen,
+                Token::Plus,
+                Token::Number(2.5),
+            ]
+        );
+    }
+
     #[test]
     fn test_expression_with_unexpected_character() {
-        let s = "A1 + $B2";
+        let s = "A1 + ~B2";
         let result = ExpressionTokenizer::new(s.chars().collect()).tokenize_expression();
         assert!(matches!(
             result,
-            Err(TokenizeError::UnexpectedCharacter('$'))
+            Err(TokenizeError::UnexpectedCharacter('~'))
+        ));
+    }
+
+    #[test]
+    fn test_absolute_and_mixed_cell_names() {
+        let s = "$A$1 + A$1 * $AB12";
+        let tokens = ExpressionTokenizer::new(s.chars().collect())
+            .tokenize_expression()
+            .unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::CellName("$A$1".to_string()),
+                Token::Plus,
+                Token::CellName("A$1".to_string()),
+                Token::Multiply,
+                Token::CellName("$AB12".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_anchored_cell_names() {
+        for s in ["$1", "A$", "$$A1", "A$$1", "$TRUE"] {
+            let result = ExpressionTokenizer::new(s.chars().collect()).tokenize_expression();
+            assert!(
+                matches!(result, Err(TokenizeError::InvalidCellName(_))),
+                "{s} should be an invalid cell name"
+            );
+        }
+    }
+
+    #[test]
+    fn test_token_spans() {
+        let s = " sum($A1:B2)";
+        let tokens = ExpressionTokenizer::new(s.chars().collect())
+            .tokenize_with_spans()
+            .unwrap();
+        let spans: Vec<_> = tokens.into_iter().map(|(_, span)| span).collect();
+        assert_eq!(spans, vec![1..4, 4..5, 5..8, 8..9, 9..11, 11..12]);
+    }
+
+    #[test]
+    fn test_error_spans() {
+        let cases = [
+            ("A1 + ~B2", 5..6),
+            ("1 + 1.2.3", 4..9),
+            ("\"abc", 0..4),
+            ("B2 + A$", 5..7),
+        ];
+        for (s, span) in cases {
+            let result = ExpressionTokenizer::new(s.chars().collect()).tokenize_with_spans();
+            assert!(
+                matches!(result, Err((_, ref error_span)) if *error_span == span),
+                "{s}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_sheet_names() {
+        let s = "Sheet2!A1 + 'My ''Data'''!$B$2:C3 + A1!=B1";
+        let tokens = ExpressionTokenizer::new(s.chars().collect())
+            .tokenize_expression()
+            .unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::SheetName("Sheet2".to_string()),
+                Token::CellName("A1".to_string()),
+                Token::Plus,
+                Token::SheetName("My 'Data'".to_string()),
+                Token::CellName("$B$2".to_string()),
+                Token::Colon,
+                Token::CellName("C3".to_string()),
+                Token::Plus,
+                Token::CellName("A1".to_string()),
+                Token::NotEquals,
+                Token::CellName("B1".to_string()),
+            ]
+        );
+
+        for invalid in ["'Sheet2 + A1", "'Sheet2' + A1", "''!A1"] {
+            let result = ExpressionTokenizer::new(invalid.chars().collect()).tokenize_expression();
+            assert!(matches!(result, Err(TokenizeError::InvalidSheetName(_))));
+        }
+    }
+
+    #[test]
+    fn test_ref_error() {
+        let s = "#REF! + A1";
+        let tokens = ExpressionTokenizer::new(s.chars().collect())
+            .tokenize_expression()
+            .unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::RefError,
+                Token::Plus,
+                Token::CellName("A1".to_string())
+            ]
+        );
+
+        let result = ExpressionTokenizer::new("#REF".chars().collect()).tokenize_expression();
+        assert!(matches!(
+            result,
+            Err(TokenizeError::UnexpectedCharacter('#'))
         ));
     }
 
+    #[test]
+    fn test_arithmetic_operators() {
+        let s = "-A1^2 & B1% mod 3 && mod";
+        let tokens = ExpressionTokenizer::new(s.chars().collect())
+            .tokenize_expression()
+            .unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Minus,
+                Token::CellName("A1".to_string()),
+                Token::Power,
+                Token::Number(2.0),
+                Token::Concat,
+                Token::CellName("B1".to_string()),
+                Token::Percent,
+                Token::Mod,
+                Token::Number(3.0),
+                Token::And,
+                Token::Mod,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_mod_function_and_operator() {
+        let tokenize = |s: &str| {
+            ExpressionTokenizer::new(s.chars().collect())
+                .tokenize_expression()
+                .unwrap()
+        };
+        assert_eq!(
+            tokenize("mod(7, 2)")[..2],
+            [Token::FunctionName("mod".to_string()), Token::LParen]
+        );
+        assert_eq!(
+            tokenize("7 mod (2)")[..3],
+            [Token::Number(7.0), Token::Mod, Token::LParen]
+        );
+    }
+
+    #[test]
+    fn test_dotted_function_names() {
+        let tokenize =
+            |s: &str| ExpressionTokenizer::new(s.chars().collect()).tokenize_expression();
+        assert_eq!(
+            tokenize("stdev.p (A1)").unwrap()[..2],
+            [Token::FunctionName("stdev.p".to_string()), Token::LParen]
+        );
+        assert_eq!(
+            tokenize("covariance.p(A1, B1)").unwrap()[0],
+            Token::FunctionName("covariance.p".to_string())
+        );
+
+        // Names and calls can not end with a dot, and names do not contain any
+        assert!(tokenize("sum.(A1)").is_err());
+        assert!(tokenize("rate.p").is_err());
+    }
+
+    #[test]
+    fn test_error_values() {
+        let s = "#DIV/0! + #N/A";
+        let tokens = ExpressionTokenizer::new(s.chars().collect())
+            .tokenize_expression()
+            .unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Error(ComputeError::DivisionByZero),
+                Token::Plus,
+                Token::Error(ComputeError::NotAvailable(
+                    "#N/A was entered directly".to_string()
+                )),
+            ]
+        );
+    }
+
     #[test]
     fn test_expression_with_nested_parentheses() {
         let s = "(((A1))) + B2";
@@ -660,11 +1146,14 @@ mod tests {
             .tokenize_expression()
             .is_err());
 
-        // Single & is invalid
+        // Single & concatenates
         let s = "A1 & B1";
-        assert!(ExpressionTokenizer::new(s.chars().collect())
-            .tokenize_expression()
-            .is_err());
+        assert_eq!(
+            ExpressionTokenizer::new(s.chars().collect())
+                .tokenize_expression()
+                .unwrap()[1],
+            Token::Concat
+        );
 
         // Single | is invalid
         let s = "A1 | B1";
@@ -0,0 +1,1207 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Display;
+use std::sync::Arc;
+
+use crate::common_types::{
+    Axis, ComputeError, Dependency, Expression, Index, NumberLocale, ParseError, StructuralChange,
+    Token, Value, AST,
+};
+use crate::spreadsheet::parser::{
+    ast_resolver::{
+        function_registry::{Function, FunctionRegistry, RegistrationError},
+        VarContext,
+    },
+    tokenizer::ExpressionTokenizer,
+    CellParser, FormulaReference,
+};
+use crate::spreadsheet::SpreadSheet;
+
+/// Why a name can not be given to a sheet.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SheetNameError {
+    Empty,
+    InvalidCharacter(char),
+    Duplicate(String),
+}
+
+impl Display for SheetNameError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SheetNameError::Empty => write!(f, "Sheet names can not be empty"),
+            SheetNameError::InvalidCharacter(c) => {
+                write!(f, "Sheet names can not contain '{c}'")
+            }
+            SheetNameError::Duplicate(name) => write!(f, "A sheet named '{name}' already exists"),
+        }
+    }
+}
+
+impl std::error::Error for SheetNameError {}
+
+/// Why a name can not be defined.
+#[derive(Debug, Clone, PartialEq)]
+pub enum NameError {
+    /// Names are a single word that is not a cell name, `TRUE` or `FALSE`.
+    InvalidName(String),
+    InvalidDefinition(ParseError),
+    /// The definition refers back to the name, directly or through other names.
+    Cycle(String),
+}
+
+impl Display for NameError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NameError::InvalidName(name) => write!(f, "'{name}' can not be used as a name"),
+            NameError::InvalidDefinition(error) => {
+                write!(f, "Invalid definition: {error}")
+            }
+            NameError::Cycle(name) => write!(f, "The definition of '{name}' refers to itself"),
+        }
+    }
+}
+
+impl std::error::Error for NameError {}
+
+#[derive(Debug)]
+struct Sheet {
+    name: String,
+    spread_sheet: SpreadSheet,
+}
+
+#[derive(Debug)]
+struct DefinedName {
+    // Written like a formula, with every reference qualified by its sheet
+    formula: String,
+    expression: Expression,
+}
+
+impl DefinedName {
+    // Parses the formula again, a definition that no longer parses computes to its parse error
+    fn reparse(&mut self, locale: NumberLocale) {
+        self.expression =
+            CellParser::parse_formula(&self.formula, locale).unwrap_or_else(|error| Expression {
+                ast: AST::Value(Value::Error(ComputeError::ParseError(error.to_string()))),
+                dependencies: Vec::new(),
+            });
+    }
+}
+
+// What a step of the workbook's history did besides changing cells and the definitions of names
+#[derive(Debug)]
+enum StepKind {
+    Edit,
+    Rename {
+        sheet: usize,
+        before: String,
+        after: String,
+    },
+    // The sheet is held here while the step is undone
+    Add {
+        position: usize,
+        sheet: Option<Sheet>,
+    },
+    // The sheet is held here until the step is undone
+    Remove {
+        position: usize,
+        sheet: Option<Sheet>,
+    },
+}
+
+// A change made through the workbook, undone and redone as a whole however many sheets it touched
+#[derive(Debug)]
+struct Step {
+    kind: StepKind,
+    // How many transactions of each sheet's own history belong to the step, by sheet position
+    transactions: Vec<(usize, usize)>,
+    // The definitions of names that were rewritten, as the name, its formula before and its formula after
+    definitions: Vec<(String, String, String)>,
+}
+
+/// Named sheets whose formulas can refer to each other's cells, as in `=Sheet2!A1` or `=sum('My Sheet'!A1:B5)`.
+///
+/// Every sheet keeps the cells that only refer to itself up to date. The workbook recomputes the cells reading
+/// other sheets, and everything depending on them, after each change made through it.
+///
+/// Names such as `TaxRate` or `Revenue` can be bound to a cell, a range or a constant expression
+/// and used in the formulas of every sheet.
+///
+/// Changes made through the workbook are undone with [`Workbook::undo`], which reverts a change in every sheet
+/// it touched at once, such as the rewritten references to a renamed sheet.
+#[derive(Debug)]
+pub struct Workbook {
+    sheets: Vec<Sheet>,
+    names: BTreeMap<String, DefinedName>,
+    number_locale: NumberLocale,
+    functions: Arc<FunctionRegistry>,
+    undo_stack: Vec<Step>,
+    redo_stack: Vec<Step>,
+}
+
+impl Default for Workbook {
+    fn default() -> Self {
+        Self::from(SpreadSheet::default())
+    }
+}
+
+impl From<SpreadSheet> for Workbook {
+    /// Creates a workbook holding a single sheet named `Sheet1`.
+    fn from(spread_sheet: SpreadSheet) -> Self {
+        let mut workbook = Self {
+            number_locale: spread_sheet.number_locale(),
+            functions: Arc::new(spread_sheet.functions().clone()),
+            sheets: vec![Sheet {
+                name: "Sheet1".to_string(),
+                spread_sheet,
+            }],
+            names: BTreeMap::new(),
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+        };
+        workbook.recompute_sheet_references();
+        workbook
+    }
+}
+
+// Resolves cell names against one of the sheets and sheet references against the whole workbook
+struct WorkbookContext<'a> {
+    workbook: &'a Workbook,
+    sheet: usize,
+}
+
+impl VarContext for WorkbookContext<'_> {
+    fn get_variable(&self, index: Index) -> Option<Result<Value, ComputeError>> {
+        self.workbook.sheets[self.sheet]
+            .spread_sheet
+            .get_computed(index)
+    }
+
+    fn get_sheet_variable(&self, sheet: &str, index: Index) -> Option<Result<Value, ComputeError>> {
+        match self.workbook.find_sheet(sheet) {
+            Some(sheet) => self.workbook.sheets[sheet].spread_sheet.get_computed(index),
+            None => Some(Err(ComputeError::UnfindableReference(format!(
+                "Unknown sheet '{sheet}'"
+            )))),
+        }
+    }
+
+    fn get_name(&self, name: &str) -> Option<&AST> {
+        self.workbook
+            .names
+            .get(name)
+            .map(|defined| &defined.expression.ast)
+    }
+
+    fn get_function(&self, name: &str) -> Option<&Function> {
+        self.workbook.sheets[self.sheet]
+            .spread_sheet
+            .functions()
+            .get(name)
+    }
+
+    fn number_locale(&self) -> NumberLocale {
+        self.workbook.number_locale
+    }
+}
+
+impl Workbook {
+    pub fn sheet_count(&self) -> usize {
+        self.sheets.len()
+    }
+
+    /// Returns the sheet at the given position. Panics if there is no such sheet.
+    pub fn sheet(&self, sheet: usize) -> &SpreadSheet {
+        &self.sheets[sheet].spread_sheet
+    }
+
+    /// Returns the name of the sheet at the given position. Panics if there is no such sheet.
+    pub fn sheet_name(&self, sheet: usize) -> &str {
+        &self.sheets[sheet].name
+    }
+
+    pub fn sheet_names(&self) -> impl Iterator<Item = &str> {
+        self.sheets.iter().map(|sheet| sheet.name.as_str())
+    }
+
+    /// Returns the position of the sheet with the given name.
+    pub fn find_sheet(&self, name: &str) -> Option<usize> {
+        self.sheets.iter().position(|sheet| sheet.name == name)
+    }
+
+    /// Returns the first of `Sheet1`, `Sheet2`, ... that is not taken yet.
+    pub fn unused_sheet_name(&self) -> String {
+        (1..)
+            .map(|i| format!("Sheet{i}"))
+            .find(|name| self.find_sheet(name).is_none())
+            .expect("There are fewer sheets than numbers")
+    }
+
+    /// Adds an empty sheet after the last one and returns its position.
+    pub fn add_sheet(&mut self, name: &str) -> Result<usize, SheetNameError> {
+        self.validate_name(name)?;
+        let mut spread_sheet = SpreadSheet::default();
+        spread_sheet.set_number_locale(self.number_locale);
+        spread_sheet.set_functions(self.functions.clone());
+        let position = self.sheets.len();
+        self.record_step(
+            StepKind::Add {
+                position,
+                sheet: None,
+            },
+            |workbook| {
+                workbook.sheets.push(Sheet {
+                    name: name.to_string(),
+                    spread_sheet,
+                });
+                // Formulas that were waiting for a sheet with this name can now be computed
+                workbook.recompute_sheet_references();
+            },
+        );
+        Ok(position)
+    }
+
+    /// Renames a sheet, rewriting every formula that refers to it. Undoing it reverts both.
+    pub fn rename_sheet(&mut self, sheet: usize, name: &str) -> Result<(), SheetNameError> {
+        if self.sheets[sheet].name == name {
+            return Ok(());
+        }
+        self.validate_name(name)?;
+
+        let old_name = std::mem::replace(&mut self.sheets[sheet].name, name.to_string());
+        let kind = StepKind::Rename {
+            sheet,
+            before: old_name.clone(),
+            after: name.to_string(),
+        };
+        self.record_step(kind, |workbook| {
+            workbook.rewrite_references(|reference| {
+                if reference.sheet.as_deref() == Some(old_name.as_str()) {
+                    Some(FormulaReference {
+                        sheet: Some(name.to_string()),
+                        ..reference
+                    })
+                } else {
+                    Some(reference)
+                }
+            })
+        });
+        Ok(())
+    }
+
+    /// Removes a sheet, turning every reference to it into `#REF!`. Undoing it brings back the sheet and
+    /// the references. Returns false without removing anything if it is the only sheet.
+    pub fn remove_sheet(&mut self, sheet: usize) -> bool {
+        if self.sheets.len() == 1 {
+            return false;
+        }
+
+        let removed = self.sheets.remove(sheet);
+        let removed_name = removed.name.clone();
+        let kind = StepKind::Remove {
+            position: sheet,
+            sheet: Some(removed),
+        };
+        self.record_step(kind, |workbook| {
+            workbook.rewrite_references(|reference| {
+                (reference.sheet.as_deref() != Some(removed_name.as_str())).then_some(reference)
+            })
+        });
+        true
+    }
+
+    /// Binds a name to a cell, a range or a constant expression, such as `$B$2`, `Data!A1:A10` or `0.2`.
+    /// References without a sheet name refer to the given sheet. Redefining a name recomputes every cell using it.
+    pub fn define_name(
+        &mut self,
+        name: &str,
+        sheet: usize,
+        definition: &str,
+    ) -> Result<(), NameError> {
+        let is_name = ExpressionTokenizer::new(name.chars().collect())
+            .tokenize_expression()
+            .is_ok_and(|tokens| tokens == [Token::Name(name.to_string())]);
+        if !is_name {
+            return Err(NameError::InvalidName(name.to_string()));
+        }
+
+        let sheet_name = &self.sheets[sheet].name;
+        let formula = CellParser::rewrite_references(
+            &format!("={}", definition.trim_start_matches('=')),
+            |reference| {
+                Some(FormulaReference {
+                    sheet: reference.sheet.or_else(|| Some(sheet_name.clone())),
+                    ..reference
+                })
+            },
+        );
+        let expression = CellParser::parse_formula(&formula, self.number_locale)
+            .map_err(NameError::InvalidDefinition)?;
+        if self.reaches_name(&expression.dependencies, name) {
+            return Err(NameError::Cycle(name.to_string()));
+        }
+
+        self.names.insert(
+            name.to_string(),
+            DefinedName {
+                formula,
+                expression,
+            },
+        );
+        self.recompute_sheet_references();
+        Ok(())
+    }
+
+    /// Removes a name and returns its definition. Cells using it fail to compute until it is defined again.
+    pub fn remove_name(&mut self, name: &str) -> Option<String> {
+        let removed = self.names.remove(name)?;
+        self.recompute_sheet_references();
+        Some(removed.formula[1..].to_string())
+    }
+
+    /// Returns the definition of a name, with every reference qualified by its sheet.
+    pub fn name_definition(&self, name: &str) -> Option<&str> {
+        self.names.get(name).map(|defined| &defined.formula[1..])
+    }
+
+    /// Returns every name and its definition, ordered by name.
+    pub fn names(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.names
+            .iter()
+            .map(|(name, defined)| (name.as_str(), &defined.formula[1..]))
+    }
+
+    /// The functions the formulas of every sheet can call.
+    pub fn functions(&self) -> &FunctionRegistry {
+        &self.functions
+    }
+
+    /// Adds a function the formulas of every sheet can call, replacing any function with the same name.
+    ///
+    /// Fails if formulas can not call the function by its name, or if the name is one of the lazily evaluated
+    /// functions such as `if`, see [`FunctionRegistry::register`].
+    pub fn register_function(&mut self, function: Function) -> Result<(), RegistrationError> {
+        Arc::make_mut(&mut self.functions).register(function)?;
+        for sheet in &mut self.sheets {
+            sheet.spread_sheet.set_functions(self.functions.clone());
+        }
+        self.recompute_sheet_references();
+        Ok(())
+    }
+
+    pub fn number_locale(&self) -> NumberLocale {
+        self.number_locale
+    }
+
+    /// Changes how numbers are written in the cells and formulas of every sheet and in the definitions of names.
+    /// Nothing is converted, so numbers written for the previous locale may no longer parse.
+    pub fn set_number_locale(&mut self, locale: NumberLocale) {
+        self.number_locale = locale;
+        for sheet in &mut self.sheets {
+            sheet.spread_sheet.set_number_locale(locale);
+        }
+        for defined in self.names.values_mut() {
+            defined.reparse(locale);
+        }
+        self.recompute_sheet_references();
+    }
+
+    /// Makes changes to a sheet, then brings the cells of every sheet that read it up to date.
+    /// The recorded changes are undone with [`Workbook::undo`] rather than the sheet's own undo.
+    pub fn edit<R>(&mut self, sheet: usize, f: impl FnOnce(&mut SpreadSheet) -> R) -> R {
+        self.record_step(StepKind::Edit, |workbook| {
+            let result = f(&mut workbook.sheets[sheet].spread_sheet);
+            workbook.recompute_sheet_references();
+            result
+        })
+    }
+
+    /// Reverts the last change made through the workbook in every sheet it touched.
+    /// Returns false if there was nothing to undo.
+    pub fn undo(&mut self) -> bool {
+        let Some(mut step) = self.undo_stack.pop() else {
+            return false;
+        };
+
+        for &(sheet, count) in step.transactions.iter().rev() {
+            for _ in 0..count {
+                self.sheets[sheet].spread_sheet.undo();
+            }
+        }
+        self.set_definitions(
+            step.definitions
+                .iter()
+                .map(|(name, before, _)| (name, before)),
+        );
+        match &mut step.kind {
+            StepKind::Edit => {}
+            StepKind::Rename { sheet, before, .. } => self.sheets[*sheet].name = before.clone(),
+            StepKind::Add { position, sheet } => *sheet = Some(self.sheets.remove(*position)),
+            StepKind::Remove { position, sheet } => {
+                let sheet = sheet.take().expect("Removed sheets are held until undone");
+                self.restore_sheet(*position, sheet);
+            }
+        }
+
+        self.recompute_sheet_references();
+        self.redo_stack.push(step);
+        true
+    }
+
+    /// Re-applies the last change undone with [`Workbook::undo`]. Returns false if there was nothing to redo.
+    pub fn redo(&mut self) -> bool {
+        let Some(mut step) = self.redo_stack.pop() else {
+            return false;
+        };
+
+        match &mut step.kind {
+            StepKind::Edit => {}
+            StepKind::Rename { sheet, after, .. } => self.sheets[*sheet].name = after.clone(),
+            StepKind::Add { position, sheet } => {
+                let sheet = sheet.take().expect("Added sheets are held while undone");
+                self.restore_sheet(*position, sheet);
+            }
+            StepKind::Remove { position, sheet } => *sheet = Some(self.sheets.remove(*position)),
+        }
+        for &(sheet, count) in &step.transactions {
+            for _ in 0..count {
+                self.sheets[sheet].spread_sheet.redo();
+            }
+        }
+        self.set_definitions(
+            step.definitions
+                .iter()
+                .map(|(name, _, after)| (name, after)),
+        );
+
+        self.recompute_sheet_references();
+        self.undo_stack.push(step);
+        true
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    // Runs a change and records it as one step, along with every transaction it added to the sheets' histories
+    // and every definition it rewrote
+    fn record_step<R>(&mut self, kind: StepKind, change: impl FnOnce(&mut Self) -> R) -> R {
+        let undo_lens: Vec<usize> = self
+            .sheets
+            .iter()
+            .map(|sheet| sheet.spread_sheet.undo_len())
+            .collect();
+        let formulas: Vec<(String, String)> = self
+            .names
+            .iter()
+            .map(|(name, defined)| (name.clone(), defined.formula.clone()))
+            .collect();
+
+        let result = change(self);
+
+        // A sheet added by the change has nothing to undo yet
+        let transactions: Vec<(usize, usize)> = self
+            .sheets
+            .iter()
+            .zip(undo_lens)
+            .enumerate()
+            .filter_map(|(position, (sheet, before))| {
+                let count = sheet.spread_sheet.undo_len().saturating_sub(before);
+                (count > 0).then_some((position, count))
+            })
+            .collect();
+        let definitions: Vec<(String, String, String)> = formulas
+            .into_iter()
+            .filter_map(|(name, before)| {
+                let after = &self.names.get(&name)?.formula;
+                (*after != before).then(|| (name.clone(), before, after.clone()))
+            })
+            .collect();
+
+        if !matches!(kind, StepKind::Edit) || !transactions.is_empty() || !definitions.is_empty() {
+            self.undo_stack.push(Step {
+                kind,
+                transactions,
+                definitions,
+            });
+            self.redo_stack.clear();
+        }
+        result
+    }
+
+    fn set_definitions<'a>(&mut self, formulas: impl Iterator<Item = (&'a String, &'a String)>) {
+        for (name, formula) in formulas {
+            if let Some(defined) = self.names.get_mut(name) {
+                defined.formula = formula.clone();
+                defined.reparse(self.number_locale);
+            }
+        }
+    }
+
+    // Puts back a sheet taken out by undo or redo, which may have missed changes to the locale or the functions
+    fn restore_sheet(&mut self, position: usize, mut sheet: Sheet) {
+        if sheet.spread_sheet.number_locale() != self.number_locale {
+            sheet.spread_sheet.set_number_locale(self.number_locale);
+        }
+        sheet.spread_sheet.set_functions(self.functions.clone());
+        self.sheets.insert(position, sheet);
+    }
+
+    /// Inserts `count` empty rows before `row` of a sheet, also rewriting the references other sheets make to it.
+    pub fn insert_rows(&mut self, sheet: usize, row: usize, count: usize) {
+        self.apply_structural_change(
+            sheet,
+            StructuralChange::Insert {
+                axis: Axis::Row,
+                at: row,
+                count,
+            },
+        );
+    }
+
+    /// Deletes `count` rows of a sheet starting at `row`, also rewriting the references other sheets make to it.
+    pub fn delete_rows(&mut self, sheet: usize, row: usize, count: usize) {
+        self.apply_structural_change(
+            sheet,
+            StructuralChange::Delete {
+                axis: Axis::Row,
+                at: row,
+                count,
+            },
+        );
+    }
+
+    /// Inserts `count` empty columns before `column` of a sheet, also rewriting the references other sheets make to it.
+    pub fn insert_columns(&mut self, sheet: usize, column: usize, count: usize) {
+        self.apply_structural_change(
+            sheet,
+            StructuralChange::Insert {
+                axis: Axis::Column,
+                at: column,
+                count,
+            },
+        );
+    }
+
+    /// Deletes `count` columns of a sheet starting at `column`, also rewriting the references other sheets make to it.
+    pub fn delete_columns(&mut self, sheet: usize, column: usize, count: usize) {
+        self.apply_structural_change(
+            sheet,
+            StructuralChange::Delete {
+                axis: Axis::Column,
+                at: column,
+                count,
+            },
+        );
+    }
+
+    fn apply_structural_change(&mut self, sheet: usize, change: StructuralChange) {
+        self.record_step(StepKind::Edit, |workbook| {
+            let spread_sheet = &mut workbook.sheets[sheet].spread_sheet;
+            match change {
+                StructuralChange::Insert { axis, at, count } => match axis {
+                    Axis::Row => spread_sheet.insert_rows(at, count),
+                    Axis::Column => spread_sheet.insert_columns(at, count),
+                },
+                StructuralChange::Delete { axis, at, count } => match axis {
+                    Axis::Row => spread_sheet.delete_rows(at, count),
+                    Axis::Column => spread_sheet.delete_columns(at, count),
+                },
+            }
+
+            // References of the form `Sheet!A1` are rewritten in every sheet, the sheet itself included
+            let name = workbook.sheets[sheet].name.clone();
+            workbook.rewrite_formulas(|raw| {
+                CellParser::apply_structural_change(raw, &change, Some(&name))
+            });
+        });
+    }
+
+    fn validate_name(&self, name: &str) -> Result<(), SheetNameError> {
+        if name.trim().is_empty() {
+            return Err(SheetNameError::Empty);
+        }
+        if let Some(c) = name.chars().find(|c| matches!(c, '\'' | '!' | ':')) {
+            return Err(SheetNameError::InvalidCharacter(c));
+        }
+        if self.find_sheet(name).is_some() {
+            return Err(SheetNameError::Duplicate(name.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Rewrites the references of every formula in every sheet.
+    fn rewrite_references<F>(&mut self, rewrite: F)
+    where
+        F: Fn(FormulaReference) -> Option<FormulaReference>,
+    {
+        self.rewrite_formulas(|raw| CellParser::rewrite_references(raw, &rewrite));
+    }
+
+    /// Replaces the raw contents of every cell in every sheet, recording the changes in each sheet's history.
+    /// Definitions of names are rewritten as well.
+    fn rewrite_formulas(&mut self, rewrite: impl Fn(&str) -> String) {
+        for defined in self.names.values_mut() {
+            let formula = rewrite(&defined.formula);
+            if formula != defined.formula {
+                defined.formula = formula;
+                defined.reparse(self.number_locale);
+            }
+        }
+
+        for sheet in &mut self.sheets {
+            let rewritten: Vec<(Index, String)> = sheet
+                .spread_sheet
+                .iter_cells()
+                .filter_map(|(index, cell)| {
+                    let raw = &cell.raw_representation;
+                    let new_raw = rewrite(raw);
+                    (&new_raw != raw).then_some((*index, new_raw))
+                })
+                .collect();
+            sheet.spread_sheet.set_raws(rewritten);
+        }
+        self.recompute_sheet_references();
+    }
+
+    /// Recomputes every cell reading another sheet or a name, and everything depending on those, in a single order across sheets.
+    /// Cells in a cycle spanning several sheets get a cycle error.
+    fn recompute_sheet_references(&mut self) {
+        let mut to_process: Vec<(usize, Index)> = self
+            .sheets
+            .iter()
+            .enumerate()
+            .flat_map(|(i, sheet)| {
+                sheet
+                    .spread_sheet
+                    .dependencies()
+                    .nodes_reading_workbook()
+                    .map(move |index| (i, index))
+            })
+            .collect();
+
+        let mut edges: HashMap<(usize, Index), Vec<(usize, Index)>> = HashMap::new();
+        let mut affected: HashSet<(usize, Index)> = HashSet::new();
+        while let Some(cell) = to_process.pop() {
+            if !affected.insert(cell) {
+                continue;
+            }
+            let dependants = self.direct_dependants(cell);
+            to_process.extend(&dependants);
+            edges.insert(cell, dependants);
+        }
+
+        let mut in_degree: HashMap<(usize, Index), usize> =
+            affected.iter().map(|cell| (*cell, 0)).collect();
+        for dependant in edges.values().flatten() {
+            *in_degree
+                .get_mut(dependant)
+                .expect("Dependants are affected") += 1;
+        }
+        let mut ready: Vec<(usize, Index)> = in_degree
+            .iter()
+            .filter(|(_, degree)| **degree == 0)
+            .map(|(cell, _)| *cell)
+            .collect();
+
+        while let Some(cell @ (sheet, index)) = ready.pop() {
+            in_degree.remove(&cell);
+            let context = WorkbookContext {
+                workbook: self,
+                sheet,
+            };
+            let computed = self.sheets[sheet].spread_sheet.compute_in(index, &context);
+            self.sheets[sheet]
+                .spread_sheet
+                .set_computed(index, computed);
+
+            for dependant in &edges[&cell] {
+                let degree = in_degree
+                    .get_mut(dependant)
+                    .expect("Dependants are affected");
+                *degree -= 1;
+                if *degree == 0 {
+                    ready.push(*dependant);
+                }
+            }
+        }
+
+        // Whatever could not be ordered is part of a cycle
+        for (sheet, index) in in_degree.into_keys() {
+            self.sheets[sheet]
+                .spread_sheet
+                .set_computed(index, Some(Err(ComputeError::Cycle)));
+        }
+    }
+
+    /// Returns the cells of every sheet that directly depend on a cell.
+    fn direct_dependants(&self, (sheet, index): (usize, Index)) -> Vec<(usize, Index)> {
+        let mut dependants: Vec<(usize, Index)> = self.sheets[sheet]
+            .spread_sheet
+            .dependencies()
+            .direct_dependants(index)
+            .into_iter()
+            .map(|dependant| (sheet, dependant))
+            .collect();
+
+        let name = &self.sheets[sheet].name;
+        for (i, other) in self.sheets.iter().enumerate() {
+            dependants.extend(
+                other
+                    .spread_sheet
+                    .dependencies()
+                    .sheet_dependants(name, index)
+                    .into_iter()
+                    .map(|dependant| (i, dependant)),
+            );
+        }
+
+        for defined_name in self.names_reading(name, index) {
+            for (i, other) in self.sheets.iter().enumerate() {
+                dependants.extend(
+                    other
+                        .spread_sheet
+                        .dependencies()
+                        .name_dependants(defined_name)
+                        .into_iter()
+                        .map(|dependant| (i, dependant)),
+                );
+            }
+        }
+        dependants
+    }
+
+    /// Returns the names whose definition reads a cell, directly or through other names.
+    fn names_reading(&self, sheet: &str, index: Index) -> Vec<&str> {
+        let mut reading: Vec<&str> = self
+            .names
+            .iter()
+            .filter(|(_, defined)| {
+                defined.expression.dependencies.iter().any(|dependency| {
+                    matches!(dependency, Dependency::Sheet { sheet: other, range }
+                        if other == sheet && range.contains(index))
+                })
+            })
+            .map(|(name, _)| name.as_str())
+            .collect();
+
+        let mut i = 0;
+        while i < reading.len() {
+            let read = Dependency::Name(reading[i].to_string());
+            for (name, defined) in &self.names {
+                if !reading.contains(&name.as_str())
+                    && defined.expression.dependencies.contains(&read)
+                {
+                    reading.push(name);
+                }
+            }
+            i += 1;
+        }
+        reading
+    }
+
+    /// Checks whether the dependencies lead to the given name through the definitions of names.
+    fn reaches_name(&self, dependencies: &[Dependency], target: &str) -> bool {
+        let mut visited = HashSet::new();
+        let mut to_visit: Vec<&Dependency> = dependencies.iter().collect();
+        while let Some(dependency) = to_visit.pop() {
+            let Dependency::Name(name) = dependency else {
+                continue;
+            };
+            if name == target {
+                return true;
+            }
+            if visited.insert(name) {
+                if let Some(defined) = self.names.get(name) {
+                    to_visit.extend(&defined.expression.dependencies);
+                }
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ArgumentType;
+
+    fn idx(name: &str) -> Index {
+        crate::common_functions::get_cell_idx(name).unwrap()
+    }
+
+    #[test]
+    fn test_cross_sheet_reference() {
+        let mut workbook = Workbook::default();
+        let data = workbook.add_sheet("Data").unwrap();
+        workbook.edit(data, |sheet| {
+            sheet.set_raw(idx("A1"), "2".to_string());
+            sheet.set_raw(idx("A2"), "3".to_string());
+        });
+        workbook.edit(0, |sheet| {
+            sheet.set_raw(idx("A1"), "=Data!A1 * 10".to_string());
+            sheet.set_raw(idx("B1"), "=sum(Data!A1:A2) + A1".to_string());
+        });
+
+        assert_eq!(
+            workbook.sheet(0).get_computed(idx("B1")),
+            Some(Ok(Value::Number(25.0)))
+        );
+
+        // Changes propagate to the other sheet and to what depends on it there
+        workbook.edit(data, |sheet| sheet.set_raw(idx("A1"), "4".to_string()));
+        assert_eq!(
+            workbook.sheet(0).get_computed(idx("A1")),
+            Some(Ok(Value::Number(40.0)))
+        );
+        assert_eq!(
+            workbook.sheet(0).get_computed(idx("B1")),
+            Some(Ok(Value::Number(47.0)))
+        );
+
+        // Undoing in the other sheet propagates as well
+        assert!(workbook.undo());
+        assert_eq!(
+            workbook.sheet(0).get_computed(idx("B1")),
+            Some(Ok(Value::Number(25.0)))
+        );
+    }
+
+    #[test]
+    fn test_unknown_sheet() {
+        let mut workbook = Workbook::default();
+        workbook.edit(0, |sheet| {
+            sheet.set_raw(idx("A1"), "='Later on'!A1 + 1".to_string())
+        });
+        assert!(matches!(
+            workbook.sheet(0).get_computed(idx("A1")),
+            Some(Err(ComputeError::UnfindableReference(_)))
+        ));
+
+        let later = workbook.add_sheet("Later on").unwrap();
+        workbook.edit(later, |sheet| sheet.set_raw(idx("A1"), "1".to_string()));
+        assert_eq!(
+            workbook.sheet(0).get_computed(idx("A1")),
+            Some(Ok(Value::Number(2.0)))
+        );
+    }
+
+    #[test]
+    fn test_cycle_across_sheets() {
+        let mut workbook = Workbook::default();
+        let other = workbook.add_sheet("Other").unwrap();
+        workbook.edit(0, |sheet| {
+            sheet.set_raw(idx("A1"), "=Other!A1 + 1".to_string())
+        });
+        workbook.edit(other, |sheet| {
+            sheet.set_raw(idx("A1"), "=Sheet1!A1 + 1".to_string())
+        });
+
+        assert_eq!(
+            workbook.sheet(0).get_computed(idx("A1")),
+            Some(Err(ComputeError::Cycle))
+        );
+        assert_eq!(
+            workbook.sheet(other).get_computed(idx("A1")),
+            Some(Err(ComputeError::Cycle))
+        );
+
+        // Breaking the cycle computes both cells again
+        workbook.edit(other, |sheet| sheet.set_raw(idx("A1"), "5".to_string()));
+        assert_eq!(
+            workbook.sheet(0).get_computed(idx("A1")),
+            Some(Ok(Value::Number(6.0)))
+        );
+    }
+
+    #[test]
+    fn test_rename_and_remove_sheet() {
+        let mut workbook = Workbook::default();
+        let data = workbook.add_sheet("Data").unwrap();
+        workbook.edit(data, |sheet| sheet.set_raw(idx("A1"), "7".to_string()));
+        workbook.edit(0, |sheet| sheet.set_raw(idx("A1"), "=Data!A1".to_string()));
+
+        workbook.rename_sheet(data, "My data").unwrap();
+        assert_eq!(workbook.sheet(0).get_raw(&idx("A1")), Some("='My data'!A1"));
+        assert_eq!(
+            workbook.sheet(0).get_computed(idx("A1")),
+            Some(Ok(Value::Number(7.0)))
+        );
+
+        assert!(workbook.remove_sheet(data));
+        assert_eq!(workbook.sheet(0).get_raw(&idx("A1")), Some("=#REF!"));
+        assert!(!workbook.remove_sheet(0));
+    }
+
+    #[test]
+    fn test_undo_sheet_changes() {
+        let mut workbook = Workbook::default();
+        let data = workbook.add_sheet("Data").unwrap();
+        workbook.edit(data, |sheet| sheet.set_raw(idx("A1"), "7".to_string()));
+        workbook.edit(0, |sheet| sheet.set_raw(idx("A1"), "=Data!A1".to_string()));
+        workbook.define_name("Total", data, "A1").unwrap();
+
+        // Renaming and the references rewritten for it are a single step
+        workbook.rename_sheet(data, "Info").unwrap();
+        assert!(workbook.undo());
+        assert_eq!(workbook.sheet_name(data), "Data");
+        assert_eq!(workbook.sheet(0).get_raw(&idx("A1")), Some("=Data!A1"));
+        assert_eq!(workbook.name_definition("Total"), Some("Data!A1"));
+        assert_eq!(
+            workbook.sheet(0).get_computed(idx("A1")),
+            Some(Ok(Value::Number(7.0)))
+        );
+        assert!(workbook.redo());
+        assert_eq!(workbook.sheet(0).get_raw(&idx("A1")), Some("=Info!A1"));
+        assert_eq!(workbook.name_definition("Total"), Some("Info!A1"));
+
+        assert!(workbook.remove_sheet(data));
+        assert!(workbook.undo());
+        assert_eq!(workbook.sheet_name(data), "Info");
+        assert_eq!(workbook.sheet(0).get_raw(&idx("A1")), Some("=Info!A1"));
+        assert_eq!(
+            workbook.sheet(0).get_computed(idx("A1")),
+            Some(Ok(Value::Number(7.0)))
+        );
+        assert!(workbook.redo());
+        assert_eq!(workbook.sheet_count(), 1);
+        assert_eq!(workbook.sheet(0).get_raw(&idx("A1")), Some("=#REF!"));
+
+        // Back to the sheet as it was added
+        assert!(workbook.undo());
+        assert!(workbook.undo());
+        assert!(workbook.undo());
+        assert_eq!(workbook.sheet(data).get_raw(&idx("A1")), Some("7"));
+        assert!(workbook.undo());
+        assert!(workbook.undo());
+        assert_eq!(workbook.sheet_count(), 1);
+        assert!(!workbook.undo());
+        assert!(workbook.redo());
+        assert_eq!(workbook.sheet_name(data), "Data");
+    }
+
+    #[test]
+    fn test_undo_structural_change_in_referenced_sheet() {
+        let mut workbook = Workbook::default();
+        let data = workbook.add_sheet("Data").unwrap();
+        workbook.edit(data, |sheet| {
+            sheet.set_raw(idx("A1"), "1".to_string());
+            sheet.set_raw(idx("A2"), "2".to_string());
+        });
+        workbook.edit(0, |sheet| sheet.set_raw(idx("A1"), "=Data!A2".to_string()));
+
+        workbook.insert_rows(data, 0, 1);
+        assert_eq!(workbook.sheet(0).get_raw(&idx("A1")), Some("=Data!A3"));
+
+        // Undoing reverts the rewritten references of the other sheet along with the moved cells
+        assert!(workbook.undo());
+        assert_eq!(workbook.sheet(0).get_raw(&idx("A1")), Some("=Data!A2"));
+        assert_eq!(
+            workbook.sheet(0).get_computed(idx("A1")),
+            Some(Ok(Value::Number(2.0)))
+        );
+        assert!(workbook.redo());
+        assert_eq!(workbook.sheet(0).get_raw(&idx("A1")), Some("=Data!A3"));
+        assert_eq!(
+            workbook.sheet(0).get_computed(idx("A1")),
+            Some(Ok(Value::Number(2.0)))
+        );
+    }
+
+    #[test]
+    fn test_invalid_sheet_names() {
+        let mut workbook = Workbook::default();
+        assert_eq!(workbook.add_sheet(" "), Err(SheetNameError::Empty));
+        assert_eq!(
+            workbook.add_sheet("a!b"),
+            Err(SheetNameError::InvalidCharacter('!'))
+        );
+        assert_eq!(
+            workbook.add_sheet("Sheet1"),
+            Err(SheetNameError::Duplicate("Sheet1".to_string()))
+        );
+        assert_eq!(workbook.unused_sheet_name(), "Sheet2");
+    }
+
+    #[test]
+    fn test_insert_rows_in_referenced_sheet() {
+        let mut workbook = Workbook::default();
+        let data = workbook.add_sheet("Data").unwrap();
+        workbook.edit(data, |sheet| sheet.set_raw(idx("A1"), "1".to_string()));
+        workbook.edit(0, |sheet| {
+            sheet.set_raw(idx("A1"), "=Data!A1".to_string());
+            sheet.set_raw(idx("A2"), "=A1".to_string());
+        });
+
+        workbook.insert_rows(data, 0, 1);
+        assert_eq!(workbook.sheet(0).get_raw(&idx("A1")), Some("=Data!A2"));
+        assert_eq!(workbook.sheet(0).get_raw(&idx("A2")), Some("=A1"));
+        assert_eq!(
+            workbook.sheet(0).get_computed(idx("A2")),
+            Some(Ok(Value::Number(1.0)))
+        );
+    }
+
+    #[test]
+    fn test_names() {
+        let mut workbook = Workbook::default();
+        let data = workbook.add_sheet("Data").unwrap();
+        workbook.edit(data, |sheet| {
+            sheet.set_raw(idx("A1"), "100".to_string());
+            sheet.set_raw(idx("A2"), "50".to_string());
+        });
+        workbook.define_name("TaxRate", 0, "0.2").unwrap();
+        workbook.define_name("Revenue", data, "A1:A2").unwrap();
+        workbook.edit(0, |sheet| {
+            sheet.set_raw(idx("A1"), "=sum(Revenue) * TaxRate".to_string());
+            sheet.set_raw(idx("A2"), "=A1 + 1".to_string());
+        });
+        assert_eq!(workbook.name_definition("Revenue"), Some("Data!A1:A2"));
+        assert_eq!(
+            workbook.sheet(0).get_computed(idx("A2")),
+            Some(Ok(Value::Number(31.0)))
+        );
+
+        // Changing a cell the name refers to recomputes its users
+        workbook.edit(data, |sheet| sheet.set_raw(idx("A2"), "150".to_string()));
+        assert_eq!(
+            workbook.sheet(0).get_computed(idx("A2")),
+            Some(Ok(Value::Number(51.0)))
+        );
+
+        // So does changing the target of the name
+        workbook.define_name("TaxRate", 0, "0.5").unwrap();
+        assert_eq!(
+            workbook.sheet(0).get_computed(idx("A2")),
+            Some(Ok(Value::Number(126.0)))
+        );
+
+        assert_eq!(workbook.remove_name("TaxRate"), Some("0.5".to_string()));
+        assert!(matches!(
+            workbook.sheet(0).get_computed(idx("A2")),
+            Some(Err(ComputeError::UnknownName(_)))
+        ));
+    }
+
+    #[test]
+    fn test_names_defined_through_names() {
+        let mut workbook = Workbook::default();
+        workbook.edit(0, |sheet| sheet.set_raw(idx("B1"), "4".to_string()));
+        workbook.define_name("Base", 0, "$B$1").unwrap();
+        workbook.define_name("Double", 0, "Base * 2").unwrap();
+        workbook.edit(0, |sheet| sheet.set_raw(idx("A1"), "=Double".to_string()));
+        assert_eq!(
+            workbook.sheet(0).get_computed(idx("A1")),
+            Some(Ok(Value::Number(8.0)))
+        );
+
+        workbook.edit(0, |sheet| sheet.set_raw(idx("B1"), "5".to_string()));
+        assert_eq!(
+            workbook.sheet(0).get_computed(idx("A1")),
+            Some(Ok(Value::Number(10.0)))
+        );
+
+        // A cell used by the name it reads is a cycle
+        workbook.define_name("Base", 0, "A1").unwrap();
+        assert_eq!(
+            workbook.sheet(0).get_computed(idx("A1")),
+            Some(Err(ComputeError::Cycle))
+        );
+    }
+
+    #[test]
+    fn test_invalid_names() {
+        let mut workbook = Workbook::default();
+        for name in ["", "A1", "TRUE", "two words", "sum(", "Sheet1!A1"] {
+            assert_eq!(
+                workbook.define_name(name, 0, "1"),
+                Err(NameError::InvalidName(name.to_string()))
+            );
+        }
+        assert!(matches!(
+            workbook.define_name("Broken", 0, "1 +"),
+            Err(NameError::InvalidDefinition(_))
+        ));
+
+        workbook.define_name("First", 0, "Second + 1").unwrap();
+        assert_eq!(
+            workbook.define_name("Second", 0, "First"),
+            Err(NameError::Cycle("Second".to_string()))
+        );
+        assert_eq!(workbook.names().count(), 1);
+    }
+
+    #[test]
+    fn test_register_function() {
+        let mut workbook = Workbook::default();
+        let data = workbook.add_sheet("Data").unwrap();
+        workbook.edit(data, |sheet| sheet.set_raw(idx("A1"), "3".to_string()));
+        workbook.edit(0, |sheet| {
+            sheet.set_raw(idx("A1"), "=triple(Data!A1)".to_string());
+        });
+        workbook
+            .register_function(
+                Function::new("triple", "", |args| {
+                    Ok(Value::Number(args[0].as_number().unwrap_or_default() * 3.0))
+                })
+                .required(&[ArgumentType::Number]),
+            )
+            .unwrap();
+        let other = workbook.add_sheet("Other").unwrap();
+        workbook.edit(other, |sheet| {
+            sheet.set_raw(idx("A1"), "=triple(2)".to_string())
+        });
+
+        assert_eq!(
+            workbook.sheet(0).get_computed(idx("A1")),
+            Some(Ok(Value::Number(9.0)))
+        );
+        assert_eq!(
+            workbook.sheet(other).get_computed(idx("A1")),
+            Some(Ok(Value::Number(6.0)))
+        );
+    }
+
+    #[test]
+    fn test_number_locale() {
+        let mut workbook = Workbook::default();
+        workbook.set_number_locale(NumberLocale::DecimalComma);
+        let data = workbook.add_sheet("Data").unwrap();
+        workbook.edit(data, |sheet| {
+            sheet.set_raw(idx("A1"), "1.000,5".to_string())
+        });
+        workbook.define_name("Half", 0, "0,5").unwrap();
+        workbook.edit(0, |sheet| {
+            sheet.set_raw(idx("A1"), "=sum(Data!A1; 1,5) * Half".to_string());
+        });
+        assert_eq!(
+            workbook.sheet(0).get_computed(idx("A1")),
+            Some(Ok(Value::Number(501.0)))
+        );
+
+        // Nothing is converted, so the definition no longer parses
+        workbook.set_number_locale(NumberLocale::DecimalPoint);
+        assert!(matches!(
+            workbook.sheet(0).get_computed(idx("A1")),
+            Some(Err(ComputeError::ParseError(_)))
+        ));
+    }
+
+    #[test]
+    fn test_names_follow_sheet_changes() {
+        let mut workbook = Workbook::default();
+        let data = workbook.add_sheet("Data").unwrap();
+        workbook.define_name("Total", data, "B2").unwrap();
+
+        workbook.rename_sheet(data, "My data").unwrap();
+        assert_eq!(workbook.name_definition("Total"), Some("'My data'!B2"));
+
+        workbook.insert_rows(data, 0, 2);
+        assert_eq!(workbook.name_definition("Total"), Some("'My data'!B4"));
+
+        workbook.remove_sheet(data);
+        assert_eq!(workbook.name_definition("Total"), Some("#REF!"));
+    }
+}